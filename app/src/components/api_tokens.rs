@@ -0,0 +1,350 @@
+use crate::{
+    components::{
+        form::{field::Field, select::Select, submit::Submit},
+        router::{AppRoute, Link},
+    },
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        cookies::get_cookie,
+    },
+};
+use anyhow::{Result, bail};
+use graphql_client::GraphQLQuery;
+use validator_derive::Validate;
+use yew::prelude::*;
+use yew_form::Form;
+use yew_form_derive::Model;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_api_tokens.graphql",
+    response_derives = "Debug,Clone,PartialEq",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetApiTokens;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/create_api_token.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct CreateApiToken;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/revoke_api_token.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RevokeApiToken;
+
+pub type ApiToken = get_api_tokens::GetApiTokensApiTokens;
+
+/// The fields of the form, with the constraints.
+#[derive(Model, Validate, PartialEq, Eq, Clone, Debug)]
+pub struct FormModel {
+    #[validate(length(min = 1, message = "The name is required"))]
+    name: String,
+    scope: String,
+    /// Number of days before the token expires, or empty for no expiry.
+    expiry_days: String,
+}
+
+impl Default for FormModel {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            scope: "READONLY".to_owned(),
+            expiry_days: String::new(),
+        }
+    }
+}
+
+pub struct ApiTokensTable {
+    common: CommonComponentParts<Self>,
+    form: Form<FormModel>,
+    tokens: Option<Vec<ApiToken>>,
+    /// The secret of the token that was just created, shown only once.
+    new_secret: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+}
+
+pub enum Msg {
+    FormUpdate,
+    ListResponse(Result<get_api_tokens::ResponseData>),
+    SubmitForm,
+    CreateResponse(Result<create_api_token::ResponseData>),
+    Revoke(i64),
+    RevokeResponse(Result<revoke_api_token::ResponseData>),
+}
+
+fn scope_description(scope: &get_api_tokens::ApiTokenScope) -> &'static str {
+    use get_api_tokens::ApiTokenScope::*;
+    match scope {
+        READONLY => "Read-only",
+        GROUP_MEMBERSHIP => "Group membership",
+        FULL => "Full access",
+        Other(_) => "Unknown",
+    }
+}
+
+fn parse_scope(scope: &str) -> Result<create_api_token::ApiTokenScope> {
+    use create_api_token::ApiTokenScope::*;
+    Ok(match scope {
+        "READONLY" => READONLY,
+        "GROUP_MEMBERSHIP" => GROUP_MEMBERSHIP,
+        "FULL" => FULL,
+        _ => bail!("Invalid scope: {}", scope),
+    })
+}
+
+impl ApiTokensTable {
+    fn is_current_user(ctx: &Context<Self>) -> bool {
+        get_cookie("user_id").ok().flatten().as_deref() == Some(ctx.props().username.as_str())
+    }
+
+    fn list_tokens(&mut self, ctx: &Context<Self>) {
+        self.common.call_graphql::<GetApiTokens, _>(
+            ctx,
+            get_api_tokens::Variables {
+                user_id: ctx.props().username.clone(),
+            },
+            Msg::ListResponse,
+            "Error trying to fetch the API tokens",
+        );
+    }
+}
+
+impl CommonComponent<ApiTokensTable> for ApiTokensTable {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::FormUpdate => Ok(true),
+            Msg::ListResponse(response) => {
+                self.tokens = Some(response?.api_tokens);
+                Ok(true)
+            }
+            Msg::SubmitForm => {
+                if !self.form.validate() {
+                    bail!("Check the form for errors");
+                }
+                let model = self.form.model();
+                let expiry_date = match model.expiry_days.as_str() {
+                    "" => None,
+                    days => Some(chrono::Utc::now() + chrono::Duration::days(days.parse()?)),
+                };
+                self.common.call_graphql::<CreateApiToken, _>(
+                    ctx,
+                    create_api_token::Variables {
+                        token: create_api_token::CreateApiTokenInput {
+                            name: model.name,
+                            scope: parse_scope(&model.scope)?,
+                            expiry_date,
+                        },
+                    },
+                    Msg::CreateResponse,
+                    "Error trying to create the API token",
+                );
+                Ok(true)
+            }
+            Msg::CreateResponse(response) => {
+                self.new_secret = Some(response?.create_api_token.secret);
+                self.form = Form::new(FormModel::default());
+                self.list_tokens(ctx);
+                Ok(true)
+            }
+            Msg::Revoke(token_id) => {
+                self.common.call_graphql::<RevokeApiToken, _>(
+                    ctx,
+                    revoke_api_token::Variables {
+                        user_id: ctx.props().username.clone(),
+                        token_id,
+                    },
+                    Msg::RevokeResponse,
+                    "Error trying to revoke the API token",
+                );
+                Ok(true)
+            }
+            Msg::RevokeResponse(response) => {
+                response?;
+                self.list_tokens(ctx);
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ApiTokensTable {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut table = Self {
+            common: CommonComponentParts::<Self>::create(),
+            form: Form::<FormModel>::new(FormModel::default()),
+            tokens: None,
+            new_secret: None,
+        };
+        table.list_tokens(ctx);
+        table
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-2">
+              <h5 class="fw-bold">
+                {"API tokens"}
+              </h5>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {self.view_new_secret()}
+            {self.view_tokens(ctx)}
+            {
+              if Self::is_current_user(ctx) {
+                self.view_create_form(ctx)
+              } else { html! {} }
+            }
+            <Link
+              classes="btn btn-secondary mt-3"
+              to={AppRoute::UserDetails{user_id: ctx.props().username.clone()}}>
+              <i class="bi-arrow-return-left me-2"></i>
+              {"Back"}
+            </Link>
+          </>
+        }
+    }
+}
+
+impl ApiTokensTable {
+    fn view_new_secret(&self) -> Html {
+        match &self.new_secret {
+            Some(secret) => html! {
+              <div class="alert alert-success">
+                <p>
+                  {"The token was created. Copy it now, it will not be shown again:"}
+                </p>
+                <code>{secret}</code>
+              </div>
+            },
+            None => html! {},
+        }
+    }
+
+    fn view_tokens(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let make_row = |token: &ApiToken| {
+            let token_id = token.id;
+            html! {
+              <tr key={token.id.to_string()}>
+                <td>{&token.name}</td>
+                <td>{scope_description(&token.scope)}</td>
+                <td>{&token.creation_date.naive_local().date()}</td>
+                <td>
+                  {token.expiry_date
+                    .map(|d| d.naive_local().date().to_string())
+                    .unwrap_or_else(|| "Never".to_owned())}
+                </td>
+                <td>
+                  <button
+                    class="btn btn-danger"
+                    disabled={self.common.is_task_running()}
+                    onclick={link.callback(move |_| Msg::Revoke(token_id))}>
+                    <i class="bi-x-circle-fill" aria-label="Revoke token" />
+                  </button>
+                </td>
+              </tr>
+            }
+        };
+        match &self.tokens {
+            None => html! {{"Loading..."}},
+            Some(tokens) if tokens.is_empty() => html! {
+              <p>{"No API tokens."}</p>
+            },
+            Some(tokens) => html! {
+              <div class="table-responsive">
+                <table class="table table-hover">
+                  <thead>
+                    <tr>
+                      <th>{"Name"}</th>
+                      <th>{"Scope"}</th>
+                      <th>{"Created"}</th>
+                      <th>{"Expires"}</th>
+                      <th>{"Revoke"}</th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {for tokens.iter().map(make_row)}
+                  </tbody>
+                </table>
+              </div>
+            },
+        }
+    }
+
+    fn view_create_form(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+          <form class="form py-3">
+            <h6 class="fw-bold">{"Create a token"}</h6>
+            <Field<FormModel>
+              form={&self.form}
+              required=true
+              label="Name"
+              field_name="name"
+              oninput={link.callback(|_| Msg::FormUpdate)} />
+            <Select<FormModel>
+              label="Scope"
+              required={true}
+              form={&self.form}
+              field_name="scope"
+              oninput={link.callback(|_| Msg::FormUpdate)}>
+              <option selected=true value="READONLY">{"Read-only"}</option>
+              <option value="GROUP_MEMBERSHIP">{"Group membership"}</option>
+              <option value="FULL">{"Full access"}</option>
+            </Select<FormModel>>
+            <Select<FormModel>
+              label="Expiration"
+              form={&self.form}
+              field_name="expiry_days"
+              oninput={link.callback(|_| Msg::FormUpdate)}>
+              <option selected=true value="">{"Never"}</option>
+              <option value="30">{"30 days"}</option>
+              <option value="90">{"90 days"}</option>
+              <option value="365">{"1 year"}</option>
+            </Select<FormModel>>
+            <Submit
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitForm})}
+              text="Create" />
+          </form>
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::{
     components::{
+        api_tokens::ApiTokensTable,
+        audit_log::AuditLogTable,
         banner::Banner,
         change_password::ChangePasswordForm,
         create_group::CreateGroupForm,
@@ -10,9 +12,12 @@ use crate::{
         group_schema_table::ListGroupSchema,
         group_table::GroupTable,
         login::LoginForm,
+        oidc_consent::OidcConsent,
         reset_password_step1::ResetPasswordStep1Form,
         reset_password_step2::ResetPasswordStep2Form,
         router::{AppRoute, Link, Redirect},
+        sessions::SessionsTable,
+        two_factor::TwoFactorForm,
         user_details::UserDetails,
         user_schema_table::ListUserSchema,
         user_table::UserTable,
@@ -22,6 +27,7 @@ use crate::{
 
 use gloo_console::error;
 use lldap_frontend_options::Options;
+use lldap_validation::password::PasswordPolicy;
 use yew::{
     Context, function_component,
     html::Scope,
@@ -45,7 +51,10 @@ pub fn app_container() -> Html {
 pub struct App {
     user_info: Option<(String, bool)>,
     redirect_to: Option<AppRoute>,
+    /// Page of another service to go back to after logging in, from the forward-auth redirect.
+    external_redirect: Option<String>,
     password_reset_enabled: Option<bool>,
+    password_policy: PasswordPolicy,
 }
 
 pub enum Msg {
@@ -74,7 +83,9 @@ impl Component for App {
                         })
                 }),
             redirect_to: Self::get_redirect_route(ctx),
+            external_redirect: Self::get_external_redirect(),
             password_reset_enabled: None,
+            password_policy: PasswordPolicy::default(),
         };
         ctx.link()
             .send_future(async move { Msg::SettingsReceived(HostService::get_settings().await) });
@@ -87,6 +98,12 @@ impl Component for App {
         match msg {
             Msg::Login((user_name, is_admin)) => {
                 self.user_info = Some((user_name.clone(), is_admin));
+                if let Some(url) = self.external_redirect.take() {
+                    match web_sys::window().map(|w| w.location().set_href(&url)) {
+                        Some(Ok(())) => return false,
+                        _ => error!(&format!("Could not redirect to {}", url)),
+                    }
+                }
                 history.push(self.redirect_to.take().unwrap_or_else(|| {
                     if is_admin {
                         AppRoute::ListUsers
@@ -104,6 +121,7 @@ impl Component for App {
             }
             Msg::SettingsReceived(Ok(settings)) => {
                 self.password_reset_enabled = Some(settings.password_reset_enabled);
+                self.password_policy = settings.password_policy;
             }
             Msg::SettingsReceived(Err(err)) => {
                 error!(err.to_string());
@@ -117,6 +135,7 @@ impl Component for App {
         let is_admin = self.is_admin();
         let username = self.user_info.clone().map(|(username, _)| username);
         let password_reset_enabled = self.password_reset_enabled;
+        let password_policy = self.password_policy.clone();
         html! {
           <div>
             <Banner is_admin={is_admin} username={username} on_logged_out={link.callback(|_| Msg::Logout)} />
@@ -124,7 +143,7 @@ impl Component for App {
               <div class="row justify-content-center" style="padding-bottom: 80px;">
                 <main class="py-3">
                   <Switch<AppRoute>
-                    render={Switch::render(move |routes| Self::dispatch_route(routes, &link, is_admin, password_reset_enabled))}
+                    render={Switch::render(move |routes| Self::dispatch_route(routes, &link, is_admin, password_reset_enabled, &password_policy))}
                   />
                 </main>
               </div>
@@ -150,6 +169,16 @@ impl App {
         })
     }
 
+    // Get the `rd` URL given by the forward-auth endpoint, only if it points to the same origin as
+    // LLDAP to avoid open redirections.
+    fn get_external_redirect() -> Option<String> {
+        let location = web_sys::window()?.location();
+        let page_url = web_sys::Url::new(&location.href().ok()?).ok()?;
+        let target = page_url.search_params().get("rd")?;
+        let target_url = web_sys::Url::new(&target).ok()?;
+        (target_url.origin() == page_url.origin()).then_some(target)
+    }
+
     fn apply_initial_redirections(&self, ctx: &Context<Self>) {
         let history = ctx.link().history().unwrap();
         let route = history.location().route::<AppRoute>();
@@ -188,13 +217,14 @@ impl App {
         link: &Scope<Self>,
         is_admin: bool,
         password_reset_enabled: Option<bool>,
+        password_policy: &PasswordPolicy,
     ) -> Html {
         match switch {
             AppRoute::Login => html! {
                 <LoginForm on_logged_in={link.callback(Msg::Login)} password_reset_enabled={password_reset_enabled.unwrap_or(false)}/>
             },
             AppRoute::CreateUser => html! {
-                <CreateUserForm/>
+                <CreateUserForm password_policy={password_policy.clone()} />
             },
             AppRoute::Index | AppRoute::ListUsers => {
                 let user_button = html! {
@@ -250,7 +280,22 @@ impl App {
                 <UserDetails username={user_id.clone()} is_admin={is_admin} />
             },
             AppRoute::ChangePassword { user_id } => html! {
-                <ChangePasswordForm username={user_id.clone()} is_admin={is_admin} />
+                <ChangePasswordForm username={user_id.clone()} is_admin={is_admin} password_policy={password_policy.clone()} />
+            },
+            AppRoute::TwoFactor { user_id } => html! {
+                <TwoFactorForm username={user_id.clone()} is_admin={is_admin} />
+            },
+            AppRoute::ApiTokens { user_id } => html! {
+                <ApiTokensTable username={user_id.clone()} />
+            },
+            AppRoute::Sessions { user_id } => html! {
+                <SessionsTable username={user_id.clone()} on_logged_out={link.callback(|_| Msg::Logout)} />
+            },
+            AppRoute::AuditLog => html! {
+                <AuditLogTable />
+            },
+            AppRoute::OidcConsent { request_id } => html! {
+                <OidcConsent request_id={request_id.clone()} />
             },
             AppRoute::StartResetPassword => match password_reset_enabled {
                 Some(true) => html! { <ResetPasswordStep1Form /> },
@@ -261,7 +306,9 @@ impl App {
                 None => html! {},
             },
             AppRoute::FinishResetPassword { token } => match password_reset_enabled {
-                Some(true) => html! { <ResetPasswordStep2Form token={token.clone()} /> },
+                Some(true) => html! {
+                    <ResetPasswordStep2Form token={token.clone()} password_policy={password_policy.clone()} />
+                },
                 Some(false) => {
                     html! { <Redirect to={AppRoute::Login}/> }
                 }
@@ -0,0 +1,285 @@
+use crate::{
+    components::form::{field::Field, select::Select, submit::Submit},
+    infra::common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use validator_derive::Validate;
+use yew::prelude::*;
+use yew_form::Form;
+use yew_form_derive::Model;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_audit_log.graphql",
+    response_derives = "Debug,Clone,PartialEq",
+    variables_derives = "Clone",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetAuditLog;
+
+pub type AuditEvent = get_audit_log::GetAuditLogAuditLog;
+
+/// Number of events per page.
+const PAGE_SIZE: i64 = 50;
+
+/// The filters of the log. Empty fields are ignored.
+#[derive(Model, Validate, PartialEq, Eq, Clone, Debug, Default)]
+pub struct FormModel {
+    actor: String,
+    target: String,
+    operation: String,
+    /// "success", "failure" or empty for both.
+    result: String,
+}
+
+impl FormModel {
+    fn to_filter(&self) -> get_audit_log::AuditEventFilter {
+        let non_empty = |s: &str| Some(s.trim().to_owned()).filter(|s| !s.is_empty());
+        get_audit_log::AuditEventFilter {
+            actor: non_empty(&self.actor),
+            target: non_empty(&self.target),
+            operation: non_empty(&self.operation),
+            source: None,
+            success: match self.result.as_str() {
+                "success" => Some(true),
+                "failure" => Some(false),
+                _ => None,
+            },
+            since: None,
+            until: None,
+        }
+    }
+}
+
+pub struct AuditLogTable {
+    common: CommonComponentParts<Self>,
+    form: Form<FormModel>,
+    /// The filters of the displayed events.
+    filter: get_audit_log::AuditEventFilter,
+    offset: i64,
+    events: Option<Vec<AuditEvent>>,
+}
+
+pub enum Msg {
+    FormUpdate,
+    SubmitForm,
+    PreviousPage,
+    NextPage,
+    ListResponse(Result<get_audit_log::ResponseData>),
+}
+
+fn source_description(source: &get_audit_log::AuditSource) -> &'static str {
+    use get_audit_log::AuditSource::*;
+    match source {
+        LDAP => "LDAP",
+        GRAPHQL => "GraphQL",
+        HTTP => "Web",
+        CLI => "Server",
+        Other(_) => "Unknown",
+    }
+}
+
+impl AuditLogTable {
+    fn list_events(&mut self, ctx: &Context<Self>) {
+        self.common.call_graphql::<GetAuditLog, _>(
+            ctx,
+            get_audit_log::Variables {
+                filters: Some(self.filter.clone()),
+                offset: Some(self.offset),
+                limit: Some(PAGE_SIZE),
+            },
+            Msg::ListResponse,
+            "Error trying to fetch the audit log",
+        );
+    }
+}
+
+impl CommonComponent<AuditLogTable> for AuditLogTable {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::FormUpdate => Ok(true),
+            Msg::SubmitForm => {
+                self.filter = self.form.model().to_filter();
+                self.offset = 0;
+                self.list_events(ctx);
+                Ok(true)
+            }
+            Msg::PreviousPage => {
+                self.offset = (self.offset - PAGE_SIZE).max(0);
+                self.list_events(ctx);
+                Ok(true)
+            }
+            Msg::NextPage => {
+                self.offset += PAGE_SIZE;
+                self.list_events(ctx);
+                Ok(true)
+            }
+            Msg::ListResponse(response) => {
+                self.events = Some(response?.audit_log);
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for AuditLogTable {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let form = Form::<FormModel>::new(FormModel::default());
+        let mut table = Self {
+            common: CommonComponentParts::<Self>::create(),
+            filter: form.model().to_filter(),
+            form,
+            offset: 0,
+            events: None,
+        };
+        table.list_events(ctx);
+        table
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let has_next_page = self
+            .events
+            .as_ref()
+            .is_some_and(|events| events.len() as i64 == PAGE_SIZE);
+        html! {
+          <>
+            <div class="mb-2 mt-2">
+              <h5 class="fw-bold">
+                {"Audit log"}
+              </h5>
+            </div>
+            {self.view_filter_form(ctx)}
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {self.view_events()}
+            <button
+              class="btn btn-secondary me-2"
+              disabled={self.common.is_task_running() || self.offset == 0}
+              onclick={link.callback(|_| Msg::PreviousPage)}>
+              <i class="bi-chevron-left me-2"></i>
+              {"Newer"}
+            </button>
+            <button
+              class="btn btn-secondary"
+              disabled={self.common.is_task_running() || !has_next_page}
+              onclick={link.callback(|_| Msg::NextPage)}>
+              {"Older"}
+              <i class="bi-chevron-right ms-2"></i>
+            </button>
+          </>
+        }
+    }
+}
+
+impl AuditLogTable {
+    fn view_filter_form(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+          <form class="form py-3">
+            <Field<FormModel>
+              form={&self.form}
+              label="Actor"
+              field_name="actor"
+              oninput={link.callback(|_| Msg::FormUpdate)} />
+            <Field<FormModel>
+              form={&self.form}
+              label="Target"
+              field_name="target"
+              oninput={link.callback(|_| Msg::FormUpdate)} />
+            <Field<FormModel>
+              form={&self.form}
+              label="Operation"
+              field_name="operation"
+              oninput={link.callback(|_| Msg::FormUpdate)} />
+            <Select<FormModel>
+              label="Result"
+              form={&self.form}
+              field_name="result"
+              oninput={link.callback(|_| Msg::FormUpdate)}>
+              <option selected=true value="">{"Any"}</option>
+              <option value="success">{"Success"}</option>
+              <option value="failure">{"Failure"}</option>
+            </Select<FormModel>>
+            <Submit
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitForm})}
+              text="Filter" />
+          </form>
+        }
+    }
+
+    fn view_events(&self) -> Html {
+        let make_row = |event: &AuditEvent| {
+            html! {
+              <tr key={event.id.to_string()}>
+                <td>{&event.date.naive_local()}</td>
+                <td>{event.actor.as_deref().unwrap_or("")}</td>
+                <td>{source_description(&event.source)}</td>
+                <td>{event.ip_address.as_deref().unwrap_or("")}</td>
+                <td>{&event.operation}</td>
+                <td>{event.target.as_deref().unwrap_or("")}</td>
+                <td>
+                  {if event.success {
+                    html! {<i class="bi-check-circle-fill text-success" aria-label="Success" />}
+                  } else {
+                    html! {<i class="bi-x-circle-fill text-danger" aria-label="Failure" />}
+                  }}
+                </td>
+                <td><code>{event.details.as_deref().unwrap_or("")}</code></td>
+              </tr>
+            }
+        };
+        match &self.events {
+            None => html! {{"Loading..."}},
+            Some(events) if events.is_empty() => html! {
+              <p>{"No events."}</p>
+            },
+            Some(events) => html! {
+              <div class="table-responsive">
+                <table class="table table-hover">
+                  <thead>
+                    <tr>
+                      <th>{"Date"}</th>
+                      <th>{"Actor"}</th>
+                      <th>{"Source"}</th>
+                      <th>{"IP address"}</th>
+                      <th>{"Operation"}</th>
+                      <th>{"Target"}</th>
+                      <th>{"Result"}</th>
+                      <th>{"Details"}</th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {for events.iter().map(make_row)}
+                  </tbody>
+                </table>
+              </div>
+            },
+        }
+    }
+}
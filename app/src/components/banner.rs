@@ -58,6 +58,14 @@ pub fn banner(props: &Props) -> Html {
                       {"Group schema"}
                     </Link>
                   </li>
+                  <li>
+                    <Link
+                      classes="nav-link px-2 h6"
+                      to={AppRoute::AuditLog}>
+                      <i class="bi-journal-text me-2"></i>
+                      {"Audit log"}
+                    </Link>
+                  </li>
                 </>
               } } else { html!{} } }
             </ul>
@@ -11,6 +11,7 @@ use crate::{
 use anyhow::{Result, anyhow, bail};
 use gloo_console::error;
 use lldap_auth::*;
+use lldap_validation::password::{PasswordPolicy, validate_password};
 use validator_derive::Validate;
 use yew::prelude::*;
 use yew_form::Form;
@@ -39,7 +40,7 @@ pub struct FormModel {
         message = "Password should be longer than 8 characters"
     ))]
     old_password: String,
-    #[validate(length(min = 8, message = "Invalid password. Min length: 8"))]
+    #[validate(length(min = 1, message = "The new password is required"))]
     password: String,
     #[validate(must_match(other = "password", message = "Passwords must match"))]
     confirm_password: String,
@@ -63,6 +64,7 @@ pub struct ChangePasswordForm {
 pub struct Props {
     pub username: String,
     pub is_admin: bool,
+    pub password_policy: PasswordPolicy,
 }
 
 pub enum Msg {
@@ -87,6 +89,12 @@ impl CommonComponent<ChangePasswordForm> for ChangePasswordForm {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
+                validate_password(
+                    &ctx.props().password_policy,
+                    &ctx.props().username,
+                    &self.form.model().password,
+                )
+                .map_err(|errors| anyhow!(errors.join(". ")))?;
                 if ctx.props().is_admin {
                     self.handle_msg(ctx, Msg::SubmitNewPassword)
                 } else {
@@ -18,10 +18,11 @@ use crate::{
         schema::AttributeType,
     },
 };
-use anyhow::{Result, ensure};
+use anyhow::{Result, anyhow, ensure};
 use gloo_console::log;
 use graphql_client::GraphQLQuery;
 use lldap_auth::{opaque, registration};
+use lldap_validation::password::{PasswordPolicy, validate_password};
 use validator_derive::Validate;
 use yew::prelude::*;
 use yew_form_derive::Model;
@@ -73,21 +74,14 @@ pub struct CreateUserForm {
 pub struct CreateUserModel {
     #[validate(length(min = 1, message = "Username is required"))]
     username: String,
-    #[validate(custom(
-        function = "empty_or_long",
-        message = "Password should be longer than 8 characters (or left empty)"
-    ))]
     password: String,
     #[validate(must_match(other = "password", message = "Passwords must match"))]
     confirm_password: String,
 }
 
-fn empty_or_long(value: &str) -> Result<(), validator::ValidationError> {
-    if value.is_empty() || value.len() >= 8 {
-        Ok(())
-    } else {
-        Err(validator::ValidationError::new(""))
-    }
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub password_policy: PasswordPolicy,
 }
 
 pub enum Msg {
@@ -120,6 +114,15 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
             }
             Msg::SubmitForm => {
                 ensure!(self.form.validate(), "Check the form for errors");
+                let model = self.form.model();
+                if !model.password.is_empty() {
+                    validate_password(
+                        &ctx.props().password_policy,
+                        &model.username,
+                        &model.password,
+                    )
+                    .map_err(|errors| anyhow!(errors.join(". ")))?;
+                }
 
                 let all_values = read_all_form_attributes(
                     self.attributes_schema.iter().flatten(),
@@ -140,7 +143,6 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
                         .collect(),
                 );
 
-                let model = self.form.model();
                 let req = create_user::Variables {
                     user: create_user::CreateUserInput {
                         id: model.username,
@@ -231,7 +233,7 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
 
 impl Component for CreateUserForm {
     type Message = Msg;
-    type Properties = ();
+    type Properties = Props;
 
     fn create(ctx: &Context<Self>) -> Self {
         let mut component = Self {
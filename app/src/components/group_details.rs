@@ -111,6 +111,12 @@ impl GroupDetails {
         let make_user_row = |user: &User| {
             let user_id = user.id.clone();
             let display_name = user.display_name.clone();
+            let expiry_date = g
+                .membership_expiry_dates
+                .iter()
+                .find(|m| m.user_id == user.id)
+                .map(|m| m.expiry_date.naive_local().date().to_string())
+                .unwrap_or_else(|| "Never".to_owned());
             html! {
               <tr>
                 <td>
@@ -119,6 +125,7 @@ impl GroupDetails {
                   </Link>
                 </td>
                 <td>{display_name}</td>
+                <td>{expiry_date}</td>
                 <td>
                   <RemoveUserFromGroupComponent
                     username={user_id}
@@ -138,6 +145,7 @@ impl GroupDetails {
                   <tr key="headerRow">
                     <th>{"User Id"}</th>
                     <th>{"Display name"}</th>
+                    <th>{"Membership expires"}</th>
                     <th></th>
                   </tr>
                 </thead>
@@ -204,12 +212,11 @@ impl CommonComponent<GroupDetails> for GroupDetails {
                 });
             }
             Msg::OnUserRemovedFromGroup((user_id, _)) => {
-                self.group_and_schema
-                    .as_mut()
-                    .unwrap()
-                    .0
-                    .users
-                    .retain(|u| u.id != user_id);
+                let group = &mut self.group_and_schema.as_mut().unwrap().0;
+                group.users.retain(|u| u.id != user_id);
+                group
+                    .membership_expiry_dates
+                    .retain(|m| m.user_id != user_id);
             }
             Msg::DisplayNameUpdated => self.get_group_details(ctx),
         }
@@ -4,7 +4,7 @@ use crate::{
         router::{AppRoute, Link},
     },
     infra::{
-        api::HostService,
+        api::{HostService, LoginFinishResponse},
         common_component::{CommonComponent, CommonComponentParts},
     },
 };
@@ -20,6 +20,8 @@ pub struct LoginForm {
     common: CommonComponentParts<Self>,
     form: Form<FormModel>,
     refreshing: bool,
+    /// Set once the password was accepted, if the user has a second factor.
+    mfa_token: Option<String>,
 }
 
 /// The fields of the form, with the constraints.
@@ -29,6 +31,7 @@ pub struct FormModel {
     username: String,
     #[validate(length(min = 8, message = "Invalid password. Min length: 8"))]
     password: String,
+    totp_code: String,
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -47,7 +50,9 @@ pub enum Msg {
             Result<Box<login::ServerLoginStartResponse>>,
         ),
     ),
-    AuthenticationFinishResponse(Result<(String, bool)>),
+    AuthenticationFinishResponse(Result<LoginFinishResponse>),
+    SubmitTotp,
+    TotpLoginResponse(Result<(String, bool)>),
 }
 
 impl CommonComponent<LoginForm> for LoginForm {
@@ -63,7 +68,9 @@ impl CommonComponent<LoginForm> for LoginForm {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
-                let FormModel { username, password } = self.form.model();
+                let FormModel {
+                    username, password, ..
+                } = self.form.model();
                 let mut rng = rand::rngs::OsRng;
                 let opaque::client::login::ClientLoginStartResult { state, message } =
                     opaque::client::login::start_login(&password, &mut rng)
@@ -103,7 +110,29 @@ impl CommonComponent<LoginForm> for LoginForm {
                 );
                 Ok(false)
             }
-            Msg::AuthenticationFinishResponse(user_info) => {
+            Msg::AuthenticationFinishResponse(response) => {
+                match response.context("Could not log in")? {
+                    LoginFinishResponse::LoggedIn(user_info) => {
+                        ctx.props().on_logged_in.emit(user_info)
+                    }
+                    LoginFinishResponse::MfaRequired(mfa_token) => self.mfa_token = Some(mfa_token),
+                }
+                Ok(true)
+            }
+            Msg::SubmitTotp => {
+                let code = self.form.model().totp_code;
+                if code.trim().is_empty() {
+                    bail!("Missing code");
+                }
+                let req = login::ClientTotpLoginRequest {
+                    mfa_token: self.mfa_token.clone().context("Missing MFA token")?,
+                    code,
+                };
+                self.common
+                    .call_backend(ctx, HostService::totp_login(req), Msg::TotpLoginResponse);
+                Ok(true)
+            }
+            Msg::TotpLoginResponse(user_info) => {
                 ctx.props()
                     .on_logged_in
                     .emit(user_info.context("Could not log in")?);
@@ -124,6 +153,41 @@ impl CommonComponent<LoginForm> for LoginForm {
     }
 }
 
+impl LoginForm {
+    fn view_totp_form(&self, ctx: &Context<Self>) -> Html {
+        type Field = yew_form::Field<FormModel>;
+        let link = &ctx.link();
+        html! {
+          <form class="form center-block col-sm-4 col-offset-4">
+            <div class="input-group">
+              <div class="input-group-prepend">
+                <span class="input-group-text">
+                  <i class="bi-shield-lock-fill"/>
+                </span>
+              </div>
+              <Field
+                class="form-control"
+                form={&self.form}
+                field_name="totp_code"
+                placeholder="Authentication or recovery code"
+                autocomplete="one-time-code"
+                oninput={link.callback(|_| Msg::Update)} />
+            </div>
+            <Submit
+              text="Verify"
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitTotp})} />
+            <div class="form-group">
+            { if let Some(e) = &self.common.error {
+                html! { e.to_string() }
+              } else { html! {} }
+            }
+            </div>
+          </form>
+        }
+    }
+}
+
 impl Component for LoginForm {
     type Message = Msg;
     type Properties = Props;
@@ -133,6 +197,7 @@ impl Component for LoginForm {
             common: CommonComponentParts::<Self>::create(),
             form: Form::<FormModel>::new(FormModel::default()),
             refreshing: true,
+            mfa_token: None,
         };
         app.common.call_backend(
             ctx,
@@ -156,6 +221,8 @@ impl Component for LoginForm {
                 <img src={"spinner.gif"} alt={"Loading"} />
               </div>
             }
+        } else if self.mfa_token.is_some() {
+            self.view_totp_form(ctx)
         } else {
             html! {
               <form class="form center-block col-sm-4 col-offset-4">
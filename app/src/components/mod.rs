@@ -1,6 +1,8 @@
 pub mod add_group_member;
 pub mod add_user_to_group;
+pub mod api_tokens;
 pub mod app;
+pub mod audit_log;
 pub mod avatar;
 pub mod banner;
 pub mod change_password;
@@ -20,11 +22,14 @@ pub mod group_schema_table;
 pub mod group_table;
 pub mod login;
 pub mod logout;
+pub mod oidc_consent;
 pub mod remove_user_from_group;
 pub mod reset_password_step1;
 pub mod reset_password_step2;
 pub mod router;
 pub mod select;
+pub mod sessions;
+pub mod two_factor;
 pub mod user_details;
 pub mod user_details_form;
 pub mod user_schema_table;
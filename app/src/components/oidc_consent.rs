@@ -0,0 +1,159 @@
+use crate::infra::{
+    api::HostService,
+    common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{Result, anyhow};
+use lldap_auth::oidc;
+use yew::prelude::*;
+
+/// Asks the user whether an application may log them in through OpenID Connect.
+pub struct OidcConsent {
+    common: CommonComponentParts<Self>,
+    request: Option<oidc::ServerAuthorizationRequestResponse>,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub request_id: String,
+}
+
+pub enum Msg {
+    AuthorizationRequestResponse(Result<oidc::ServerAuthorizationRequestResponse>),
+    Decide(bool),
+    DecisionResponse(Result<oidc::ServerAuthorizationDecisionResponse>),
+}
+
+fn get_scope_description(scope: &str) -> &str {
+    match scope {
+        "openid" => "Your identity",
+        "profile" => "Your user name and profile",
+        "email" => "Your email address",
+        "groups" => "The groups you belong to",
+        other => other,
+    }
+}
+
+impl CommonComponent<OidcConsent> for OidcConsent {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::AuthorizationRequestResponse(response) => {
+                self.request = Some(response?);
+                Ok(true)
+            }
+            Msg::Decide(approve) => {
+                self.common.call_backend(
+                    ctx,
+                    HostService::oidc_decide_authorization_request(
+                        ctx.props().request_id.clone(),
+                        approve,
+                    ),
+                    Msg::DecisionResponse,
+                );
+                Ok(true)
+            }
+            Msg::DecisionResponse(response) => {
+                // Send the browser back to the application, with the code or the error.
+                web_sys::window()
+                    .ok_or_else(|| anyhow!("Could not get the window"))?
+                    .location()
+                    .set_href(&response?.redirect_uri)
+                    .map_err(|_| anyhow!("Could not redirect to the application"))?;
+                Ok(false)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for OidcConsent {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut component = OidcConsent {
+            common: CommonComponentParts::<Self>::create(),
+            request: None,
+        };
+        component.common.call_backend(
+            ctx,
+            HostService::oidc_get_authorization_request(ctx.props().request_id.clone()),
+            Msg::AuthorizationRequestResponse,
+        );
+        component
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+          <div class="row justify-content-center">
+            <div class="col-sm-8 col-md-6 shadow-sm py-3">
+              {
+                if let Some(request) = &self.request {
+                  html! {
+                    <>
+                      <h5 class="fw-bold mb-3">
+                        {format!("Log in to {}", request.client_name)}
+                      </h5>
+                      {
+                        if request.scopes.is_empty() {
+                          html! {
+                            <div class="alert alert-warning">
+                              {"You are not allowed to log in to this application."}
+                            </div>
+                          }
+                        } else {
+                          html! {
+                            <>
+                              <p>{"This application will get access to:"}</p>
+                              <ul>
+                                {for request.scopes.iter().map(|scope| html! {
+                                  <li>{get_scope_description(scope)}</li>
+                                })}
+                              </ul>
+                              <button
+                                class="btn btn-primary me-2"
+                                disabled={self.common.is_task_running()}
+                                onclick={link.callback(|_| Msg::Decide(true))}>
+                                <i class="bi-check-circle me-2"></i>
+                                {"Allow"}
+                              </button>
+                            </>
+                          }
+                        }
+                      }
+                      <button
+                        class="btn btn-secondary"
+                        disabled={self.common.is_task_running()}
+                        onclick={link.callback(|_| Msg::Decide(false))}>
+                        <i class="bi-x-circle me-2"></i>
+                        {"Deny"}
+                      </button>
+                    </>
+                  }
+                } else { html! {} }
+              }
+              {
+                if let Some(e) = &self.common.error {
+                  html! {
+                    <div class="alert alert-danger mt-3 mb-3">
+                      {e.to_string() }
+                    </div>
+                  }
+                } else { html! {} }
+              }
+            </div>
+          </div>
+        }
+    }
+}
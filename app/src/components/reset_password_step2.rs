@@ -8,11 +8,12 @@ use crate::{
         common_component::{CommonComponent, CommonComponentParts},
     },
 };
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use lldap_auth::{
     opaque::client::registration as opaque_registration,
     password_reset::ServerPasswordResetResponse, registration,
 };
+use lldap_validation::password::{PasswordPolicy, validate_password};
 use validator_derive::Validate;
 use yew::prelude::*;
 use yew_form::Form;
@@ -22,7 +23,7 @@ use yew_router::{prelude::History, scope_ext::RouterScopeExt};
 /// The fields of the form, with the constraints.
 #[derive(Model, Validate, PartialEq, Eq, Clone, Default)]
 pub struct FormModel {
-    #[validate(length(min = 8, message = "Invalid password. Min length: 8"))]
+    #[validate(length(min = 1, message = "The password is required"))]
     password: String,
     #[validate(must_match(other = "password", message = "Passwords must match"))]
     confirm_password: String,
@@ -38,6 +39,7 @@ pub struct ResetPasswordStep2Form {
 #[derive(Clone, PartialEq, Eq, Properties)]
 pub struct Props {
     pub token: String,
+    pub password_policy: PasswordPolicy,
 }
 
 pub enum Msg {
@@ -67,6 +69,12 @@ impl CommonComponent<ResetPasswordStep2Form> for ResetPasswordStep2Form {
                 }
                 let mut rng = rand::rngs::OsRng;
                 let new_password = self.form.model().password;
+                validate_password(
+                    &ctx.props().password_policy,
+                    self.username.as_deref().unwrap_or_default(),
+                    &new_password,
+                )
+                .map_err(|errors| anyhow!(errors.join(". ")))?;
                 let registration_start_request =
                     opaque_registration::start_registration(new_password.as_bytes(), &mut rng)
                         .context("Could not initiate password change")?;
@@ -14,6 +14,12 @@ pub enum AppRoute {
     ListUsers,
     #[at("/user/:user_id/password")]
     ChangePassword { user_id: String },
+    #[at("/user/:user_id/two-factor")]
+    TwoFactor { user_id: String },
+    #[at("/user/:user_id/api-tokens")]
+    ApiTokens { user_id: String },
+    #[at("/user/:user_id/sessions")]
+    Sessions { user_id: String },
     #[at("/user/:user_id")]
     UserDetails { user_id: String },
     #[at("/groups/create")]
@@ -30,6 +36,10 @@ pub enum AppRoute {
     ListGroupSchema,
     #[at("/group-attributes/create")]
     CreateGroupAttribute,
+    #[at("/audit-log")]
+    AuditLog,
+    #[at("/oidc/authorize/:request_id")]
+    OidcConsent { request_id: String },
     #[at("/")]
     Index,
 }
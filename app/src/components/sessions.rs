@@ -0,0 +1,242 @@
+use crate::{
+    components::router::{AppRoute, Link},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        cookies::{delete_cookie, get_cookie},
+    },
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_sessions.graphql",
+    response_derives = "Debug,Clone,PartialEq",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetSessions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/revoke_session.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RevokeSession;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/revoke_all_sessions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RevokeAllSessions;
+
+pub type Session = get_sessions::GetSessionsSessions;
+
+pub struct SessionsTable {
+    common: CommonComponentParts<Self>,
+    sessions: Option<Vec<Session>>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub username: String,
+    /// Called when the current user revoked all their sessions, including this one.
+    pub on_logged_out: Callback<()>,
+}
+
+pub enum Msg {
+    ListResponse(Result<get_sessions::ResponseData>),
+    Revoke(String),
+    RevokeResponse(Result<revoke_session::ResponseData>),
+    RevokeAll,
+    RevokeAllResponse(Result<revoke_all_sessions::ResponseData>),
+}
+
+impl SessionsTable {
+    fn is_current_user(ctx: &Context<Self>) -> bool {
+        get_cookie("user_id").ok().flatten().as_deref() == Some(ctx.props().username.as_str())
+    }
+
+    fn list_sessions(&mut self, ctx: &Context<Self>) {
+        self.common.call_graphql::<GetSessions, _>(
+            ctx,
+            get_sessions::Variables {
+                user_id: ctx.props().username.clone(),
+            },
+            Msg::ListResponse,
+            "Error trying to fetch the sessions",
+        );
+    }
+}
+
+impl CommonComponent<SessionsTable> for SessionsTable {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::ListResponse(response) => {
+                self.sessions = Some(response?.sessions);
+                Ok(true)
+            }
+            Msg::Revoke(session_id) => {
+                self.common.call_graphql::<RevokeSession, _>(
+                    ctx,
+                    revoke_session::Variables {
+                        user_id: ctx.props().username.clone(),
+                        session_id,
+                    },
+                    Msg::RevokeResponse,
+                    "Error trying to revoke the session",
+                );
+                Ok(true)
+            }
+            Msg::RevokeResponse(response) => {
+                response?;
+                self.list_sessions(ctx);
+                Ok(true)
+            }
+            Msg::RevokeAll => {
+                self.common.call_graphql::<RevokeAllSessions, _>(
+                    ctx,
+                    revoke_all_sessions::Variables {
+                        user_id: ctx.props().username.clone(),
+                    },
+                    Msg::RevokeAllResponse,
+                    "Error trying to revoke the sessions",
+                );
+                Ok(true)
+            }
+            Msg::RevokeAllResponse(response) => {
+                response?;
+                if Self::is_current_user(ctx) {
+                    delete_cookie("user_id")?;
+                    ctx.props().on_logged_out.emit(());
+                    return Ok(false);
+                }
+                self.list_sessions(ctx);
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for SessionsTable {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut table = Self {
+            common: CommonComponentParts::<Self>::create(),
+            sessions: None,
+        };
+        table.list_sessions(ctx);
+        table
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+          <>
+            <div class="mb-2 mt-2">
+              <h5 class="fw-bold">
+                {"Sessions"}
+              </h5>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {self.view_sessions(ctx)}
+            <button
+              class="btn btn-danger mt-3 me-2"
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|_| Msg::RevokeAll)}>
+              <i class="bi-x-circle-fill me-2"></i>
+              {
+                if Self::is_current_user(ctx) {
+                  "Log out everywhere"
+                } else {
+                  "Revoke all sessions"
+                }
+              }
+            </button>
+            <Link
+              classes="btn btn-secondary mt-3"
+              to={AppRoute::UserDetails{user_id: ctx.props().username.clone()}}>
+              <i class="bi-arrow-return-left me-2"></i>
+              {"Back"}
+            </Link>
+          </>
+        }
+    }
+}
+
+impl SessionsTable {
+    fn view_sessions(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let make_row = |session: &Session| {
+            let session_id = session.id.clone();
+            html! {
+              <tr key={session.id.clone()}>
+                <td>{&session.creation_date.naive_local()}</td>
+                <td>{&session.expiry_date.naive_local().date()}</td>
+                <td>{session.ip_address.as_deref().unwrap_or("Unknown")}</td>
+                <td>{session.user_agent.as_deref().unwrap_or("Unknown")}</td>
+                <td>
+                  <button
+                    class="btn btn-danger"
+                    disabled={self.common.is_task_running()}
+                    onclick={link.callback(move |_| Msg::Revoke(session_id.clone()))}>
+                    <i class="bi-x-circle-fill" aria-label="Revoke session" />
+                  </button>
+                </td>
+              </tr>
+            }
+        };
+        match &self.sessions {
+            None => html! {{"Loading..."}},
+            Some(sessions) if sessions.is_empty() => html! {
+              <p>{"No active sessions."}</p>
+            },
+            Some(sessions) => html! {
+              <div class="table-responsive">
+                <table class="table table-hover">
+                  <thead>
+                    <tr>
+                      <th>{"Logged in"}</th>
+                      <th>{"Expires"}</th>
+                      <th>{"IP address"}</th>
+                      <th>{"Browser"}</th>
+                      <th>{"Revoke"}</th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {for sessions.iter().map(make_row)}
+                  </tbody>
+                </table>
+              </div>
+            },
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{
+    components::{
+        form::{field::Field, submit::Submit},
+        router::{AppRoute, Link},
+    },
+    infra::{
+        api::HostService,
+        common_component::{CommonComponent, CommonComponentParts},
+        cookies::get_cookie,
+    },
+};
+use anyhow::{Result, bail};
+use lldap_auth::mfa;
+use qrcode::{QrCode, render::svg};
+use validator_derive::Validate;
+use yew::prelude::*;
+use yew_form::Form;
+use yew_form_derive::Model;
+
+/// The fields of the form, with the constraints.
+#[derive(Model, Validate, PartialEq, Eq, Clone, Default)]
+pub struct FormModel {
+    #[validate(length(min = 6, max = 6, message = "The code should have 6 digits"))]
+    code: String,
+}
+
+/// The password, to confirm the reset of one's own second factor.
+#[derive(Model, Validate, PartialEq, Eq, Clone, Default)]
+pub struct ResetFormModel {
+    #[validate(length(min = 1, message = "The password is required"))]
+    password: String,
+}
+
+/// An enrollment that was started, waiting for the first code.
+struct Enrollment {
+    secret: String,
+    /// The `otpauth://` URI, rendered as an SVG data URL.
+    qr_code: String,
+}
+
+pub struct TwoFactorForm {
+    common: CommonComponentParts<Self>,
+    form: Form<FormModel>,
+    reset_form: Form<ResetFormModel>,
+    enrollment: Option<Enrollment>,
+    recovery_codes: Option<Vec<String>>,
+    /// The current user asked to remove their second factor, and is entering their password.
+    confirming_reset: bool,
+    reset_done: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+    pub is_admin: bool,
+}
+
+pub enum Msg {
+    FormUpdate,
+    StartEnrollment,
+    EnrollStartResponse(Result<mfa::ServerTotpEnrollStartResponse>),
+    SubmitCode,
+    EnrollFinishResponse(Result<mfa::ServerTotpEnrollFinishResponse>),
+    Reset,
+    ConfirmReset,
+    CancelReset,
+    ResetResponse(Result<()>),
+}
+
+fn get_qr_code_data_url(uri: &str) -> Result<String> {
+    use anyhow::Context;
+    let image = QrCode::new(uri.as_bytes())
+        .context("Could not generate the QR code")?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(format!(
+        "data:image/svg+xml;base64,{}",
+        base64::encode(image)
+    ))
+}
+
+impl TwoFactorForm {
+    fn is_current_user(ctx: &Context<Self>) -> bool {
+        get_cookie("user_id").ok().flatten().as_deref() == Some(ctx.props().username.as_str())
+    }
+}
+
+impl CommonComponent<TwoFactorForm> for TwoFactorForm {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        use anyhow::Context;
+        match msg {
+            Msg::FormUpdate => Ok(true),
+            Msg::StartEnrollment => {
+                self.reset_done = false;
+                self.common.call_backend(
+                    ctx,
+                    HostService::totp_enroll_start(),
+                    Msg::EnrollStartResponse,
+                );
+                Ok(true)
+            }
+            Msg::EnrollStartResponse(response) => {
+                let response = response?;
+                self.enrollment = Some(Enrollment {
+                    qr_code: get_qr_code_data_url(&response.uri)?,
+                    secret: response.secret,
+                });
+                Ok(true)
+            }
+            Msg::SubmitCode => {
+                if !self.form.validate() {
+                    bail!("Check the form for errors");
+                }
+                let req = mfa::ClientTotpEnrollFinishRequest {
+                    secret: self
+                        .enrollment
+                        .as_ref()
+                        .context("No enrollment in progress")?
+                        .secret
+                        .clone(),
+                    code: self.form.model().code,
+                };
+                self.common.call_backend(
+                    ctx,
+                    HostService::totp_enroll_finish(req),
+                    Msg::EnrollFinishResponse,
+                );
+                Ok(true)
+            }
+            Msg::EnrollFinishResponse(response) => {
+                self.recovery_codes = Some(response?.recovery_codes);
+                self.enrollment = None;
+                Ok(true)
+            }
+            Msg::Reset => {
+                if Self::is_current_user(ctx) {
+                    self.confirming_reset = true;
+                    return Ok(true);
+                }
+                self.common.call_backend(
+                    ctx,
+                    HostService::totp_reset(
+                        ctx.props().username.clone(),
+                        mfa::ClientTotpResetRequest::default(),
+                    ),
+                    Msg::ResetResponse,
+                );
+                Ok(true)
+            }
+            Msg::ConfirmReset => {
+                if !self.reset_form.validate() {
+                    bail!("Check the form for errors");
+                }
+                let req = mfa::ClientTotpResetRequest {
+                    password: Some(self.reset_form.model().password),
+                    code: None,
+                };
+                self.common.call_backend(
+                    ctx,
+                    HostService::totp_reset(ctx.props().username.clone(), req),
+                    Msg::ResetResponse,
+                );
+                Ok(true)
+            }
+            Msg::CancelReset => {
+                self.confirming_reset = false;
+                self.reset_form = Form::<ResetFormModel>::new(ResetFormModel::default());
+                Ok(true)
+            }
+            Msg::ResetResponse(response) => {
+                response?;
+                self.reset_done = true;
+                self.confirming_reset = false;
+                self.reset_form = Form::<ResetFormModel>::new(ResetFormModel::default());
+                self.recovery_codes = None;
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for TwoFactorForm {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_: &Context<Self>) -> Self {
+        TwoFactorForm {
+            common: CommonComponentParts::<Self>::create(),
+            form: Form::<FormModel>::new(FormModel::default()),
+            reset_form: Form::<ResetFormModel>::new(ResetFormModel::default()),
+            enrollment: None,
+            recovery_codes: None,
+            confirming_reset: false,
+            reset_done: false,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-2">
+              <h5 class="fw-bold">
+                {"Two-factor authentication"}
+              </h5>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {
+              if let Some(codes) = &self.recovery_codes {
+                self.view_recovery_codes(codes)
+              } else if let Some(enrollment) = &self.enrollment {
+                self.view_enrollment(ctx, enrollment)
+              } else if self.confirming_reset {
+                self.view_reset_confirmation(ctx)
+              } else {
+                self.view_actions(ctx)
+              }
+            }
+            <Link
+              classes="btn btn-secondary mt-3"
+              to={AppRoute::UserDetails{user_id: ctx.props().username.clone()}}>
+              <i class="bi-arrow-return-left me-2"></i>
+              {"Back"}
+            </Link>
+          </>
+        }
+    }
+}
+
+impl TwoFactorForm {
+    fn view_actions(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        let is_current_user = Self::is_current_user(ctx);
+        html! {
+          <div>
+            {if self.reset_done {
+              html! {
+                <div class="alert alert-success">
+                  {"The second factor was removed."}
+                </div>
+              }
+            } else { html! {} }}
+            {if is_current_user {
+              html! {
+                <button
+                  class="btn btn-primary me-2"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::StartEnrollment)}>
+                  <i class="bi-shield-lock me-2"></i>
+                  {"Set up an authenticator app"}
+                </button>
+              }
+            } else { html! {} }}
+            {if is_current_user || ctx.props().is_admin {
+              html! {
+                <button
+                  class="btn btn-danger"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::Reset)}>
+                  <i class="bi-x-circle me-2"></i>
+                  {"Remove the second factor"}
+                </button>
+              }
+            } else { html! {} }}
+          </div>
+        }
+    }
+
+    fn view_enrollment(&self, ctx: &Context<Self>, enrollment: &Enrollment) -> Html {
+        let link = ctx.link();
+        html! {
+          <form class="form">
+            <p>
+              {"Scan this QR code with your authenticator app, then enter the code it displays."}
+            </p>
+            <div class="mb-3">
+              <img src={enrollment.qr_code.clone()} alt="QR code" />
+            </div>
+            <p>
+              {"Or enter this key manually: "}
+              <code>{&enrollment.secret}</code>
+            </p>
+            <Field<FormModel>
+              form={&self.form}
+              required=true
+              label="Code"
+              field_name="code"
+              autocomplete="one-time-code"
+              oninput={link.callback(|_| Msg::FormUpdate)} />
+            <Submit
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitCode})}
+              text="Enable" />
+          </form>
+        }
+    }
+
+    fn view_reset_confirmation(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+          <form class="form">
+            <p>
+              {"Enter your password to remove the second factor."}
+            </p>
+            <Field<ResetFormModel>
+              form={&self.reset_form}
+              required=true
+              label="Password"
+              field_name="password"
+              input_type="password"
+              autocomplete="current-password"
+              oninput={link.callback(|_| Msg::FormUpdate)} />
+            <Submit
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::ConfirmReset})}
+              text="Remove the second factor" />
+            <button
+              type="button"
+              class="btn btn-secondary ms-2"
+              onclick={link.callback(|_| Msg::CancelReset)}>
+              {"Cancel"}
+            </button>
+          </form>
+        }
+    }
+
+    fn view_recovery_codes(&self, codes: &[String]) -> Html {
+        html! {
+          <div>
+            <div class="alert alert-success">
+              {"Two-factor authentication is now enabled."}
+            </div>
+            <p>
+              {"Store these recovery codes somewhere safe. Each of them can be used once to log in \
+                if you lose access to your authenticator app. They will not be shown again."}
+            </p>
+            <ul class="list-unstyled">
+              {for codes.iter().map(|code| html! { <li><code>{code}</code></li> })}
+            </ul>
+          </div>
+        }
+    }
+}
@@ -24,8 +24,18 @@ use yew::prelude::*;
 )]
 pub struct GetUserDetails;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/set_user_account_status.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct SetUserAccountStatus;
+
 pub type User = get_user_details::GetUserDetailsUser;
 pub type Group = get_user_details::GetUserDetailsUserGroups;
+pub type OwnedGroup = get_user_details::GetUserDetailsUserOwnedGroups;
 pub type Attribute = get_user_details::GetUserDetailsUserAttributes;
 pub type AttributeSchema = get_user_details::GetUserDetailsSchemaUserSchemaAttributes;
 pub type AttributeType = get_user_details::AttributeType;
@@ -64,6 +74,9 @@ pub enum Msg {
     OnError(Error),
     OnUserAddedToGroup(Group),
     OnUserRemovedFromGroup((String, i64)),
+    /// Disable the user if they're enabled, and vice versa.
+    ToggleDisabled,
+    AccountStatusResponse(Result<set_user_account_status::ResponseData>),
 }
 
 #[derive(yew::Properties, Clone, PartialEq, Eq)]
@@ -73,7 +86,11 @@ pub struct Props {
 }
 
 impl CommonComponent<UserDetails> for UserDetails {
-    fn handle_msg(&mut self, _: &Context<Self>, msg: <Self as Component>::Message) -> Result<bool> {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
         match msg {
             Msg::UserDetailsResponse(response) => match response {
                 Ok(user) => {
@@ -91,6 +108,25 @@ impl CommonComponent<UserDetails> for UserDetails {
             Msg::OnUserRemovedFromGroup((_, group_id)) => {
                 self.mut_groups().retain(|g| g.id != group_id);
             }
+            Msg::ToggleDisabled => {
+                let user = &self.user_and_schema.as_ref().unwrap().0;
+                let variables = set_user_account_status::Variables {
+                    user_id: user.id.clone(),
+                    disabled: !user.disabled,
+                    expiry_date: user.expiry_date,
+                };
+                self.common.call_graphql::<SetUserAccountStatus, _>(
+                    ctx,
+                    variables,
+                    Msg::AccountStatusResponse,
+                    "Error trying to change the account status",
+                );
+            }
+            Msg::AccountStatusResponse(response) => {
+                response?;
+                let user = &mut self.user_and_schema.as_mut().unwrap().0;
+                user.disabled = !user.disabled;
+            }
         }
         Ok(true)
     }
@@ -179,6 +215,78 @@ impl UserDetails {
         }
     }
 
+    fn view_owned_groups(&self, u: &User) -> Html {
+        if u.owned_groups.is_empty() {
+            return html! {};
+        }
+        let make_group_row = |group: &OwnedGroup| {
+            html! {
+              <tr key={"ownedGroupRow_".to_string() + &group.display_name}>
+                <td>
+                  <Link to={AppRoute::GroupDetails{group_id: group.id}}>
+                    {&group.display_name}
+                  </Link>
+                </td>
+              </tr>
+            }
+        };
+        html! {
+          <>
+            <h5 class="row m-3 fw-bold">{"Managed groups"}</h5>
+            <div class="table-responsive">
+              <table class="table table-hover">
+                <thead>
+                  <tr key="headerRow">
+                    <th>{"Group"}</th>
+                  </tr>
+                </thead>
+                <tbody>
+                  {u.owned_groups.iter().map(make_group_row).collect::<Vec<_>>()}
+                </tbody>
+              </table>
+            </div>
+          </>
+        }
+    }
+
+    fn view_account_status(&self, u: &User) -> Html {
+        html! {
+          <>
+            {if u.disabled {
+              html! {
+                <div class="alert alert-warning">
+                  {"This account is disabled: the user cannot log in."}
+                </div>
+              }
+            } else { html! {} }}
+            {if let Some(expiry_date) = &u.expiry_date {
+              html! {
+                <div class="alert alert-info">
+                  {"This account expires on "}{expiry_date.naive_local().date()}{"."}
+                </div>
+              }
+            } else { html! {} }}
+          </>
+        }
+    }
+
+    fn view_disable_button(&self, ctx: &Context<Self>, u: &User) -> Html {
+        let link = &ctx.link();
+        if ctx.props().is_admin {
+            html! {
+              <button
+                class="btn btn-warning me-2"
+                disabled={self.common.is_task_running()}
+                onclick={link.callback(|_| Msg::ToggleDisabled)}>
+                <i class="bi-person-lock me-2"></i>
+                {if u.disabled { "Enable account" } else { "Disable account" }}
+              </button>
+            }
+        } else {
+            html! {}
+        }
+    }
+
     fn view_add_group_button(&self, ctx: &Context<Self>, u: &User) -> Html {
         let link = &ctx.link();
         if ctx.props().is_admin {
@@ -218,6 +326,7 @@ impl Component for UserDetails {
                 html! {
                   <>
                     <h3>{u.id.to_string()}</h3>
+                    {self.view_account_status(u)}
                     <div class="d-flex flex-row-reverse">
                       <Link
                         to={AppRoute::ChangePassword{user_id: u.id.clone()}}
@@ -225,6 +334,25 @@ impl Component for UserDetails {
                         <i class="bi-key me-2"></i>
                         {"Modify password"}
                       </Link>
+                      <Link
+                        to={AppRoute::TwoFactor{user_id: u.id.clone()}}
+                        classes="btn btn-secondary me-2">
+                        <i class="bi-shield-lock me-2"></i>
+                        {"Two-factor authentication"}
+                      </Link>
+                      <Link
+                        to={AppRoute::ApiTokens{user_id: u.id.clone()}}
+                        classes="btn btn-secondary me-2">
+                        <i class="bi-braces me-2"></i>
+                        {"API tokens"}
+                      </Link>
+                      <Link
+                        to={AppRoute::Sessions{user_id: u.id.clone()}}
+                        classes="btn btn-secondary me-2">
+                        <i class="bi-laptop me-2"></i>
+                        {"Sessions"}
+                      </Link>
+                      {self.view_disable_button(ctx, u)}
                     </div>
                     <div>
                       <h5 class="row m-3 fw-bold">{"User details"}</h5>
@@ -237,6 +365,7 @@ impl Component for UserDetails {
                     />
                     {self.view_group_memberships(ctx, u)}
                     {self.view_add_group_button(ctx, u)}
+                    {self.view_owned_groups(u)}
                     {self.view_messages(error)}
                   </>
                 }
@@ -2,7 +2,7 @@ use super::cookies::set_cookie;
 use anyhow::{Context, Result, anyhow};
 use gloo_net::http::{Method, RequestBuilder};
 use graphql_client::GraphQLQuery;
-use lldap_auth::{JWTClaims, login, registration};
+use lldap_auth::{JWTClaims, login, mfa, oidc, registration};
 
 use lldap_frontend_options::Options;
 use serde::{Serialize, de::DeserializeOwned};
@@ -86,6 +86,14 @@ fn set_cookies_from_jwt(response: login::ServerLoginResponse) -> Result<(String,
         .context("Error setting cookie")
 }
 
+/// The outcome of the password step of the login.
+pub enum LoginFinishResponse {
+    /// The user ID and whether they are an admin.
+    LoggedIn((String, bool)),
+    /// The user enrolled a second factor: the token to pass to [`HostService::totp_login`].
+    MfaRequired(String),
+}
+
 impl HostService {
     pub async fn graphql_query<QueryType>(
         variables: QueryType::Variables,
@@ -128,16 +136,99 @@ impl HostService {
         .await
     }
 
-    pub async fn login_finish(request: login::ClientLoginFinishRequest) -> Result<(String, bool)> {
-        call_server_json_with_error_message::<login::ServerLoginResponse, _>(
+    pub async fn login_finish(
+        request: login::ClientLoginFinishRequest,
+    ) -> Result<LoginFinishResponse> {
+        match call_server_json_with_error_message::<login::ServerLoginFinishResponse, _>(
             &(base_url() + "/auth/opaque/login/finish"),
             RequestType::Post(request),
             "Could not finish authentication",
         )
+        .await?
+        {
+            login::ServerLoginFinishResponse::LoggedIn(response) => {
+                set_cookies_from_jwt(response).map(LoginFinishResponse::LoggedIn)
+            }
+            login::ServerLoginFinishResponse::MfaRequired(response) => {
+                Ok(LoginFinishResponse::MfaRequired(response.mfa_token))
+            }
+        }
+    }
+
+    pub async fn totp_login(request: login::ClientTotpLoginRequest) -> Result<(String, bool)> {
+        call_server_json_with_error_message::<login::ServerLoginResponse, _>(
+            &(base_url() + "/auth/totp/login"),
+            RequestType::Post(request),
+            "Could not verify the code",
+        )
         .await
         .and_then(set_cookies_from_jwt)
     }
 
+    pub async fn totp_enroll_start() -> Result<mfa::ServerTotpEnrollStartResponse> {
+        call_server_json_with_error_message(
+            &(base_url() + "/auth/totp/enroll/start"),
+            RequestType::Post(""),
+            "Could not start the two-factor enrollment",
+        )
+        .await
+    }
+
+    pub async fn totp_enroll_finish(
+        request: mfa::ClientTotpEnrollFinishRequest,
+    ) -> Result<mfa::ServerTotpEnrollFinishResponse> {
+        call_server_json_with_error_message(
+            &(base_url() + "/auth/totp/enroll/finish"),
+            RequestType::Post(request),
+            "Could not finish the two-factor enrollment",
+        )
+        .await
+    }
+
+    pub async fn totp_reset(username: String, request: mfa::ClientTotpResetRequest) -> Result<()> {
+        call_server_empty_response_with_error_message(
+            &format!(
+                "{}/auth/totp/reset/{}",
+                base_url(),
+                url_escape::encode_query(&username)
+            ),
+            RequestType::Post(request),
+            "Could not reset the second factor",
+        )
+        .await
+    }
+
+    pub async fn oidc_get_authorization_request(
+        request_id: String,
+    ) -> Result<oidc::ServerAuthorizationRequestResponse> {
+        call_server_json_with_error_message(
+            &format!(
+                "{}/auth/oidc/consent/{}",
+                base_url(),
+                url_escape::encode_query(&request_id)
+            ),
+            GET_REQUEST,
+            "Could not fetch the authorization request",
+        )
+        .await
+    }
+
+    pub async fn oidc_decide_authorization_request(
+        request_id: String,
+        approve: bool,
+    ) -> Result<oidc::ServerAuthorizationDecisionResponse> {
+        call_server_json_with_error_message(
+            &format!(
+                "{}/auth/oidc/consent/{}",
+                base_url(),
+                url_escape::encode_query(&request_id)
+            ),
+            RequestType::Post(oidc::ClientAuthorizationDecisionRequest { approve }),
+            "Could not answer the authorization request",
+        )
+        .await
+    }
+
     pub async fn get_settings() -> Result<Options> {
         call_server_json_with_error_message::<Options, _>(
             &(base_url() + "/settings"),
@@ -13,7 +13,7 @@ pub mod group {
             "creation_date" => Some(AttributeDescription {
                 attribute_identifier: name,
                 attribute_name: "creationdate",
-                aliases: vec![name, "createtimestamp", "modifytimestamp"],
+                aliases: vec![name, "createtimestamp"],
             }),
             "display_name" => Some(AttributeDescription {
                 attribute_identifier: name,
@@ -60,7 +60,7 @@ pub mod user {
             "creation_date" => Some(AttributeDescription {
                 attribute_identifier: name,
                 attribute_name: "creationdate",
-                aliases: vec![name, "createtimestamp", "modifytimestamp"],
+                aliases: vec![name, "createtimestamp"],
             }),
             "display_name" => Some(AttributeDescription {
                 attribute_identifier: name,
@@ -72,6 +72,11 @@ pub mod user {
                 attribute_name: "firstname",
                 aliases: vec![name, "givenname"],
             }),
+            "last_login" => Some(AttributeDescription {
+                attribute_identifier: name,
+                attribute_name: "lastlogin",
+                aliases: vec![name, "authtimestamp"],
+            }),
             "last_name" => Some(AttributeDescription {
                 attribute_identifier: name,
                 attribute_name: "lastname",
@@ -82,6 +87,11 @@ pub mod user {
                 attribute_name: name,
                 aliases: vec!["email"],
             }),
+            "password_changed" => Some(AttributeDescription {
+                attribute_identifier: name,
+                attribute_name: "passwordchanged",
+                aliases: vec![name, "pwdchangedtime"],
+            }),
             "user_id" => Some(AttributeDescription {
                 attribute_identifier: name,
                 attribute_name: "uid",
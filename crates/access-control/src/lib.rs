@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use lldap_auth::access_control::{Permission, ValidationResults};
+use lldap_auth::access_control::{Permission, RolePermission, ValidationResults};
 use lldap_domain::{
     public_schema::PublicSchema,
     requests::{
@@ -13,9 +13,12 @@ use lldap_domain::{
     },
 };
 use lldap_domain_handlers::handler::{
-    BackendHandler, GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
-    ReadSchemaBackendHandler, SchemaBackendHandler, UserBackendHandler, UserListerBackendHandler,
-    UserRequestFilter,
+    ApiToken, ApiTokenBackendHandler, AuditEvent, AuditEventFilter, AuditLogBackendHandler,
+    BackendHandler, CreateApiTokenRequest, DeletedEntry, DeletedEntryBackendHandler,
+    GroupBackendHandler, GroupListerBackendHandler, GroupOwnerBackendHandler, GroupRequestFilter,
+    LoginThrottleBackendHandler, LoginThrottleSubject, ReadSchemaBackendHandler, Role,
+    RoleBackendHandler, SchemaBackendHandler, Session, SessionBackendHandler, UserBackendHandler,
+    UserListerBackendHandler, UserRequestFilter,
 };
 use lldap_domain_model::error::Result;
 use std::collections::HashSet;
@@ -25,6 +28,7 @@ use tracing::info;
 pub trait UserReadableBackendHandler: ReadSchemaBackendHandler {
     async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+    async fn get_owned_groups(&self, user_id: &UserId) -> Result<Vec<GroupId>>;
     async fn get_schema(&self) -> Result<PublicSchema>;
 }
 
@@ -37,11 +41,73 @@ pub trait ReadonlyBackendHandler: UserReadableBackendHandler {
     ) -> Result<Vec<UserAndGroups>>;
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
+    async fn list_group_owners(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+    async fn get_group_filter(&self, group_id: GroupId) -> Result<Option<UserRequestFilter>>;
+    async fn list_membership_expiry_dates(
+        &self,
+        group_id: GroupId,
+    ) -> Result<Vec<(UserId, chrono::NaiveDateTime)>>;
+    async fn list_expired_memberships(
+        &self,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<(UserId, GroupName)>>;
+    async fn list_deleted_entries(&self, since: chrono::NaiveDateTime)
+    -> Result<Vec<DeletedEntry>>;
 }
 
 #[async_trait]
 pub trait UserWriteableBackendHandler: UserReadableBackendHandler {
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+    async fn list_api_tokens(&self, user_id: &UserId) -> Result<Vec<ApiToken>>;
+    async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+    async fn delete_api_token(&self, user_id: &UserId, token_id: i32) -> Result<()>;
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+    async fn revoke_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+}
+
+/// Edits the attributes allowed by a role, see `ValidationResults::can_edit_user_attribute`.
+#[async_trait]
+pub trait UserAttributeEditorBackendHandler: UserReadableBackendHandler {
+    async fn update_user_attributes(&self, request: UpdateUserRequest) -> Result<()>;
+}
+
+#[async_trait]
+pub trait UserCreationBackendHandler: UserReadableBackendHandler {
+    async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+}
+
+#[async_trait]
+pub trait UserDeletionBackendHandler: UserReadableBackendHandler {
+    async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+}
+
+/// Renaming a user is equivalent to deleting it and creating it again.
+#[async_trait]
+pub trait UserRenameBackendHandler: UserReadableBackendHandler {
+    /// Returns the hashes of the JWTs of the user, which are no longer valid.
+    async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<HashSet<u64>>;
+}
+
+#[async_trait]
+pub trait GroupMembershipBackendHandler: ReadonlyBackendHandler {
+    async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    async fn update_group_members(
+        &self,
+        group_id: GroupId,
+        added: &[UserId],
+        removed: &[UserId],
+    ) -> Result<()>;
+    async fn set_membership_expiry_date(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()>;
+    /// Whether the members of the group get permissions, from a built-in group or from a role,
+    /// either directly or through the groups it's nested in.
+    async fn group_grants_permissions(&self, group_id: GroupId) -> Result<bool>;
 }
 
 #[async_trait]
@@ -49,15 +115,30 @@ pub trait AdminBackendHandler:
     UserWriteableBackendHandler
     + ReadonlyBackendHandler
     + UserWriteableBackendHandler
+    + GroupMembershipBackendHandler
+    + UserCreationBackendHandler
+    + UserDeletionBackendHandler
+    + UserRenameBackendHandler
     + SchemaBackendHandler
 {
-    async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
-    async fn delete_user(&self, user_id: &UserId) -> Result<()>;
-    async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
-    async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+    async fn add_group_to_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()>;
+    async fn remove_group_from_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()>;
+    async fn set_group_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()>;
     async fn add_user_attribute(&self, request: CreateAttributeRequest) -> Result<()>;
     async fn add_group_attribute(&self, request: CreateAttributeRequest) -> Result<()>;
     async fn delete_user_attribute(&self, name: &AttributeName) -> Result<()>;
@@ -66,6 +147,21 @@ pub trait AdminBackendHandler:
     async fn add_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
     async fn delete_user_object_class(&self, name: &LdapObjectClass) -> Result<()>;
     async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
+    /// Lifts the lockout caused by repeated failed logins.
+    async fn unlock_user(&self, user_id: &UserId) -> Result<()>;
+    async fn list_roles(&self) -> Result<Vec<Role>>;
+    async fn set_role(&self, name: &str, permissions: Vec<RolePermission>) -> Result<()>;
+    async fn delete_role(&self, name: &str) -> Result<()>;
+    async fn add_role_to_group(&self, name: &str, group_id: GroupId) -> Result<()>;
+    async fn remove_role_from_group(&self, name: &str, group_id: GroupId) -> Result<()>;
+    async fn add_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    async fn remove_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    async fn list_audit_events(
+        &self,
+        filter: AuditEventFilter,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<AuditEvent>>;
 }
 
 #[async_trait]
@@ -76,6 +172,9 @@ impl<Handler: BackendHandler> UserReadableBackendHandler for Handler {
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>> {
         <Handler as UserBackendHandler>::get_user_groups(self, user_id).await
     }
+    async fn get_owned_groups(&self, user_id: &UserId) -> Result<Vec<GroupId>> {
+        <Handler as GroupOwnerBackendHandler>::get_owned_groups(self, user_id).await
+    }
     async fn get_schema(&self) -> Result<PublicSchema> {
         Ok(PublicSchema::from(
             <Handler as ReadSchemaBackendHandler>::get_schema(self).await?,
@@ -98,6 +197,30 @@ impl<Handler: BackendHandler> ReadonlyBackendHandler for Handler {
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails> {
         <Handler as GroupBackendHandler>::get_group_details(self, group_id).await
     }
+    async fn list_group_owners(&self, group_id: GroupId) -> Result<Vec<UserId>> {
+        <Handler as GroupOwnerBackendHandler>::list_group_owners(self, group_id).await
+    }
+    async fn get_group_filter(&self, group_id: GroupId) -> Result<Option<UserRequestFilter>> {
+        <Handler as GroupBackendHandler>::get_group_filter(self, group_id).await
+    }
+    async fn list_membership_expiry_dates(
+        &self,
+        group_id: GroupId,
+    ) -> Result<Vec<(UserId, chrono::NaiveDateTime)>> {
+        <Handler as GroupBackendHandler>::list_membership_expiry_dates(self, group_id).await
+    }
+    async fn list_expired_memberships(
+        &self,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<(UserId, GroupName)>> {
+        <Handler as GroupBackendHandler>::list_expired_memberships(self, since).await
+    }
+    async fn list_deleted_entries(
+        &self,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<DeletedEntry>> {
+        <Handler as DeletedEntryBackendHandler>::list_deleted_entries(self, since).await
+    }
 }
 
 #[async_trait]
@@ -105,21 +228,103 @@ impl<Handler: BackendHandler> UserWriteableBackendHandler for Handler {
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
         <Handler as UserBackendHandler>::update_user(self, request).await
     }
+    async fn list_api_tokens(&self, user_id: &UserId) -> Result<Vec<ApiToken>> {
+        <Handler as ApiTokenBackendHandler>::list_api_tokens(self, user_id).await
+    }
+    async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)> {
+        <Handler as ApiTokenBackendHandler>::create_api_token(self, request).await
+    }
+    async fn delete_api_token(&self, user_id: &UserId, token_id: i32) -> Result<()> {
+        <Handler as ApiTokenBackendHandler>::delete_api_token(self, user_id, token_id).await
+    }
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>> {
+        <Handler as SessionBackendHandler>::list_sessions(self, user_id).await
+    }
+    async fn revoke_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>> {
+        <Handler as SessionBackendHandler>::revoke_session(self, user_id, session_id).await
+    }
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>> {
+        <Handler as SessionBackendHandler>::revoke_all_sessions(self, user_id).await
+    }
+}
+#[async_trait]
+impl<Handler: BackendHandler> UserAttributeEditorBackendHandler for Handler {
+    async fn update_user_attributes(&self, request: UpdateUserRequest) -> Result<()> {
+        <Handler as UserBackendHandler>::update_user(self, request).await
+    }
 }
 #[async_trait]
-impl<Handler: BackendHandler> AdminBackendHandler for Handler {
+impl<Handler: BackendHandler> UserCreationBackendHandler for Handler {
     async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
         <Handler as UserBackendHandler>::create_user(self, request).await
     }
+}
+#[async_trait]
+impl<Handler: BackendHandler> UserDeletionBackendHandler for Handler {
     async fn delete_user(&self, user_id: &UserId) -> Result<()> {
         <Handler as UserBackendHandler>::delete_user(self, user_id).await
     }
+}
+#[async_trait]
+impl<Handler: BackendHandler> GroupMembershipBackendHandler for Handler {
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
         <Handler as UserBackendHandler>::add_user_to_group(self, user_id, group_id).await
     }
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
         <Handler as UserBackendHandler>::remove_user_from_group(self, user_id, group_id).await
     }
+    async fn update_group_members(
+        &self,
+        group_id: GroupId,
+        added: &[UserId],
+        removed: &[UserId],
+    ) -> Result<()> {
+        <Handler as UserBackendHandler>::update_group_members(self, group_id, added, removed).await
+    }
+    async fn set_membership_expiry_date(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        <Handler as UserBackendHandler>::set_membership_expiry_date(
+            self,
+            user_id,
+            group_id,
+            expiry_date,
+        )
+        .await
+    }
+    async fn group_grants_permissions(&self, group_id: GroupId) -> Result<bool> {
+        // The members of the group are also members of the groups it's nested in.
+        let mut groups =
+            vec![<Handler as GroupBackendHandler>::get_group_details(self, group_id).await?];
+        groups.extend(<Handler as GroupBackendHandler>::get_ancestor_groups(self, group_id).await?);
+        if groups.iter().any(|group| {
+            [
+                "lldap_admin",
+                "lldap_password_manager",
+                "lldap_strict_readonly",
+            ]
+            .into_iter()
+            .any(|name| group.display_name == name.into())
+        }) {
+            return Ok(true);
+        }
+        Ok(<Handler as RoleBackendHandler>::list_roles(self)
+            .await?
+            .iter()
+            .any(|role| groups.iter().any(|g| role.group_ids.contains(&g.group_id))))
+    }
+}
+#[async_trait]
+impl<Handler: BackendHandler> UserRenameBackendHandler for Handler {
+    async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<HashSet<u64>> {
+        <Handler as UserBackendHandler>::rename_user(self, user_id, new_user_id).await
+    }
+}
+#[async_trait]
+impl<Handler: BackendHandler> AdminBackendHandler for Handler {
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()> {
         <Handler as GroupBackendHandler>::update_group(self, request).await
     }
@@ -129,6 +334,33 @@ impl<Handler: BackendHandler> AdminBackendHandler for Handler {
     async fn delete_group(&self, group_id: GroupId) -> Result<()> {
         <Handler as GroupBackendHandler>::delete_group(self, group_id).await
     }
+    async fn add_group_to_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()> {
+        <Handler as GroupBackendHandler>::add_group_to_group(self, parent_group_id, child_group_id)
+            .await
+    }
+    async fn remove_group_from_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()> {
+        <Handler as GroupBackendHandler>::remove_group_from_group(
+            self,
+            parent_group_id,
+            child_group_id,
+        )
+        .await
+    }
+    async fn set_group_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()> {
+        <Handler as GroupBackendHandler>::set_group_filter(self, group_id, filter).await
+    }
     async fn add_user_attribute(&self, request: CreateAttributeRequest) -> Result<()> {
         <Handler as SchemaBackendHandler>::add_user_attribute(self, request).await
     }
@@ -153,6 +385,42 @@ impl<Handler: BackendHandler> AdminBackendHandler for Handler {
     async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()> {
         <Handler as SchemaBackendHandler>::delete_group_object_class(self, name).await
     }
+    async fn unlock_user(&self, user_id: &UserId) -> Result<()> {
+        <Handler as LoginThrottleBackendHandler>::clear_login_failures(
+            self,
+            &LoginThrottleSubject::User(user_id.clone()),
+        )
+        .await
+    }
+    async fn list_roles(&self) -> Result<Vec<Role>> {
+        <Handler as RoleBackendHandler>::list_roles(self).await
+    }
+    async fn set_role(&self, name: &str, permissions: Vec<RolePermission>) -> Result<()> {
+        <Handler as RoleBackendHandler>::set_role(self, name, permissions).await
+    }
+    async fn delete_role(&self, name: &str) -> Result<()> {
+        <Handler as RoleBackendHandler>::delete_role(self, name).await
+    }
+    async fn add_role_to_group(&self, name: &str, group_id: GroupId) -> Result<()> {
+        <Handler as RoleBackendHandler>::add_role_to_group(self, name, group_id).await
+    }
+    async fn remove_role_from_group(&self, name: &str, group_id: GroupId) -> Result<()> {
+        <Handler as RoleBackendHandler>::remove_role_from_group(self, name, group_id).await
+    }
+    async fn add_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        <Handler as GroupOwnerBackendHandler>::add_group_owner(self, group_id, user_id).await
+    }
+    async fn remove_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        <Handler as GroupOwnerBackendHandler>::remove_group_owner(self, group_id, user_id).await
+    }
+    async fn list_audit_events(
+        &self,
+        filter: AuditEventFilter,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<AuditEvent>> {
+        <Handler as AuditLogBackendHandler>::list_audit_events(self, filter, offset, limit).await
+    }
 }
 
 pub struct AccessControlledBackendHandler<Handler> {
@@ -199,6 +467,62 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
         validation_result.can_read_all().then_some(&self.handler)
     }
 
+    /// The caller must check that the group doesn't grant permissions, unless
+    /// `validation_result.is_admin()`.
+    pub fn get_group_member_editor_handler(
+        &self,
+        validation_result: &ValidationResults,
+        group_id: GroupId,
+    ) -> Option<&(impl GroupMembershipBackendHandler + use<Handler>)> {
+        validation_result
+            .can_manage_group_members(group_id.0)
+            .then_some(&self.handler)
+    }
+
+    pub fn get_user_creation_handler(
+        &self,
+        validation_result: &ValidationResults,
+    ) -> Option<&(impl UserCreationBackendHandler + use<Handler>)> {
+        validation_result
+            .can_create_users()
+            .then_some(&self.handler)
+    }
+
+    /// The caller must check that the user to delete is not an admin, unless
+    /// `validation_result.is_admin()`.
+    pub fn get_user_deletion_handler(
+        &self,
+        validation_result: &ValidationResults,
+    ) -> Option<&(impl UserDeletionBackendHandler + use<Handler>)> {
+        validation_result
+            .can_delete_users()
+            .then_some(&self.handler)
+    }
+
+    /// The caller must check that the user to rename is not an admin, unless
+    /// `validation_result.is_admin()`.
+    pub fn get_user_rename_handler(
+        &self,
+        validation_result: &ValidationResults,
+    ) -> Option<&(impl UserRenameBackendHandler + use<Handler>)> {
+        (validation_result.can_create_users() && validation_result.can_delete_users())
+            .then_some(&self.handler)
+    }
+
+    /// The caller must check that the edited user is not an admin and that all the edited
+    /// attributes are allowed by `validation_result.can_edit_user_attribute`.
+    pub fn get_user_attribute_editor_handler(
+        &self,
+        validation_result: &ValidationResults,
+    ) -> Option<&(impl UserAttributeEditorBackendHandler + use<Handler>)> {
+        (validation_result.is_admin()
+            || validation_result
+                .role_permissions
+                .iter()
+                .any(|p| matches!(p, RolePermission::EditUserAttribute(_))))
+        .then_some(&self.handler)
+    }
+
     pub fn get_writeable_handler(
         &self,
         validation_result: &ValidationResults,
@@ -221,33 +545,59 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
         &self,
         validation_result: &ValidationResults,
     ) -> UserRestrictedListerBackendHandler<'_, Handler> {
+        if validation_result.can_read_all() {
+            return UserRestrictedListerBackendHandler {
+                handler: &self.handler,
+                user_filter: None,
+                readable_groups: Vec::new(),
+                list_all_users: true,
+            };
+        }
+        info!("Unprivileged search, limiting results");
         UserRestrictedListerBackendHandler {
             handler: &self.handler,
-            user_filter: if validation_result.can_read_all() {
-                None
-            } else {
-                info!("Unprivileged search, limiting results");
-                Some(validation_result.user.clone())
-            },
+            user_filter: Some(validation_result.user.clone()),
+            readable_groups: validation_result
+                .readable_group_ids()
+                .into_iter()
+                .map(GroupId)
+                .collect(),
+            list_all_users: validation_result.can_list_all_users(),
         }
     }
 
     pub async fn get_permissions_for_user(&self, user_id: UserId) -> Result<ValidationResults> {
         let user_groups = self.handler.get_user_groups(&user_id).await?;
-        Ok(self.get_permissions_from_groups(user_id, user_groups.iter().map(|g| &g.display_name)))
+        self.get_permissions_from_groups(user_id, user_groups.iter().map(|g| &g.display_name))
+            .await
     }
 
-    pub fn get_permissions_from_groups<Groups, T>(
+    /// Computes the permissions granted by the built-in groups, by the roles of the groups and by
+    /// the groups owned by the user.
+    pub async fn get_permissions_from_groups<Groups, T>(
         &self,
         user_id: UserId,
         groups: Groups,
-    ) -> ValidationResults
+    ) -> Result<ValidationResults>
     where
         Groups: Iterator<Item = T> + Clone,
         T: AsRef<GroupName>,
     {
         let is_in_group = |name: GroupName| groups.clone().any(|g| *g.as_ref() == name);
-        ValidationResults {
+        let group_names: Vec<GroupName> = groups.clone().map(|g| g.as_ref().clone()).collect();
+        let mut role_permissions = if group_names.is_empty() {
+            HashSet::new()
+        } else {
+            self.handler.get_role_permissions(&group_names).await?
+        };
+        role_permissions.extend(
+            self.handler
+                .get_owned_groups(&user_id)
+                .await?
+                .into_iter()
+                .map(|g| RolePermission::ManageGroupMembers(g.0)),
+        );
+        Ok(ValidationResults {
             user: user_id,
             permission: if is_in_group("lldap_admin".into()) {
                 Permission::Admin
@@ -258,13 +608,20 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
             } else {
                 Permission::Regular
             },
-        }
+            api_token_scope: None,
+            role_permissions,
+        })
     }
 }
 
 pub struct UserRestrictedListerBackendHandler<'a, Handler> {
     handler: &'a Handler,
     user_filter: Option<UserId>,
+    /// Groups whose members can be listed despite the user filter, granted by the roles or owned.
+    readable_groups: Vec<GroupId>,
+    /// Whether the plain list of all the users (without filter or groups) can be fetched despite
+    /// the user filter, for the group owners to pick the new members.
+    list_all_users: bool,
 }
 
 #[async_trait]
@@ -296,7 +653,23 @@ impl<Handler: UserListerBackendHandler + Sync> UserListerBackendHandler
         let user_filter = self
             .user_filter
             .as_ref()
-            .map(|u| UserRequestFilter::UserId(u.clone()));
+            .filter(|_| !(self.list_all_users && filters.is_none() && !get_groups))
+            .map(|u| {
+                let user_filter = UserRequestFilter::UserId(u.clone());
+                if self.readable_groups.is_empty() {
+                    user_filter
+                } else {
+                    UserRequestFilter::Or(
+                        std::iter::once(user_filter)
+                            .chain(
+                                self.readable_groups
+                                    .iter()
+                                    .map(|g| UserRequestFilter::MemberOfId(*g)),
+                            )
+                            .collect(),
+                    )
+                }
+            });
         let filters = match (filters, user_filter) {
             (None, None) => None,
             (None, u) => u,
@@ -312,17 +685,39 @@ impl<Handler: GroupListerBackendHandler + Sync> GroupListerBackendHandler
     for UserRestrictedListerBackendHandler<'_, Handler>
 {
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
-        let group_filter = self
-            .user_filter
-            .as_ref()
-            .map(|u| GroupRequestFilter::Member(u.clone()));
+        let group_filter = self.user_filter.as_ref().map(|u| {
+            let group_filter = GroupRequestFilter::Member(u.clone());
+            if self.readable_groups.is_empty() {
+                group_filter
+            } else {
+                GroupRequestFilter::Or(
+                    std::iter::once(group_filter)
+                        .chain(
+                            self.readable_groups
+                                .iter()
+                                .map(|g| GroupRequestFilter::GroupId(*g)),
+                        )
+                        .collect(),
+                )
+            }
+        });
         let filters = match (filters, group_filter) {
             (None, None) => None,
             (None, u) => u,
             (f, None) => f,
             (Some(f), Some(u)) => Some(GroupRequestFilter::And(vec![f, u])),
         };
-        self.handler.list_groups(filters).await
+        let mut groups = self.handler.list_groups(filters).await?;
+        if let Some(user) = &self.user_filter {
+            // Only the members of the readable groups are visible, besides the user.
+            for group in groups
+                .iter_mut()
+                .filter(|g| !self.readable_groups.contains(&g.id))
+            {
+                group.users.retain(|u| u == user);
+            }
+        }
+        Ok(groups)
     }
 }
 
@@ -330,6 +725,7 @@ impl<Handler: GroupListerBackendHandler + Sync> GroupListerBackendHandler
 pub trait UserAndGroupListerBackendHandler:
     UserListerBackendHandler + GroupListerBackendHandler
 {
+    /// The only user that can appear in the member lists of the groups, if restricted.
     fn user_filter(&self) -> &Option<UserId>;
 }
 
@@ -338,6 +734,11 @@ impl<Handler: GroupListerBackendHandler + UserListerBackendHandler + Sync>
     UserAndGroupListerBackendHandler for UserRestrictedListerBackendHandler<'_, Handler>
 {
     fn user_filter(&self) -> &Option<UserId> {
-        &self.user_filter
+        if self.readable_groups.is_empty() {
+            &self.user_filter
+        } else {
+            // The member lists were already restricted in `list_groups`.
+            &None
+        }
     }
 }
@@ -1,5 +1,6 @@
 use crate::types::UserId;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Permission {
@@ -9,10 +10,125 @@ pub enum Permission {
     Regular,
 }
 
+/// Restricts what a request authenticated with an API token can do, on top of the permissions of
+/// the user owning the token.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ApiTokenScope {
+    /// Only read operations.
+    Readonly,
+    /// Read operations, and adding or removing users from groups.
+    GroupMembership,
+    /// Everything the user can do.
+    Full,
+}
+
+impl ApiTokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenScope::Readonly => "readonly",
+            ApiTokenScope::GroupMembership => "group_membership",
+            ApiTokenScope::Full => "full",
+        }
+    }
+}
+
+impl std::str::FromStr for ApiTokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "readonly" => Ok(ApiTokenScope::Readonly),
+            "group_membership" => Ok(ApiTokenScope::GroupMembership),
+            "full" => Ok(ApiTokenScope::Full),
+            _ => Err(format!("Unknown API token scope: {s}")),
+        }
+    }
+}
+
+/// A fine-grained permission, granted through a role to the members of the groups the role is
+/// attached to, or to the owners of a group. The permissions add up to the ones of the built-in
+/// groups.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum RolePermission {
+    /// Read all the users and groups, like `lldap_strict_readonly`.
+    ReadAll,
+    /// Read the group with the given ID and its members.
+    ReadGroupMembers(i32),
+    /// Change the password of the non-admin users. Like `lldap_password_manager`, it also allows
+    /// reading all the users and groups.
+    ResetPasswords,
+    /// Edit the given attribute of the non-admin users.
+    EditUserAttribute(String),
+    CreateUsers,
+    /// Delete the non-admin users.
+    DeleteUsers,
+    /// Add and remove users from the groups that don't grant any permission.
+    ManageGroupMemberships,
+    /// Read all the users, and add and remove users from the group with the given ID, unless it
+    /// grants permissions. Granted to the owners of the group.
+    ManageGroupMembers(i32),
+}
+
+impl std::fmt::Display for RolePermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RolePermission::ReadAll => write!(f, "read_all"),
+            RolePermission::ReadGroupMembers(group_id) => {
+                write!(f, "read_group_members:{group_id}")
+            }
+            RolePermission::ResetPasswords => write!(f, "reset_passwords"),
+            RolePermission::EditUserAttribute(attribute) => {
+                write!(f, "edit_user_attribute:{attribute}")
+            }
+            RolePermission::CreateUsers => write!(f, "create_users"),
+            RolePermission::DeleteUsers => write!(f, "delete_users"),
+            RolePermission::ManageGroupMemberships => write!(f, "manage_group_memberships"),
+            RolePermission::ManageGroupMembers(group_id) => {
+                write!(f, "manage_group_members:{group_id}")
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for RolePermission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None => match s {
+                "read_all" => Ok(RolePermission::ReadAll),
+                "reset_passwords" => Ok(RolePermission::ResetPasswords),
+                "create_users" => Ok(RolePermission::CreateUsers),
+                "delete_users" => Ok(RolePermission::DeleteUsers),
+                "manage_group_memberships" => Ok(RolePermission::ManageGroupMemberships),
+                _ => Err(format!("Unknown permission: {s}")),
+            },
+            Some(("read_group_members", group_id)) => group_id
+                .parse()
+                .map(RolePermission::ReadGroupMembers)
+                .map_err(|_| format!("Invalid group ID in permission: {s}")),
+            Some(("manage_group_members", group_id)) => group_id
+                .parse()
+                .map(RolePermission::ManageGroupMembers)
+                .map_err(|_| format!("Invalid group ID in permission: {s}")),
+            Some(("edit_user_attribute", attribute)) if !attribute.is_empty() => Ok(
+                RolePermission::EditUserAttribute(attribute.to_ascii_lowercase()),
+            ),
+            _ => Err(format!("Unknown permission: {s}")),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ValidationResults {
     pub user: UserId,
     pub permission: Permission,
+    /// Set when the request was authenticated with an API token rather than a session.
+    #[serde(default)]
+    pub api_token_scope: Option<ApiTokenScope>,
+    /// Permissions granted by the roles of the user's groups and by the groups they own.
+    #[serde(default)]
+    pub role_permissions: HashSet<RolePermission>,
 }
 
 impl ValidationResults {
@@ -21,12 +137,22 @@ impl ValidationResults {
         Self {
             user: UserId::new("admin"),
             permission: Permission::Admin,
+            api_token_scope: None,
+            role_permissions: HashSet::new(),
         }
     }
 
+    fn has_full_scope(&self) -> bool {
+        matches!(self.api_token_scope, None | Some(ApiTokenScope::Full))
+    }
+
     #[must_use]
     pub fn is_admin(&self) -> bool {
-        self.permission == Permission::Admin
+        self.permission == Permission::Admin && self.has_full_scope()
+    }
+
+    fn has_role_permission(&self, permission: &RolePermission) -> bool {
+        self.role_permissions.contains(permission)
     }
 
     #[must_use]
@@ -34,25 +160,95 @@ impl ValidationResults {
         self.permission == Permission::Admin
             || self.permission == Permission::Readonly
             || self.permission == Permission::PasswordManager
+            || self.has_role_permission(&RolePermission::ReadAll)
+            || self.has_role_permission(&RolePermission::ResetPasswords)
     }
 
     #[must_use]
     pub fn can_read(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin
-            || self.permission == Permission::PasswordManager
-            || self.permission == Permission::Readonly
-            || &self.user == user
+        self.can_read_all() || &self.user == user
+    }
+
+    /// Whether the list of all the users can be fetched, even though the users' groups can't all
+    /// be read.
+    #[must_use]
+    pub fn can_list_all_users(&self) -> bool {
+        self.can_read_all()
+            || self
+                .role_permissions
+                .iter()
+                .any(|p| matches!(p, RolePermission::ManageGroupMembers(_)))
+    }
+
+    /// The IDs of the groups whose members can be read, on top of the user themselves. Irrelevant
+    /// if `can_read_all` is true.
+    #[must_use]
+    pub fn readable_group_ids(&self) -> Vec<i32> {
+        let mut group_ids: Vec<i32> = self
+            .role_permissions
+            .iter()
+            .filter_map(|p| match p {
+                RolePermission::ReadGroupMembers(group_id)
+                | RolePermission::ManageGroupMembers(group_id) => Some(*group_id),
+                _ => None,
+            })
+            .collect();
+        group_ids.sort_unstable();
+        group_ids.dedup();
+        group_ids
     }
 
     #[must_use]
     pub fn can_change_password(&self, user: &UserId, user_is_admin: bool) -> bool {
-        self.permission == Permission::Admin
-            || (self.permission == Permission::PasswordManager && !user_is_admin)
-            || &self.user == user
+        self.has_full_scope()
+            && (self.permission == Permission::Admin
+                || ((self.permission == Permission::PasswordManager
+                    || self.has_role_permission(&RolePermission::ResetPasswords))
+                    && !user_is_admin)
+                || &self.user == user)
+    }
+
+    /// Whether the given attribute of another, non-admin user can be edited.
+    #[must_use]
+    pub fn can_edit_user_attribute(&self, attribute: &str) -> bool {
+        self.has_full_scope()
+            && (self.permission == Permission::Admin
+                || self.has_role_permission(&RolePermission::EditUserAttribute(
+                    attribute.to_ascii_lowercase(),
+                )))
+    }
+
+    #[must_use]
+    pub fn can_create_users(&self) -> bool {
+        self.is_admin()
+            || (self.has_full_scope() && self.has_role_permission(&RolePermission::CreateUsers))
+    }
+
+    /// Whether non-admin users can be deleted.
+    #[must_use]
+    pub fn can_delete_users(&self) -> bool {
+        self.is_admin()
+            || (self.has_full_scope() && self.has_role_permission(&RolePermission::DeleteUsers))
     }
 
     #[must_use]
     pub fn can_write(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin || &self.user == user
+        self.has_full_scope() && (self.permission == Permission::Admin || &self.user == user)
+    }
+
+    #[must_use]
+    pub fn can_manage_group_memberships(&self) -> bool {
+        (self.permission == Permission::Admin
+            || self.has_role_permission(&RolePermission::ManageGroupMemberships))
+            && self.api_token_scope != Some(ApiTokenScope::Readonly)
+    }
+
+    /// Whether users can be added to and removed from the given group. The caller must still
+    /// check that the group doesn't grant permissions, unless the user is an admin.
+    #[must_use]
+    pub fn can_manage_group_members(&self, group_id: i32) -> bool {
+        self.can_manage_group_memberships()
+            || (self.has_role_permission(&RolePermission::ManageGroupMembers(group_id))
+                && self.api_token_scope != Some(ApiTokenScope::Readonly))
     }
 }
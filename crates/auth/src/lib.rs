@@ -42,6 +42,9 @@ pub mod login {
     pub struct ClientSimpleLoginRequest {
         pub username: UserId,
         pub password: String,
+        /// TOTP or recovery code, required if the user enrolled a second factor.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub totp_code: Option<String>,
     }
 
     impl fmt::Debug for ClientSimpleLoginRequest {
@@ -49,6 +52,7 @@ pub mod login {
             f.debug_struct("ClientSimpleLoginRequest")
                 .field("username", &self.username.as_str())
                 .field("password", &"***********")
+                .field("totp_code", &self.totp_code.as_ref().map(|_| "******"))
                 .finish()
         }
     }
@@ -59,6 +63,31 @@ pub mod login {
         #[serde(rename = "refreshToken", skip_serializing_if = "Option::is_none")]
         pub refresh_token: Option<String>,
     }
+
+    /// Sent instead of a [`ServerLoginResponse`] when the password was correct but the user
+    /// enrolled a second factor.
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerMfaRequiredResponse {
+        /// Short-lived token to be passed back with the code.
+        #[serde(rename = "mfaToken")]
+        pub mfa_token: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    #[serde(untagged)]
+    pub enum ServerLoginFinishResponse {
+        LoggedIn(ServerLoginResponse),
+        MfaRequired(ServerMfaRequiredResponse),
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientTotpLoginRequest {
+        /// Token from the [`ServerMfaRequiredResponse`].
+        #[serde(rename = "mfaToken")]
+        pub mfa_token: String,
+        /// TOTP or recovery code.
+        pub code: String,
+    }
 }
 
 /// The messages for the 3-step OPAQUE registration process.
@@ -105,6 +134,69 @@ pub mod password_reset {
     }
 }
 
+/// The messages to enroll a TOTP second factor.
+pub mod mfa {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerTotpEnrollStartResponse {
+        /// Base32-encoded secret, to be passed back to the server with the first code.
+        pub secret: String,
+        /// `otpauth://` URI, to be displayed as a QR code.
+        pub uri: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientTotpEnrollFinishRequest {
+        /// Secret from the [`ServerTotpEnrollStartResponse`].
+        pub secret: String,
+        /// Current code, to prove that the authenticator app was set up.
+        pub code: String,
+    }
+
+    /// To reset one's own second factor, either the password or a current code (or a recovery
+    /// code) is required. Administrators resetting another user's second factor send neither.
+    #[derive(Serialize, Deserialize, Clone, Default)]
+    pub struct ClientTotpResetRequest {
+        #[serde(default)]
+        pub password: Option<String>,
+        #[serde(default)]
+        pub code: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerTotpEnrollFinishResponse {
+        /// Single-use codes to log in if the authenticator app is lost.
+        #[serde(rename = "recoveryCodes")]
+        pub recovery_codes: Vec<String>,
+    }
+}
+
+/// The messages for the consent page of the OpenID Connect provider.
+pub mod oidc {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerAuthorizationRequestResponse {
+        #[serde(rename = "clientName")]
+        pub client_name: String,
+        /// The scopes the client will get, if approved.
+        pub scopes: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientAuthorizationDecisionRequest {
+        pub approve: bool,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerAuthorizationDecisionResponse {
+        /// Where to send the browser back to, with either the code or an error.
+        #[serde(rename = "redirectUri")]
+        pub redirect_uri: String,
+    }
+}
+
 pub mod types {
     use serde::{Deserialize, Serialize};
 
@@ -206,9 +298,42 @@ pub mod types {
     }
 }
 
+/// Dates in JWT claims are serialized as a number of seconds since the epoch, as required by the
+/// standard. Tokens issued by older versions used RFC 3339 strings, which are still accepted.
+mod numeric_date {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumericOrStringDate {
+        Numeric(i64),
+        String(DateTime<Utc>),
+    }
+
+    pub fn serialize<S: Serializer>(
+        date: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(date.timestamp())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        match NumericOrStringDate::deserialize(deserializer)? {
+            NumericOrStringDate::Numeric(timestamp) => DateTime::from_timestamp(timestamp, 0)
+                .ok_or_else(|| serde::de::Error::custom("Invalid timestamp")),
+            NumericOrStringDate::String(date) => Ok(date),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct JWTClaims {
+    #[serde(with = "numeric_date")]
     pub exp: DateTime<Utc>,
+    #[serde(with = "numeric_date")]
     pub iat: DateTime<Utc>,
     pub user: String,
     pub groups: HashSet<String>,
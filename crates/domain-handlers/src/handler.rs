@@ -1,5 +1,7 @@
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use ldap3_proto::proto::LdapSubstringFilter;
+use lldap_auth::access_control::{ApiTokenScope, RolePermission};
 use lldap_domain::{
     requests::{
         CreateAttributeRequest, CreateGroupRequest, CreateUserRequest, UpdateGroupRequest,
@@ -7,13 +9,14 @@ use lldap_domain::{
     },
     schema::Schema,
     types::{
-        AttributeName, AttributeValue, Group, GroupDetails, GroupId, GroupName, LdapObjectClass,
-        User, UserAndGroups, UserId, Uuid,
+        Attribute, AttributeName, AttributeValue, Cardinality, Group, GroupDetails, GroupId,
+        GroupName, LdapObjectClass, User, UserAndGroups, UserId, Uuid,
     },
 };
 use lldap_domain_model::{error::Result, model::UserColumn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct BindRequest {
@@ -84,7 +87,19 @@ pub enum UserRequestFilter {
     MemberOf(GroupName),
     // Same, by id.
     MemberOfId(GroupId),
+    // Check if a user belongs to a group, directly or through its nested groups.
+    TransitiveMemberOf(GroupName),
     CustomAttributePresent(AttributeName),
+    // The user is disabled.
+    Disabled,
+    // The user has an expiry date, whether it's passed or not.
+    HasExpiryDate,
+    // The date column is set, and on or after the given date.
+    DateGreaterOrEqual(UserColumn, NaiveDateTime),
+    // The date column is set, and on or before the given date.
+    DateLessOrEqual(UserColumn, NaiveDateTime),
+    // The (nullable) column is set.
+    HasValue(UserColumn),
 }
 
 impl From<bool> for UserRequestFilter {
@@ -116,11 +131,273 @@ impl From<bool> for GroupRequestFilter {
     }
 }
 
+/// Prefix of the API token secrets, to tell them apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "lldap_";
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub token_id: i32,
+    pub user_id: UserId,
+    pub name: String,
+    pub scope: ApiTokenScope,
+    pub creation_date: NaiveDateTime,
+    pub expiry_date: Option<NaiveDateTime>,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct CreateApiTokenRequest {
+    pub user_id: UserId,
+    pub name: String,
+    pub scope: ApiTokenScope,
+    pub expiry_date: Option<NaiveDateTime>,
+}
+
+/// A login session, backed by a refresh token.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct Session {
+    /// Hash of the refresh token.
+    pub session_id: i64,
+    pub user_id: UserId,
+    pub creation_date: NaiveDateTime,
+    pub expiry_date: NaiveDateTime,
+    /// Where the user logged in from, if known.
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// A named set of permissions, granted to the members of its groups.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Vec<RolePermission>,
+    pub group_ids: Vec<GroupId>,
+}
+
+/// Where a change or a login attempt came from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum AuditSource {
+    Ldap,
+    GraphQl,
+    /// The login and password endpoints of the web server.
+    Http,
+    /// The server itself, e.g. when creating the admin user on startup.
+    Cli,
+}
+
+impl AuditSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditSource::Ldap => "ldap",
+            AuditSource::GraphQl => "graphql",
+            AuditSource::Http => "http",
+            AuditSource::Cli => "cli",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ldap" => Ok(AuditSource::Ldap),
+            "graphql" => Ok(AuditSource::GraphQl),
+            "http" => Ok(AuditSource::Http),
+            "cli" => Ok(AuditSource::Cli),
+            _ => Err(format!("Unknown audit source: {s}")),
+        }
+    }
+}
+
+/// The kind of a deleted entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DeletedEntryKind {
+    User,
+    Group,
+}
+
+impl DeletedEntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeletedEntryKind::User => "user",
+            DeletedEntryKind::Group => "group",
+        }
+    }
+}
+
+impl std::str::FromStr for DeletedEntryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(DeletedEntryKind::User),
+            "group" => Ok(DeletedEntryKind::Group),
+            _ => Err(format!("Unknown deleted entry kind: {s}")),
+        }
+    }
+}
+
+/// A user or group that was deleted, kept so that LDAP replicas can catch up with the deletion.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct DeletedEntry {
+    pub uuid: Uuid,
+    pub kind: DeletedEntryKind,
+    /// The user ID or the group display name, to rebuild the DN.
+    pub name: String,
+    pub deletion_date: NaiveDateTime,
+}
+
+/// An entry of the audit log.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEvent {
+    pub event_id: i32,
+    pub date: NaiveDateTime,
+    /// Who made the change or tried to log in. It's not necessarily an existing user.
+    pub actor: Option<String>,
+    pub source: AuditSource,
+    pub ip_address: Option<String>,
+    /// What the operation was about, e.g. `user:john` or `group:3`.
+    pub target: Option<String>,
+    pub operation: String,
+    pub success: bool,
+    /// A JSON object with the details of the operation, e.g. the old and new values of the
+    /// changed attributes.
+    pub details: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct CreateAuditEventRequest {
+    pub actor: Option<String>,
+    pub source: AuditSource,
+    pub ip_address: Option<String>,
+    pub target: Option<String>,
+    pub operation: String,
+    pub success: bool,
+    pub details: Option<String>,
+}
+
+/// Restricts the listed audit events. All the conditions must match.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AuditEventFilter {
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub operation: Option<String>,
+    pub source: Option<AuditSource>,
+    pub success: Option<bool>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+fn audit_value(value: &AttributeValue) -> serde_json::Value {
+    fn to_json<T: Clone, F: Fn(&T) -> serde_json::Value>(
+        values: &Cardinality<T>,
+        f: F,
+    ) -> serde_json::Value {
+        match values {
+            Cardinality::Singleton(v) => f(v),
+            Cardinality::Unbounded(l) => l.iter().map(f).collect(),
+        }
+    }
+    match value {
+        AttributeValue::String(s) => to_json(s, |v| v.as_str().into()),
+        AttributeValue::Integer(i) => to_json(i, |v| (*v).into()),
+        // The photos are too big for the log, only their size is kept.
+        AttributeValue::JpegPhoto(p) => {
+            to_json(p, |v| format!("<jpeg photo, {} bytes>", v.len()).into())
+        }
+        AttributeValue::DateTime(d) => to_json(d, |v| v.and_utc().to_rfc3339().into()),
+    }
+}
+
+fn audit_attribute_values(attributes: &[Attribute]) -> BTreeMap<String, serde_json::Value> {
+    attributes
+        .iter()
+        .map(|a| (a.name.to_string(), audit_value(&a.value)))
+        .collect()
+}
+
+/// The values of the user that are tracked in the audit log, by name.
+pub fn user_audit_values(user: &User) -> BTreeMap<String, serde_json::Value> {
+    let mut values = audit_attribute_values(&user.attributes);
+    values.insert("mail".to_owned(), user.email.as_str().into());
+    if let Some(display_name) = &user.display_name {
+        values.insert("display_name".to_owned(), display_name.as_str().into());
+    }
+    values.insert("disabled".to_owned(), user.disabled.into());
+    if let Some(expiry_date) = &user.expiry_date {
+        values.insert(
+            "expiry_date".to_owned(),
+            expiry_date.and_utc().to_rfc3339().into(),
+        );
+    }
+    values
+}
+
+/// The values of the group that are tracked in the audit log, by name.
+pub fn group_audit_values(group: &GroupDetails) -> BTreeMap<String, serde_json::Value> {
+    let mut values = audit_attribute_values(&group.attributes);
+    values.insert(
+        "display_name".to_owned(),
+        group.display_name.as_str().into(),
+    );
+    values
+}
+
+/// Describes the values that changed, as a JSON object mapping each of them to its old and new
+/// versions, `null` meaning unset. Returns `None` if nothing changed.
+pub fn audit_diff(
+    before: &BTreeMap<String, serde_json::Value>,
+    after: &BTreeMap<String, serde_json::Value>,
+) -> Option<String> {
+    let diff: serde_json::Map<_, _> = before
+        .keys()
+        .chain(after.keys())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| {
+            (
+                name.clone(),
+                serde_json::json!({"before": before.get(name), "after": after.get(name)}),
+            )
+        })
+        .collect();
+    (!diff.is_empty()).then(|| serde_json::Value::Object(diff).to_string())
+}
+
 #[async_trait]
 pub trait LoginHandler: Send + Sync {
     async fn bind(&self, request: BindRequest) -> Result<()>;
 }
 
+/// What failed login attempts are counted against.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum LoginThrottleSubject {
+    User(UserId),
+    Ip(IpAddr),
+}
+
+impl std::fmt::Display for LoginThrottleSubject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginThrottleSubject::User(user_id) => write!(f, "user:{}", user_id),
+            LoginThrottleSubject::Ip(ip) => write!(f, "ip:{}", ip),
+        }
+    }
+}
+
+#[async_trait]
+pub trait LoginThrottleBackendHandler: Send + Sync {
+    /// Returns the end of the current lockout of the subject, if any.
+    async fn get_login_lockout(
+        &self,
+        subject: &LoginThrottleSubject,
+    ) -> Result<Option<NaiveDateTime>>;
+    /// Counts a failed attempt, and locks the subject out if there were too many of them.
+    async fn register_login_failure(&self, subject: &LoginThrottleSubject) -> Result<()>;
+    async fn clear_login_failures(&self, subject: &LoginThrottleSubject) -> Result<()>;
+}
+
 #[async_trait]
 pub trait GroupListerBackendHandler: ReadSchemaBackendHandler {
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
@@ -132,10 +409,46 @@ pub trait GroupBackendHandler: ReadSchemaBackendHandler {
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+    /// Makes the child group a member of the parent group. Fails if it would create a cycle.
+    async fn add_group_to_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()>;
+    async fn remove_group_from_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()>;
+    /// The groups the given group is nested in, directly or through other groups.
+    async fn get_ancestor_groups(&self, group_id: GroupId) -> Result<Vec<GroupDetails>>;
+    /// The filter that computes the members of a dynamic group, `None` for a static group.
+    async fn get_group_filter(&self, group_id: GroupId) -> Result<Option<UserRequestFilter>>;
+    /// Turns the group into a dynamic group, whose members are the users matching the filter,
+    /// replacing its current members. `None` turns it back into an (empty) static group.
+    async fn set_group_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()>;
+    /// The members of the group whose membership expires in the future. The expired memberships
+    /// are left out, like everywhere else, even before they are purged.
+    async fn list_membership_expiry_dates(
+        &self,
+        group_id: GroupId,
+    ) -> Result<Vec<(UserId, NaiveDateTime)>>;
+    /// The memberships that expired since the given date and are not purged yet, with the name
+    /// of their group: the user and the group changed, but their modification date didn't.
+    async fn list_expired_memberships(
+        &self,
+        since: NaiveDateTime,
+    ) -> Result<Vec<(UserId, GroupName)>>;
 }
 
 #[async_trait]
 pub trait UserListerBackendHandler: ReadSchemaBackendHandler {
+    /// With `get_groups`, the groups of the users also include the groups they belong to
+    /// through nested groups.
     async fn list_users(
         &self,
         filters: Option<UserRequestFilter>,
@@ -149,11 +462,97 @@ pub trait UserBackendHandler: ReadSchemaBackendHandler {
     async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
     async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+    /// Changes the ID of the user, and logs them out: returns the hashes of their JWTs, which
+    /// are now blacklisted.
+    async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<HashSet<u64>>;
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    /// Adds and removes members of the group in a single transaction: either all the changes
+    /// are applied, or none of them.
+    async fn update_group_members(
+        &self,
+        group_id: GroupId,
+        added: &[UserId],
+        removed: &[UserId],
+    ) -> Result<()>;
+    /// Makes an existing membership expire at the given date, or never with `None`. The expired
+    /// memberships are ignored, and eventually purged.
+    async fn set_membership_expiry_date(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<NaiveDateTime>,
+    ) -> Result<()>;
+    /// The groups of the user, including the dynamic groups whose filter matches them and the
+    /// ones they belong to through nested groups, and excluding the expired memberships.
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
 }
 
+#[async_trait]
+pub trait ApiTokenBackendHandler {
+    async fn list_api_tokens(&self, user_id: &UserId) -> Result<Vec<ApiToken>>;
+    /// Returns the new token along with its secret. Only a hash of the secret is stored, so it
+    /// cannot be retrieved later.
+    async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+    /// Finds the token matching the secret, if it exists and hasn't expired.
+    async fn get_api_token_from_secret(&self, secret: &str) -> Result<Option<ApiToken>>;
+    async fn delete_api_token(&self, user_id: &UserId, token_id: i32) -> Result<()>;
+}
+
+#[async_trait]
+pub trait SessionBackendHandler {
+    /// The sessions of the user that haven't expired yet, most recent first.
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+    /// Deletes the session, and blacklists the JWTs that were issued for it. Returns the hashes
+    /// of the newly blacklisted JWTs.
+    async fn revoke_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+    /// Same, for all the sessions of the user.
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+}
+
+#[async_trait]
+pub trait RoleBackendHandler {
+    async fn list_roles(&self) -> Result<Vec<Role>>;
+    /// Creates the role, or replaces its permissions if it already exists.
+    async fn set_role(&self, name: &str, permissions: Vec<RolePermission>) -> Result<()>;
+    async fn delete_role(&self, name: &str) -> Result<()>;
+    async fn add_role_to_group(&self, name: &str, group_id: GroupId) -> Result<()>;
+    async fn remove_role_from_group(&self, name: &str, group_id: GroupId) -> Result<()>;
+    /// The permissions granted by the roles attached to any of the groups.
+    async fn get_role_permissions(
+        &self,
+        group_names: &[GroupName],
+    ) -> Result<HashSet<RolePermission>>;
+}
+
+#[async_trait]
+pub trait GroupOwnerBackendHandler {
+    async fn list_group_owners(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+    /// The groups whose members the user can manage.
+    async fn get_owned_groups(&self, user_id: &UserId) -> Result<Vec<GroupId>>;
+    async fn add_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    async fn remove_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+}
+
+#[async_trait]
+pub trait AuditLogBackendHandler {
+    /// Appends an event to the audit log. Events are never modified afterwards.
+    async fn record_audit_event(&self, request: CreateAuditEventRequest) -> Result<()>;
+    /// The matching events, most recent first.
+    async fn list_audit_events(
+        &self,
+        filter: AuditEventFilter,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<AuditEvent>>;
+}
+
+#[async_trait]
+pub trait DeletedEntryBackendHandler {
+    /// The users and groups deleted at or after the given date, oldest first.
+    async fn list_deleted_entries(&self, since: NaiveDateTime) -> Result<Vec<DeletedEntry>>;
+}
+
 #[async_trait]
 pub trait ReadSchemaBackendHandler {
     async fn get_schema(&self) -> Result<Schema>;
@@ -183,6 +582,13 @@ pub trait BackendHandler:
     + GroupListerBackendHandler
     + ReadSchemaBackendHandler
     + SchemaBackendHandler
+    + ApiTokenBackendHandler
+    + LoginThrottleBackendHandler
+    + SessionBackendHandler
+    + RoleBackendHandler
+    + GroupOwnerBackendHandler
+    + AuditLogBackendHandler
+    + DeletedEntryBackendHandler
 {
 }
 
@@ -191,7 +597,7 @@ mod tests {
     use super::*;
     use base64::Engine;
     use lldap_domain::types::JpegPhoto;
-    use pretty_assertions::assert_ne;
+    use pretty_assertions::{assert_eq, assert_ne};
 
     #[test]
     fn test_uuid_time() {
@@ -219,4 +625,25 @@ mod tests {
             .unwrap();
         JpegPhoto::try_from(base64_jpeg).unwrap();
     }
+
+    #[test]
+    fn test_audit_diff() {
+        let before = BTreeMap::from([
+            ("mail".to_owned(), serde_json::json!("bob@example.com")),
+            ("first_name".to_owned(), serde_json::json!("Bob")),
+        ]);
+        let after = BTreeMap::from([
+            ("mail".to_owned(), serde_json::json!("bob@example.com")),
+            ("last_name".to_owned(), serde_json::json!("Bobberson")),
+        ]);
+        assert_eq!(audit_diff(&before, &before), None);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&audit_diff(&before, &after).unwrap())
+                .unwrap(),
+            serde_json::json!({
+                "first_name": {"before": "Bob", "after": null},
+                "last_name": {"before": null, "after": "Bobberson"},
+            })
+        );
+    }
 }
@@ -0,0 +1,569 @@
+//! The stored form of the user filters, e.g. the filters of the dynamic groups.
+//!
+//! It's an LDAP filter (RFC 4515) on the names of the user fields and attributes, e.g.
+//! `(&(department=Sales)(!(memberOf=contractors)))`, so that it doesn't depend on the layout of
+//! `UserRequestFilter`.
+
+use crate::handler::{SubStringFilter, UserRequestFilter};
+use chrono::NaiveDateTime;
+use lldap_domain::{
+    deserialize::deserialize_attribute_value,
+    schema::AttributeList,
+    types::{AttributeName, AttributeValue, Cardinality, GroupId, GroupName},
+};
+use lldap_domain_model::model::UserColumn;
+
+/// `LDAP_MATCHING_RULE_IN_CHAIN`, for the transitive group memberships.
+const IN_CHAIN_RULE: &str = "1.2.840.113556.1.4.1941";
+
+fn column_name(column: UserColumn) -> &'static str {
+    match column {
+        UserColumn::UserId => "user_id",
+        UserColumn::Email => "email",
+        UserColumn::LowercaseEmail => "lowercase_email",
+        UserColumn::DisplayName => "display_name",
+        UserColumn::CreationDate => "creation_date",
+        UserColumn::PasswordHash => "password_hash",
+        UserColumn::TotpSecret => "totp_secret",
+        UserColumn::MfaType => "mfa_type",
+        UserColumn::TotpLastCounter => "totp_last_counter",
+        UserColumn::Uuid => "uuid",
+        UserColumn::Disabled => "disabled",
+        UserColumn::ExpiryDate => "expiry_date",
+        UserColumn::LastLogin => "last_login",
+        UserColumn::PasswordChanged => "password_changed",
+        UserColumn::ModifiedDate => "modified_date",
+    }
+}
+
+fn parse_column(name: &str) -> Option<UserColumn> {
+    Some(match name {
+        "user_id" => UserColumn::UserId,
+        "email" => UserColumn::Email,
+        "lowercase_email" => UserColumn::LowercaseEmail,
+        "display_name" => UserColumn::DisplayName,
+        "creation_date" => UserColumn::CreationDate,
+        "password_hash" => UserColumn::PasswordHash,
+        "totp_secret" => UserColumn::TotpSecret,
+        "mfa_type" => UserColumn::MfaType,
+        "totp_last_counter" => UserColumn::TotpLastCounter,
+        "uuid" => UserColumn::Uuid,
+        "disabled" => UserColumn::Disabled,
+        "expiry_date" => UserColumn::ExpiryDate,
+        "last_login" => UserColumn::LastLogin,
+        "password_changed" => UserColumn::PasswordChanged,
+        "modified_date" => UserColumn::ModifiedDate,
+        _ => return None,
+    })
+}
+
+fn format_date(date: &NaiveDateTime) -> String {
+    date.and_utc().to_rfc3339()
+}
+
+fn parse_date(value: &str) -> Result<NaiveDateTime, String> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|d| d.naive_utc())
+        .map_err(|e| format!("Invalid date `{}`: {}", value, e))
+}
+
+fn attribute_value_strings(value: &AttributeValue) -> Vec<String> {
+    fn to_strings<T: Clone>(values: &Cardinality<T>, f: impl Fn(&T) -> String) -> Vec<String> {
+        match values {
+            Cardinality::Singleton(v) => vec![f(v)],
+            Cardinality::Unbounded(vs) => vs.iter().map(f).collect(),
+        }
+    }
+    match value {
+        AttributeValue::String(v) => to_strings(v, String::clone),
+        AttributeValue::Integer(v) => to_strings(v, i64::to_string),
+        AttributeValue::JpegPhoto(v) => to_strings(v, |photo| photo.into()),
+        AttributeValue::DateTime(v) => to_strings(v, format_date),
+    }
+}
+
+fn write_value(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '*' => out.push_str("\\2a"),
+            '(' => out.push_str("\\28"),
+            ')' => out.push_str("\\29"),
+            '\\' => out.push_str("\\5c"),
+            '\0' => out.push_str("\\00"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_equality(name: &str, value: &str, out: &mut String) {
+    out.push_str(name);
+    out.push('=');
+    write_value(value, out);
+}
+
+fn write_presence(name: &str, out: &mut String) {
+    out.push_str(name);
+    out.push_str("=*");
+}
+
+fn write_substring(name: &str, filter: &SubStringFilter, out: &mut String) {
+    out.push_str(name);
+    out.push('=');
+    if let Some(initial) = &filter.initial {
+        write_value(initial, out);
+    }
+    out.push('*');
+    for any in &filter.any {
+        write_value(any, out);
+        out.push('*');
+    }
+    if let Some(final_) = &filter.final_ {
+        write_value(final_, out);
+    }
+}
+
+fn write_filter(filter: &UserRequestFilter, out: &mut String) {
+    use UserRequestFilter::*;
+    out.push('(');
+    match filter {
+        True => out.push('&'),
+        False => out.push('|'),
+        And(fs) => {
+            out.push('&');
+            fs.iter().for_each(|f| write_filter(f, out));
+        }
+        Or(fs) => {
+            out.push('|');
+            fs.iter().for_each(|f| write_filter(f, out));
+        }
+        Not(f) => {
+            out.push('!');
+            write_filter(f, out);
+        }
+        UserId(user_id) => write_equality("user_id", user_id.as_str(), out),
+        UserIdSubString(s) => write_substring("user_id", s, out),
+        Equality(column, value) => write_equality(column_name(*column), value, out),
+        AttributeEquality(name, value) => match attribute_value_strings(value).as_slice() {
+            [value] => write_equality(name.as_str(), value, out),
+            values => {
+                // A list value: the user must have all of them.
+                out.push('&');
+                for value in values {
+                    out.push('(');
+                    write_equality(name.as_str(), value, out);
+                    out.push(')');
+                }
+            }
+        },
+        SubString(column, s) => write_substring(column_name(*column), s, out),
+        MemberOf(group) => write_equality("memberOf", group.as_str(), out),
+        MemberOfId(group_id) => write_equality("memberOfId", &group_id.0.to_string(), out),
+        TransitiveMemberOf(group) => {
+            out.push_str("memberOf:");
+            out.push_str(IN_CHAIN_RULE);
+            out.push_str(":=");
+            write_value(group.as_str(), out);
+        }
+        CustomAttributePresent(name) => write_presence(name.as_str(), out),
+        Disabled => write_equality("disabled", "TRUE", out),
+        HasExpiryDate => write_presence("expiry_date", out),
+        DateGreaterOrEqual(column, date) => {
+            out.push_str(column_name(*column));
+            out.push_str(">=");
+            write_value(&format_date(date), out);
+        }
+        DateLessOrEqual(column, date) => {
+            out.push_str(column_name(*column));
+            out.push_str("<=");
+            write_value(&format_date(date), out);
+        }
+        HasValue(column) => write_presence(column_name(*column), out),
+    }
+    out.push(')');
+}
+
+/// Serializes the filter to its stored form.
+pub fn serialize_user_filter(filter: &UserRequestFilter) -> String {
+    let mut out = String::new();
+    write_filter(filter, &mut out);
+    out
+}
+
+/// Parses the stored form of a filter, resolving the attribute values with the user schema.
+pub fn parse_user_filter(
+    filter: &str,
+    user_attributes: &AttributeList,
+) -> Result<UserRequestFilter, String> {
+    let mut parser = Parser {
+        input: filter,
+        pos: 0,
+        user_attributes,
+    };
+    let parsed = parser.filter()?;
+    if parser.pos != filter.len() {
+        return Err(format!(
+            "Unexpected characters at position {} of the filter `{}`",
+            parser.pos, filter
+        ));
+    }
+    Ok(parsed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    GreaterOrEqual,
+    LessOrEqual,
+    InChain,
+}
+
+#[derive(Debug)]
+enum Value {
+    Present,
+    Exact(String),
+    SubString(SubStringFilter),
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    user_attributes: &'a AttributeList,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!(
+                "Expected `{}` at position {} of the filter `{}`",
+                c as char, self.pos, self.input
+            ));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn filter(&mut self) -> Result<UserRequestFilter, String> {
+        self.expect(b'(')?;
+        let filter = match self.peek() {
+            Some(b'&') => {
+                self.pos += 1;
+                let filters = self.filter_list()?;
+                if filters.is_empty() {
+                    UserRequestFilter::True
+                } else {
+                    UserRequestFilter::And(filters)
+                }
+            }
+            Some(b'|') => {
+                self.pos += 1;
+                let filters = self.filter_list()?;
+                if filters.is_empty() {
+                    UserRequestFilter::False
+                } else {
+                    UserRequestFilter::Or(filters)
+                }
+            }
+            Some(b'!') => {
+                self.pos += 1;
+                UserRequestFilter::Not(Box::new(self.filter()?))
+            }
+            _ => self.item()?,
+        };
+        self.expect(b')')?;
+        Ok(filter)
+    }
+
+    fn filter_list(&mut self) -> Result<Vec<UserRequestFilter>, String> {
+        let mut filters = Vec::new();
+        while self.peek() == Some(b'(') {
+            filters.push(self.filter()?);
+        }
+        Ok(filters)
+    }
+
+    fn take_until(&mut self, delimiters: &[u8]) -> &'a str {
+        let input = self.input;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !delimiters.contains(&c)) {
+            self.pos += 1;
+        }
+        &input[start..self.pos]
+    }
+
+    fn item(&mut self) -> Result<UserRequestFilter, String> {
+        let name = self.take_until(b"=<>:()").to_ascii_lowercase();
+        let operator = match self.peek() {
+            Some(b'=') => Operator::Equal,
+            Some(b'>') => {
+                self.pos += 1;
+                Operator::GreaterOrEqual
+            }
+            Some(b'<') => {
+                self.pos += 1;
+                Operator::LessOrEqual
+            }
+            Some(b':') => {
+                self.pos += 1;
+                let rule = self.take_until(b":()");
+                if rule != IN_CHAIN_RULE {
+                    return Err(format!("Unsupported matching rule `{}`", rule));
+                }
+                self.expect(b':')?;
+                Operator::InChain
+            }
+            _ => {
+                return Err(format!(
+                    "Expected an operator at position {} of the filter `{}`",
+                    self.pos, self.input
+                ));
+            }
+        };
+        self.expect(b'=')?;
+        let value = self.value()?;
+        self.make_item(&name, operator, value)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let mut parts = vec![Vec::new()];
+        while let Some(c) = self.peek() {
+            match c {
+                b')' => break,
+                b'(' => {
+                    return Err(format!(
+                        "Unescaped `(` at position {} of the filter `{}`",
+                        self.pos, self.input
+                    ));
+                }
+                b'*' => parts.push(Vec::new()),
+                b'\\' => {
+                    let byte = self
+                        .input
+                        .get(self.pos + 1..self.pos + 3)
+                        .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        .ok_or_else(|| {
+                            format!(
+                                "Invalid escape at position {} of the filter `{}`",
+                                self.pos, self.input
+                            )
+                        })?;
+                    parts.last_mut().unwrap().push(byte);
+                    self.pos += 2;
+                }
+                c => parts.last_mut().unwrap().push(c),
+            }
+            self.pos += 1;
+        }
+        let mut parts = parts
+            .into_iter()
+            .map(|part| {
+                String::from_utf8(part)
+                    .map_err(|_| format!("Invalid UTF-8 value in the filter `{}`", self.input))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let non_empty = |s: String| Some(s).filter(|s| !s.is_empty());
+        Ok(match parts.len() {
+            1 => Value::Exact(parts.remove(0)),
+            2 if parts.iter().all(String::is_empty) => Value::Present,
+            _ => {
+                let final_ = non_empty(parts.pop().unwrap_or_default());
+                let initial = non_empty(parts.remove(0));
+                Value::SubString(SubStringFilter {
+                    initial,
+                    any: parts.into_iter().filter_map(non_empty).collect(),
+                    final_,
+                })
+            }
+        })
+    }
+
+    fn make_item(
+        &self,
+        name: &str,
+        operator: Operator,
+        value: Value,
+    ) -> Result<UserRequestFilter, String> {
+        use UserRequestFilter::*;
+        let invalid = || format!("Invalid filter on `{}` in `{}`", name, self.input);
+        Ok(match (name, operator, value) {
+            ("memberof", Operator::Equal, Value::Exact(group)) => MemberOf(GroupName::from(group)),
+            ("memberof", Operator::InChain, Value::Exact(group)) => {
+                TransitiveMemberOf(GroupName::from(group))
+            }
+            ("memberofid", Operator::Equal, Value::Exact(group_id)) => {
+                MemberOfId(GroupId(group_id.parse().map_err(|_| invalid())?))
+            }
+            ("user_id", Operator::Equal, Value::Exact(user_id)) => {
+                UserId(lldap_domain::types::UserId::new(&user_id))
+            }
+            ("user_id", Operator::Equal, Value::SubString(s)) => UserIdSubString(s),
+            ("disabled", Operator::Equal, Value::Exact(value)) if value == "TRUE" => Disabled,
+            (_, Operator::InChain, _) => return Err(invalid()),
+            (name, operator, value) => match parse_column(name) {
+                Some(column) => match (operator, value) {
+                    (Operator::Equal, Value::Present) => HasValue(column),
+                    (Operator::Equal, Value::Exact(value)) => Equality(column, value),
+                    (Operator::Equal, Value::SubString(s)) => SubString(column, s),
+                    (Operator::GreaterOrEqual, Value::Exact(value)) => {
+                        DateGreaterOrEqual(column, parse_date(&value)?)
+                    }
+                    (Operator::LessOrEqual, Value::Exact(value)) => {
+                        DateLessOrEqual(column, parse_date(&value)?)
+                    }
+                    _ => return Err(invalid()),
+                },
+                None => {
+                    let name = AttributeName::from(name);
+                    let (typ, _) = self
+                        .user_attributes
+                        .get_attribute_type(&name)
+                        .ok_or_else(|| format!("Unknown user attribute `{}`", name))?;
+                    match (operator, value) {
+                        (Operator::Equal, Value::Present) => CustomAttributePresent(name),
+                        (Operator::Equal, Value::Exact(value)) => {
+                            let value = deserialize_attribute_value(&[value], typ, false)
+                                .map_err(|e| format!("Invalid value for `{}`: {:#}", name, e))?;
+                            AttributeEquality(name, value)
+                        }
+                        _ => return Err(invalid()),
+                    }
+                }
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use lldap_domain::{schema::AttributeSchema, types::AttributeType};
+    use pretty_assertions::assert_eq;
+
+    fn make_attributes() -> AttributeList {
+        let make_attribute = |name: &str, attribute_type| AttributeSchema {
+            name: name.into(),
+            attribute_type,
+            is_list: false,
+            is_visible: true,
+            is_editable: true,
+            is_hardcoded: false,
+            is_readonly: false,
+        };
+        AttributeList {
+            attributes: vec![
+                make_attribute("department", AttributeType::String),
+                make_attribute("floor", AttributeType::Integer),
+                make_attribute("birthday", AttributeType::DateTime),
+            ],
+        }
+    }
+
+    fn round_trip(filter: UserRequestFilter, expected: &str) {
+        let serialized = serialize_user_filter(&filter);
+        assert_eq!(serialized, expected);
+        assert_eq!(
+            parse_user_filter(&serialized, &make_attributes()).unwrap(),
+            filter
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        use UserRequestFilter::*;
+        let date = chrono::Utc
+            .with_ymd_and_hms(2024, 2, 3, 4, 5, 6)
+            .unwrap()
+            .naive_utc();
+        round_trip(True, "(&)");
+        round_trip(False, "(|)");
+        round_trip(
+            And(vec![
+                AttributeEquality("department".into(), "Sales".to_owned().into()),
+                Not(Box::new(MemberOf("contractors".into()))),
+            ]),
+            "(&(department=Sales)(!(memberOf=contractors)))",
+        );
+        round_trip(
+            Or(vec![
+                UserId(lldap_domain::types::UserId::new("bob")),
+                MemberOfId(GroupId(3)),
+                TransitiveMemberOf("engineering".into()),
+            ]),
+            "(|(user_id=bob)(memberOfId=3)(memberOf:1.2.840.113556.1.4.1941:=engineering))",
+        );
+        round_trip(
+            And(vec![
+                AttributeEquality("floor".into(), 3i64.into()),
+                AttributeEquality("birthday".into(), date.into()),
+                CustomAttributePresent("department".into()),
+            ]),
+            "(&(floor=3)(birthday=2024-02-03T04:05:06+00:00)(department=*))",
+        );
+        round_trip(
+            And(vec![
+                Equality(UserColumn::DisplayName, "Bob (the *best*)".to_owned()),
+                SubString(
+                    UserColumn::Email,
+                    SubStringFilter {
+                        initial: Some("bob".to_owned()),
+                        any: vec!["example".to_owned()],
+                        final_: Some(".com".to_owned()),
+                    },
+                ),
+                UserIdSubString(SubStringFilter {
+                    initial: None,
+                    any: vec![],
+                    final_: Some("\\x".to_owned()),
+                }),
+            ]),
+            r"(&(display_name=Bob \28the \2abest\2a\29)(email=bob*example*.com)(user_id=*\5cx))",
+        );
+        round_trip(
+            And(vec![
+                Disabled,
+                HasValue(UserColumn::LastLogin),
+                DateGreaterOrEqual(UserColumn::CreationDate, date),
+                DateLessOrEqual(UserColumn::ExpiryDate, date),
+            ]),
+            "(&(disabled=TRUE)(last_login=*)(creation_date>=2024-02-03T04:05:06+00:00)\
+             (expiry_date<=2024-02-03T04:05:06+00:00))",
+        );
+    }
+
+    #[test]
+    fn test_has_expiry_date() {
+        let serialized = serialize_user_filter(&UserRequestFilter::HasExpiryDate);
+        assert_eq!(serialized, "(expiry_date=*)");
+        assert_eq!(
+            parse_user_filter(&serialized, &make_attributes()).unwrap(),
+            UserRequestFilter::HasValue(UserColumn::ExpiryDate)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for filter in [
+            "",
+            "(department=Sales",
+            "(department=Sales))",
+            "(unknown=value)",
+            "(floor=three)",
+            "(department:1.2.3:=Sales)",
+            "(department=\\zz)",
+            "(memberOfId=three)",
+            "(department>=Sales)",
+            "{\"UserId\":\"bob\"}",
+        ] {
+            assert!(
+                parse_user_filter(filter, &make_attributes()).is_err(),
+                "{}",
+                filter
+            );
+        }
+    }
+}
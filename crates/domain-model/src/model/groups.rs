@@ -14,6 +14,10 @@ pub struct Model {
     pub lowercase_display_name: String,
     pub creation_date: chrono::NaiveDateTime,
     pub uuid: Uuid,
+    pub modified_date: chrono::NaiveDateTime,
+    /// For dynamic groups, the filter that computes the members, stored as an LDAP filter on the
+    /// user fields, e.g. `(&(department=Sales)(!(memberOf=contractors)))`.
+    pub dynamic_filter: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -36,8 +40,10 @@ impl From<Model> for lldap_domain::types::Group {
             id: group.group_id,
             display_name: group.display_name,
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid,
             users: vec![],
+            subgroups: vec![],
             attributes: Vec::new(),
         }
     }
@@ -49,6 +55,7 @@ impl From<Model> for lldap_domain::types::GroupDetails {
             group_id: group.group_id,
             display_name: group.display_name,
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid,
             attributes: Vec::new(),
         }
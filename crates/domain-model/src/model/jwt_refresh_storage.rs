@@ -12,6 +12,9 @@ pub struct Model {
     pub refresh_token_hash: i64,
     pub user_id: UserId,
     pub expiry_date: chrono::NaiveDateTime,
+    pub creation_date: chrono::NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -13,6 +13,8 @@ pub struct Model {
     pub user_id: UserId,
     pub expiry_date: chrono::NaiveDateTime,
     pub blacklisted: bool,
+    /// The refresh token of the session the JWT was issued for, if any.
+    pub refresh_token_hash: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,6 +1,9 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.3
 
-use sea_orm::entity::prelude::*;
+use sea_orm::{
+    entity::prelude::*,
+    sea_query::{Condition, DynIden, Expr},
+};
 use serde::{Deserialize, Serialize};
 
 use lldap_domain::types::{GroupId, UserId};
@@ -12,6 +15,28 @@ pub struct Model {
     pub user_id: UserId,
     #[sea_orm(primary_key)]
     pub group_id: GroupId,
+    /// The end of a temporary membership.
+    pub expiry_date: Option<chrono::NaiveDateTime>,
+}
+
+impl Model {
+    pub fn is_active(&self, now: chrono::NaiveDateTime) -> bool {
+        self.expiry_date.is_none_or(|expiry_date| expiry_date > now)
+    }
+}
+
+/// Excludes the expired memberships, which are only purged periodically.
+pub fn active_condition() -> Condition {
+    Condition::any()
+        .add(Column::ExpiryDate.is_null())
+        .add(Column::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+}
+
+/// Same as `active_condition`, for the joins where the table is aliased.
+fn active_condition_on(table: DynIden) -> Condition {
+    Condition::any()
+        .add(Expr::col((table.clone(), Column::ExpiryDate)).is_null())
+        .add(Expr::col((table, Column::ExpiryDate)).gt(chrono::Utc::now().naive_utc()))
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -54,7 +79,13 @@ impl Linked for UserToGroup {
     type ToEntity = super::Group;
 
     fn link(&self) -> Vec<RelationDef> {
-        vec![Relation::Users.def().rev(), Relation::Groups.def()]
+        vec![
+            Relation::Users
+                .def()
+                .rev()
+                .on_condition(|_, memberships| active_condition_on(memberships)),
+            Relation::Groups.def(),
+        ]
     }
 }
 
@@ -66,7 +97,13 @@ impl Linked for GroupToUser {
     type ToEntity = super::User;
 
     fn link(&self) -> Vec<RelationDef> {
-        vec![Relation::Groups.def().rev(), Relation::Users.def()]
+        vec![
+            Relation::Groups
+                .def()
+                .rev()
+                .on_condition(|_, memberships| active_condition_on(memberships)),
+            Relation::Users.def(),
+        ]
     }
 }
 
@@ -1,11 +1,23 @@
 pub mod prelude;
 
+pub mod api_tokens;
+pub mod audit_log;
+pub mod deleted_entries;
 pub mod deserialize;
 pub mod groups;
 pub mod jwt_refresh_storage;
 pub mod jwt_storage;
+pub mod login_failures;
 pub mod memberships;
+pub mod mfa_login_tokens;
+pub mod mfa_recovery_codes;
+pub mod oidc_authorizations;
+pub mod oidc_client_scope_groups;
+pub mod oidc_clients;
+pub mod oidc_refresh_tokens;
 pub mod password_reset_tokens;
+pub mod role_groups;
+pub mod roles;
 pub mod users;
 
 pub mod user_attribute_schema;
@@ -14,6 +26,8 @@ pub mod user_object_classes;
 
 pub mod group_attribute_schema;
 pub mod group_attributes;
+pub mod group_memberships;
 pub mod group_object_classes;
+pub mod group_owners;
 
 pub use prelude::*;
@@ -1,21 +1,49 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.3
 
+pub use super::api_tokens::Column as ApiTokensColumn;
+pub use super::api_tokens::Entity as ApiTokens;
+pub use super::audit_log::Column as AuditLogColumn;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::deleted_entries::Column as DeletedEntriesColumn;
+pub use super::deleted_entries::Entity as DeletedEntries;
 pub use super::group_attribute_schema::Column as GroupAttributeSchemaColumn;
 pub use super::group_attribute_schema::Entity as GroupAttributeSchema;
 pub use super::group_attributes::Column as GroupAttributesColumn;
 pub use super::group_attributes::Entity as GroupAttributes;
+pub use super::group_memberships::Column as GroupMembershipsColumn;
+pub use super::group_memberships::Entity as GroupMemberships;
 pub use super::group_object_classes::Column as GroupObjectClassesColumn;
 pub use super::group_object_classes::Entity as GroupObjectClasses;
+pub use super::group_owners::Column as GroupOwnersColumn;
+pub use super::group_owners::Entity as GroupOwners;
 pub use super::groups::Column as GroupColumn;
 pub use super::groups::Entity as Group;
 pub use super::jwt_refresh_storage::Column as JwtRefreshStorageColumn;
 pub use super::jwt_refresh_storage::Entity as JwtRefreshStorage;
 pub use super::jwt_storage::Column as JwtStorageColumn;
 pub use super::jwt_storage::Entity as JwtStorage;
+pub use super::login_failures::Column as LoginFailuresColumn;
+pub use super::login_failures::Entity as LoginFailures;
 pub use super::memberships::Column as MembershipColumn;
 pub use super::memberships::Entity as Membership;
+pub use super::mfa_login_tokens::Column as MfaLoginTokensColumn;
+pub use super::mfa_login_tokens::Entity as MfaLoginTokens;
+pub use super::mfa_recovery_codes::Column as MfaRecoveryCodesColumn;
+pub use super::mfa_recovery_codes::Entity as MfaRecoveryCodes;
+pub use super::oidc_authorizations::Column as OidcAuthorizationsColumn;
+pub use super::oidc_authorizations::Entity as OidcAuthorizations;
+pub use super::oidc_client_scope_groups::Column as OidcClientScopeGroupsColumn;
+pub use super::oidc_client_scope_groups::Entity as OidcClientScopeGroups;
+pub use super::oidc_clients::Column as OidcClientsColumn;
+pub use super::oidc_clients::Entity as OidcClients;
+pub use super::oidc_refresh_tokens::Column as OidcRefreshTokensColumn;
+pub use super::oidc_refresh_tokens::Entity as OidcRefreshTokens;
 pub use super::password_reset_tokens::Column as PasswordResetTokensColumn;
 pub use super::password_reset_tokens::Entity as PasswordResetTokens;
+pub use super::role_groups::Column as RoleGroupsColumn;
+pub use super::role_groups::Entity as RoleGroups;
+pub use super::roles::Column as RolesColumn;
+pub use super::roles::Entity as Roles;
 pub use super::user_attribute_schema::Column as UserAttributeSchemaColumn;
 pub use super::user_attribute_schema::Entity as UserAttributeSchema;
 pub use super::user_attributes::Column as UserAttributesColumn;
@@ -20,7 +20,14 @@ pub struct Model {
     pub password_hash: Option<Vec<u8>>,
     pub totp_secret: Option<String>,
     pub mfa_type: Option<String>,
+    /// Time step of the last accepted TOTP code, to reject replays.
+    pub totp_last_counter: Option<i64>,
     pub uuid: Uuid,
+    pub disabled: bool,
+    pub expiry_date: Option<chrono::NaiveDateTime>,
+    pub last_login: Option<chrono::NaiveDateTime>,
+    pub password_changed: Option<chrono::NaiveDateTime>,
+    pub modified_date: chrono::NaiveDateTime,
 }
 
 impl EntityName for Entity {
@@ -39,7 +46,13 @@ pub enum Column {
     PasswordHash,
     TotpSecret,
     MfaType,
+    TotpLastCounter,
     Uuid,
+    Disabled,
+    ExpiryDate,
+    LastLogin,
+    PasswordChanged,
+    ModifiedDate,
 }
 
 impl ColumnTrait for Column {
@@ -55,7 +68,13 @@ impl ColumnTrait for Column {
             Column::PasswordHash => ColumnType::Blob,
             Column::TotpSecret => ColumnType::String(StringLen::N(64)),
             Column::MfaType => ColumnType::String(StringLen::N(64)),
+            Column::TotpLastCounter => ColumnType::BigInteger,
             Column::Uuid => ColumnType::String(StringLen::N(36)),
+            Column::Disabled => ColumnType::Boolean,
+            Column::ExpiryDate => ColumnType::DateTime,
+            Column::LastLogin => ColumnType::DateTime,
+            Column::PasswordChanged => ColumnType::DateTime,
+            Column::ModifiedDate => ColumnType::DateTime,
         }
         .def()
     }
@@ -71,6 +90,12 @@ pub enum Relation {
     JwtStorage,
     #[sea_orm(has_many = "super::password_reset_tokens::Entity")]
     PasswordResetTokens,
+    #[sea_orm(has_many = "super::mfa_login_tokens::Entity")]
+    MfaLoginTokens,
+    #[sea_orm(has_many = "super::mfa_recovery_codes::Entity")]
+    MfaRecoveryCodes,
+    #[sea_orm(has_many = "super::api_tokens::Entity")]
+    ApiTokens,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DerivePrimaryKey)]
@@ -110,6 +135,24 @@ impl Related<super::password_reset_tokens::Entity> for Entity {
     }
 }
 
+impl Related<super::mfa_login_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MfaLoginTokens.def()
+    }
+}
+
+impl Related<super::mfa_recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MfaRecoveryCodes.def()
+    }
+}
+
+impl Related<super::api_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiTokens.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl From<Model> for lldap_domain::types::User {
@@ -119,7 +162,12 @@ impl From<Model> for lldap_domain::types::User {
             email: user.email,
             display_name: user.display_name,
             creation_date: user.creation_date,
+            modified_date: user.modified_date,
             uuid: user.uuid,
+            disabled: user.disabled,
+            expiry_date: user.expiry_date,
+            last_login: user.last_login,
+            password_changed: user.password_changed,
             attributes: Vec::new(),
         }
     }
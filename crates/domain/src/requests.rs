@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 use crate::types::{Attribute, AttributeName, AttributeType, Email, GroupId, GroupName, UserId};
@@ -19,6 +20,9 @@ pub struct UpdateUserRequest {
     pub display_name: Option<String>,
     pub delete_attributes: Vec<AttributeName>,
     pub insert_attributes: Vec<Attribute>,
+    pub disabled: Option<bool>,
+    /// `Some(None)` removes the expiry date.
+    pub expiry_date: Option<Option<NaiveDateTime>>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -387,6 +387,10 @@ impl JpegPhoto {
         self.0.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn null() -> Self {
         Self(vec![])
     }
@@ -544,10 +548,32 @@ pub struct User {
     pub email: Email,
     pub display_name: Option<String>,
     pub creation_date: NaiveDateTime,
+    /// The last change of the user's attributes or group memberships.
+    #[serde(default)]
+    pub modified_date: NaiveDateTime,
     pub uuid: Uuid,
+    /// A disabled user cannot log in, but is kept in the directory.
+    #[serde(default)]
+    pub disabled: bool,
+    /// After this date, the user cannot log in anymore.
+    #[serde(default)]
+    pub expiry_date: Option<NaiveDateTime>,
+    /// The last successful login, over LDAP or HTTP.
+    #[serde(default)]
+    pub last_login: Option<NaiveDateTime>,
+    /// The last time the password was set.
+    #[serde(default)]
+    pub password_changed: Option<NaiveDateTime>,
     pub attributes: Vec<Attribute>,
 }
 
+impl User {
+    /// Whether the user is allowed to log in at the given time.
+    pub fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        !self.disabled && self.expiry_date.is_none_or(|expiry_date| expiry_date > now)
+    }
+}
+
 #[cfg(feature = "test")]
 impl Default for User {
     fn default() -> Self {
@@ -557,7 +583,12 @@ impl Default for User {
             email: Email::default(),
             display_name: None,
             creation_date: epoch,
+            modified_date: epoch,
             uuid: Uuid::from_name_and_date("", &epoch),
+            disabled: false,
+            expiry_date: None,
+            last_login: None,
+            password_changed: None,
             attributes: Vec::new(),
         }
     }
@@ -651,8 +682,14 @@ pub struct Group {
     pub id: GroupId,
     pub display_name: GroupName,
     pub creation_date: NaiveDateTime,
+    /// The last change of the group's attributes or members.
+    #[serde(default)]
+    pub modified_date: NaiveDateTime,
     pub uuid: Uuid,
     pub users: Vec<UserId>,
+    /// The groups that are direct members of this group.
+    #[serde(default)]
+    pub subgroups: Vec<GroupName>,
     pub attributes: Vec<Attribute>,
 }
 
@@ -661,6 +698,8 @@ pub struct GroupDetails {
     pub group_id: GroupId,
     pub display_name: GroupName,
     pub creation_date: NaiveDateTime,
+    #[serde(default)]
+    pub modified_date: NaiveDateTime,
     pub uuid: Uuid,
     pub attributes: Vec<Attribute>,
 }
@@ -1,6 +1,9 @@
+use lldap_validation::password::PasswordPolicy;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct Options {
     pub password_reset_enabled: bool,
+    #[serde(default)]
+    pub password_policy: PasswordPolicy,
 }
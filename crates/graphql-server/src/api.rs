@@ -1,16 +1,27 @@
 use crate::{mutation::Mutation, query::Query};
 use juniper::{EmptySubscription, FieldError, RootNode};
 use lldap_access_control::{
-    AccessControlledBackendHandler, AdminBackendHandler, ReadonlyBackendHandler,
-    UserReadableBackendHandler, UserWriteableBackendHandler,
+    AccessControlledBackendHandler, AdminBackendHandler, GroupMembershipBackendHandler,
+    ReadonlyBackendHandler, UserAttributeEditorBackendHandler, UserCreationBackendHandler,
+    UserDeletionBackendHandler, UserReadableBackendHandler, UserRestrictedListerBackendHandler,
+    UserWriteableBackendHandler,
 };
 use lldap_auth::{access_control::ValidationResults, types::UserId};
-use lldap_domain_handlers::handler::BackendHandler;
+use lldap_domain::types::GroupId;
+use lldap_domain_handlers::handler::{AuditSource, BackendHandler, CreateAuditEventRequest};
+use std::{collections::HashSet, net::IpAddr};
 use tracing::debug;
 
 pub struct Context<Handler: BackendHandler> {
     pub handler: AccessControlledBackendHandler<Handler>,
     pub validation_result: ValidationResults,
+    /// JWTs that were blacklisted in the database, to add to the in-memory blacklist once the
+    /// request is done.
+    pub blacklisted_jwts: std::sync::Mutex<HashSet<u64>>,
+    /// Address of the client, for the audit log.
+    pub source_ip: Option<IpAddr>,
+    /// Changes to record in the audit log once the request is done.
+    pub audit_events: std::sync::Mutex<Vec<CreateAuditEventRequest>>,
 }
 
 pub fn field_error_callback<'a>(
@@ -29,9 +40,28 @@ impl<Handler: BackendHandler> Context<Handler> {
         Self {
             handler: AccessControlledBackendHandler::new(handler),
             validation_result,
+            blacklisted_jwts: Default::default(),
+            source_ip: None,
+            audit_events: Default::default(),
         }
     }
 
+    /// Records a successful change in the audit log, once the request is done.
+    pub fn record_change(&self, operation: &str, target: String, details: Option<String>) {
+        self.audit_events
+            .lock()
+            .unwrap()
+            .push(CreateAuditEventRequest {
+                actor: Some(self.validation_result.user.to_string()),
+                source: AuditSource::GraphQl,
+                ip_address: self.source_ip.map(|ip| ip.to_string()),
+                target: Some(target),
+                operation: operation.to_owned(),
+                success: true,
+                details,
+            });
+    }
+
     pub fn get_admin_handler(&self) -> Option<&(impl AdminBackendHandler + use<Handler>)> {
         self.handler.get_admin_handler(&self.validation_result)
     }
@@ -40,6 +70,46 @@ impl<Handler: BackendHandler> Context<Handler> {
         self.handler.get_readonly_handler(&self.validation_result)
     }
 
+    pub fn get_group_member_editor_handler(
+        &self,
+        group_id: GroupId,
+    ) -> Option<&(impl GroupMembershipBackendHandler + use<Handler>)> {
+        self.handler
+            .get_group_member_editor_handler(&self.validation_result, group_id)
+    }
+
+    pub fn get_user_creation_handler(
+        &self,
+    ) -> Option<&(impl UserCreationBackendHandler + use<Handler>)> {
+        self.handler
+            .get_user_creation_handler(&self.validation_result)
+    }
+
+    pub fn get_user_deletion_handler(
+        &self,
+    ) -> Option<&(impl UserDeletionBackendHandler + use<Handler>)> {
+        self.handler
+            .get_user_deletion_handler(&self.validation_result)
+    }
+
+    pub fn get_user_attribute_editor_handler(
+        &self,
+    ) -> Option<&(impl UserAttributeEditorBackendHandler + use<Handler>)> {
+        self.handler
+            .get_user_attribute_editor_handler(&self.validation_result)
+    }
+
+    /// Lists the users and groups made readable by the roles or by the group ownership, for the
+    /// users who cannot read everything.
+    pub fn get_role_restricted_lister_handler(
+        &self,
+    ) -> Option<UserRestrictedListerBackendHandler<'_, Handler>> {
+        (!self.validation_result.readable_group_ids().is_empty()).then(|| {
+            self.handler
+                .get_user_restricted_lister_handler(&self.validation_result)
+        })
+    }
+
     pub fn get_writeable_handler(
         &self,
         user_id: &UserId,
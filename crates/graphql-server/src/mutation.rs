@@ -1,10 +1,15 @@
-use crate::api::{Context, field_error_callback};
+use crate::{
+    api::{Context, field_error_callback},
+    query::{ApiToken, ApiTokenScope, RequestFilter},
+};
 use anyhow::{Context as AnyhowContext, anyhow};
 use juniper::{FieldError, FieldResult, GraphQLInputObject, GraphQLObject, graphql_object};
 use lldap_access_control::{
-    AdminBackendHandler, ReadonlyBackendHandler, UserReadableBackendHandler,
-    UserWriteableBackendHandler,
+    AdminBackendHandler, GroupMembershipBackendHandler, ReadonlyBackendHandler,
+    UserAttributeEditorBackendHandler, UserCreationBackendHandler, UserDeletionBackendHandler,
+    UserReadableBackendHandler, UserWriteableBackendHandler,
 };
+use lldap_auth::access_control::RolePermission;
 use lldap_domain::{
     deserialize::deserialize_attribute_value,
     public_schema::PublicSchema,
@@ -15,11 +20,21 @@ use lldap_domain::{
     schema::AttributeList,
     types::{
         Attribute as DomainAttribute, AttributeName, AttributeType, Email, GroupId,
-        LdapObjectClass, UserId,
+        LdapObjectClass, User, UserId,
+    },
+};
+use lldap_domain_handlers::handler::{
+    BackendHandler, CreateApiTokenRequest, UserRequestFilter, audit_diff, group_audit_values,
+    user_audit_values,
+};
+use lldap_domain_model::model::UserColumn;
+use lldap_validation::{
+    attributes::{ALLOWED_CHARACTERS_DESCRIPTION, validate_attribute_name},
+    user_id::{
+        ALLOWED_CHARACTERS_DESCRIPTION as USER_ID_ALLOWED_CHARACTERS_DESCRIPTION, validate_user_id,
     },
 };
-use lldap_domain_handlers::handler::BackendHandler;
-use lldap_validation::attributes::{ALLOWED_CHARACTERS_DESCRIPTION, validate_attribute_name};
+use serde_json::json;
 use std::{collections::BTreeMap, sync::Arc};
 use tracing::{Instrument, Span, debug, debug_span};
 
@@ -124,6 +139,22 @@ pub struct UpdateGroupInput {
     insert_attributes: Option<Vec<AttributeValue>>,
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The details required to create an API token for the current user.
+pub struct CreateApiTokenInput {
+    name: String,
+    scope: ApiTokenScope,
+    /// If not set, the token never expires.
+    expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+pub struct CreateApiTokenResponse {
+    token: ApiToken,
+    /// The secret to send as a bearer token. It cannot be retrieved later.
+    secret: String,
+}
+
 #[derive(PartialEq, Eq, Debug, GraphQLObject)]
 pub struct Success {
     ok: bool,
@@ -230,7 +261,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             debug!("{:?}", &user.id);
         });
         let handler = context
-            .get_admin_handler()
+            .get_user_creation_handler()
             .ok_or_else(field_error_callback(&span, "Unauthorized user creation"))?;
         let user_id = UserId::new(&user.id);
         let schema = handler.get_schema().await?;
@@ -259,6 +290,11 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .instrument(span.clone())
             .await?;
         let user_details = handler.get_user_details(&user_id).instrument(span).await?;
+        context.record_change(
+            "create_user",
+            format!("user:{}", user_id),
+            audit_diff(&BTreeMap::new(), &user_audit_values(&user_details)),
+        );
         super::query::User::<Handler>::from_user(user_details, Arc::new(schema))
     }
 
@@ -300,53 +336,47 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             debug!(?user.id);
         });
         let user_id = UserId::new(&user.id);
+        if let Some(handler) = context.get_writeable_handler(&user_id) {
+            let is_admin = context.validation_result.is_admin();
+            let schema = handler.get_schema().await?;
+            let request = make_update_user_request(user, &schema, is_admin)?;
+            let before = handler.get_user_details(&user_id).await?;
+            handler.update_user(request).instrument(span).await?;
+            record_user_update(context, handler, "update_user", &before).await;
+            return Ok(Success::new());
+        }
+        // Roles can allow editing some attributes of the other users.
         let handler = context
-            .get_writeable_handler(&user_id)
+            .get_user_attribute_editor_handler()
             .ok_or_else(field_error_callback(&span, "Unauthorized user update"))?;
-        let is_admin = context.validation_result.is_admin();
+        if is_admin_user(handler, &user_id).await? {
+            span.in_scope(|| debug!("Cannot edit an admin"));
+            return Err("Unauthorized user update".into());
+        }
         let schema = handler.get_schema().await?;
-        // Consolidate attributes and fields into a combined attribute list
-        let consolidated_attributes = consolidate_attributes(
-            user.insert_attributes.unwrap_or_default(),
-            user.first_name,
-            user.last_name,
-            user.avatar,
-        );
-        // Extract any empty attributes into a list of attributes for deletion
-        let (delete_attrs, insert_attrs): (Vec<_>, Vec<_>) = consolidated_attributes
+        // The allowed attributes are checked below, regardless of their schema.
+        let request = make_update_user_request(user, &schema, true)?;
+        let edited_attributes = request
+            .email
+            .as_ref()
+            .map(|_| "mail")
             .into_iter()
-            .partition(|a| a.value == vec!["".to_string()]);
-        // Combine lists of attributes for removal
-        let mut delete_attributes: Vec<String> =
-            delete_attrs.iter().map(|a| a.name.to_owned()).collect();
-        delete_attributes.extend(user.remove_attributes.unwrap_or_default());
-        // Unpack attributes for update
-        let UnpackedAttributes {
-            email,
-            display_name,
-            attributes: insert_attributes,
-        } = unpack_attributes(insert_attrs, &schema, is_admin)?;
-        let display_name = display_name.or_else(|| {
-            // If the display name is not inserted, but removed, reset it.
-            delete_attributes
-                .iter()
-                .find(|attr| *attr == "display_name")
-                .map(|_| String::new())
-        });
+            .chain(request.display_name.as_ref().map(|_| "display_name"))
+            .chain(request.insert_attributes.iter().map(|a| a.name.as_str()))
+            .chain(request.delete_attributes.iter().map(AttributeName::as_str));
+        for attribute in edited_attributes {
+            if !context.validation_result.can_edit_user_attribute(attribute) {
+                return Err(
+                    anyhow!("Permission denied: Cannot edit the attribute {}", attribute).into(),
+                );
+            }
+        }
+        let before = handler.get_user_details(&user_id).await?;
         handler
-            .update_user(UpdateUserRequest {
-                user_id,
-                email: user.email.map(Into::into).or(email),
-                display_name: user.display_name.or(display_name),
-                delete_attributes: delete_attributes
-                    .into_iter()
-                    .filter(|attr| attr != "mail" && attr != "display_name")
-                    .map(Into::into)
-                    .collect(),
-                insert_attributes,
-            })
+            .update_user_attributes(request)
             .instrument(span)
             .await?;
+        record_user_update(context, handler, "update_user", &before).await;
         Ok(Success::new())
     }
 
@@ -373,6 +403,8 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             return Err("Cannot change lldap_admin group name".into());
         }
         let schema = handler.get_schema().await?;
+        let group_id = GroupId(group.id);
+        let before = handler.get_group_details(group_id).await?;
         let insert_attributes = group
             .insert_attributes
             .unwrap_or_default()
@@ -382,7 +414,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .collect::<Result<Vec<_>, _>>()?;
         handler
             .update_group(UpdateGroupRequest {
-                group_id: GroupId(group.id),
+                group_id,
                 display_name: new_display_name.map(|s| s.as_str().into()),
                 delete_attributes: group
                     .remove_attributes
@@ -395,6 +427,14 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             })
             .instrument(span)
             .await?;
+        let details = handler
+            .get_group_details(group_id)
+            .await
+            .ok()
+            .and_then(|after| {
+                audit_diff(&group_audit_values(&before), &group_audit_values(&after))
+            });
+        context.record_change("update_group", format!("group:{}", group.id), details);
         Ok(Success::new())
     }
 
@@ -408,15 +448,22 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             debug!(?user_id, ?group_id);
         });
         let handler = context
-            .get_admin_handler()
+            .get_group_member_editor_handler(GroupId(group_id))
             .ok_or_else(field_error_callback(
                 &span,
                 "Unauthorized group membership modification",
             ))?;
+        check_group_membership_change(context, handler, group_id, &span).await?;
+        let user_id = UserId::new(&user_id);
         handler
-            .add_user_to_group(&UserId::new(&user_id), GroupId(group_id))
+            .add_user_to_group(&user_id, GroupId(group_id))
             .instrument(span)
             .await?;
+        context.record_change(
+            "add_user_to_group",
+            format!("group:{}", group_id),
+            Some(json!({"user_id": user_id}).to_string()),
+        );
         Ok(Success::new())
     }
 
@@ -430,7 +477,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             debug!(?user_id, ?group_id);
         });
         let handler = context
-            .get_admin_handler()
+            .get_group_member_editor_handler(GroupId(group_id))
             .ok_or_else(field_error_callback(
                 &span,
                 "Unauthorized group membership modification",
@@ -440,10 +487,55 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             span.in_scope(|| debug!("Cannot remove admin rights for current user"));
             return Err("Cannot remove admin rights for current user".into());
         }
+        check_group_membership_change(context, handler, group_id, &span).await?;
         handler
             .remove_user_from_group(&user_id, GroupId(group_id))
             .instrument(span)
             .await?;
+        context.record_change(
+            "remove_user_from_group",
+            format!("group:{}", group_id),
+            Some(json!({"user_id": user_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    /// Makes the membership expire at the given date, or never if the date is absent.
+    async fn set_membership_expiry_date(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+        expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_membership_expiry_date");
+        span.in_scope(|| {
+            debug!(?user_id, ?group_id, ?expiry_date);
+        });
+        let handler = context
+            .get_group_member_editor_handler(GroupId(group_id))
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        let user_id = UserId::new(&user_id);
+        if context.validation_result.user == user_id && group_id == 1 && expiry_date.is_some() {
+            span.in_scope(|| debug!("Cannot remove admin rights for current user"));
+            return Err("Cannot remove admin rights for current user".into());
+        }
+        check_group_membership_change(context, handler, group_id, &span).await?;
+        handler
+            .set_membership_expiry_date(
+                &user_id,
+                GroupId(group_id),
+                expiry_date.map(|d| d.naive_utc()),
+            )
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "set_membership_expiry_date",
+            format!("group:{}", group_id),
+            Some(json!({"user_id": user_id, "expiry_date": expiry_date}).to_string()),
+        );
         Ok(Success::new())
     }
 
@@ -454,13 +546,142 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         });
         let user_id = UserId::new(&user_id);
         let handler = context
-            .get_admin_handler()
+            .get_user_deletion_handler()
             .ok_or_else(field_error_callback(&span, "Unauthorized user deletion"))?;
         if context.validation_result.user == user_id {
             span.in_scope(|| debug!("Cannot delete current user"));
             return Err("Cannot delete current user".into());
         }
+        if !context.validation_result.is_admin() && is_admin_user(handler, &user_id).await? {
+            span.in_scope(|| debug!("Cannot delete an admin"));
+            return Err("Unauthorized user deletion".into());
+        }
+        let before = handler.get_user_details(&user_id).await?;
         handler.delete_user(&user_id).instrument(span).await?;
+        context.record_change(
+            "delete_user",
+            format!("user:{}", user_id),
+            audit_diff(&user_audit_values(&before), &BTreeMap::new()),
+        );
+        Ok(Success::new())
+    }
+
+    async fn rename_user(
+        context: &Context<Handler>,
+        user_id: String,
+        new_user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] rename_user");
+        span.in_scope(|| {
+            debug!(?user_id, ?new_user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let new_user_id = UserId::new(&new_user_id);
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user rename"))?;
+        if new_user_id.as_str().is_empty() {
+            span.in_scope(|| debug!("Empty user id"));
+            return Err("New user id cannot be empty".into());
+        }
+        validate_user_id(new_user_id.as_str()).map_err(|invalid_chars: Vec<char>| -> FieldError {
+            let chars = String::from_iter(invalid_chars);
+            span.in_scope(|| debug!("Invalid chars in the new user id: {}", chars));
+            anyhow!(
+                "Cannot rename user to an invalid user id. Valid characters: {}. Invalid chars found: {}",
+                USER_ID_ALLOWED_CHARACTERS_DESCRIPTION,
+                chars
+            )
+            .into()
+        })?;
+        // The password reset accepts either a user id or an email, they must not be confused.
+        if handler
+            .list_users(
+                Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    new_user_id.to_string(),
+                )),
+                false,
+            )
+            .instrument(span.clone())
+            .await?
+            .iter()
+            .any(|u| u.user.user_id != user_id)
+        {
+            span.in_scope(|| debug!("User id already used as an email"));
+            return Err(format!(
+                "The user id `{}` is already the email of another user",
+                new_user_id
+            )
+            .into());
+        }
+        let jwt_hashes = handler
+            .rename_user(&user_id, &new_user_id)
+            .instrument(span)
+            .await?;
+        context.blacklisted_jwts.lock().unwrap().extend(jwt_hashes);
+        context.record_change(
+            "rename_user",
+            format!("user:{}", user_id),
+            Some(json!({"user_id": {"before": user_id, "after": new_user_id}}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    /// Disable or re-enable a user, and set or remove the expiry date of the account.
+    async fn set_user_account_status(
+        context: &Context<Handler>,
+        user_id: String,
+        disabled: bool,
+        expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_user_account_status");
+        span.in_scope(|| {
+            debug!(?user_id, ?disabled, ?expiry_date);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized user status change",
+            ))?;
+        let expiry_date = expiry_date.map(|d| d.naive_utc());
+        let is_active = !disabled && expiry_date.is_none_or(|d| d > chrono::Utc::now().naive_utc());
+        if !is_active && context.validation_result.user == user_id {
+            span.in_scope(|| debug!("Cannot disable current user"));
+            return Err("Cannot disable current user".into());
+        }
+        let before = handler.get_user_details(&user_id).await?;
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: user_id.clone(),
+                disabled: Some(disabled),
+                expiry_date: Some(expiry_date),
+                ..Default::default()
+            })
+            .instrument(span)
+            .await?;
+        record_user_update(context, handler, "set_user_account_status", &before).await;
+        if !is_active {
+            let jwt_hashes = handler.revoke_all_sessions(&user_id).await?;
+            context.blacklisted_jwts.lock().unwrap().extend(jwt_hashes);
+        }
+        Ok(Success::new())
+    }
+
+    /// Lift the lockout caused by repeated failed logins.
+    async fn unlock_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] unlock_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user unlock"))?;
+        handler.unlock_user(&user_id).instrument(span).await?;
+        context.record_change("unlock_user", format!("user:{}", user_id), None);
         Ok(Success::new())
     }
 
@@ -476,10 +697,16 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             span.in_scope(|| debug!("Cannot delete admin group"));
             return Err("Cannot delete admin group".into());
         }
+        let before = handler.get_group_details(GroupId(group_id)).await?;
         handler
             .delete_group(GroupId(group_id))
             .instrument(span)
             .await?;
+        context.record_change(
+            "delete_group",
+            format!("group:{}", group_id),
+            audit_diff(&group_audit_values(&before), &BTreeMap::new()),
+        );
         Ok(Success::new())
     }
 
@@ -519,7 +746,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             ))?;
         handler
             .add_user_attribute(CreateAttributeRequest {
-                name: name.into(),
+                name: name.as_str().into(),
                 attribute_type,
                 is_list,
                 is_visible,
@@ -527,6 +754,19 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             })
             .instrument(span)
             .await?;
+        context.record_change(
+            "add_user_attribute",
+            format!("user_attribute:{}", name),
+            Some(
+                json!({
+                    "attribute_type": attribute_type,
+                    "is_list": is_list,
+                    "is_visible": is_visible,
+                    "is_editable": is_editable,
+                })
+                .to_string(),
+            ),
+        );
         Ok(Success::new())
     }
 
@@ -564,7 +804,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             ))?;
         handler
             .add_group_attribute(CreateAttributeRequest {
-                name: name.into(),
+                name: name.as_str().into(),
                 attribute_type,
                 is_list,
                 is_visible,
@@ -572,6 +812,19 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             })
             .instrument(span)
             .await?;
+        context.record_change(
+            "add_group_attribute",
+            format!("group_attribute:{}", name),
+            Some(
+                json!({
+                    "attribute_type": attribute_type,
+                    "is_list": is_list,
+                    "is_visible": is_visible,
+                    "is_editable": is_editable,
+                })
+                .to_string(),
+            ),
+        );
         Ok(Success::new())
     }
 
@@ -603,6 +856,11 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .delete_user_attribute(&name)
             .instrument(span)
             .await?;
+        context.record_change(
+            "delete_user_attribute",
+            format!("user_attribute:{}", name),
+            None,
+        );
         Ok(Success::new())
     }
 
@@ -634,6 +892,11 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .delete_group_attribute(&name)
             .instrument(span)
             .await?;
+        context.record_change(
+            "delete_group_attribute",
+            format!("group_attribute:{}", name),
+            None,
+        );
         Ok(Success::new())
     }
 
@@ -652,9 +915,14 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 "Unauthorized object class addition",
             ))?;
         handler
-            .add_user_object_class(&LdapObjectClass::from(name))
+            .add_user_object_class(&LdapObjectClass::from(name.as_str()))
             .instrument(span)
             .await?;
+        context.record_change(
+            "add_user_object_class",
+            format!("user_object_class:{}", name),
+            None,
+        );
         Ok(Success::new())
     }
 
@@ -673,9 +941,14 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 "Unauthorized object class addition",
             ))?;
         handler
-            .add_group_object_class(&LdapObjectClass::from(name))
+            .add_group_object_class(&LdapObjectClass::from(name.as_str()))
             .instrument(span)
             .await?;
+        context.record_change(
+            "add_group_object_class",
+            format!("group_object_class:{}", name),
+            None,
+        );
         Ok(Success::new())
     }
 
@@ -694,9 +967,14 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 "Unauthorized object class deletion",
             ))?;
         handler
-            .delete_user_object_class(&LdapObjectClass::from(name))
+            .delete_user_object_class(&LdapObjectClass::from(name.as_str()))
             .instrument(span)
             .await?;
+        context.record_change(
+            "delete_user_object_class",
+            format!("user_object_class:{}", name),
+            None,
+        );
         Ok(Success::new())
     }
 
@@ -715,404 +993,1599 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 "Unauthorized object class deletion",
             ))?;
         handler
-            .delete_group_object_class(&LdapObjectClass::from(name))
+            .delete_group_object_class(&LdapObjectClass::from(name.as_str()))
             .instrument(span)
             .await?;
+        context.record_change(
+            "delete_group_object_class",
+            format!("group_object_class:{}", name),
+            None,
+        );
         Ok(Success::new())
     }
-}
-
-async fn create_group_with_details<Handler: BackendHandler>(
-    context: &Context<Handler>,
-    request: CreateGroupInput,
-    span: Span,
-) -> FieldResult<super::query::Group<Handler>> {
-    let handler = context
-        .get_admin_handler()
-        .ok_or_else(field_error_callback(&span, "Unauthorized group creation"))?;
-    let schema = handler.get_schema().await?;
-    let attributes = request
-        .attributes
-        .unwrap_or_default()
-        .into_iter()
-        .map(|attr| deserialize_attribute(&schema.get_schema().group_attributes, attr, true))
-        .collect::<Result<Vec<_>, _>>()?;
-    let request = CreateGroupRequest {
-        display_name: request.display_name.into(),
-        attributes,
-    };
-    let group_id = handler.create_group(request).await?;
-    let group_details = handler.get_group_details(group_id).instrument(span).await?;
-    super::query::Group::<Handler>::from_group_details(group_details, Arc::new(schema))
-}
 
-fn deserialize_attribute(
-    attribute_schema: &AttributeList,
-    attribute: AttributeValue,
-    is_admin: bool,
-) -> FieldResult<DomainAttribute> {
-    let attribute_name = AttributeName::from(attribute.name.as_str());
-    let attribute_schema = attribute_schema
-        .get_attribute_schema(&attribute_name)
-        .ok_or_else(|| anyhow!("Attribute {} is not defined in the schema", attribute.name))?;
-    if attribute_schema.is_readonly {
-        return Err(anyhow!(
-            "Permission denied: Attribute {} is read-only",
-            attribute.name
-        )
-        .into());
-    }
-    if !is_admin && !attribute_schema.is_editable {
-        return Err(anyhow!(
-            "Permission denied: Attribute {} is not editable by regular users",
-            attribute.name
-        )
-        .into());
+    /// Creates the role, or replaces its permissions if it already exists.
+    async fn set_role(
+        context: &Context<Handler>,
+        name: String,
+        permissions: Vec<String>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_role");
+        span.in_scope(|| {
+            debug!(?name, ?permissions);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized role modification",
+            ))?;
+        let permissions = permissions
+            .iter()
+            .map(|p| p.parse::<RolePermission>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?;
+        let details = json!({
+            "permissions": permissions.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        });
+        handler
+            .set_role(&name, permissions)
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "set_role",
+            format!("role:{}", name),
+            Some(details.to_string()),
+        );
+        Ok(Success::new())
     }
-    let deserialized_values = deserialize_attribute_value(
-        &attribute.value,
-        attribute_schema.attribute_type,
-        attribute_schema.is_list,
-    )
-    .context(format!("While deserializing attribute {}", attribute.name))?;
-    Ok(DomainAttribute {
-        name: attribute_name,
-        value: deserialized_values,
-    })
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::query::Query;
-    use juniper::{
-        DefaultScalarValue, EmptySubscription, GraphQLType, InputValue, RootNode, Variables,
-        execute, graphql_value,
-    };
-    use lldap_auth::access_control::{Permission, ValidationResults};
-    use lldap_domain::types::{AttributeName, AttributeType};
-    use lldap_test_utils::MockTestBackendHandler;
-    use mockall::predicate::eq;
-    use pretty_assertions::assert_eq;
+    async fn delete_role(context: &Context<Handler>, name: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_role");
+        span.in_scope(|| {
+            debug!(?name);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized role deletion"))?;
+        handler.delete_role(&name).instrument(span).await?;
+        context.record_change("delete_role", format!("role:{}", name), None);
+        Ok(Success::new())
+    }
 
-    fn mutation_schema<'q, C, Q, M>(
-        query_root: Q,
-        mutation_root: M,
-    ) -> RootNode<'q, Q, M, EmptySubscription<C>>
-    where
-        Q: GraphQLType<DefaultScalarValue, Context = C, TypeInfo = ()> + 'q,
-        M: GraphQLType<DefaultScalarValue, Context = C, TypeInfo = ()> + 'q,
-    {
-        RootNode::new(query_root, mutation_root, EmptySubscription::<C>::new())
+    async fn add_role_to_group(
+        context: &Context<Handler>,
+        name: String,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_role_to_group");
+        span.in_scope(|| {
+            debug!(?name, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized role modification",
+            ))?;
+        handler
+            .add_role_to_group(&name, GroupId(group_id))
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "add_role_to_group",
+            format!("role:{}", name),
+            Some(json!({"group_id": group_id}).to_string()),
+        );
+        Ok(Success::new())
     }
 
-    #[tokio::test]
-    async fn test_create_user_attribute_valid() {
-        const QUERY: &str = r#"
-            mutation CreateUserAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!, $isEditable: Boolean!) {
-                addUserAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: $isEditable) {
-                    ok
+    async fn remove_role_from_group(
+        context: &Context<Handler>,
+        name: String,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_role_from_group");
+        span.in_scope(|| {
+            debug!(?name, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized role modification",
+            ))?;
+        handler
+            .remove_role_from_group(&name, GroupId(group_id))
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "remove_role_from_group",
+            format!("role:{}", name),
+            Some(json!({"group_id": group_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    /// Lets the user add and remove members of the group, without being an admin.
+    async fn add_group_owner(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_owner");
+        span.in_scope(|| {
+            debug!(?user_id, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group owner modification",
+            ))?;
+        let user_id = UserId::new(&user_id);
+        handler
+            .add_group_owner(GroupId(group_id), &user_id)
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "add_group_owner",
+            format!("group:{}", group_id),
+            Some(json!({"user_id": user_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    async fn remove_group_owner(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_owner");
+        span.in_scope(|| {
+            debug!(?user_id, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group owner modification",
+            ))?;
+        let user_id = UserId::new(&user_id);
+        handler
+            .remove_group_owner(GroupId(group_id), &user_id)
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "remove_group_owner",
+            format!("group:{}", group_id),
+            Some(json!({"user_id": user_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    /// Makes the child group a member of the parent group, for the LDAP memberships.
+    async fn add_group_to_group(
+        context: &Context<Handler>,
+        parent_group_id: i32,
+        child_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_to_group");
+        span.in_scope(|| {
+            debug!(?parent_group_id, ?child_group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        handler
+            .add_group_to_group(GroupId(parent_group_id), GroupId(child_group_id))
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "add_group_to_group",
+            format!("group:{}", parent_group_id),
+            Some(json!({"group_id": child_group_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    async fn remove_group_from_group(
+        context: &Context<Handler>,
+        parent_group_id: i32,
+        child_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_from_group");
+        span.in_scope(|| {
+            debug!(?parent_group_id, ?child_group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        handler
+            .remove_group_from_group(GroupId(parent_group_id), GroupId(child_group_id))
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "remove_group_from_group",
+            format!("group:{}", parent_group_id),
+            Some(json!({"group_id": child_group_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    /// Computes the members of the group from the filter. Without a filter, the group is static.
+    async fn set_group_filter(
+        context: &Context<Handler>,
+        group_id: i32,
+        filter: Option<RequestFilter>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_group_filter");
+        span.in_scope(|| {
+            debug!(?group_id, ?filter);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        let schema = handler.get_schema().await?;
+        let filter = filter
+            .map(|f| f.try_into_domain_filter(&schema))
+            .transpose()?;
+        let details = json!({ "filter": filter }).to_string();
+        handler
+            .set_group_filter(GroupId(group_id), filter)
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "set_group_filter",
+            format!("group:{}", group_id),
+            Some(details),
+        );
+        Ok(Success::new())
+    }
+
+    async fn create_api_token(
+        context: &Context<Handler>,
+        token: CreateApiTokenInput,
+    ) -> FieldResult<CreateApiTokenResponse> {
+        let span = debug_span!("[GraphQL mutation] create_api_token");
+        span.in_scope(|| {
+            debug!(?token.name, ?token.scope);
+        });
+        if context.validation_result.api_token_scope.is_some() {
+            span.in_scope(|| debug!("Cannot create an API token with another API token"));
+            return Err("Cannot create an API token with another API token".into());
+        }
+        let user_id = context.validation_result.user.clone();
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized API token creation",
+            ))?;
+        if token.name.is_empty() {
+            span.in_scope(|| debug!("Empty token name"));
+            return Err("API token name cannot be empty".into());
+        }
+        let (api_token, secret) = handler
+            .create_api_token(CreateApiTokenRequest {
+                user_id: user_id.clone(),
+                name: token.name,
+                scope: token.scope.into(),
+                expiry_date: token.expiry_date.map(|d| d.naive_utc()),
+            })
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "create_api_token",
+            format!("user:{}", user_id),
+            Some(
+                json!({
+                    "token_id": api_token.token_id,
+                    "name": api_token.name,
+                    "scope": api_token.scope.as_str(),
+                })
+                .to_string(),
+            ),
+        );
+        Ok(CreateApiTokenResponse {
+            token: api_token.into(),
+            secret,
+        })
+    }
+
+    async fn revoke_api_token(
+        context: &Context<Handler>,
+        user_id: String,
+        token_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_api_token");
+        span.in_scope(|| {
+            debug!(?user_id, ?token_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized API token revocation",
+            ))?;
+        handler
+            .delete_api_token(&user_id, token_id)
+            .instrument(span)
+            .await?;
+        context.record_change(
+            "revoke_api_token",
+            format!("user:{}", user_id),
+            Some(json!({"token_id": token_id}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    async fn revoke_session(
+        context: &Context<Handler>,
+        user_id: String,
+        session_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_session");
+        span.in_scope(|| {
+            debug!(?user_id, ?session_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let session_id = session_id
+            .parse::<i64>()
+            .map_err(|_| FieldError::from("Invalid session ID"))?;
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized session revocation",
+            ))?;
+        let jwt_hashes = handler
+            .revoke_session(&user_id, session_id)
+            .instrument(span)
+            .await?;
+        context.blacklisted_jwts.lock().unwrap().extend(jwt_hashes);
+        context.record_change(
+            "revoke_session",
+            format!("user:{}", user_id),
+            Some(json!({"session_id": session_id.to_string()}).to_string()),
+        );
+        Ok(Success::new())
+    }
+
+    async fn revoke_all_sessions(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_all_sessions");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized session revocation",
+            ))?;
+        let jwt_hashes = handler
+            .revoke_all_sessions(&user_id)
+            .instrument(span)
+            .await?;
+        context.blacklisted_jwts.lock().unwrap().extend(jwt_hashes);
+        context.record_change("revoke_all_sessions", format!("user:{}", user_id), None);
+        Ok(Success::new())
+    }
+}
+
+/// Builds the update request from the input, checking the attributes against the schema.
+fn make_update_user_request(
+    user: UpdateUserInput,
+    schema: &PublicSchema,
+    is_admin: bool,
+) -> FieldResult<UpdateUserRequest> {
+    // Consolidate attributes and fields into a combined attribute list
+    let consolidated_attributes = consolidate_attributes(
+        user.insert_attributes.unwrap_or_default(),
+        user.first_name,
+        user.last_name,
+        user.avatar,
+    );
+    // Extract any empty attributes into a list of attributes for deletion
+    let (delete_attrs, insert_attrs): (Vec<_>, Vec<_>) = consolidated_attributes
+        .into_iter()
+        .partition(|a| a.value == vec!["".to_string()]);
+    // Combine lists of attributes for removal
+    let mut delete_attributes: Vec<String> =
+        delete_attrs.iter().map(|a| a.name.to_owned()).collect();
+    delete_attributes.extend(user.remove_attributes.unwrap_or_default());
+    // Unpack attributes for update
+    let UnpackedAttributes {
+        email,
+        display_name,
+        attributes: insert_attributes,
+    } = unpack_attributes(insert_attrs, schema, is_admin)?;
+    let display_name = display_name.or_else(|| {
+        // If the display name is not inserted, but removed, reset it.
+        delete_attributes
+            .iter()
+            .find(|attr| *attr == "display_name")
+            .map(|_| String::new())
+    });
+    Ok(UpdateUserRequest {
+        user_id: UserId::new(&user.id),
+        email: user.email.map(Into::into).or(email),
+        display_name: user.display_name.or(display_name),
+        delete_attributes: delete_attributes
+            .into_iter()
+            .filter(|attr| attr != "mail" && attr != "display_name")
+            .map(Into::into)
+            .collect(),
+        insert_attributes,
+        ..Default::default()
+    })
+}
+
+/// Only the admins can change the members of the groups that grant permissions.
+async fn check_group_membership_change<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    handler: &impl GroupMembershipBackendHandler,
+    group_id: i32,
+    span: &Span,
+) -> FieldResult<()> {
+    if !context.validation_result.is_admin()
+        && handler.group_grants_permissions(GroupId(group_id)).await?
+    {
+        span.in_scope(|| debug!("Only admins can change the members of a privileged group"));
+        return Err("Unauthorized group membership modification".into());
+    }
+    Ok(())
+}
+
+/// Records the update of the user in the audit log, along with the values that changed.
+async fn record_user_update<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    handler: &impl UserReadableBackendHandler,
+    operation: &str,
+    before: &User,
+) {
+    // The change went through: if the new values cannot be read, record it without them.
+    let details = handler
+        .get_user_details(&before.user_id)
+        .await
+        .ok()
+        .and_then(|after| audit_diff(&user_audit_values(before), &user_audit_values(&after)));
+    context.record_change(operation, format!("user:{}", before.user_id), details);
+}
+
+async fn is_admin_user(
+    handler: &impl UserReadableBackendHandler,
+    user_id: &UserId,
+) -> FieldResult<bool> {
+    Ok(handler
+        .get_user_groups(user_id)
+        .await?
+        .iter()
+        .any(|g| g.display_name == "lldap_admin".into()))
+}
+
+async fn create_group_with_details<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    request: CreateGroupInput,
+    span: Span,
+) -> FieldResult<super::query::Group<Handler>> {
+    let handler = context
+        .get_admin_handler()
+        .ok_or_else(field_error_callback(&span, "Unauthorized group creation"))?;
+    let schema = handler.get_schema().await?;
+    let attributes = request
+        .attributes
+        .unwrap_or_default()
+        .into_iter()
+        .map(|attr| deserialize_attribute(&schema.get_schema().group_attributes, attr, true))
+        .collect::<Result<Vec<_>, _>>()?;
+    let request = CreateGroupRequest {
+        display_name: request.display_name.into(),
+        attributes,
+    };
+    let group_id = handler.create_group(request).await?;
+    let group_details = handler.get_group_details(group_id).instrument(span).await?;
+    context.record_change(
+        "create_group",
+        format!("group:{}", group_id.0),
+        audit_diff(&BTreeMap::new(), &group_audit_values(&group_details)),
+    );
+    super::query::Group::<Handler>::from_group_details(group_details, Arc::new(schema))
+}
+
+fn deserialize_attribute(
+    attribute_schema: &AttributeList,
+    attribute: AttributeValue,
+    is_admin: bool,
+) -> FieldResult<DomainAttribute> {
+    let attribute_name = AttributeName::from(attribute.name.as_str());
+    let attribute_schema = attribute_schema
+        .get_attribute_schema(&attribute_name)
+        .ok_or_else(|| anyhow!("Attribute {} is not defined in the schema", attribute.name))?;
+    if attribute_schema.is_readonly {
+        return Err(anyhow!(
+            "Permission denied: Attribute {} is read-only",
+            attribute.name
+        )
+        .into());
+    }
+    if !is_admin && !attribute_schema.is_editable {
+        return Err(anyhow!(
+            "Permission denied: Attribute {} is not editable by regular users",
+            attribute.name
+        )
+        .into());
+    }
+    let deserialized_values = deserialize_attribute_value(
+        &attribute.value,
+        attribute_schema.attribute_type,
+        attribute_schema.is_list,
+    )
+    .context(format!("While deserializing attribute {}", attribute.name))?;
+    Ok(DomainAttribute {
+        name: attribute_name,
+        value: deserialized_values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Query;
+    use juniper::{
+        DefaultScalarValue, EmptySubscription, GraphQLType, InputValue, RootNode, Variables,
+        execute, graphql_value,
+    };
+    use lldap_auth::access_control::{
+        ApiTokenScope as DomainApiTokenScope, Permission, ValidationResults,
+    };
+    use lldap_domain::types::{AttributeName, AttributeType, GroupDetails, UserAndGroups};
+    use lldap_domain_handlers::handler::Role;
+    use lldap_test_utils::{MockTestBackendHandler, setup_default_schema};
+    use mockall::predicate::eq;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashSet;
+
+    fn mutation_schema<'q, C, Q, M>(
+        query_root: Q,
+        mutation_root: M,
+    ) -> RootNode<'q, Q, M, EmptySubscription<C>>
+    where
+        Q: GraphQLType<DefaultScalarValue, Context = C, TypeInfo = ()> + 'q,
+        M: GraphQLType<DefaultScalarValue, Context = C, TypeInfo = ()> + 'q,
+    {
+        RootNode::new(query_root, mutation_root, EmptySubscription::<C>::new())
+    }
+
+    #[tokio::test]
+    async fn test_create_user_attribute_valid() {
+        const QUERY: &str = r#"
+            mutation CreateUserAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!, $isEditable: Boolean!) {
+                addUserAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: $isEditable) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_add_user_attribute()
+            .with(eq(CreateAttributeRequest {
+                name: AttributeName::new("AttrName0"),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_visible: false,
+                is_editable: false,
+            }))
+            .return_once(|_| Ok(()));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Admin,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("name".to_string(), InputValue::scalar("AttrName0")),
+            (
+                "attributeType".to_string(),
+                InputValue::enum_value("STRING"),
+            ),
+            ("isList".to_string(), InputValue::scalar(false)),
+            ("isVisible".to_string(), InputValue::scalar(false)),
+            ("isEditable".to_string(), InputValue::scalar(false)),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        assert_eq!(
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "addUserAttribute": {
+                        "ok": true
+                    }
+                } ),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_user_attribute_invalid() {
+        const QUERY: &str = r#"
+            mutation CreateUserAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!, $isEditable: Boolean!) {
+                addUserAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: $isEditable) {
+                    ok
+                }
+            }
+        "#;
+        let mock = MockTestBackendHandler::new();
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Admin,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("name".to_string(), InputValue::scalar("AttrName_0")),
+            (
+                "attributeType".to_string(),
+                InputValue::enum_value("STRING"),
+            ),
+            ("isList".to_string(), InputValue::scalar(false)),
+            ("isVisible".to_string(), InputValue::scalar(false)),
+            ("isEditable".to_string(), InputValue::scalar(false)),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        let result = execute(QUERY, None, &schema, &vars, &context).await;
+        match result {
+            Ok(res) => {
+                let (response, errors) = res;
+                assert!(response.is_null());
+                let expected_error_msg =
+                    "Cannot create attribute with invalid name. Valid characters: a-z, A-Z, 0-9, and dash (-). Invalid chars found: _"
+                        .to_string();
+                assert!(
+                    errors
+                        .iter()
+                        .all(|e| e.error().message() == expected_error_msg)
+                );
+            }
+            Err(_) => {
+                panic!();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_group_attribute_valid() {
+        const QUERY: &str = r#"
+            mutation CreateGroupAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!) {
+                addGroupAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: false) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_add_group_attribute()
+            .with(eq(CreateAttributeRequest {
+                name: AttributeName::new("AttrName0"),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_visible: false,
+                is_editable: false,
+            }))
+            .return_once(|_| Ok(()));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Admin,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("name".to_string(), InputValue::scalar("AttrName0")),
+            (
+                "attributeType".to_string(),
+                InputValue::enum_value("STRING"),
+            ),
+            ("isList".to_string(), InputValue::scalar(false)),
+            ("isVisible".to_string(), InputValue::scalar(false)),
+            ("isEditable".to_string(), InputValue::scalar(false)),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        assert_eq!(
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "addGroupAttribute": {
+                        "ok": true
+                    }
+                } ),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_group_attribute_invalid() {
+        const QUERY: &str = r#"
+            mutation CreateUserAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!, $isEditable: Boolean!) {
+                addUserAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: $isEditable) {
+                    ok
+                }
+            }
+        "#;
+        let mock = MockTestBackendHandler::new();
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Admin,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("name".to_string(), InputValue::scalar("AttrName_0")),
+            (
+                "attributeType".to_string(),
+                InputValue::enum_value("STRING"),
+            ),
+            ("isList".to_string(), InputValue::scalar(false)),
+            ("isVisible".to_string(), InputValue::scalar(false)),
+            ("isEditable".to_string(), InputValue::scalar(false)),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        let result = execute(QUERY, None, &schema, &vars, &context).await;
+        match result {
+            Ok(res) => {
+                let (response, errors) = res;
+                assert!(response.is_null());
+                let expected_error_msg =
+                    "Cannot create attribute with invalid name. Valid characters: a-z, A-Z, 0-9, and dash (-). Invalid chars found: _"
+                        .to_string();
+                assert!(
+                    errors
+                        .iter()
+                        .all(|e| e.error().message() == expected_error_msg)
+                );
+            }
+            Err(_) => {
+                panic!();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attribute_consolidation_attr_precedence() {
+        let attributes = vec![
+            AttributeValue {
+                name: "first_name".to_string(),
+                value: vec!["expected-first".to_string()],
+            },
+            AttributeValue {
+                name: "last_name".to_string(),
+                value: vec!["expected-last".to_string()],
+            },
+            AttributeValue {
+                name: "avatar".to_string(),
+                value: vec!["expected-avatar".to_string()],
+            },
+        ];
+        let res = consolidate_attributes(
+            attributes.clone(),
+            Some("overridden-first".to_string()),
+            Some("overridden-last".to_string()),
+            Some("overriden-avatar".to_string()),
+        );
+        assert_eq!(
+            res,
+            vec![
+                AttributeValue {
+                    name: "avatar".to_string(),
+                    value: vec!["expected-avatar".to_string()],
+                },
+                AttributeValue {
+                    name: "first_name".to_string(),
+                    value: vec!["expected-first".to_string()],
+                },
+                AttributeValue {
+                    name: "last_name".to_string(),
+                    value: vec!["expected-last".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attribute_consolidation_field_fallback() {
+        let attributes = Vec::new();
+        let res = consolidate_attributes(
+            attributes.clone(),
+            Some("expected-first".to_string()),
+            Some("expected-last".to_string()),
+            Some("expected-avatar".to_string()),
+        );
+        assert_eq!(
+            res,
+            vec![
+                AttributeValue {
+                    name: "avatar".to_string(),
+                    value: vec!["expected-avatar".to_string()],
+                },
+                AttributeValue {
+                    name: "first_name".to_string(),
+                    value: vec!["expected-first".to_string()],
+                },
+                AttributeValue {
+                    name: "last_name".to_string(),
+                    value: vec!["expected-last".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attribute_consolidation_field_fallback_2() {
+        let attributes = vec![AttributeValue {
+            name: "First_Name".to_string(),
+            value: vec!["expected-first".to_string()],
+        }];
+        let res = consolidate_attributes(
+            attributes.clone(),
+            Some("overriden-first".to_string()),
+            Some("expected-last".to_string()),
+            Some("expected-avatar".to_string()),
+        );
+        assert_eq!(
+            res,
+            vec![
+                AttributeValue {
+                    name: "avatar".to_string(),
+                    value: vec!["expected-avatar".to_string()],
+                },
+                AttributeValue {
+                    name: "first_name".to_string(),
+                    value: vec!["expected-first".to_string()],
+                },
+                AttributeValue {
+                    name: "last_name".to_string(),
+                    value: vec!["expected-last".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user() {
+        const QUERY: &str = r#"
+            mutation RenameUser($userId: String!, $newUserId: String!) {
+                renameUser(userId: $userId, newUserId: $newUserId) {
+                    ok
                 }
             }
         "#;
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_add_user_attribute()
-            .with(eq(CreateAttributeRequest {
-                name: AttributeName::new("AttrName0"),
-                attribute_type: AttributeType::String,
-                is_list: false,
-                is_visible: false,
-                is_editable: false,
-            }))
+        mock.expect_list_users()
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        mock.expect_rename_user()
+            .with(eq(UserId::new("bob")), eq(UserId::new("robert")))
+            .return_once(|_, _| Ok(HashSet::from([1234])));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("admin"),
+                permission: Permission::Admin,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("bob")),
+            ("newUserId".to_string(), InputValue::scalar("Robert")),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        assert_eq!(
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "renameUser": {
+                        "ok": true
+                    }
+                } ),
+                vec![]
+            ))
+        );
+        assert_eq!(
+            *context.blacklisted_jwts.lock().unwrap(),
+            HashSet::from([1234])
+        );
+    }
+
+    async fn rename_user_errors(
+        mock: MockTestBackendHandler,
+        permission: Permission,
+        new_user_id: &str,
+    ) -> Vec<String> {
+        const QUERY: &str = r#"
+            mutation RenameUser($userId: String!, $newUserId: String!) {
+                renameUser(userId: $userId, newUserId: $newUserId) {
+                    ok
+                }
+            }
+        "#;
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("admin"),
+                permission,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("bob")),
+            ("newUserId".to_string(), InputValue::scalar(new_user_id)),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert!(context.blacklisted_jwts.lock().unwrap().is_empty());
+        errors
+            .iter()
+            .map(|e| e.error().message().to_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_unauthorized() {
+        let mock = MockTestBackendHandler::new();
+        assert_eq!(
+            rename_user_errors(mock, Permission::Regular, "robert").await,
+            vec!["Unauthorized user rename"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_invalid_id() {
+        let mock = MockTestBackendHandler::new();
+        assert_eq!(
+            rename_user_errors(mock, Permission::Admin, "robert,ou=x").await,
+            vec![
+                "Cannot rename user to an invalid user id. Valid characters: a-z, A-Z, 0-9, dash (-), underscore (_), dot (.) and at sign (@). Invalid chars found: ,="
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_to_email_of_other_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "robert@example.com".to_owned(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("robert"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        assert_eq!(
+            rename_user_errors(mock, Permission::Admin, "robert@example.com").await,
+            vec!["The user id `robert@example.com` is already the email of another user"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disable_user_revokes_sessions() {
+        const QUERY: &str = r#"
+            mutation SetUserAccountStatus($userId: String!) {
+                setUserAccountStatus(userId: $userId, disabled: true) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details().times(2).returning(|_| {
+            Ok(User {
+                user_id: UserId::new("bob"),
+                ..Default::default()
+            })
+        });
+        mock.expect_update_user().times(1).return_once(|_| Ok(()));
+        mock.expect_revoke_all_sessions()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(HashSet::from([1234])));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("admin"),
+                permission: Permission::Admin,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([("userId".to_string(), InputValue::scalar("bob"))]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        assert_eq!(
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((
+                graphql_value!({"setUserAccountStatus": {"ok": true}}),
+                vec![]
+            ))
+        );
+        assert_eq!(
+            *context.blacklisted_jwts.lock().unwrap(),
+            HashSet::from([1234])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_membership_api_token() {
+        const QUERY: &str = r#"
+            mutation AddUserToGroup($userId: String!, $groupId: Int!) {
+                addUserToGroup(userId: $userId, groupId: $groupId) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(3)))
+            .return_once(|_, _| Ok(()));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("admin"),
+                permission: Permission::Admin,
+                api_token_scope: Some(DomainApiTokenScope::GroupMembership),
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("bob")),
+            ("groupId".to_string(), InputValue::scalar(3)),
+        ]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        assert_eq!(
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "addUserToGroup": {
+                        "ok": true
+                    }
+                } ),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readonly_api_token_cannot_write() {
+        const QUERY: &str = r#"
+            mutation DeleteUser($userId: String!) {
+                deleteUser(userId: $userId) {
+                    ok
+                }
+            }
+        "#;
+        let mock = MockTestBackendHandler::new();
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("admin"),
+                permission: Permission::Admin,
+                api_token_scope: Some(DomainApiTokenScope::Readonly),
+                role_permissions: HashSet::new(),
+            },
+        );
+        let vars = Variables::from([("userId".to_string(), InputValue::scalar("bob"))]);
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error().message(), "Unauthorized user deletion");
+    }
+
+    #[tokio::test]
+    async fn test_role_can_edit_allowed_attributes() {
+        const ALLOWED_QUERY: &str = r#"
+            mutation {
+                updateUser(user: { id: "bob", firstName: "Bob" }) {
+                    ok
+                }
+            }
+        "#;
+        const DENIED_QUERY: &str = r#"
+            mutation {
+                updateUser(user: { id: "bob", lastName: "Bobberson" }) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_update_user()
+            .withf(|request| {
+                request.user_id == UserId::new("bob")
+                    && request.insert_attributes.len() == 1
+                    && request.insert_attributes[0].name == "first_name".into()
+            })
+            .times(1)
             .return_once(|_| Ok(()));
+        let mut seq = mockall::Sequence::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    attributes: vec![DomainAttribute {
+                        name: "first_name".into(),
+                        value: "Bob".to_string().into(),
+                    }],
+                    ..Default::default()
+                })
+            });
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("helpdesk"),
+                permission: Permission::Regular,
+                api_token_scope: None,
+                role_permissions: HashSet::from([RolePermission::EditUserAttribute(
+                    "first_name".to_owned(),
+                )]),
+            },
+        );
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+        );
+        let vars = Variables::new();
+        assert_eq!(
+            execute(ALLOWED_QUERY, None, &schema, &vars, &context).await,
+            Ok((graphql_value!({"updateUser": {"ok": true}}), vec![]))
+        );
+        let (response, errors) = execute(DENIED_QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().message(),
+            "Permission denied: Cannot edit the attribute last_name"
+        );
+        let audit_events = context.audit_events.lock().unwrap();
+        assert_eq!(audit_events.len(), 1);
+        assert_eq!(audit_events[0].actor.as_deref(), Some("helpdesk"));
+        assert_eq!(audit_events[0].operation, "update_user");
+        assert_eq!(audit_events[0].target.as_deref(), Some("user:bob"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(audit_events[0].details.as_ref().unwrap())
+                .unwrap(),
+            json!({"first_name": {"before": null, "after": "Bob"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_role_cannot_add_to_privileged_group() {
+        const QUERY: &str = r#"
+            mutation AddUserToGroup($userId: String!, $groupId: Int!) {
+                addUserToGroup(userId: $userId, groupId: $groupId) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_group_details()
+            .with(eq(GroupId(1)))
+            .return_once(|_| {
+                Ok(GroupDetails {
+                    group_id: GroupId(1),
+                    display_name: "lldap_admin".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                })
+            });
+        mock.expect_get_ancestor_groups()
+            .with(eq(GroupId(1)))
+            .return_once(|_| Ok(Vec::new()));
         let context = Context::<MockTestBackendHandler>::new_for_tests(
             mock,
             ValidationResults {
-                user: UserId::new("bob"),
-                permission: Permission::Admin,
+                user: UserId::new("manager"),
+                permission: Permission::Regular,
+                api_token_scope: None,
+                role_permissions: HashSet::from([RolePermission::ManageGroupMemberships]),
             },
         );
         let vars = Variables::from([
-            ("name".to_string(), InputValue::scalar("AttrName0")),
-            (
-                "attributeType".to_string(),
-                InputValue::enum_value("STRING"),
-            ),
-            ("isList".to_string(), InputValue::scalar(false)),
-            ("isVisible".to_string(), InputValue::scalar(false)),
-            ("isEditable".to_string(), InputValue::scalar(false)),
+            ("userId".to_string(), InputValue::scalar("manager")),
+            ("groupId".to_string(), InputValue::scalar(1)),
         ]);
         let schema = mutation_schema(
             Query::<MockTestBackendHandler>::new(),
             Mutation::<MockTestBackendHandler>::new(),
         );
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
         assert_eq!(
-            execute(QUERY, None, &schema, &vars, &context).await,
-            Ok((
-                graphql_value!(
-                {
-                    "addUserAttribute": {
-                        "ok": true
-                    }
-                } ),
-                vec![]
-            ))
+            errors[0].error().message(),
+            "Unauthorized group membership modification"
         );
     }
 
     #[tokio::test]
-    async fn test_create_user_attribute_invalid() {
+    async fn test_role_cannot_add_to_group_nested_in_privileged_group() {
         const QUERY: &str = r#"
-            mutation CreateUserAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!, $isEditable: Boolean!) {
-                addUserAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: $isEditable) {
+            mutation AddUserToGroup($userId: String!, $groupId: Int!) {
+                addUserToGroup(userId: $userId, groupId: $groupId) {
                     ok
                 }
             }
         "#;
-        let mock = MockTestBackendHandler::new();
+        let make_group = |id, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.into(),
+            creation_date: chrono::Utc::now().naive_utc(),
+            modified_date: chrono::Utc::now().naive_utc(),
+            uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            attributes: Vec::new(),
+        };
+        let mut mock = MockTestBackendHandler::new();
+        let group = make_group(5, "sysadmins");
+        mock.expect_get_group_details()
+            .with(eq(GroupId(5)))
+            .return_once(move |_| Ok(group));
+        // "sysadmins" is nested in "it", itself nested in "lldap_admin".
+        let ancestors = vec![make_group(1, "lldap_admin"), make_group(3, "it")];
+        mock.expect_get_ancestor_groups()
+            .with(eq(GroupId(5)))
+            .return_once(move |_| Ok(ancestors));
+        mock.expect_add_user_to_group().never();
         let context = Context::<MockTestBackendHandler>::new_for_tests(
             mock,
             ValidationResults {
-                user: UserId::new("bob"),
-                permission: Permission::Admin,
+                user: UserId::new("manager"),
+                permission: Permission::Regular,
+                api_token_scope: None,
+                role_permissions: HashSet::from([RolePermission::ManageGroupMemberships]),
             },
         );
         let vars = Variables::from([
-            ("name".to_string(), InputValue::scalar("AttrName_0")),
-            (
-                "attributeType".to_string(),
-                InputValue::enum_value("STRING"),
-            ),
-            ("isList".to_string(), InputValue::scalar(false)),
-            ("isVisible".to_string(), InputValue::scalar(false)),
-            ("isEditable".to_string(), InputValue::scalar(false)),
+            ("userId".to_string(), InputValue::scalar("manager")),
+            ("groupId".to_string(), InputValue::scalar(5)),
         ]);
         let schema = mutation_schema(
             Query::<MockTestBackendHandler>::new(),
             Mutation::<MockTestBackendHandler>::new(),
         );
-        let result = execute(QUERY, None, &schema, &vars, &context).await;
-        match result {
-            Ok(res) => {
-                let (response, errors) = res;
-                assert!(response.is_null());
-                let expected_error_msg =
-                    "Cannot create attribute with invalid name. Valid characters: a-z, A-Z, 0-9, and dash (-). Invalid chars found: _"
-                        .to_string();
-                assert!(
-                    errors
-                        .iter()
-                        .all(|e| e.error().message() == expected_error_msg)
-                );
-            }
-            Err(_) => {
-                panic!();
-            }
-        }
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().message(),
+            "Unauthorized group membership modification"
+        );
     }
 
     #[tokio::test]
-    async fn test_create_group_attribute_valid() {
+    async fn test_role_cannot_add_to_group_nested_in_role_group() {
         const QUERY: &str = r#"
-            mutation CreateGroupAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!) {
-                addGroupAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: false) {
+            mutation AddUserToGroup($userId: String!, $groupId: Int!) {
+                addUserToGroup(userId: $userId, groupId: $groupId) {
                     ok
                 }
             }
         "#;
+        let make_group = |id, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.into(),
+            creation_date: chrono::Utc::now().naive_utc(),
+            modified_date: chrono::Utc::now().naive_utc(),
+            uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            attributes: Vec::new(),
+        };
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_add_group_attribute()
-            .with(eq(CreateAttributeRequest {
-                name: AttributeName::new("AttrName0"),
-                attribute_type: AttributeType::String,
-                is_list: false,
-                is_visible: false,
-                is_editable: false,
-            }))
-            .return_once(|_| Ok(()));
+        let group = make_group(5, "interns");
+        mock.expect_get_group_details()
+            .with(eq(GroupId(5)))
+            .return_once(move |_| Ok(group));
+        let ancestors = vec![make_group(3, "helpdesk")];
+        mock.expect_get_ancestor_groups()
+            .with(eq(GroupId(5)))
+            .return_once(move |_| Ok(ancestors));
+        mock.expect_list_roles().return_once(|| {
+            Ok(vec![Role {
+                name: "user_editor".to_owned(),
+                permissions: vec![RolePermission::CreateUsers],
+                group_ids: vec![GroupId(3)],
+            }])
+        });
+        mock.expect_add_user_to_group().never();
         let context = Context::<MockTestBackendHandler>::new_for_tests(
             mock,
             ValidationResults {
-                user: UserId::new("bob"),
-                permission: Permission::Admin,
+                user: UserId::new("manager"),
+                permission: Permission::Regular,
+                api_token_scope: None,
+                role_permissions: HashSet::from([RolePermission::ManageGroupMemberships]),
             },
         );
         let vars = Variables::from([
-            ("name".to_string(), InputValue::scalar("AttrName0")),
-            (
-                "attributeType".to_string(),
-                InputValue::enum_value("STRING"),
-            ),
-            ("isList".to_string(), InputValue::scalar(false)),
-            ("isVisible".to_string(), InputValue::scalar(false)),
-            ("isEditable".to_string(), InputValue::scalar(false)),
+            ("userId".to_string(), InputValue::scalar("manager")),
+            ("groupId".to_string(), InputValue::scalar(5)),
         ]);
         let schema = mutation_schema(
             Query::<MockTestBackendHandler>::new(),
             Mutation::<MockTestBackendHandler>::new(),
         );
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
         assert_eq!(
-            execute(QUERY, None, &schema, &vars, &context).await,
-            Ok((
-                graphql_value!(
-                {
-                    "addGroupAttribute": {
-                        "ok": true
-                    }
-                } ),
-                vec![]
-            ))
+            errors[0].error().message(),
+            "Unauthorized group membership modification"
         );
     }
 
     #[tokio::test]
-    async fn test_create_group_attribute_invalid() {
+    async fn test_admin_token_cannot_add_to_privileged_group() {
         const QUERY: &str = r#"
-            mutation CreateUserAttribute($name: String!, $attributeType: AttributeType!, $isList: Boolean!, $isVisible: Boolean!, $isEditable: Boolean!) {
-                addUserAttribute(name: $name, attributeType: $attributeType, isList: $isList, isVisible: $isVisible, isEditable: $isEditable) {
+            mutation AddUserToGroup($userId: String!, $groupId: Int!) {
+                addUserToGroup(userId: $userId, groupId: $groupId) {
                     ok
                 }
             }
         "#;
-        let mock = MockTestBackendHandler::new();
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_group_details()
+            .with(eq(GroupId(1)))
+            .return_once(|_| {
+                Ok(GroupDetails {
+                    group_id: GroupId(1),
+                    display_name: "lldap_admin".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                })
+            });
+        mock.expect_get_ancestor_groups()
+            .with(eq(GroupId(1)))
+            .return_once(|_| Ok(Vec::new()));
+        mock.expect_add_user_to_group().never();
+        // A token restricted to group memberships must not be able to grant the admin rights.
         let context = Context::<MockTestBackendHandler>::new_for_tests(
             mock,
             ValidationResults {
-                user: UserId::new("bob"),
+                user: UserId::new("admin"),
                 permission: Permission::Admin,
+                api_token_scope: Some(DomainApiTokenScope::GroupMembership),
+                role_permissions: HashSet::new(),
             },
         );
         let vars = Variables::from([
-            ("name".to_string(), InputValue::scalar("AttrName_0")),
-            (
-                "attributeType".to_string(),
-                InputValue::enum_value("STRING"),
-            ),
-            ("isList".to_string(), InputValue::scalar(false)),
-            ("isVisible".to_string(), InputValue::scalar(false)),
-            ("isEditable".to_string(), InputValue::scalar(false)),
+            ("userId".to_string(), InputValue::scalar("bob")),
+            ("groupId".to_string(), InputValue::scalar(1)),
         ]);
         let schema = mutation_schema(
             Query::<MockTestBackendHandler>::new(),
             Mutation::<MockTestBackendHandler>::new(),
         );
-        let result = execute(QUERY, None, &schema, &vars, &context).await;
-        match result {
-            Ok(res) => {
-                let (response, errors) = res;
-                assert!(response.is_null());
-                let expected_error_msg =
-                    "Cannot create attribute with invalid name. Valid characters: a-z, A-Z, 0-9, and dash (-). Invalid chars found: _"
-                        .to_string();
-                assert!(
-                    errors
-                        .iter()
-                        .all(|e| e.error().message() == expected_error_msg)
-                );
-            }
-            Err(_) => {
-                panic!();
-            }
-        }
-    }
-
-    #[tokio::test]
-    async fn test_attribute_consolidation_attr_precedence() {
-        let attributes = vec![
-            AttributeValue {
-                name: "first_name".to_string(),
-                value: vec!["expected-first".to_string()],
-            },
-            AttributeValue {
-                name: "last_name".to_string(),
-                value: vec!["expected-last".to_string()],
-            },
-            AttributeValue {
-                name: "avatar".to_string(),
-                value: vec!["expected-avatar".to_string()],
-            },
-        ];
-        let res = consolidate_attributes(
-            attributes.clone(),
-            Some("overridden-first".to_string()),
-            Some("overridden-last".to_string()),
-            Some("overriden-avatar".to_string()),
-        );
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
         assert_eq!(
-            res,
-            vec![
-                AttributeValue {
-                    name: "avatar".to_string(),
-                    value: vec!["expected-avatar".to_string()],
-                },
-                AttributeValue {
-                    name: "first_name".to_string(),
-                    value: vec!["expected-first".to_string()],
-                },
-                AttributeValue {
-                    name: "last_name".to_string(),
-                    value: vec!["expected-last".to_string()],
-                },
-            ]
+            errors[0].error().message(),
+            "Unauthorized group membership modification"
         );
     }
 
     #[tokio::test]
-    async fn test_attribute_consolidation_field_fallback() {
-        let attributes = Vec::new();
-        let res = consolidate_attributes(
-            attributes.clone(),
-            Some("expected-first".to_string()),
-            Some("expected-last".to_string()),
-            Some("expected-avatar".to_string()),
+    async fn test_cannot_expire_own_admin_rights() {
+        const QUERY: &str = r#"
+            mutation SetMembershipExpiryDate($userId: String!, $groupId: Int!, $expiryDate: DateTimeUtc) {
+                setMembershipExpiryDate(userId: $userId, groupId: $groupId, expiryDate: $expiryDate) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_set_membership_expiry_date()
+            .with(eq(UserId::new("admin")), eq(GroupId(1)), eq(None))
+            .times(1)
+            .return_once(|_, _, _| Ok(()));
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
         );
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("admin")),
+            ("groupId".to_string(), InputValue::scalar(1)),
+            (
+                "expiryDate".to_string(),
+                InputValue::scalar("2030-01-01T00:00:00Z"),
+            ),
+        ]);
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(errors.len(), 1);
         assert_eq!(
-            res,
-            vec![
-                AttributeValue {
-                    name: "avatar".to_string(),
-                    value: vec!["expected-avatar".to_string()],
-                },
-                AttributeValue {
-                    name: "first_name".to_string(),
-                    value: vec!["expected-first".to_string()],
-                },
-                AttributeValue {
-                    name: "last_name".to_string(),
-                    value: vec!["expected-last".to_string()],
-                },
-            ]
+            errors[0].error().message(),
+            "Cannot remove admin rights for current user"
+        );
+        // Making the admin rights permanent again is fine.
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("admin")),
+            ("groupId".to_string(), InputValue::scalar(1)),
+            ("expiryDate".to_string(), InputValue::null()),
+        ]);
+        assert_eq!(
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((
+                graphql_value!({"setMembershipExpiryDate": {"ok": true}}),
+                vec![]
+            ))
         );
     }
 
     #[tokio::test]
-    async fn test_attribute_consolidation_field_fallback_2() {
-        let attributes = vec![AttributeValue {
-            name: "First_Name".to_string(),
-            value: vec!["expected-first".to_string()],
-        }];
-        let res = consolidate_attributes(
-            attributes.clone(),
-            Some("overriden-first".to_string()),
-            Some("expected-last".to_string()),
-            Some("expected-avatar".to_string()),
+    async fn test_owner_can_only_manage_owned_group() {
+        const QUERY: &str = r#"
+            mutation AddUserToGroup($userId: String!, $groupId: Int!) {
+                addUserToGroup(userId: $userId, groupId: $groupId) {
+                    ok
+                }
+            }
+        "#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_group_details()
+            .with(eq(GroupId(5)))
+            .return_once(|_| {
+                Ok(GroupDetails {
+                    group_id: GroupId(5),
+                    display_name: "team".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                })
+            });
+        mock.expect_get_ancestor_groups()
+            .with(eq(GroupId(5)))
+            .return_once(|_| Ok(Vec::new()));
+        mock.expect_list_roles().return_once(|| Ok(Vec::new()));
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(5)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("lead"),
+                permission: Permission::Regular,
+                api_token_scope: None,
+                role_permissions: HashSet::from([RolePermission::ManageGroupMembers(5)]),
+            },
+        );
+        let schema = mutation_schema(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
         );
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("bob")),
+            ("groupId".to_string(), InputValue::scalar(5)),
+        ]);
         assert_eq!(
-            res,
-            vec![
-                AttributeValue {
-                    name: "avatar".to_string(),
-                    value: vec!["expected-avatar".to_string()],
-                },
-                AttributeValue {
-                    name: "first_name".to_string(),
-                    value: vec!["expected-first".to_string()],
-                },
-                AttributeValue {
-                    name: "last_name".to_string(),
-                    value: vec!["expected-last".to_string()],
-                },
-            ]
+            execute(QUERY, None, &schema, &vars, &context).await,
+            Ok((graphql_value!({"addUserToGroup": {"ok": true}}), vec![]))
+        );
+        let vars = Variables::from([
+            ("userId".to_string(), InputValue::scalar("bob")),
+            ("groupId".to_string(), InputValue::scalar(6)),
+        ]);
+        let (response, errors) = execute(QUERY, None, &schema, &vars, &context)
+            .await
+            .unwrap();
+        assert!(response.is_null());
+        assert_eq!(
+            errors[0].error().message(),
+            "Unauthorized group membership modification"
         );
     }
 }
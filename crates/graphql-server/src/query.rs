@@ -1,14 +1,19 @@
 use crate::api::{Context, field_error_callback};
 use anyhow::Context as AnyhowContext;
 use chrono::TimeZone;
-use juniper::{FieldResult, GraphQLInputObject, graphql_object};
-use lldap_access_control::{ReadonlyBackendHandler, UserReadableBackendHandler};
+use juniper::{FieldResult, GraphQLEnum, GraphQLInputObject, GraphQLObject, graphql_object};
+use lldap_access_control::{
+    AdminBackendHandler, ReadonlyBackendHandler, UserReadableBackendHandler,
+    UserWriteableBackendHandler,
+};
 use lldap_domain::{
     deserialize::deserialize_attribute_value,
     public_schema::PublicSchema,
     types::{AttributeType, Cardinality, GroupDetails, GroupId, LdapObjectClass, UserId},
 };
-use lldap_domain_handlers::handler::{BackendHandler, ReadSchemaBackendHandler};
+use lldap_domain_handlers::handler::{
+    BackendHandler, GroupListerBackendHandler, ReadSchemaBackendHandler, UserListerBackendHandler,
+};
 use lldap_domain_model::model::UserColumn;
 use lldap_ldap::{
     UserFieldType, get_default_group_object_classes, get_default_user_object_classes,
@@ -26,6 +31,18 @@ type DomainAttributeList = lldap_domain::schema::AttributeList;
 type DomainAttributeSchema = lldap_domain::schema::AttributeSchema;
 type DomainAttribute = lldap_domain::types::Attribute;
 type DomainAttributeValue = lldap_domain::types::AttributeValue;
+type DomainApiToken = lldap_domain_handlers::handler::ApiToken;
+type DomainApiTokenScope = lldap_auth::access_control::ApiTokenScope;
+type DomainSession = lldap_domain_handlers::handler::Session;
+type DomainRole = lldap_domain_handlers::handler::Role;
+type DomainGroupRequestFilter = lldap_domain_handlers::handler::GroupRequestFilter;
+type DomainAuditEvent = lldap_domain_handlers::handler::AuditEvent;
+type DomainAuditEventFilter = lldap_domain_handlers::handler::AuditEventFilter;
+type DomainAuditSource = lldap_domain_handlers::handler::AuditSource;
+
+/// The number of audit events returned when no limit is given, and the maximum limit.
+const DEFAULT_AUDIT_LOG_LIMIT: i32 = 100;
+const MAX_AUDIT_LOG_LIMIT: i32 = 1000;
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
 /// A filter for requests, specifying a boolean expression based on field constraints. Only one of
@@ -37,10 +54,14 @@ pub struct RequestFilter {
     eq: Option<EqualityConstraint>,
     member_of: Option<String>,
     member_of_id: Option<i32>,
+    date: Option<DateConstraint>,
 }
 
 impl RequestFilter {
-    fn try_into_domain_filter(self, schema: &PublicSchema) -> FieldResult<DomainRequestFilter> {
+    pub(crate) fn try_into_domain_filter(
+        self,
+        schema: &PublicSchema,
+    ) -> FieldResult<DomainRequestFilter> {
         match (
             self.eq,
             self.any,
@@ -48,8 +69,9 @@ impl RequestFilter {
             self.not,
             self.member_of,
             self.member_of_id,
+            self.date,
         ) {
-            (Some(eq), None, None, None, None, None) => {
+            (Some(eq), None, None, None, None, None, None) => {
                 match map_user_field(&eq.field.as_str().into(), schema) {
                     UserFieldType::NoMatch => {
                         Err(format!("Unknown request filter: {}", &eq.field).into())
@@ -74,26 +96,27 @@ impl RequestFilter {
                     }
                 }
             }
-            (None, Some(any), None, None, None, None) => Ok(DomainRequestFilter::Or(
+            (None, Some(any), None, None, None, None, None) => Ok(DomainRequestFilter::Or(
                 any.into_iter()
                     .map(|f| f.try_into_domain_filter(schema))
                     .collect::<FieldResult<Vec<_>>>()?,
             )),
-            (None, None, Some(all), None, None, None) => Ok(DomainRequestFilter::And(
+            (None, None, Some(all), None, None, None, None) => Ok(DomainRequestFilter::And(
                 all.into_iter()
                     .map(|f| f.try_into_domain_filter(schema))
                     .collect::<FieldResult<Vec<_>>>()?,
             )),
-            (None, None, None, Some(not), None, None) => Ok(DomainRequestFilter::Not(Box::new(
-                (*not).try_into_domain_filter(schema)?,
-            ))),
-            (None, None, None, None, Some(group), None) => {
+            (None, None, None, Some(not), None, None, None) => Ok(DomainRequestFilter::Not(
+                Box::new((*not).try_into_domain_filter(schema)?),
+            )),
+            (None, None, None, None, Some(group), None, None) => {
                 Ok(DomainRequestFilter::MemberOf(group.into()))
             }
-            (None, None, None, None, None, Some(group_id)) => {
+            (None, None, None, None, None, Some(group_id), None) => {
                 Ok(DomainRequestFilter::MemberOfId(GroupId(group_id)))
             }
-            (None, None, None, None, None, None) => {
+            (None, None, None, None, None, None, Some(date)) => date.try_into_domain_filter(schema),
+            (None, None, None, None, None, None, None) => {
                 Err("No field specified in request filter".into())
             }
             _ => Err("Multiple fields specified in request filter".into()),
@@ -107,6 +130,46 @@ pub struct EqualityConstraint {
     value: String,
 }
 
+/// Matches the users for which the date field is set, and within the bounds (inclusive), if any.
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+pub struct DateConstraint {
+    field: String,
+    after: Option<chrono::DateTime<chrono::Utc>>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DateConstraint {
+    fn try_into_domain_filter(self, schema: &PublicSchema) -> FieldResult<DomainRequestFilter> {
+        let column = match map_user_field(&self.field.as_str().into(), schema) {
+            UserFieldType::PrimaryField(
+                column @ (UserColumn::CreationDate
+                | UserColumn::ModifiedDate
+                | UserColumn::ExpiryDate
+                | UserColumn::LastLogin
+                | UserColumn::PasswordChanged),
+            ) => column,
+            UserFieldType::NoMatch => {
+                return Err(format!("Unknown request filter: {}", &self.field).into());
+            }
+            _ => return Err(format!("Not a date field: {}", &self.field).into()),
+        };
+        let mut filters = self
+            .after
+            .map(|after| DomainRequestFilter::DateGreaterOrEqual(column, after.naive_utc()))
+            .into_iter()
+            .chain(
+                self.before
+                    .map(|before| DomainRequestFilter::DateLessOrEqual(column, before.naive_utc())),
+            )
+            .collect::<Vec<_>>();
+        Ok(match filters.len() {
+            0 => DomainRequestFilter::HasValue(column),
+            1 => filters.remove(0),
+            _ => DomainRequestFilter::And(filters),
+        })
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL query type.
 pub struct Query<Handler: BackendHandler> {
@@ -141,15 +204,29 @@ impl<Handler: BackendHandler> Query<Handler> {
         });
         let user_id = urlencoding::decode(&user_id).context("Invalid user parameter")?;
         let user_id = UserId::new(&user_id);
-        let handler = context
-            .get_readable_handler(&user_id)
+        let schema = Arc::new(self.get_schema(context, span.clone()).await?);
+        if let Some(handler) = context.get_readable_handler(&user_id) {
+            let user = handler.get_user_details(&user_id).instrument(span).await?;
+            return User::<Handler>::from_user(user, schema);
+        }
+        let handler =
+            context
+                .get_role_restricted_lister_handler()
+                .ok_or_else(field_error_callback(
+                    &span,
+                    "Unauthorized access to user data",
+                ))?;
+        let user = handler
+            .list_users(Some(DomainRequestFilter::UserId(user_id)), false)
+            .instrument(span.clone())
+            .await?
+            .into_iter()
+            .next()
             .ok_or_else(field_error_callback(
                 &span,
                 "Unauthorized access to user data",
             ))?;
-        let schema = Arc::new(self.get_schema(context, span.clone()).await?);
-        let user = handler.get_user_details(&user_id).instrument(span).await?;
-        User::<Handler>::from_user(user, schema)
+        User::<Handler>::from_user_and_groups(user, schema)
     }
 
     async fn users(
@@ -160,22 +237,23 @@ impl<Handler: BackendHandler> Query<Handler> {
         span.in_scope(|| {
             debug!(?filters);
         });
-        let handler = context
-            .get_readonly_handler()
-            .ok_or_else(field_error_callback(
-                &span,
-                "Unauthorized access to user list",
-            ))?;
         let schema = Arc::new(self.get_schema(context, span.clone()).await?);
-        let users = handler
-            .list_users(
-                filters
-                    .map(|f| f.try_into_domain_filter(&schema))
-                    .transpose()?,
-                false,
-            )
-            .instrument(span)
-            .await?;
+        let filters = filters
+            .map(|f| f.try_into_domain_filter(&schema))
+            .transpose()?;
+        let users = if let Some(handler) = context.get_readonly_handler() {
+            handler.list_users(filters, false).instrument(span).await?
+        } else {
+            context
+                .get_role_restricted_lister_handler()
+                .ok_or_else(field_error_callback(
+                    &span,
+                    "Unauthorized access to user list",
+                ))?
+                .list_users(filters, false)
+                .instrument(span)
+                .await?
+        };
         users
             .into_iter()
             .map(|u| User::<Handler>::from_user_and_groups(u, schema.clone()))
@@ -184,14 +262,20 @@ impl<Handler: BackendHandler> Query<Handler> {
 
     async fn groups(context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
         let span = debug_span!("[GraphQL query] groups");
-        let handler = context
-            .get_readonly_handler()
-            .ok_or_else(field_error_callback(
-                &span,
-                "Unauthorized access to group list",
-            ))?;
         let schema = Arc::new(self.get_schema(context, span.clone()).await?);
-        let domain_groups = handler.list_groups(None).instrument(span).await?;
+        let domain_groups = if let Some(handler) = context.get_readonly_handler() {
+            handler.list_groups(None).instrument(span).await?
+        } else {
+            context
+                .get_role_restricted_lister_handler()
+                .ok_or_else(field_error_callback(
+                    &span,
+                    "Unauthorized access to group list",
+                ))?
+                .list_groups(None)
+                .instrument(span)
+                .await?
+        };
         domain_groups
             .into_iter()
             .map(|g| Group::<Handler>::from_group(g, schema.clone()))
@@ -203,24 +287,123 @@ impl<Handler: BackendHandler> Query<Handler> {
         span.in_scope(|| {
             debug!(?group_id);
         });
-        let handler = context
-            .get_readonly_handler()
+        let schema = Arc::new(self.get_schema(context, span.clone()).await?);
+        if let Some(handler) = context.get_readonly_handler() {
+            let group_details = handler
+                .get_group_details(GroupId(group_id))
+                .instrument(span)
+                .await?;
+            return Group::<Handler>::from_group_details(group_details, schema.clone());
+        }
+        let group = context
+            .get_role_restricted_lister_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?
+            .list_groups(Some(DomainGroupRequestFilter::GroupId(GroupId(group_id))))
+            .instrument(span.clone())
+            .await?
+            .into_iter()
+            .next()
             .ok_or_else(field_error_callback(
                 &span,
                 "Unauthorized access to group data",
             ))?;
-        let schema = Arc::new(self.get_schema(context, span.clone()).await?);
-        let group_details = handler
-            .get_group_details(GroupId(group_id))
-            .instrument(span)
-            .await?;
-        Group::<Handler>::from_group_details(group_details, schema.clone())
+        Group::<Handler>::from_group(group, schema)
     }
 
     async fn schema(context: &Context<Handler>) -> FieldResult<Schema<Handler>> {
         let span = debug_span!("[GraphQL query] get_schema");
         self.get_schema(context, span).await.map(Into::into)
     }
+
+    async fn api_tokens(context: &Context<Handler>, user_id: String) -> FieldResult<Vec<ApiToken>> {
+        let span = debug_span!("[GraphQL query] api_tokens");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to API tokens",
+            ))?;
+        Ok(handler
+            .list_api_tokens(&user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn roles(context: &Context<Handler>) -> FieldResult<Vec<Role>> {
+        let span = debug_span!("[GraphQL query] roles");
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized access to roles"))?;
+        Ok(handler
+            .list_roles()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn sessions(context: &Context<Handler>, user_id: String) -> FieldResult<Vec<Session>> {
+        let span = debug_span!("[GraphQL query] sessions");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to sessions",
+            ))?;
+        Ok(handler
+            .list_sessions(&user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The audit log, most recent events first.
+    async fn audit_log(
+        context: &Context<Handler>,
+        filters: Option<AuditEventFilter>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<AuditEvent>> {
+        let span = debug_span!("[GraphQL query] audit_log");
+        span.in_scope(|| {
+            debug!(?filters, ?offset, ?limit);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the audit log",
+            ))?;
+        let filter = filters.map(Into::into).unwrap_or_default();
+        let offset = offset.unwrap_or(0).max(0) as u64;
+        let limit = limit
+            .unwrap_or(DEFAULT_AUDIT_LOG_LIMIT)
+            .clamp(0, MAX_AUDIT_LOG_LIMIT) as u64;
+        Ok(handler
+            .list_audit_events(filter, offset, limit)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
 }
 
 impl<Handler: BackendHandler> Query<Handler> {
@@ -240,6 +423,204 @@ impl<Handler: BackendHandler> Query<Handler> {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+/// What a request authenticated with an API token is allowed to do, within the permissions of
+/// the token's user.
+pub enum ApiTokenScope {
+    /// Only read operations.
+    Readonly,
+    /// Read operations, and adding or removing users from groups.
+    GroupMembership,
+    /// Everything the user can do.
+    Full,
+}
+
+impl From<DomainApiTokenScope> for ApiTokenScope {
+    fn from(scope: DomainApiTokenScope) -> Self {
+        match scope {
+            DomainApiTokenScope::Readonly => Self::Readonly,
+            DomainApiTokenScope::GroupMembership => Self::GroupMembership,
+            DomainApiTokenScope::Full => Self::Full,
+        }
+    }
+}
+
+impl From<ApiTokenScope> for DomainApiTokenScope {
+    fn from(scope: ApiTokenScope) -> Self {
+        match scope {
+            ApiTokenScope::Readonly => Self::Readonly,
+            ApiTokenScope::GroupMembership => Self::GroupMembership,
+            ApiTokenScope::Full => Self::Full,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A personal API token. The secret is only returned when the token is created.
+pub struct ApiToken {
+    id: i32,
+    name: String,
+    scope: ApiTokenScope,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    /// If not set, the token never expires.
+    expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainApiToken> for ApiToken {
+    fn from(token: DomainApiToken) -> Self {
+        Self {
+            id: token.token_id,
+            name: token.name,
+            scope: token.scope.into(),
+            creation_date: chrono::Utc.from_utc_datetime(&token.creation_date),
+            expiry_date: token.expiry_date.map(|d| chrono::Utc.from_utc_datetime(&d)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A login session of a user, which can be refreshed until it expires.
+pub struct Session {
+    id: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    expiry_date: chrono::DateTime<chrono::Utc>,
+    /// The IP address the user logged in from.
+    ip_address: Option<String>,
+    /// The user agent (browser) the user logged in with.
+    user_agent: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The end of a temporary group membership.
+pub struct MembershipExpiry {
+    user_id: String,
+    expiry_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainSession> for Session {
+    fn from(session: DomainSession) -> Self {
+        Self {
+            id: session.session_id.to_string(),
+            creation_date: chrono::Utc.from_utc_datetime(&session.creation_date),
+            expiry_date: chrono::Utc.from_utc_datetime(&session.expiry_date),
+            ip_address: session.ip_address,
+            user_agent: session.user_agent,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A named set of permissions, granted to the members of its groups.
+pub struct Role {
+    name: String,
+    /// The permissions, e.g. `reset_passwords` or `edit_user_attribute:phone`.
+    permissions: Vec<String>,
+    group_ids: Vec<i32>,
+}
+
+impl From<DomainRole> for Role {
+    fn from(role: DomainRole) -> Self {
+        Self {
+            name: role.name,
+            permissions: role.permissions.iter().map(ToString::to_string).collect(),
+            group_ids: role.group_ids.into_iter().map(|g| g.0).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+/// Where an audited operation came from.
+pub enum AuditSource {
+    Ldap,
+    #[graphql(name = "GRAPHQL")]
+    GraphQl,
+    /// The login and password endpoints of the web server.
+    Http,
+    /// The server itself, e.g. when creating the admin user at startup.
+    Cli,
+}
+
+impl From<DomainAuditSource> for AuditSource {
+    fn from(source: DomainAuditSource) -> Self {
+        match source {
+            DomainAuditSource::Ldap => Self::Ldap,
+            DomainAuditSource::GraphQl => Self::GraphQl,
+            DomainAuditSource::Http => Self::Http,
+            DomainAuditSource::Cli => Self::Cli,
+        }
+    }
+}
+
+impl From<AuditSource> for DomainAuditSource {
+    fn from(source: AuditSource) -> Self {
+        match source {
+            AuditSource::Ldap => Self::Ldap,
+            AuditSource::GraphQl => Self::GraphQl,
+            AuditSource::Http => Self::Http,
+            AuditSource::Cli => Self::Cli,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// Restricts the audit events returned. All the given fields must match.
+pub struct AuditEventFilter {
+    actor: Option<String>,
+    target: Option<String>,
+    operation: Option<String>,
+    source: Option<AuditSource>,
+    success: Option<bool>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<AuditEventFilter> for DomainAuditEventFilter {
+    fn from(filter: AuditEventFilter) -> Self {
+        Self {
+            actor: filter.actor,
+            target: filter.target,
+            operation: filter.operation,
+            source: filter.source.map(Into::into),
+            success: filter.success,
+            since: filter.since.map(|d| d.naive_utc()),
+            until: filter.until.map(|d| d.naive_utc()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A recorded login attempt or change.
+pub struct AuditEvent {
+    id: i32,
+    date: chrono::DateTime<chrono::Utc>,
+    /// Who made the change or tried to log in.
+    actor: Option<String>,
+    source: AuditSource,
+    ip_address: Option<String>,
+    /// What the operation was about, e.g. `user:john` or `group:3`.
+    target: Option<String>,
+    operation: String,
+    success: bool,
+    /// A JSON object with the details of the operation, e.g. the changed attributes.
+    details: Option<String>,
+}
+
+impl From<DomainAuditEvent> for AuditEvent {
+    fn from(event: DomainAuditEvent) -> Self {
+        Self {
+            id: event.event_id,
+            date: chrono::Utc.from_utc_datetime(&event.date),
+            actor: event.actor,
+            source: event.source.into(),
+            ip_address: event.ip_address,
+            target: event.target,
+            operation: event.operation,
+            success: event.success,
+            details: event.details,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 /// Represents a single user.
 pub struct User<Handler: BackendHandler> {
@@ -329,10 +710,41 @@ impl<Handler: BackendHandler> User<Handler> {
         chrono::Utc.from_utc_datetime(&self.user.creation_date)
     }
 
+    /// The last change of the user's attributes or group memberships.
+    fn modified_date(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.from_utc_datetime(&self.user.modified_date)
+    }
+
     fn uuid(&self) -> &str {
         self.user.uuid.as_str()
     }
 
+    /// A disabled user cannot log in.
+    fn disabled(&self) -> bool {
+        self.user.disabled
+    }
+
+    /// After this date, the user cannot log in. If not set, the account never expires.
+    fn expiry_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .expiry_date
+            .map(|d| chrono::Utc.from_utc_datetime(&d))
+    }
+
+    /// The last successful login, over LDAP or the web UI. Not set if the user never logged in.
+    fn last_login(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .last_login
+            .map(|d| chrono::Utc.from_utc_datetime(&d))
+    }
+
+    /// The last time the password was set. Not set if the user doesn't have a password.
+    fn password_changed(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .password_changed
+            .map(|d| chrono::Utc.from_utc_datetime(&d))
+    }
+
     /// User-defined attributes.
     fn attributes(&self) -> &[AttributeValue<Handler>] {
         &self.attributes
@@ -347,16 +759,64 @@ impl<Handler: BackendHandler> User<Handler> {
         span.in_scope(|| {
             debug!(user_id = ?self.user.user_id);
         });
-        let handler = context
-            .get_readable_handler(&self.user.user_id)
-            .expect("We shouldn't be able to get there without readable permission");
-        let domain_groups = handler
-            .get_user_groups(&self.user.user_id)
-            .instrument(span)
+        let mut groups = if let Some(handler) = context.get_readable_handler(&self.user.user_id) {
+            handler
+                .get_user_groups(&self.user.user_id)
+                .instrument(span)
+                .await?
+                .into_iter()
+                .map(|g| Group::<Handler>::from_group_details(g, self.schema.clone()))
+                .collect::<FieldResult<Vec<Group<Handler>>>>()?
+        } else {
+            // Only the groups made readable by the roles are visible.
+            context
+                .get_role_restricted_lister_handler()
+                .expect("We shouldn't be able to get there without readable permission")
+                .list_groups(Some(DomainGroupRequestFilter::Member(
+                    self.user.user_id.clone(),
+                )))
+                .instrument(span)
+                .await?
+                .into_iter()
+                .map(|g| Group::<Handler>::from_group(g, self.schema.clone()))
+                .collect::<FieldResult<Vec<Group<Handler>>>>()?
+        };
+        groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
+        Ok(groups)
+    }
+
+    /// The groups whose members this user can manage.
+    async fn owned_groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] user::owned_groups");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        let Some(handler) = context.get_readable_handler(&self.user.user_id) else {
+            return Ok(Vec::new());
+        };
+        let group_ids = handler
+            .get_owned_groups(&self.user.user_id)
+            .instrument(span.clone())
             .await?;
+        if group_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filters = Some(DomainGroupRequestFilter::Or(
+            group_ids
+                .into_iter()
+                .map(DomainGroupRequestFilter::GroupId)
+                .collect(),
+        ));
+        let domain_groups = if let Some(handler) = context.get_readonly_handler() {
+            handler.list_groups(filters).instrument(span).await?
+        } else if let Some(handler) = context.get_role_restricted_lister_handler() {
+            handler.list_groups(filters).instrument(span).await?
+        } else {
+            Vec::new()
+        };
         let mut groups = domain_groups
             .into_iter()
-            .map(|g| Group::<Handler>::from_group_details(g, self.schema.clone()))
+            .map(|g| Group::<Handler>::from_group(g, self.schema.clone()))
             .collect::<FieldResult<Vec<Group<Handler>>>>()?;
         groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
         Ok(groups)
@@ -369,6 +829,7 @@ pub struct Group<Handler: BackendHandler> {
     group_id: i32,
     display_name: String,
     creation_date: chrono::NaiveDateTime,
+    modified_date: chrono::NaiveDateTime,
     uuid: String,
     attributes: Vec<AttributeValue<Handler>>,
     schema: Arc<PublicSchema>,
@@ -386,6 +847,7 @@ impl<Handler: BackendHandler> Group<Handler> {
             group_id: group.id.0,
             display_name: group.display_name.to_string(),
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid.into_string(),
             attributes,
             schema,
@@ -405,6 +867,7 @@ impl<Handler: BackendHandler> Group<Handler> {
             group_id: group_details.group_id.0,
             display_name: group_details.display_name.to_string(),
             creation_date: group_details.creation_date,
+            modified_date: group_details.modified_date,
             uuid: group_details.uuid.into_string(),
             attributes,
             schema,
@@ -419,6 +882,7 @@ impl<Handler: BackendHandler> Clone for Group<Handler> {
             group_id: self.group_id,
             display_name: self.display_name.clone(),
             creation_date: self.creation_date,
+            modified_date: self.modified_date,
             uuid: self.uuid.clone(),
             attributes: self.attributes.clone(),
             schema: self.schema.clone(),
@@ -438,6 +902,10 @@ impl<Handler: BackendHandler> Group<Handler> {
     fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
         chrono::Utc.from_utc_datetime(&self.creation_date)
     }
+    /// The last change of the group's attributes or members.
+    fn modified_date(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.from_utc_datetime(&self.modified_date)
+    }
     fn uuid(&self) -> String {
         self.uuid.clone()
     }
@@ -453,24 +921,150 @@ impl<Handler: BackendHandler> Group<Handler> {
         span.in_scope(|| {
             debug!(name = %self.display_name);
         });
+        let filters = Some(DomainRequestFilter::MemberOfId(GroupId(self.group_id)));
+        let domain_users = if let Some(handler) = context.get_readonly_handler() {
+            handler.list_users(filters, false).instrument(span).await?
+        } else {
+            context
+                .get_role_restricted_lister_handler()
+                .ok_or_else(field_error_callback(
+                    &span,
+                    "Unauthorized access to group data",
+                ))?
+                .list_users(filters, false)
+                .instrument(span)
+                .await?
+        };
+        domain_users
+            .into_iter()
+            .map(|u| User::<Handler>::from_user_and_groups(u, self.schema.clone()))
+            .collect()
+    }
+
+    /// The temporary memberships, including the expired ones that aren't purged yet.
+    async fn membership_expiry_dates(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<MembershipExpiry>> {
+        let span = debug_span!("[GraphQL query] group::membership_expiry_dates");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        let group_id = GroupId(self.group_id);
+        // Hidden from the users who can list the members but not manage them.
+        let expiry_dates = if let Some(handler) = context.get_readonly_handler() {
+            handler
+                .list_membership_expiry_dates(group_id)
+                .instrument(span)
+                .await?
+        } else if let Some(handler) = context.get_group_member_editor_handler(group_id) {
+            handler
+                .list_membership_expiry_dates(group_id)
+                .instrument(span)
+                .await?
+        } else {
+            Vec::new()
+        };
+        Ok(expiry_dates
+            .into_iter()
+            .map(|(user_id, expiry_date)| MembershipExpiry {
+                user_id: user_id.into_string(),
+                expiry_date: chrono::Utc.from_utc_datetime(&expiry_date),
+            })
+            .collect())
+    }
+
+    /// The users who can manage the members of this group.
+    async fn owners(&self, context: &Context<Handler>) -> FieldResult<Vec<User<Handler>>> {
+        let span = debug_span!("[GraphQL query] group::owners");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
         let handler = context
             .get_readonly_handler()
             .ok_or_else(field_error_callback(
                 &span,
-                "Unauthorized access to group data",
+                "Unauthorized access to group owners",
             ))?;
-        let domain_users = handler
-            .list_users(
-                Some(DomainRequestFilter::MemberOfId(GroupId(self.group_id))),
-                false,
-            )
-            .instrument(span)
+        let owners = handler
+            .list_group_owners(GroupId(self.group_id))
+            .instrument(span.clone())
             .await?;
-        domain_users
+        if owners.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filters = Some(DomainRequestFilter::Or(
+            owners
+                .into_iter()
+                .map(DomainRequestFilter::UserId)
+                .collect(),
+        ));
+        handler
+            .list_users(filters, false)
+            .instrument(span)
+            .await?
             .into_iter()
             .map(|u| User::<Handler>::from_user_and_groups(u, self.schema.clone()))
             .collect()
     }
+
+    /// Whether the members are computed from a filter, rather than added manually.
+    async fn is_dynamic(&self, context: &Context<Handler>) -> FieldResult<bool> {
+        let span = debug_span!("[GraphQL query] group::is_dynamic");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        Ok(handler
+            .get_group_filter(GroupId(self.group_id))
+            .instrument(span)
+            .await?
+            .is_some())
+    }
+
+    /// The groups that are direct members of this group.
+    async fn subgroups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] group::subgroups");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        let subgroups = handler
+            .list_groups(Some(DomainGroupRequestFilter::GroupId(GroupId(
+                self.group_id,
+            ))))
+            .instrument(span.clone())
+            .await?
+            .into_iter()
+            .flat_map(|g| g.subgroups)
+            .collect::<Vec<_>>();
+        if subgroups.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filters = Some(DomainGroupRequestFilter::Or(
+            subgroups
+                .into_iter()
+                .map(DomainGroupRequestFilter::DisplayName)
+                .collect(),
+        ));
+        handler
+            .list_groups(filters)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(|g| Group::<Handler>::from_group(g, self.schema.clone()))
+            .collect()
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -716,6 +1310,8 @@ impl<Handler: BackendHandler> AttributeValue<Handler> {
                 let value: Option<DomainAttributeValue> = match attribute_schema.name.as_str() {
                     "user_id" => Some(user.user_id.clone().into_string().into()),
                     "creation_date" => Some(user.creation_date.into()),
+                    "last_login" => user.last_login.map(Into::into),
+                    "password_changed" => user.password_changed.map(Into::into),
                     "mail" => Some(user.email.clone().into_string().into()),
                     "uuid" => Some(user.uuid.clone().into_string().into()),
                     "display_name" => user.display_name.as_ref().map(|d| d.clone().into()),
@@ -953,6 +1549,7 @@ mod tests {
             group_id: GroupId(3),
             display_name: "Bobbersons".into(),
             creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
             uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
             attributes: vec![DomainAttribute {
                 name: "club_name".into(),
@@ -963,6 +1560,7 @@ mod tests {
             group_id: GroupId(7),
             display_name: "Jefferees".into(),
             creation_date: chrono::Utc.timestamp_nanos(12).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(12).naive_utc(),
             uuid: lldap_domain::uuid!("b1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
             attributes: Vec::new(),
         });
@@ -1230,6 +1828,14 @@ mod tests {
                                     "isEditable": true,
                                     "isHardcoded": true,
                                 },
+                                {
+                                    "name": "last_login",
+                                    "attributeType": "DATE_TIME",
+                                    "isList": false,
+                                    "isVisible": true,
+                                    "isEditable": false,
+                                    "isHardcoded": true,
+                                },
                                 {
                                     "name": "last_name",
                                     "attributeType": "STRING",
@@ -1246,6 +1852,14 @@ mod tests {
                                     "isEditable": true,
                                     "isHardcoded": true,
                                 },
+                                {
+                                    "name": "password_changed",
+                                    "attributeType": "DATE_TIME",
+                                    "isList": false,
+                                    "isVisible": true,
+                                    "isEditable": false,
+                                    "isHardcoded": true,
+                                },
                                 {
                                     "name": "user_id",
                                     "attributeType": "STRING",
@@ -1350,6 +1964,8 @@ mod tests {
             ValidationResults {
                 user: UserId::new("bob"),
                 permission: Permission::Regular,
+                api_token_scope: None,
+                role_permissions: HashSet::new(),
             },
         );
 
@@ -1364,7 +1980,9 @@ mod tests {
                             "attributes": [
                                 {"name": "creation_date"},
                                 {"name": "display_name"},
+                                {"name": "last_login"},
                                 {"name": "mail"},
+                                {"name": "password_changed"},
                                 {"name": "user_id"},
                                 {"name": "uuid"},
                             ],
@@ -0,0 +1,252 @@
+use crate::core::utils::{
+    LdapInfo, UserOrGroupName, get_user_id_from_distinguished_name,
+    get_user_or_group_id_from_distinguished_name,
+};
+use ldap3_proto::proto::{
+    LdapModifyType, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest, LdapResultCode,
+    OID_PASSWORD_MODIFY,
+};
+use lldap_domain::types::UserId;
+use lldap_domain_handlers::handler::{AuditSource, CreateAuditEventRequest};
+use serde_json::{Value, json};
+use std::net::IpAddr;
+
+/// A bind or write operation, to record in the audit log once its result is known.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct AuditedOperation {
+    operation: &'static str,
+    actor: Option<String>,
+    target: Option<String>,
+    details: Option<Value>,
+}
+
+impl AuditedOperation {
+    /// Builds the audit event from the responses to the operation.
+    pub(crate) fn into_event(
+        self,
+        source_ip: Option<IpAddr>,
+        responses: &[LdapOp],
+    ) -> CreateAuditEventRequest {
+        let code = responses.iter().rev().find_map(get_result_code);
+        let mut details = self.details.unwrap_or_else(|| json!({}));
+        if let (Some(code), Value::Object(map)) = (&code, &mut details) {
+            map.insert("result".to_owned(), json!(format!("{:?}", code)));
+        }
+        CreateAuditEventRequest {
+            actor: self.actor,
+            source: AuditSource::Ldap,
+            ip_address: source_ip.map(|ip| ip.to_string()),
+            target: self.target,
+            operation: self.operation.to_owned(),
+            success: code == Some(LdapResultCode::Success),
+            details: Some(details.to_string()),
+        }
+    }
+}
+
+fn get_result_code(op: &LdapOp) -> Option<LdapResultCode> {
+    match op {
+        LdapOp::BindResponse(response) => Some(response.res.code.clone()),
+        LdapOp::ExtendedResponse(response) => Some(response.res.code.clone()),
+        LdapOp::ModifyResponse(res)
+        | LdapOp::AddResponse(res)
+        | LdapOp::DelResponse(res)
+        | LdapOp::ModifyDNResponse(res) => Some(res.code.clone()),
+        _ => None,
+    }
+}
+
+/// Users are referred to as `user:<id>`, like in the rest of the audit log. Other entries keep
+/// their DN.
+fn make_target(ldap_info: &LdapInfo, dn: &str) -> String {
+    match get_user_or_group_id_from_distinguished_name(&dn.to_ascii_lowercase(), &ldap_info.base_dn)
+    {
+        UserOrGroupName::User(user_id) => format!("user:{}", user_id),
+        _ => format!("dn:{}", dn),
+    }
+}
+
+fn is_password_attribute(name: &str) -> bool {
+    name.eq_ignore_ascii_case("userPassword")
+}
+
+fn format_values(attribute: &LdapPartialAttribute) -> Value {
+    if is_password_attribute(&attribute.atype) {
+        return json!("<redacted>");
+    }
+    Value::Array(
+        attribute
+            .vals
+            .iter()
+            .map(|v| match std::str::from_utf8(v) {
+                Ok(s) => json!(s),
+                Err(_) => json!(format!("<{} bytes>", v.len())),
+            })
+            .collect(),
+    )
+}
+
+/// Returns the description of the operation if it should be audited: binds and writes.
+pub(crate) fn describe_operation(
+    ldap_info: &LdapInfo,
+    bound_user: Option<&UserId>,
+    ldap_op: &LdapOp,
+) -> Option<AuditedOperation> {
+    let actor = bound_user.map(|u| u.to_string());
+    Some(match ldap_op {
+        LdapOp::BindRequest(request) => AuditedOperation {
+            operation: "login",
+            actor: Some(
+                get_user_id_from_distinguished_name(
+                    &request.dn.to_ascii_lowercase(),
+                    &ldap_info.base_dn,
+                    &ldap_info.base_dn_str,
+                )
+                .map(|u| u.to_string())
+                .unwrap_or_else(|_| request.dn.clone()),
+            ),
+            target: None,
+            details: None,
+        },
+        LdapOp::ModifyRequest(request) => AuditedOperation {
+            operation: "ldap_modify",
+            actor,
+            target: Some(make_target(ldap_info, &request.dn)),
+            details: Some(json!({
+                "changes": request
+                    .changes
+                    .iter()
+                    .map(|change| json!({
+                        "operation": match change.operation {
+                            LdapModifyType::Add => "add",
+                            LdapModifyType::Delete => "delete",
+                            LdapModifyType::Replace => "replace",
+                        },
+                        "attribute": change.modification.atype,
+                        "values": format_values(&change.modification),
+                    }))
+                    .collect::<Vec<_>>(),
+            })),
+        },
+        LdapOp::AddRequest(request) => AuditedOperation {
+            operation: "ldap_add",
+            actor,
+            target: Some(make_target(ldap_info, &request.dn)),
+            details: Some(json!({
+                "attributes": request
+                    .attributes
+                    .iter()
+                    .map(|attribute| (attribute.atype.clone(), format_values(attribute)))
+                    .collect::<serde_json::Map<_, _>>(),
+            })),
+        },
+        LdapOp::DelRequest(dn) => AuditedOperation {
+            operation: "ldap_delete",
+            actor,
+            target: Some(make_target(ldap_info, dn)),
+            details: None,
+        },
+        LdapOp::ModifyDNRequest(request) => AuditedOperation {
+            operation: "ldap_modify_dn",
+            actor,
+            target: Some(make_target(ldap_info, &request.dn)),
+            details: Some(json!({
+                "new_rdn": request.newrdn,
+                "new_superior": request.new_superior,
+            })),
+        },
+        LdapOp::ExtendedRequest(request) if request.name == OID_PASSWORD_MODIFY => {
+            let target = LdapPasswordModifyRequest::try_from(request)
+                .ok()
+                .and_then(|r| r.user_identity)
+                .map(|dn| make_target(ldap_info, &dn))
+                .or_else(|| actor.as_ref().map(|u| format!("user:{}", u)));
+            AuditedOperation {
+                operation: "change_password",
+                actor,
+                target,
+                details: None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{core::utils::parse_distinguished_name, search::make_search_request};
+    use ldap3_proto::proto::{LdapFilter, LdapModify, LdapModifyRequest, LdapResult};
+    use pretty_assertions::assert_eq;
+
+    fn make_ldap_info() -> LdapInfo {
+        LdapInfo {
+            base_dn: parse_distinguished_name("dc=example,dc=com").unwrap(),
+            base_dn_str: "dc=example,dc=com".to_owned(),
+            ignored_user_attributes: vec![],
+            ignored_group_attributes: vec![],
+            password_policy: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_modify_is_audited_without_the_password() {
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=Bob,ou=people,dc=example,dc=com".to_owned(),
+            changes: vec![
+                LdapModify {
+                    operation: LdapModifyType::Replace,
+                    modification: LdapPartialAttribute {
+                        atype: "userPassword".to_owned(),
+                        vals: vec![b"secret".to_vec()],
+                    },
+                },
+                LdapModify {
+                    operation: LdapModifyType::Add,
+                    modification: LdapPartialAttribute {
+                        atype: "mail".to_owned(),
+                        vals: vec![b"bob@example.com".to_vec()],
+                    },
+                },
+            ],
+        });
+        let operation =
+            describe_operation(&make_ldap_info(), Some(&UserId::new("admin")), &request).unwrap();
+        let event = operation.into_event(
+            None,
+            &[LdapOp::ModifyResponse(LdapResult {
+                code: LdapResultCode::Success,
+                matcheddn: String::new(),
+                message: String::new(),
+                referral: vec![],
+            })],
+        );
+        assert_eq!(event.actor.as_deref(), Some("admin"));
+        assert_eq!(event.target.as_deref(), Some("user:bob"));
+        assert!(event.success);
+        let details: Value = serde_json::from_str(event.details.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            details,
+            json!({
+                "changes": [
+                    {"operation": "replace", "attribute": "userPassword", "values": "<redacted>"},
+                    {"operation": "add", "attribute": "mail", "values": ["bob@example.com"]},
+                ],
+                "result": "Success",
+            })
+        );
+    }
+
+    #[test]
+    fn test_searches_are_not_audited() {
+        let request = LdapOp::SearchRequest(make_search_request::<String>(
+            "dc=example,dc=com",
+            LdapFilter::Present("objectClass".to_owned()),
+            vec![],
+        ));
+        assert_eq!(
+            describe_operation(&make_ldap_info(), Some(&UserId::new("bob")), &request),
+            None
+        );
+    }
+}
@@ -121,8 +121,10 @@ mod tests {
                 id: GroupId(1),
                 display_name: "group".into(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                subgroups: vec![],
                 attributes: Vec::new(),
             }])
         });
@@ -215,8 +217,10 @@ mod tests {
                 id: GroupId(1),
                 display_name: "group".into(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                subgroups: vec![],
                 attributes: Vec::new(),
             }])
         });
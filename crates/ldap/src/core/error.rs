@@ -15,3 +15,10 @@ impl std::fmt::Display for LdapError {
 impl std::error::Error for LdapError {}
 
 pub type LdapResult<T> = std::result::Result<T, LdapError>;
+
+pub(crate) fn unauthorized_write() -> LdapError {
+    LdapError {
+        code: LdapResultCode::InsufficentAccessRights,
+        message: "Unauthorized write".to_string(),
+    }
+}
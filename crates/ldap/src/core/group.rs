@@ -72,11 +72,24 @@ pub fn get_group_attribute(
                 .to_rfc3339()
                 .into_bytes(),
         ],
+        GroupFieldType::ModifiedDate => vec![
+            chrono::Utc
+                .from_utc_datetime(&group.modified_date)
+                .to_rfc3339()
+                .into_bytes(),
+        ],
         GroupFieldType::Member => group
             .users
             .iter()
             .filter(|u| user_filter.as_ref().map(|f| *u == f).unwrap_or(true))
             .map(|u| format!("uid={},ou=people,{}", u, base_dn_str).into_bytes())
+            .chain(
+                group
+                    .subgroups
+                    .iter()
+                    .filter(|_| user_filter.is_none())
+                    .map(|g| format!("cn={},ou=groups,{}", g, base_dn_str).into_bytes()),
+            )
             .collect(),
         GroupFieldType::Uuid => vec![group.uuid.to_string().into_bytes()],
         GroupFieldType::Attribute(attr, _, _) => get_custom_attribute(&group.attributes, &attr)?,
@@ -257,9 +270,9 @@ fn convert_group_filter(
                 GroupFieldType::Attribute(field, typ, is_list) => Ok(
                     get_group_attribute_equality_filter(&field, typ, is_list, value),
                 ),
-                GroupFieldType::CreationDate => Err(LdapError {
+                GroupFieldType::CreationDate | GroupFieldType::ModifiedDate => Err(LdapError {
                     code: LdapResultCode::UnwillingToPerform,
-                    message: "Creation date filter for groups not supported".to_owned(),
+                    message: "Date filters for groups not supported".to_owned(),
                 }),
             }
         }
@@ -6,9 +6,10 @@ use crate::core::{
         get_user_id_from_distinguished_name_or_plain_name, map_user_field,
     },
 };
-use chrono::TimeZone;
+use chrono::{NaiveDateTime, TimeZone};
 use ldap3_proto::{
-    LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry, proto::LdapOp,
+    LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
+    proto::{LdapMatchingRuleAssertion, LdapOp},
 };
 use lldap_domain::{
     deserialize::deserialize_attribute_value,
@@ -23,6 +24,9 @@ use tracing::{debug, instrument, warn};
 
 pub const REQUIRED_USER_ATTRIBUTES: &[&str] = &["user_id", "mail"];
 
+/// The Active Directory matching rule to walk the nested groups, LDAP_MATCHING_RULE_IN_CHAIN.
+const LDAP_MATCHING_RULE_IN_CHAIN: &str = "1.2.840.113556.1.4.1941";
+
 const DEFAULT_USER_OBJECT_CLASSES: &[&str] =
     &["inetOrgPerson", "posixAccount", "mailAccount", "person"];
 
@@ -93,6 +97,38 @@ pub fn get_user_attribute(
                 .to_rfc3339()
                 .into_bytes(),
         ],
+        UserFieldType::PrimaryField(UserColumn::Disabled) => {
+            if !user.disabled {
+                return None;
+            }
+            vec![PERMANENTLY_LOCKED_TIME.as_bytes().to_vec()]
+        }
+        UserFieldType::PrimaryField(UserColumn::ModifiedDate) => vec![
+            chrono::Utc
+                .from_utc_datetime(&user.modified_date)
+                .to_rfc3339()
+                .into_bytes(),
+        ],
+        UserFieldType::PrimaryField(UserColumn::LastLogin) => vec![
+            chrono::Utc
+                .from_utc_datetime(&user.last_login?)
+                .to_rfc3339()
+                .into_bytes(),
+        ],
+        UserFieldType::PrimaryField(UserColumn::PasswordChanged) => vec![
+            chrono::Utc
+                .from_utc_datetime(&user.password_changed?)
+                .to_rfc3339()
+                .into_bytes(),
+        ],
+        UserFieldType::PrimaryField(UserColumn::ExpiryDate) => {
+            // Number of days since the epoch, like in /etc/shadow.
+            vec![
+                (user.expiry_date?.and_utc().timestamp() / (24 * 3600))
+                    .to_string()
+                    .into_bytes(),
+            ]
+        }
         UserFieldType::Attribute(attr, _, _) => get_custom_attribute(&user.attributes, &attr)?,
         UserFieldType::NoMatch => match attribute.as_str() {
             "1.1" => return None,
@@ -125,6 +161,10 @@ pub fn get_user_attribute(
     }
 }
 
+/// Value of `pwdAccountLockedTime` for an account locked until an admin unlocks it (RFC draft
+/// behera-ldap-password-policy).
+const PERMANENTLY_LOCKED_TIME: &str = "000001010000Z";
+
 const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "objectclass",
     "uid",
@@ -135,6 +175,7 @@ const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "jpegPhoto",
     "createtimestamp",
     "entryuuid",
+    "shadowexpire",
 ];
 
 fn make_ldap_search_user_result_entry(
@@ -200,6 +241,64 @@ fn get_user_attribute_equality_filter(
     }
 }
 
+/// Parses the date of an ordering filter, either in the LDAP generalized time format or in RFC
+/// 3339, like the dates returned by the server.
+fn parse_filter_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S%.fZ")
+        .ok()
+        .or_else(|| {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|d| d.naive_utc())
+        })
+}
+
+fn convert_user_date_filter(
+    field: &str,
+    value: &str,
+    schema: &PublicSchema,
+    make_filter: fn(UserColumn, NaiveDateTime) -> UserRequestFilter,
+) -> LdapResult<UserRequestFilter> {
+    let field = AttributeName::from(field);
+    match map_user_field(&field, schema) {
+        UserFieldType::PrimaryField(
+            column @ (UserColumn::CreationDate
+            | UserColumn::ModifiedDate
+            | UserColumn::LastLogin
+            | UserColumn::PasswordChanged),
+        ) => Ok(match parse_filter_date(value) {
+            Some(date) => make_filter(column, date),
+            None => {
+                warn!("Invalid date for attribute {}: {}", field, value);
+                UserRequestFilter::from(false)
+            }
+        }),
+        UserFieldType::NoMatch => Ok(UserRequestFilter::from(false)),
+        _ => Err(LdapError {
+            code: LdapResultCode::UnwillingToPerform,
+            message: format!(
+                "Unsupported user attribute for ordering filter: {:?}",
+                field
+            ),
+        }),
+    }
+}
+
+/// memberOf includes the groups inherited through nested groups, so the filters on it (including
+/// the Active Directory "in chain" matching rule) match the members of the nested groups.
+fn get_member_of_filter(ldap_info: &LdapInfo, value_lc: &str) -> UserRequestFilter {
+    get_group_id_from_distinguished_name_or_plain_name(
+        value_lc,
+        &ldap_info.base_dn,
+        &ldap_info.base_dn_str,
+    )
+    .map(UserRequestFilter::TransitiveMemberOf)
+    .unwrap_or_else(|e| {
+        warn!("Invalid memberOf filter: {}", e);
+        UserRequestFilter::from(false)
+    })
+}
+
 fn convert_user_filter(
     ldap_info: &LdapInfo,
     filter: &LdapFilter,
@@ -225,6 +324,25 @@ fn convert_user_filter(
                     UserColumn::LowercaseEmail,
                     value_lc,
                 )),
+                UserFieldType::PrimaryField(UserColumn::Disabled) => {
+                    Ok(if value.eq_ignore_ascii_case(PERMANENTLY_LOCKED_TIME) {
+                        UserRequestFilter::Disabled
+                    } else {
+                        UserRequestFilter::from(false)
+                    })
+                }
+                UserFieldType::PrimaryField(
+                    UserColumn::ModifiedDate
+                    | UserColumn::ExpiryDate
+                    | UserColumn::LastLogin
+                    | UserColumn::PasswordChanged,
+                ) => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported user attribute for equality filter: {:?}",
+                        field
+                    ),
+                }),
                 UserFieldType::PrimaryField(field) => {
                     Ok(UserRequestFilter::Equality(field, value_lc))
                 }
@@ -250,16 +368,7 @@ fn convert_user_filter(
                             .extra_user_object_classes
                             .contains(&LdapObjectClass::from(value_lc)),
                 )),
-                UserFieldType::MemberOf => Ok(get_group_id_from_distinguished_name_or_plain_name(
-                    &value_lc,
-                    &ldap_info.base_dn,
-                    &ldap_info.base_dn_str,
-                )
-                .map(UserRequestFilter::MemberOf)
-                .unwrap_or_else(|e| {
-                    warn!("Invalid memberOf filter: {}", e);
-                    UserRequestFilter::from(false)
-                })),
+                UserFieldType::MemberOf => Ok(get_member_of_filter(ldap_info, &value_lc)),
                 UserFieldType::EntryDn | UserFieldType::Dn => {
                     Ok(get_user_id_from_distinguished_name_or_plain_name(
                         value_lc.as_str(),
@@ -280,6 +389,13 @@ fn convert_user_filter(
                 UserFieldType::Attribute(name, _, _) => {
                     UserRequestFilter::CustomAttributePresent(name)
                 }
+                UserFieldType::PrimaryField(UserColumn::Disabled) => UserRequestFilter::Disabled,
+                UserFieldType::PrimaryField(UserColumn::ExpiryDate) => {
+                    UserRequestFilter::HasExpiryDate
+                }
+                UserFieldType::PrimaryField(
+                    column @ (UserColumn::LastLogin | UserColumn::PasswordChanged),
+                ) => UserRequestFilter::HasValue(column),
                 UserFieldType::NoMatch => UserRequestFilter::from(false),
                 _ => UserRequestFilter::from(true),
             })
@@ -296,7 +412,12 @@ fn convert_user_filter(
                 | UserFieldType::Dn
                 | UserFieldType::EntryDn
                 | UserFieldType::PrimaryField(UserColumn::CreationDate)
-                | UserFieldType::PrimaryField(UserColumn::Uuid) => Err(LdapError {
+                | UserFieldType::PrimaryField(UserColumn::ModifiedDate)
+                | UserFieldType::PrimaryField(UserColumn::Uuid)
+                | UserFieldType::PrimaryField(UserColumn::Disabled)
+                | UserFieldType::PrimaryField(UserColumn::ExpiryDate)
+                | UserFieldType::PrimaryField(UserColumn::LastLogin)
+                | UserFieldType::PrimaryField(UserColumn::PasswordChanged) => Err(LdapError {
                     code: LdapResultCode::UnwillingToPerform,
                     message: format!(
                         "Unsupported user attribute for substring filter: {:?}",
@@ -314,6 +435,28 @@ fn convert_user_filter(
                 )),
             }
         }
+        LdapFilter::GreaterOrEqual(field, value) => {
+            convert_user_date_filter(field, value, schema, UserRequestFilter::DateGreaterOrEqual)
+        }
+        LdapFilter::LessOrEqual(field, value) => {
+            convert_user_date_filter(field, value, schema, UserRequestFilter::DateLessOrEqual)
+        }
+        LdapFilter::Extensible(LdapMatchingRuleAssertion {
+            matching_rule: Some(rule),
+            type_: Some(field),
+            match_value,
+            ..
+        }) if rule == LDAP_MATCHING_RULE_IN_CHAIN
+            && matches!(
+                map_user_field(&AttributeName::from(field.as_str()), schema),
+                UserFieldType::MemberOf
+            ) =>
+        {
+            Ok(get_member_of_filter(
+                ldap_info,
+                &match_value.to_ascii_lowercase(),
+            ))
+        }
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
             message: format!("Unsupported user filter: {:?}", filter),
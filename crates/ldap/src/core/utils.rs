@@ -15,6 +15,7 @@ use lldap_domain::{
     },
 };
 use lldap_domain_model::model::UserColumn;
+use lldap_validation::password::PasswordPolicy;
 use std::collections::BTreeMap;
 use tracing::{debug, instrument, warn};
 
@@ -240,10 +241,21 @@ pub fn map_user_field(field: &AttributeName, schema: &PublicSchema) -> UserField
             AttributeType::JpegPhoto,
             false,
         ),
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
+        "creationdate" | "createtimestamp" | "creation_date" => {
             UserFieldType::PrimaryField(UserColumn::CreationDate)
         }
+        "modifytimestamp" | "modified_date" => {
+            UserFieldType::PrimaryField(UserColumn::ModifiedDate)
+        }
         "entryuuid" | "uuid" => UserFieldType::PrimaryField(UserColumn::Uuid),
+        "pwdaccountlockedtime" => UserFieldType::PrimaryField(UserColumn::Disabled),
+        "shadowexpire" => UserFieldType::PrimaryField(UserColumn::ExpiryDate),
+        "authtimestamp" | "lastlogin" | "last_login" => {
+            UserFieldType::PrimaryField(UserColumn::LastLogin)
+        }
+        "pwdchangedtime" | "passwordchanged" | "password_changed" => {
+            UserFieldType::PrimaryField(UserColumn::PasswordChanged)
+        }
         _ => schema
             .get_schema()
             .user_attributes
@@ -258,6 +270,7 @@ pub enum GroupFieldType {
     GroupId,
     DisplayName,
     CreationDate,
+    ModifiedDate,
     ObjectClass,
     Dn,
     // Like Dn, but returned as part of the attributes.
@@ -273,9 +286,8 @@ pub fn map_group_field(field: &AttributeName, schema: &PublicSchema) -> GroupFie
         "entrydn" => GroupFieldType::EntryDn,
         "objectclass" => GroupFieldType::ObjectClass,
         "cn" | "displayname" | "uid" | "display_name" | "id" => GroupFieldType::DisplayName,
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
-            GroupFieldType::CreationDate
-        }
+        "creationdate" | "createtimestamp" | "creation_date" => GroupFieldType::CreationDate,
+        "modifytimestamp" | "modified_date" => GroupFieldType::ModifiedDate,
         "member" | "uniquemember" => GroupFieldType::Member,
         "entryuuid" | "uuid" => GroupFieldType::Uuid,
         "group_id" | "groupid" => GroupFieldType::GroupId,
@@ -293,6 +305,7 @@ pub struct LdapInfo {
     pub base_dn_str: String,
     pub ignored_user_attributes: Vec<AttributeName>,
     pub ignored_group_attributes: Vec<AttributeName>,
+    pub password_policy: PasswordPolicy,
 }
 
 pub fn get_custom_attribute(
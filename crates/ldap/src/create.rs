@@ -1,6 +1,6 @@
 use crate::{
     core::{
-        error::{LdapError, LdapResult},
+        error::{LdapError, LdapResult, unauthorized_write},
         utils::{LdapInfo, UserOrGroupName, get_user_or_group_id_from_distinguished_name},
     },
     handler::make_add_response,
@@ -8,33 +8,44 @@ use crate::{
 use ldap3_proto::proto::{
     LdapAddRequest, LdapAttribute, LdapOp, LdapPartialAttribute, LdapResultCode,
 };
-use lldap_access_control::AdminBackendHandler;
+use lldap_access_control::{
+    AccessControlledBackendHandler, AdminBackendHandler, UserCreationBackendHandler,
+};
+use lldap_auth::access_control::ValidationResults;
 use lldap_domain::{
     deserialize,
     requests::{CreateGroupRequest, CreateUserRequest},
     types::{Attribute, AttributeName, AttributeType, Email, GroupName, UserId},
 };
+use lldap_domain_handlers::handler::BackendHandler;
 use std::collections::HashMap;
 use tracing::instrument;
 
 #[instrument(skip_all, level = "debug")]
-pub(crate) async fn create_user_or_group(
-    backend_handler: &impl AdminBackendHandler,
+pub(crate) async fn create_user_or_group<Handler: BackendHandler>(
+    backend_handler: &AccessControlledBackendHandler<Handler>,
     ldap_info: &LdapInfo,
+    credentials: &ValidationResults,
     request: LdapAddRequest,
 ) -> LdapResult<Vec<LdapOp>> {
     let base_dn_str = &ldap_info.base_dn_str;
     match get_user_or_group_id_from_distinguished_name(&request.dn, &ldap_info.base_dn) {
         UserOrGroupName::User(user_id) => {
+            let backend_handler = backend_handler
+                .get_user_creation_handler(credentials)
+                .ok_or_else(unauthorized_write)?;
             create_user(backend_handler, user_id, request.attributes).await
         }
         UserOrGroupName::Group(group_name) => {
+            let backend_handler = backend_handler
+                .get_admin_handler(credentials)
+                .ok_or_else(unauthorized_write)?;
             create_group(backend_handler, group_name, request.attributes).await
         }
         err => Err(err.into_ldap_error(
             &request.dn,
             format!(
-                r#""uid=id,ou=people,{}" or "uid=id,ou=groups,{}""#,
+                r#""uid=id,ou=people,{}" or "cn=id,ou=groups,{}""#,
                 base_dn_str, base_dn_str
             ),
         )),
@@ -43,7 +54,7 @@ pub(crate) async fn create_user_or_group(
 
 #[instrument(skip_all, level = "debug")]
 async fn create_user(
-    backend_handler: &impl AdminBackendHandler,
+    backend_handler: &impl UserCreationBackendHandler,
     user_id: UserId,
     attributes: Vec<LdapAttribute>,
 ) -> LdapResult<Vec<LdapOp>> {
@@ -167,7 +178,8 @@ async fn create_group(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::handler::tests::setup_bound_admin_handler;
+    use crate::handler::tests::{setup_bound_admin_handler, setup_bound_handler_with_role};
+    use lldap_auth::access_control::RolePermission;
     use lldap_domain::types::*;
     use lldap_test_utils::MockTestBackendHandler;
     use mockall::predicate::eq;
@@ -267,4 +279,53 @@ mod tests {
             )])
         );
     }
+
+    #[tokio::test]
+    async fn test_create_user_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_create_user()
+            .with(eq(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".into(),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let ldap_handler =
+            setup_bound_handler_with_role(mock, vec![RolePermission::CreateUsers]).await;
+        let request = LdapAddRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "mail".to_owned(),
+                vals: vec![b"bob@example.com".to_vec()],
+            }],
+        };
+        assert_eq!(
+            ldap_handler.create_user_or_group(request).await,
+            Ok(vec![make_add_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_group_with_role() {
+        let ldap_handler = setup_bound_handler_with_role(
+            MockTestBackendHandler::new(),
+            vec![RolePermission::CreateUsers],
+        )
+        .await;
+        let request = LdapAddRequest {
+            dn: "cn=bob,ou=groups,dc=example,dc=com".to_owned(),
+            attributes: Vec::new(),
+        };
+        assert_eq!(
+            ldap_handler.create_user_or_group(request).await,
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Unauthorized write".to_string(),
+            })
+        );
+    }
 }
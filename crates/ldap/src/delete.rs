@@ -1,11 +1,14 @@
 use crate::core::{
-    error::{LdapError, LdapResult},
+    error::{LdapError, LdapResult, unauthorized_write},
     utils::{LdapInfo, UserOrGroupName, get_user_or_group_id_from_distinguished_name},
 };
 use ldap3_proto::proto::{LdapOp, LdapResult as LdapResultOp, LdapResultCode};
-use lldap_access_control::AdminBackendHandler;
+use lldap_access_control::{
+    AccessControlledBackendHandler, AdminBackendHandler, UserDeletionBackendHandler,
+};
+use lldap_auth::access_control::ValidationResults;
 use lldap_domain::types::{GroupName, UserId};
-use lldap_domain_handlers::handler::GroupRequestFilter;
+use lldap_domain_handlers::handler::{BackendHandler, GroupRequestFilter};
 use lldap_domain_model::error::DomainError;
 use tracing::instrument;
 
@@ -19,19 +22,30 @@ pub(crate) fn make_del_response(code: LdapResultCode, message: String) -> LdapOp
 }
 
 #[instrument(skip_all, level = "debug")]
-pub(crate) async fn delete_user_or_group(
-    backend_handler: &impl AdminBackendHandler,
+pub(crate) async fn delete_user_or_group<Handler: BackendHandler>(
+    backend_handler: &AccessControlledBackendHandler<Handler>,
     ldap_info: &LdapInfo,
+    credentials: &ValidationResults,
     request: String,
 ) -> LdapResult<Vec<LdapOp>> {
     let base_dn_str = &ldap_info.base_dn_str;
     match get_user_or_group_id_from_distinguished_name(&request, &ldap_info.base_dn) {
-        UserOrGroupName::User(user_id) => delete_user(backend_handler, user_id).await,
-        UserOrGroupName::Group(group_name) => delete_group(backend_handler, group_name).await,
+        UserOrGroupName::User(user_id) => {
+            let backend_handler = backend_handler
+                .get_user_deletion_handler(credentials)
+                .ok_or_else(unauthorized_write)?;
+            delete_user(backend_handler, credentials, user_id).await
+        }
+        UserOrGroupName::Group(group_name) => {
+            let backend_handler = backend_handler
+                .get_admin_handler(credentials)
+                .ok_or_else(unauthorized_write)?;
+            delete_group(backend_handler, group_name).await
+        }
         err => Err(err.into_ldap_error(
             &request,
             format!(
-                r#""uid=id,ou=people,{}" or "uid=id,ou=groups,{}""#,
+                r#""uid=id,ou=people,{}" or "cn=id,ou=groups,{}""#,
                 base_dn_str, base_dn_str
             ),
         )),
@@ -40,7 +54,8 @@ pub(crate) async fn delete_user_or_group(
 
 #[instrument(skip_all, level = "debug")]
 async fn delete_user(
-    backend_handler: &impl AdminBackendHandler,
+    backend_handler: &impl UserDeletionBackendHandler,
+    credentials: &ValidationResults,
     user_id: UserId,
 ) -> LdapResult<Vec<LdapOp>> {
     backend_handler
@@ -56,6 +71,27 @@ async fn delete_user(
                 message: format!("Error while finding user: {:?}", e),
             },
         })?;
+    // The roles can only delete the regular users.
+    if !credentials.is_admin() {
+        let user_is_admin = backend_handler
+            .get_user_groups(&user_id)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while requesting user's groups: {:#?}", e),
+            })?
+            .iter()
+            .any(|g| g.display_name == "lldap_admin".into());
+        if user_is_admin {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    "User `{}` cannot delete the admin `{}`",
+                    credentials.user, user_id
+                ),
+            });
+        }
+    }
     backend_handler
         .delete_user(&user_id)
         .await
@@ -105,16 +141,18 @@ async fn delete_group(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::handler::tests::setup_bound_admin_handler;
+    use crate::handler::tests::{setup_bound_admin_handler, setup_bound_handler_with_role};
     use chrono::TimeZone;
+    use lldap_auth::access_control::RolePermission;
     use lldap_domain::{
-        types::{Group, GroupId, User},
+        types::{Group, GroupDetails, GroupId, User},
         uuid,
     };
     use lldap_domain_model::error::DomainError;
     use lldap_test_utils::MockTestBackendHandler;
     use mockall::predicate::eq;
     use pretty_assertions::assert_eq;
+    use std::collections::HashSet;
 
     #[tokio::test]
     async fn test_delete_user() {
@@ -154,8 +192,10 @@ mod tests {
                     id: GroupId(34),
                     display_name: GroupName::from("bob"),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     users: Vec::new(),
+                    subgroups: Vec::new(),
                     attributes: Vec::new(),
                 }])
             });
@@ -284,8 +324,10 @@ mod tests {
                     id: GroupId(34),
                     display_name: GroupName::from("bob"),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     users: Vec::new(),
+                    subgroups: Vec::new(),
                     attributes: Vec::new(),
                 }])
             });
@@ -303,4 +345,90 @@ mod tests {
             )])
         );
     }
+
+    fn expect_get_user_groups(mock: &mut MockTestBackendHandler, user_id: &str, group: &str) {
+        let group = GroupName::from(group);
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new(user_id)))
+            .times(1)
+            .return_once(move |_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: group,
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                }]))
+            });
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        expect_get_user_groups(&mut mock, "bob", "employees");
+        mock.expect_delete_user()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler =
+            setup_bound_handler_with_role(mock, vec![RolePermission::DeleteUsers]).await;
+        let request = LdapOp::DelRequest("uid=bob,ou=people,dc=example,dc=com".to_owned());
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_del_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_admin_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        expect_get_user_groups(&mut mock, "bob", "lldap_admin");
+        let mut ldap_handler =
+            setup_bound_handler_with_role(mock, vec![RolePermission::DeleteUsers]).await;
+        let request = LdapOp::DelRequest("uid=bob,ou=people,dc=example,dc=com".to_owned());
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_del_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot delete the admin `bob`".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_group_with_role() {
+        let mut ldap_handler = setup_bound_handler_with_role(
+            MockTestBackendHandler::new(),
+            vec![RolePermission::DeleteUsers],
+        )
+        .await;
+        let request = LdapOp::DelRequest("cn=bob,ou=groups,dc=example,dc=com".to_owned());
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_del_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Unauthorized write".to_string()
+            )])
+        );
+    }
 }
@@ -1,26 +1,44 @@
 use crate::{
+    audit::describe_operation,
     compare,
     core::{
-        error::{LdapError, LdapResult},
+        error::{LdapError, LdapResult, unauthorized_write},
         utils::{LdapInfo, parse_distinguished_name},
     },
     create, delete, modify,
+    modify_dn::{self, make_modify_dn_response},
+    paged_results::{PagedSearches, get_paged_results_control},
     password::{self, do_password_modification},
     search::{
         self, is_root_dse_request, is_subschema_entry_request, make_ldap_subschema_entry,
         make_search_error, make_search_request, make_search_success, root_dse_response,
     },
+    sort::{SortRequest, SortResult, get_sort_control, make_sort_response_control},
+    sync::{
+        self, PersistentSync, SyncRequest, SyncStage, get_sync_request_control, make_cookie,
+        make_sync_done_control, make_sync_info_refresh_done, make_sync_search_request,
+    },
 };
-use ldap3_proto::proto::{
-    LdapAddRequest, LdapBindRequest, LdapBindResponse, LdapCompareRequest, LdapExtendedRequest,
-    LdapExtendedResponse, LdapFilter, LdapModifyRequest, LdapOp, LdapPasswordModifyRequest,
-    LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest, OID_PASSWORD_MODIFY, OID_WHOAMI,
+use chrono::NaiveDateTime;
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{
+        LdapAddRequest, LdapBindRequest, LdapBindResponse, LdapCompareRequest, LdapExtendedRequest,
+        LdapExtendedResponse, LdapFilter, LdapModifyDNRequest, LdapModifyRequest, LdapOp,
+        LdapPasswordModifyRequest, LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest,
+        OID_PASSWORD_MODIFY, OID_WHOAMI,
+    },
 };
-use lldap_access_control::AccessControlledBackendHandler;
+use lldap_access_control::{AccessControlledBackendHandler, ReadonlyBackendHandler};
 use lldap_auth::access_control::ValidationResults;
 use lldap_domain::{public_schema::PublicSchema, types::AttributeName};
-use lldap_domain_handlers::handler::{BackendHandler, LoginHandler, ReadSchemaBackendHandler};
+use lldap_domain_handlers::handler::{
+    AuditLogBackendHandler, BackendHandler, CreateAuditEventRequest, LoginHandler,
+    LoginThrottleBackendHandler, LoginThrottleSubject, ReadSchemaBackendHandler,
+};
 use lldap_opaque_handler::OpaqueHandler;
+use lldap_validation::password::PasswordPolicy;
+use std::{collections::HashSet, net::IpAddr};
 use tracing::{debug, instrument};
 
 use super::delete::make_del_response;
@@ -34,6 +52,18 @@ pub(crate) fn make_add_response(code: LdapResultCode, message: String) -> LdapOp
     })
 }
 
+fn make_bind_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::BindResponse(LdapBindResponse {
+        res: LdapResultOp {
+            code,
+            matcheddn: "".to_string(),
+            message,
+            referral: vec![],
+        },
+        saslcreds: None,
+    })
+}
+
 pub(crate) fn make_extended_response(code: LdapResultCode, message: String) -> LdapOp {
     LdapOp::ExtendedResponse(LdapExtendedResponse {
         res: LdapResultOp {
@@ -56,17 +86,71 @@ pub(crate) fn make_modify_response(code: LdapResultCode, message: String) -> Lda
     })
 }
 
+// See RFC 4511, section 4.14.
+pub const OID_START_TLS: &str = "1.3.6.1.4.1.1466.20037";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsStatus {
+    /// Plain connection, StartTLS is not configured.
+    Unavailable,
+    /// Plain connection, that can be upgraded with StartTLS.
+    StartTlsAvailable,
+    /// StartTLS was accepted: the connection must be upgraded before reading the next message.
+    StartTlsPending,
+    /// The connection is encrypted, either with LDAPS or after StartTLS.
+    Encrypted,
+}
+
 pub struct LdapHandler<Backend> {
     user_info: Option<ValidationResults>,
     backend_handler: AccessControlledBackendHandler<Backend>,
     ldap_info: LdapInfo,
     session_uuid: uuid::Uuid,
+    /// Address of the client, used to throttle the failed binds.
+    source_ip: Option<IpAddr>,
+    paged_searches: PagedSearches,
+    /// The refreshAndPersist content synchronization, if any.
+    persistent_sync: Option<PersistentSync>,
+    tls_status: TlsStatus,
+    require_tls_for_bind: bool,
+    /// Binds and changes to record in the audit log.
+    audit_events: Vec<CreateAuditEventRequest>,
+    /// The JWTs of the users that were logged out, e.g. by a rename.
+    blacklisted_jwts: HashSet<u64>,
 }
 
 impl<Backend> LdapHandler<Backend> {
     pub fn session_uuid(&self) -> &uuid::Uuid {
         &self.session_uuid
     }
+
+    pub fn tls_status(&self) -> TlsStatus {
+        self.tls_status
+    }
+
+    pub fn set_tls_status(&mut self, tls_status: TlsStatus) {
+        self.tls_status = tls_status;
+    }
+
+    pub fn has_persistent_search(&self) -> bool {
+        self.persistent_sync.is_some()
+    }
+
+    /// Stops the refreshAndPersist content synchronization, e.g. when the client abandons it.
+    pub fn cancel_persistent_search(&mut self) {
+        self.persistent_sync = None;
+    }
+
+    /// Returns the audit events of the operations handled since the last call.
+    pub fn take_audit_events(&mut self) -> Vec<CreateAuditEventRequest> {
+        std::mem::take(&mut self.audit_events)
+    }
+
+    /// Returns the JWTs blacklisted by the operations handled since the last call. They must be
+    /// refused by the HTTP server too.
+    pub fn take_blacklisted_jwts(&mut self) -> HashSet<u64> {
+        std::mem::take(&mut self.blacklisted_jwts)
+    }
 }
 
 impl<Backend: LoginHandler> LdapHandler<Backend> {
@@ -75,6 +159,18 @@ impl<Backend: LoginHandler> LdapHandler<Backend> {
     }
 }
 
+impl<Backend: LoginThrottleBackendHandler> LdapHandler<Backend> {
+    pub fn get_login_throttle_handler(&self) -> &(impl LoginThrottleBackendHandler + use<Backend>) {
+        self.backend_handler.unsafe_get_handler()
+    }
+}
+
+impl<Backend: AuditLogBackendHandler> LdapHandler<Backend> {
+    pub fn get_audit_log_handler(&self) -> &(impl AuditLogBackendHandler + use<Backend>) {
+        self.backend_handler.unsafe_get_handler()
+    }
+}
+
 impl<Backend: OpaqueHandler> LdapHandler<Backend> {
     pub fn get_opaque_handler(&self) -> &(impl OpaqueHandler + use<Backend>) {
         self.backend_handler.unsafe_get_handler()
@@ -87,12 +183,17 @@ enum Credentials<'s> {
 }
 
 impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         backend_handler: AccessControlledBackendHandler<Backend>,
         mut ldap_base_dn: String,
         ignored_user_attributes: Vec<AttributeName>,
         ignored_group_attributes: Vec<AttributeName>,
+        password_policy: PasswordPolicy,
         session_uuid: uuid::Uuid,
+        source_ip: Option<IpAddr>,
+        tls_status: TlsStatus,
+        require_tls_for_bind: bool,
     ) -> Self {
         ldap_base_dn.make_ascii_lowercase();
         Self {
@@ -108,8 +209,16 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 base_dn_str: ldap_base_dn,
                 ignored_user_attributes,
                 ignored_group_attributes,
+                password_policy,
             },
             session_uuid,
+            source_ip,
+            paged_searches: PagedSearches::default(),
+            persistent_sync: None,
+            tls_status,
+            require_tls_for_bind,
+            audit_events: Vec::new(),
+            blacklisted_jwts: HashSet::new(),
         }
     }
 
@@ -120,7 +229,11 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             ldap_base_dn.to_string(),
             vec![],
             vec![],
+            PasswordPolicy::default(),
             uuid::Uuid::parse_str("550e8400-e29b-41d4-a716-446655440000").unwrap(),
+            None,
+            TlsStatus::Unavailable,
+            false,
         )
     }
 
@@ -135,12 +248,28 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
     }
 
     pub async fn do_search_or_dse(&self, request: &LdapSearchRequest) -> LdapResult<Vec<LdapOp>> {
+        self.do_sorted_search_or_dse(request, None)
+            .await
+            .map(|(results, _)| results)
+    }
+
+    async fn do_sorted_search_or_dse(
+        &self,
+        request: &LdapSearchRequest,
+        sort_request: Option<&SortRequest>,
+    ) -> LdapResult<(Vec<LdapOp>, Option<LdapControl>)> {
+        // There is only ever one entry, it's trivially sorted.
+        let sort_control =
+            || sort_request.map(|_| make_sort_response_control(SortResult::Success, None));
         if is_root_dse_request(request) {
             debug!("rootDSE request");
-            return Ok(vec![
-                root_dse_response(&self.ldap_info.base_dn_str),
-                make_search_success(),
-            ]);
+            return Ok((
+                vec![
+                    root_dse_response(&self.ldap_info.base_dn_str),
+                    make_search_success(),
+                ],
+                sort_control(),
+            ));
         } else if is_subschema_entry_request(request) {
             // See RFC4512 section 4.4 "Subschema discovery"
             debug!("Schema request");
@@ -157,16 +286,23 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 code: LdapResultCode::OperationsError,
                 message: format!("Unable to get schema: {:#}", e),
             })?;
-            return Ok(vec![
-                make_ldap_subschema_entry(PublicSchema::from(schema)),
-                make_search_success(),
-            ]);
+            return Ok((
+                vec![
+                    make_ldap_subschema_entry(PublicSchema::from(schema)),
+                    make_search_success(),
+                ],
+                sort_control(),
+            ));
         }
-        self.do_search(request).await
+        self.do_search(request, sort_request).await
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn do_search(&self, request: &LdapSearchRequest) -> LdapResult<Vec<LdapOp>> {
+    async fn do_search(
+        &self,
+        request: &LdapSearchRequest,
+        sort_request: Option<&SortRequest>,
+    ) -> LdapResult<(Vec<LdapOp>, Option<LdapControl>)> {
         let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
             code: LdapResultCode::InsufficentAccessRights,
             message: "No user currently bound".to_string(),
@@ -174,11 +310,37 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         let backend_handler = self
             .backend_handler
             .get_user_restricted_lister_handler(user_info);
-        search::do_search(&backend_handler, &self.ldap_info, request).await
+        search::do_search(&backend_handler, &self.ldap_info, request, sort_request).await
     }
 
     #[instrument(skip_all, level = "debug", fields(dn = %request.dn))]
     pub async fn do_bind(&mut self, request: &LdapBindRequest) -> Vec<LdapOp> {
+        // The paged results were computed with the previous user's permissions.
+        self.paged_searches.clear();
+        self.persistent_sync = None;
+        if self.require_tls_for_bind && self.tls_status != TlsStatus::Encrypted {
+            self.user_info = None;
+            return vec![make_bind_response(
+                LdapResultCode::ConfidentialityRequired,
+                "Binding requires an encrypted connection (LDAPS or StartTLS)".to_string(),
+            )];
+        }
+        let source_ip = self.source_ip.map(LoginThrottleSubject::Ip);
+        if let Some(source_ip) = &source_ip {
+            // Fail closed: if the lockout can't be checked, refuse the bind.
+            if !matches!(
+                self.get_login_throttle_handler()
+                    .get_login_lockout(source_ip)
+                    .await,
+                Ok(None)
+            ) {
+                self.user_info = None;
+                return vec![make_bind_response(
+                    LdapResultCode::InvalidCredentials,
+                    "Too many failed login attempts, try again later".to_string(),
+                )];
+            }
+        }
         let (code, message) =
             match password::do_bind(&self.ldap_info, request, self.get_login_handler()).await {
                 Ok(user_id) => {
@@ -190,21 +352,24 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                     debug!("Success!");
                     (LdapResultCode::Success, "".to_string())
                 }
-                Err(err) => (err.code, err.message),
+                Err(err) => {
+                    if let Some(source_ip) = &source_ip {
+                        if let Err(e) = self
+                            .get_login_throttle_handler()
+                            .register_login_failure(source_ip)
+                            .await
+                        {
+                            debug!(?e, "Could not register the failed bind");
+                        }
+                    }
+                    (err.code, err.message)
+                }
             };
-        vec![LdapOp::BindResponse(LdapBindResponse {
-            res: LdapResultOp {
-                code,
-                matcheddn: "".to_string(),
-                message,
-                referral: vec![],
-            },
-            saslcreds: None,
-        })]
+        vec![make_bind_response(code, message)]
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn do_extended_request(&self, request: &LdapExtendedRequest) -> Vec<LdapOp> {
+    async fn do_extended_request(&mut self, request: &LdapExtendedRequest) -> Vec<LdapOp> {
         match request.name.as_str() {
             OID_PASSWORD_MODIFY => match LdapPasswordModifyRequest::try_from(request) {
                 Ok(password_request) => {
@@ -241,6 +406,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                     .unwrap_or_default();
                 vec![make_extended_response(LdapResultCode::Success, authz_id)]
             }
+            OID_START_TLS => self.do_start_tls(),
             _ => vec![make_extended_response(
                 LdapResultCode::UnwillingToPerform,
                 format!("Unsupported extended operation: {}", &request.name),
@@ -248,6 +414,34 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         }
     }
 
+    fn do_start_tls(&mut self) -> Vec<LdapOp> {
+        let (code, message) = match self.tls_status {
+            TlsStatus::StartTlsAvailable => {
+                debug!("Accepting StartTLS");
+                self.tls_status = TlsStatus::StartTlsPending;
+                (LdapResultCode::Success, String::new())
+            }
+            TlsStatus::Unavailable => (
+                LdapResultCode::Unavailable,
+                "StartTLS is not enabled on this server".to_string(),
+            ),
+            TlsStatus::StartTlsPending | TlsStatus::Encrypted => (
+                LdapResultCode::OperationsError,
+                "TLS is already established".to_string(),
+            ),
+        };
+        vec![LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message,
+                referral: vec![],
+            },
+            name: Some(OID_START_TLS.to_string()),
+            value: None,
+        })]
+    }
+
     #[instrument(skip_all, level = "debug", fields(dn = %request.dn))]
     pub async fn do_modify_request(&self, request: &LdapModifyRequest) -> Vec<LdapOp> {
         let credentials = match self.get_credentials() {
@@ -256,10 +450,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         };
         modify::handle_modify_request(
             self.get_opaque_handler(),
-            |credentials, user_id| {
-                self.backend_handler
-                    .get_readable_handler(credentials, &user_id)
-            },
+            &self.backend_handler,
             &self.ldap_info,
             credentials,
             request,
@@ -270,28 +461,32 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
 
     #[instrument(skip_all, level = "debug")]
     pub async fn create_user_or_group(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
-        let backend_handler = self
-            .user_info
-            .as_ref()
-            .and_then(|u| self.backend_handler.get_admin_handler(u))
-            .ok_or_else(|| LdapError {
-                code: LdapResultCode::InsufficentAccessRights,
-                message: "Unauthorized write".to_string(),
-            })?;
-        create::create_user_or_group(backend_handler, &self.ldap_info, request).await
+        let credentials = self.user_info.as_ref().ok_or_else(unauthorized_write)?;
+        create::create_user_or_group(&self.backend_handler, &self.ldap_info, credentials, request)
+            .await
     }
 
     #[instrument(skip_all, level = "debug")]
     pub async fn delete_user_or_group(&self, request: String) -> LdapResult<Vec<LdapOp>> {
-        let backend_handler = self
-            .user_info
-            .as_ref()
-            .and_then(|u| self.backend_handler.get_admin_handler(u))
-            .ok_or_else(|| LdapError {
-                code: LdapResultCode::InsufficentAccessRights,
-                message: "Unauthorized write".to_string(),
-            })?;
-        delete::delete_user_or_group(backend_handler, &self.ldap_info, request).await
+        let credentials = self.user_info.as_ref().ok_or_else(unauthorized_write)?;
+        delete::delete_user_or_group(&self.backend_handler, &self.ldap_info, credentials, request)
+            .await
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    pub async fn rename_user_or_group(
+        &mut self,
+        request: LdapModifyDNRequest,
+    ) -> LdapResult<Vec<LdapOp>> {
+        let credentials = self.user_info.as_ref().ok_or_else(unauthorized_write)?;
+        modify_dn::rename_user_or_group(
+            &self.backend_handler,
+            &self.ldap_info,
+            credentials,
+            request,
+            &mut self.blacklisted_jwts,
+        )
+        .await
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -303,12 +498,26 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         );
         compare::compare(
             request,
-            self.do_search(&req).await?,
+            self.do_search(&req, None).await?.0,
             &self.ldap_info.base_dn_str,
         )
     }
 
     pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
+        let audited_operation = describe_operation(
+            &self.ldap_info,
+            self.user_info.as_ref().map(|u| &u.user),
+            &ldap_op,
+        );
+        let responses = self.dispatch_ldap_message(ldap_op).await?;
+        if let Some(operation) = audited_operation {
+            self.audit_events
+                .push(operation.into_event(self.source_ip, &responses));
+        }
+        Some(responses)
+    }
+
+    async fn dispatch_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
         Some(match ldap_op {
             LdapOp::BindRequest(request) => self.do_bind(&request).await,
             LdapOp::SearchRequest(request) => self
@@ -324,6 +533,8 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                         .unwrap_or("<not bound>"),
                 );
                 self.user_info = None;
+                self.paged_searches.clear();
+                self.persistent_sync = None;
                 // No need to notify on unbind (per rfc4511)
                 return None;
             }
@@ -337,6 +548,10 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 .delete_user_or_group(request)
                 .await
                 .unwrap_or_else(|e: LdapError| vec![make_del_response(e.code, e.message)]),
+            LdapOp::ModifyDNRequest(request) => self
+                .rename_user_or_group(request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_modify_dn_response(e.code, e.message)]),
             LdapOp::CompareRequest(request) => self
                 .do_compare(request)
                 .await
@@ -347,6 +562,198 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             )],
         })
     }
+
+    /// The entries changed since the last synchronization, or all of them for a full refresh.
+    async fn get_sync_changes(
+        &self,
+        request: &LdapSearchRequest,
+        since: Option<NaiveDateTime>,
+        stage: SyncStage,
+        cookie: &[u8],
+    ) -> LdapResult<Vec<(LdapOp, Vec<LdapControl>)>> {
+        // The deleted entries can't be filtered by permissions, only the replication accounts
+        // can see them.
+        let readonly_handler = self
+            .user_info
+            .as_ref()
+            .and_then(|u| self.backend_handler.get_readonly_handler(u))
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Content synchronization requires read access to all the entries"
+                    .to_string(),
+            })?;
+        let expired_memberships = match since {
+            Some(since) => readonly_handler
+                .list_expired_memberships(since)
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Unable to get the expired memberships: {:#}", e),
+                })?,
+            None => Vec::new(),
+        };
+        let changed_dns = sync::get_expired_membership_dns(expired_memberships, &self.ldap_info);
+        let (search_request, added_attributes) = make_sync_search_request(request);
+        let (results, _) = self.do_search(&search_request, None).await?;
+        let mut changes = sync::make_sync_entries(
+            results,
+            &added_attributes,
+            since,
+            &changed_dns,
+            stage,
+            cookie,
+        )?;
+        if let Some(since) = since {
+            let deleted_entries =
+                readonly_handler
+                    .list_deleted_entries(since)
+                    .await
+                    .map_err(|e| LdapError {
+                        code: LdapResultCode::OperationsError,
+                        message: format!("Unable to get the deleted entries: {:#}", e),
+                    })?;
+            changes.extend(sync::make_deleted_entries(
+                deleted_entries,
+                &self.ldap_info,
+                &request.base,
+                stage,
+                cookie,
+            )?);
+        }
+        Ok(changes)
+    }
+
+    /// Answers a search with the content synchronization control (RFC 4533). With a cookie, only
+    /// the changes since the last synchronization are sent, including the deleted entries.
+    #[instrument(skip_all, level = "debug")]
+    async fn do_sync_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        sync_request: SyncRequest,
+    ) -> Vec<(LdapOp, Vec<LdapControl>)> {
+        self.persistent_sync = None;
+        let now = chrono::Utc::now().naive_utc();
+        let cookie = make_cookie(now);
+        let mut results = match self
+            .get_sync_changes(request, sync_request.since, SyncStage::Refresh, &cookie)
+            .await
+        {
+            Ok(changes) => changes,
+            Err(e) => return vec![(make_search_error(e.code, e.message), Vec::new())],
+        };
+        // Without a cookie, the entries that were not sent must be deleted by the consumer.
+        let refresh_deletes = sync_request.since.is_some();
+        if sync_request.persist {
+            results.push((
+                make_sync_info_refresh_done(cookie, refresh_deletes),
+                Vec::new(),
+            ));
+            self.persistent_sync = Some(PersistentSync {
+                request: request.clone(),
+                last_sync: now,
+            });
+        } else {
+            results.push((
+                make_search_success(),
+                vec![make_sync_done_control(cookie, refresh_deletes)],
+            ));
+        }
+        results
+    }
+
+    /// Returns the changes since the last poll of the refreshAndPersist synchronization, to send
+    /// with the message ID of its search request.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn poll_persistent_search(&mut self) -> Vec<(LdapOp, Vec<LdapControl>)> {
+        let Some(persistent_sync) = &self.persistent_sync else {
+            return Vec::new();
+        };
+        let request = persistent_sync.request.clone();
+        let since = persistent_sync.last_sync;
+        let now = chrono::Utc::now().naive_utc();
+        match self
+            .get_sync_changes(&request, Some(since), SyncStage::Persist, &make_cookie(now))
+            .await
+        {
+            Ok(changes) => {
+                if let Some(persistent_sync) = &mut self.persistent_sync {
+                    persistent_sync.last_sync = now;
+                }
+                changes
+            }
+            Err(e) => {
+                self.persistent_sync = None;
+                vec![(make_search_error(e.code, e.message), Vec::new())]
+            }
+        }
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn do_search_with_controls(
+        &mut self,
+        request: &LdapSearchRequest,
+        controls: &[LdapControl],
+    ) -> Vec<(LdapOp, Vec<LdapControl>)> {
+        if let Some(sync_request) = get_sync_request_control(controls) {
+            return self.do_sync_search(request, sync_request).await;
+        }
+        let sort_request = match get_sort_control(controls).transpose() {
+            Ok(sort_request) => sort_request,
+            Err(e) => return vec![(make_search_error(e.code, e.message), Vec::new())],
+        };
+        let paged_results = get_paged_results_control(controls);
+        if let Some((size, cookie)) = paged_results.filter(|(_, cookie)| !cookie.is_empty()) {
+            return self
+                .paged_searches
+                .next_page(request, size, cookie)
+                .unwrap_or_else(|e: LdapError| {
+                    vec![(make_search_error(e.code, e.message), Vec::new())]
+                });
+        }
+        let (results, sort_control) = self
+            .do_sorted_search_or_dse(request, sort_request.as_ref())
+            .await
+            .unwrap_or_else(|e: LdapError| (vec![make_search_error(e.code, e.message)], None));
+        let done_controls: Vec<_> = sort_control.into_iter().collect();
+        match paged_results {
+            Some((size, _)) => {
+                self.paged_searches
+                    .first_page(request, size, results, done_controls)
+            }
+            None => {
+                // Some clients expect the paged results control even if they didn't ask for it.
+                let mut controls = vec![LdapControl::SimplePagedResults {
+                    size: results
+                        .len()
+                        .saturating_sub(1)
+                        .try_into()
+                        .unwrap_or(i64::MAX),
+                    cookie: vec![],
+                }];
+                controls.extend(done_controls);
+                let mut results: Vec<_> = results.into_iter().map(|op| (op, Vec::new())).collect();
+                if let Some((LdapOp::SearchResultDone(_), done_controls)) = results.last_mut() {
+                    *done_controls = controls;
+                }
+                results
+            }
+        }
+    }
+
+    /// Same as `handle_ldap_message`, but takes the request controls into account, and returns
+    /// the controls to attach to each response.
+    pub async fn handle_ldap_message_with_controls(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: &[LdapControl],
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>)>> {
+        if let LdapOp::SearchRequest(request) = &ldap_op {
+            return Some(self.do_search_with_controls(request, controls).await);
+        }
+        self.handle_ldap_message(ldap_op)
+            .await
+            .map(|ops| ops.into_iter().map(|op| (op, Vec::new())).collect())
+    }
 }
 
 #[cfg(test)]
@@ -355,6 +762,7 @@ pub mod tests {
     use crate::password::tests::make_bind_success;
     use chrono::TimeZone;
     use ldap3_proto::proto::{LdapBindCred, LdapWhoamiRequest};
+    use lldap_auth::access_control::RolePermission;
     use lldap_domain::{
         types::{GroupDetails, GroupId, UserId},
         uuid,
@@ -381,8 +789,33 @@ pub mod tests {
     }
 
     pub async fn setup_bound_handler_with_group(
+        mock: MockTestBackendHandler,
+        group: &str,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_group_and_owned_groups(mock, group, Vec::new()).await
+    }
+
+    pub async fn setup_bound_handler_with_group_and_owned_groups(
+        mock: MockTestBackendHandler,
+        group: &str,
+        owned_groups: Vec<GroupId>,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_permissions(mock, group, owned_groups, Vec::new()).await
+    }
+
+    /// Binds a user whose only group has a role with the given permissions.
+    pub async fn setup_bound_handler_with_role(
+        mock: MockTestBackendHandler,
+        role_permissions: Vec<RolePermission>,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_permissions(mock, "helpdesk", Vec::new(), role_permissions).await
+    }
+
+    async fn setup_bound_handler_with_permissions(
         mut mock: MockTestBackendHandler,
         group: &str,
+        owned_groups: Vec<GroupId>,
+        role_permissions: Vec<RolePermission>,
     ) -> LdapHandler<MockTestBackendHandler> {
         mock.expect_bind()
             .with(eq(BindRequest {
@@ -399,11 +832,16 @@ pub mod tests {
                     group_id: GroupId(42),
                     display_name: group.into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     attributes: Vec::new(),
                 });
                 Ok(set)
             });
+        mock.expect_get_role_permissions()
+            .returning(move |_| Ok(role_permissions.iter().cloned().collect()));
+        mock.expect_get_owned_groups()
+            .return_once(|_| Ok(owned_groups));
         setup_default_schema(&mut mock);
         let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=Example,dc=com");
         let request = LdapBindRequest {
@@ -459,4 +897,107 @@ pub mod tests {
             )])
         );
     }
+
+    fn make_start_tls_request() -> LdapOp {
+        LdapOp::ExtendedRequest(LdapExtendedRequest {
+            name: OID_START_TLS.to_string(),
+            value: None,
+        })
+    }
+
+    fn make_start_tls_response(code: LdapResultCode, message: &str) -> Vec<LdapOp> {
+        vec![LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message: message.to_string(),
+                referral: vec![],
+            },
+            name: Some(OID_START_TLS.to_string()),
+            value: None,
+        })]
+    }
+
+    #[tokio::test]
+    async fn test_start_tls() {
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        ldap_handler.set_tls_status(TlsStatus::StartTlsAvailable);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_start_tls_request())
+                .await,
+            Some(make_start_tls_response(LdapResultCode::Success, ""))
+        );
+        assert_eq!(ldap_handler.tls_status(), TlsStatus::StartTlsPending);
+        ldap_handler.set_tls_status(TlsStatus::Encrypted);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_start_tls_request())
+                .await,
+            Some(make_start_tls_response(
+                LdapResultCode::OperationsError,
+                "TLS is already established"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_tls_unavailable() {
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_start_tls_request())
+                .await,
+            Some(make_start_tls_response(
+                LdapResultCode::Unavailable,
+                "StartTLS is not enabled on this server"
+            ))
+        );
+        assert_eq!(ldap_handler.tls_status(), TlsStatus::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_bind_requires_tls() {
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        ldap_handler.require_tls_for_bind = true;
+        ldap_handler.set_tls_status(TlsStatus::StartTlsAvailable);
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await,
+            vec![make_bind_response(
+                LdapResultCode::ConfidentialityRequired,
+                "Binding requires an encrypted connection (LDAPS or StartTLS)".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_with_tls_required_on_encrypted_connection() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| Ok(HashSet::new()));
+        mock.expect_get_owned_groups()
+            .return_once(|_| Ok(Vec::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        ldap_handler.require_tls_for_bind = true;
+        ldap_handler.set_tls_status(TlsStatus::Encrypted);
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(ldap_handler.do_bind(&request).await, make_bind_success());
+    }
 }
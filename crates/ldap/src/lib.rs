@@ -1,14 +1,19 @@
+pub(crate) mod audit;
 pub(crate) mod compare;
 pub(crate) mod core;
 pub(crate) mod create;
 pub(crate) mod delete;
 pub(crate) mod handler;
 pub(crate) mod modify;
+pub(crate) mod modify_dn;
+pub(crate) mod paged_results;
 pub(crate) mod password;
 pub(crate) mod search;
+pub(crate) mod sort;
+pub(crate) mod sync;
 
 pub use core::utils::{UserFieldType, map_group_field, map_user_field};
-pub use handler::LdapHandler;
+pub use handler::{LdapHandler, OID_START_TLS, TlsStatus};
 
 pub use core::group::get_default_group_object_classes;
 pub use core::user::get_default_user_object_classes;
@@ -1,30 +1,45 @@
 use crate::{
     core::{
         error::{LdapError, LdapResult},
-        utils::{LdapInfo, get_user_id_from_distinguished_name},
+        utils::{
+            LdapInfo, UserFieldType, UserOrGroupName, get_custom_attribute,
+            get_user_id_from_distinguished_name, get_user_or_group_id_from_distinguished_name,
+            map_user_field,
+        },
     },
     handler::make_modify_response,
     password::{self},
 };
 use ldap3_proto::proto::{LdapModify, LdapModifyRequest, LdapModifyType, LdapOp, LdapResultCode};
-use lldap_access_control::UserReadableBackendHandler;
+use lldap_access_control::{
+    AccessControlledBackendHandler, GroupMembershipBackendHandler,
+    UserAttributeEditorBackendHandler, UserReadableBackendHandler, UserWriteableBackendHandler,
+};
 use lldap_auth::access_control::ValidationResults;
-use lldap_domain::types::UserId;
+use lldap_domain::{
+    deserialize::deserialize_attribute_value,
+    public_schema::PublicSchema,
+    requests::UpdateUserRequest,
+    types::{
+        Attribute, AttributeName, AttributeType, AttributeValue, Email, GroupId, GroupName,
+        JpegPhoto, User, UserId,
+    },
+};
+use lldap_domain_handlers::handler::{
+    BackendHandler, GroupListerBackendHandler, GroupRequestFilter,
+};
+use lldap_domain_model::{error::DomainError, model::UserColumn};
 use lldap_opaque_handler::OpaqueHandler;
 
 async fn handle_modify_change(
     opaque_handler: &impl OpaqueHandler,
+    ldap_info: &LdapInfo,
     user_id: UserId,
     credentials: &ValidationResults,
     user_is_admin: bool,
     change: &LdapModify,
 ) -> LdapResult<()> {
-    if !change
-        .modification
-        .atype
-        .eq_ignore_ascii_case("userpassword")
-        || change.operation != LdapModifyType::Replace
-    {
+    if change.operation != LdapModifyType::Replace {
         return Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
             message: format!(
@@ -43,6 +58,7 @@ async fn handle_modify_change(
         });
     }
     if let [value] = &change.modification.vals.as_slice() {
+        password::check_password_policy(ldap_info, &user_id, value)?;
         password::change_password(opaque_handler, user_id, value)
             .await
             .map_err(|e| LdapError {
@@ -61,62 +77,615 @@ async fn handle_modify_change(
     Ok(())
 }
 
-pub(crate) async fn handle_modify_request<'cred, UserBackendHandler>(
+enum UserModifyTarget {
+    Email,
+    DisplayName,
+    Attribute(AttributeType, bool),
+}
+
+/// The state of one user attribute, as modified by the changes of the request so far.
+struct UserAttributeModification {
+    name: AttributeName,
+    target: UserModifyTarget,
+    values: Vec<Vec<u8>>,
+}
+
+fn get_modify_target(
+    atype: &str,
+    schema: &PublicSchema,
+    is_admin: bool,
+) -> LdapResult<(AttributeName, UserModifyTarget)> {
+    let (name, target) = match map_user_field(&AttributeName::from(atype), schema) {
+        UserFieldType::PrimaryField(UserColumn::UserId) => {
+            return Err(LdapError {
+                code: LdapResultCode::NotAllowedOnRDN,
+                message: format!("Cannot modify attribute `{}`: it is part of the DN", atype),
+            });
+        }
+        UserFieldType::PrimaryField(UserColumn::Email) => {
+            (AttributeName::from("mail"), UserModifyTarget::Email)
+        }
+        UserFieldType::PrimaryField(UserColumn::DisplayName) => (
+            AttributeName::from("display_name"),
+            UserModifyTarget::DisplayName,
+        ),
+        UserFieldType::PrimaryField(UserColumn::CreationDate) => (
+            AttributeName::from("creation_date"),
+            UserModifyTarget::Attribute(AttributeType::DateTime, false),
+        ),
+        UserFieldType::PrimaryField(UserColumn::Uuid) => (
+            AttributeName::from("uuid"),
+            UserModifyTarget::Attribute(AttributeType::String, false),
+        ),
+        UserFieldType::Attribute(name, typ, is_list) => {
+            (name, UserModifyTarget::Attribute(typ, is_list))
+        }
+        UserFieldType::NoMatch => {
+            return Err(LdapError {
+                code: LdapResultCode::UndefinedAttributeType,
+                message: format!("Attribute `{}` is not defined in the schema", atype),
+            });
+        }
+        _ => {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: format!("Cannot modify attribute `{}`", atype),
+            });
+        }
+    };
+    // Same rules as the GraphQL `updateUser` mutation.
+    let attribute_schema = schema
+        .get_schema()
+        .user_attributes
+        .get_attribute_schema(&name)
+        .ok_or_else(|| LdapError {
+            code: LdapResultCode::UndefinedAttributeType,
+            message: format!("Attribute `{}` is not defined in the schema", atype),
+        })?;
+    if attribute_schema.is_readonly {
+        return Err(LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: format!("Permission denied: Attribute `{}` is read-only", atype),
+        });
+    }
+    if !is_admin && !attribute_schema.is_editable {
+        return Err(LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: format!(
+                "Permission denied: Attribute `{}` is not editable by regular users",
+                atype
+            ),
+        });
+    }
+    Ok((name, target))
+}
+
+fn get_current_values(
+    user: &User,
+    name: &AttributeName,
+    target: &UserModifyTarget,
+) -> Vec<Vec<u8>> {
+    match target {
+        UserModifyTarget::Email => vec![user.email.to_string().into_bytes()],
+        UserModifyTarget::DisplayName => user
+            .display_name
+            .iter()
+            .map(|name| name.clone().into_bytes())
+            .collect(),
+        UserModifyTarget::Attribute(_, _) => {
+            get_custom_attribute(&user.attributes, name).unwrap_or_default()
+        }
+    }
+    .into_iter()
+    .filter(|value| !value.is_empty())
+    .collect()
+}
+
+fn apply_change(values: &mut Vec<Vec<u8>>, change: &LdapModify) -> LdapResult<()> {
+    let atype = &change.modification.atype;
+    match change.operation {
+        LdapModifyType::Add => {
+            if change.modification.vals.is_empty() {
+                return Err(LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: format!("Missing value for attribute {}", atype),
+                });
+            }
+            for value in &change.modification.vals {
+                if values.contains(value) {
+                    return Err(LdapError {
+                        code: LdapResultCode::AttributeOrValueExists,
+                        message: format!("Value already present for attribute {}", atype),
+                    });
+                }
+                values.push(value.clone());
+            }
+        }
+        LdapModifyType::Delete => {
+            if values.is_empty() {
+                return Err(LdapError {
+                    code: LdapResultCode::NoSuchAttribute,
+                    message: format!("Attribute {} has no value", atype),
+                });
+            }
+            if change.modification.vals.is_empty() {
+                values.clear();
+            }
+            for value in &change.modification.vals {
+                let position = values
+                    .iter()
+                    .position(|v| v == value)
+                    .ok_or_else(|| LdapError {
+                        code: LdapResultCode::NoSuchAttribute,
+                        message: format!("Value not present for attribute {}", atype),
+                    })?;
+                values.remove(position);
+            }
+        }
+        LdapModifyType::Replace => {
+            *values = change.modification.vals.clone();
+        }
+    }
+    Ok(())
+}
+
+fn decode_attribute_value(atype: &AttributeName, val: &[u8]) -> LdapResult<String> {
+    std::str::from_utf8(val)
+        .map_err(|e| LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: format!(
+                "Attribute value is invalid UTF-8 for attribute {}: {:#?} (value {:?})",
+                atype, e, val
+            ),
+        })
+        .map(str::to_owned)
+}
+
+fn make_attribute_value(
+    values: &[Vec<u8>],
+    typ: AttributeType,
+    is_list: bool,
+) -> anyhow::Result<AttributeValue> {
+    if typ != AttributeType::JpegPhoto {
+        let values = values
+            .iter()
+            .map(|v| String::from_utf8(v.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        return deserialize_attribute_value(&values, typ, is_list);
+    }
+    // Photos are sent as raw bytes over LDAP, not base64.
+    let mut photos = values
+        .iter()
+        .map(|v| JpegPhoto::try_from(v.as_slice()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if is_list {
+        Ok(photos.into())
+    } else if photos.len() == 1 {
+        Ok(photos.remove(0).into())
+    } else {
+        anyhow::bail!("Attribute is not a list, but multiple values were provided")
+    }
+}
+
+fn make_update_user_request(
+    user_id: UserId,
+    modifications: Vec<UserAttributeModification>,
+) -> LdapResult<UpdateUserRequest> {
+    let mut request = UpdateUserRequest {
+        user_id,
+        ..Default::default()
+    };
+    let get_single_value = |name: &AttributeName, values: &[Vec<u8>]| match values {
+        [] => Ok(None),
+        [value] => decode_attribute_value(name, value).map(Some),
+        _ => Err(LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: format!("Expected a single value for attribute {}", name),
+        }),
+    };
+    for UserAttributeModification {
+        name,
+        target,
+        values,
+    } in modifications
+    {
+        match target {
+            UserModifyTarget::Email => {
+                let email = get_single_value(&name, &values)?.ok_or_else(|| LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: format!("Missing value for attribute {}", name),
+                })?;
+                request.email = Some(Email::from(email));
+            }
+            UserModifyTarget::DisplayName => {
+                // An empty display name resets it.
+                request.display_name = Some(get_single_value(&name, &values)?.unwrap_or_default());
+            }
+            UserModifyTarget::Attribute(_, _) if values.is_empty() => {
+                request.delete_attributes.push(name);
+            }
+            UserModifyTarget::Attribute(typ, is_list) => {
+                let value = make_attribute_value(&values, typ, is_list).map_err(|e| LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: format!("Invalid attribute value for {}: {}", name, e),
+                })?;
+                request.insert_attributes.push(Attribute { name, value });
+            }
+        }
+    }
+    Ok(request)
+}
+
+async fn get_user_update_request(
+    backend_handler: &impl UserReadableBackendHandler,
+    user_id: &UserId,
+    is_admin: bool,
+    changes: &[&LdapModify],
+) -> LdapResult<UpdateUserRequest> {
+    let schema = backend_handler.get_schema().await.map_err(|e| LdapError {
+        code: LdapResultCode::OperationsError,
+        message: format!("Unable to get schema: {:#}", e),
+    })?;
+    let targets = changes
+        .iter()
+        .map(|change| get_modify_target(&change.modification.atype, &schema, is_admin))
+        .collect::<LdapResult<Vec<_>>>()?;
+    let user = backend_handler
+        .get_user_details(user_id)
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::NoSuchObject,
+            message: format!("Unable to get user `{}`: {:#}", user_id, e),
+        })?;
+    let mut modifications: Vec<UserAttributeModification> = Vec::new();
+    for (change, (name, target)) in changes.iter().zip(targets) {
+        let index = match modifications.iter().position(|m| m.name == name) {
+            Some(index) => index,
+            None => {
+                let values = get_current_values(&user, &name, &target);
+                modifications.push(UserAttributeModification {
+                    name,
+                    target,
+                    values,
+                });
+                modifications.len() - 1
+            }
+        };
+        apply_change(&mut modifications[index].values, change)?;
+    }
+    make_update_user_request(user_id.clone(), modifications)
+}
+
+async fn is_admin_user(
+    backend_handler: &impl UserReadableBackendHandler,
+    user_id: &UserId,
+) -> LdapResult<bool> {
+    Ok(backend_handler
+        .get_user_groups(user_id)
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::OperationsError,
+            message: format!("Internal error while requesting user's groups: {:#?}", e),
+        })?
+        .iter()
+        .any(|g| g.display_name == "lldap_admin".into()))
+}
+
+async fn handle_user_modify_request<Handler: BackendHandler>(
     opaque_handler: &impl OpaqueHandler,
-    get_readable_handler: impl FnOnce(
-        &'cred ValidationResults,
-        UserId,
-    ) -> Option<&'cred UserBackendHandler>,
+    backend_handler: &AccessControlledBackendHandler<Handler>,
     ldap_info: &LdapInfo,
-    credentials: &'cred ValidationResults,
+    credentials: &ValidationResults,
     request: &LdapModifyRequest,
-) -> LdapResult<Vec<LdapOp>>
-where
-    // Note: ideally, get_readable_handler would take UserId by reference, but I couldn't make the lifetimes work.
-    UserBackendHandler: UserReadableBackendHandler + 'cred,
-{
-    match get_user_id_from_distinguished_name(
+) -> LdapResult<Vec<LdapOp>> {
+    let uid = get_user_id_from_distinguished_name(
         &request.dn,
         &ldap_info.base_dn,
         &ldap_info.base_dn_str,
-    ) {
-        Ok(uid) => {
-            let user_is_admin = get_readable_handler(credentials, uid.clone())
-                .ok_or_else(|| LdapError {
+    )
+    .map_err(|e| LdapError {
+        code: LdapResultCode::InvalidDNSyntax,
+        message: format!("Invalid username: {}", e),
+    })?;
+    let permission_error = || LdapError {
+        code: LdapResultCode::InsufficentAccessRights,
+        message: format!(
+            "User `{}` cannot modify user `{}`",
+            credentials.user.as_str(),
+            uid.as_str()
+        ),
+    };
+    let (password_changes, attribute_changes): (Vec<_>, Vec<_>) =
+        request.changes.iter().partition(|change| {
+            change
+                .modification
+                .atype
+                .eq_ignore_ascii_case("userpassword")
+        });
+    // Validate all the attribute changes before applying anything: the update only runs once
+    // awaited, after the password changes.
+    let update_user = if attribute_changes.is_empty() {
+        None
+    } else if let Some(writeable_handler) = backend_handler.get_writeable_handler(credentials, &uid)
+    {
+        let update_request = get_user_update_request(
+            writeable_handler,
+            &uid,
+            credentials.is_admin(),
+            &attribute_changes,
+        )
+        .await?;
+        Some(writeable_handler.update_user(update_request))
+    } else {
+        // Roles can allow editing some attributes of the other users.
+        let editor_handler = backend_handler
+            .get_user_attribute_editor_handler(credentials)
+            .ok_or_else(permission_error)?;
+        if is_admin_user(editor_handler, &uid).await? {
+            return Err(permission_error());
+        }
+        // The allowed attributes are checked below, regardless of their schema.
+        let update_request =
+            get_user_update_request(editor_handler, &uid, true, &attribute_changes).await?;
+        let edited_attributes = update_request
+            .email
+            .as_ref()
+            .map(|_| "mail")
+            .into_iter()
+            .chain(update_request.display_name.as_ref().map(|_| "display_name"))
+            .chain(
+                update_request
+                    .insert_attributes
+                    .iter()
+                    .map(|a| a.name.as_str()),
+            )
+            .chain(
+                update_request
+                    .delete_attributes
+                    .iter()
+                    .map(AttributeName::as_str),
+            );
+        for attribute in edited_attributes {
+            if !credentials.can_edit_user_attribute(attribute) {
+                return Err(LdapError {
                     code: LdapResultCode::InsufficentAccessRights,
                     message: format!(
-                        "User `{}` cannot modify user `{}`",
-                        credentials.user.as_str(),
-                        uid.as_str()
+                        "Permission denied: Cannot edit the attribute `{}`",
+                        attribute
                     ),
-                })?
-                .get_user_groups(&uid)
-                .await
-                .map_err(|e| LdapError {
-                    code: LdapResultCode::OperationsError,
-                    message: format!("Internal error while requesting user's groups: {:#?}", e),
-                })?
-                .iter()
-                .any(|g| g.display_name == "lldap_admin".into());
-            for change in &request.changes {
-                handle_modify_change(
-                    opaque_handler,
-                    uid.clone(),
-                    credentials,
-                    user_is_admin,
-                    change,
-                )
-                .await?
+                });
             }
-            Ok(vec![make_modify_response(
-                LdapResultCode::Success,
-                String::new(),
-            )])
         }
-        Err(e) => Err(LdapError {
-            code: LdapResultCode::InvalidDNSyntax,
-            message: format!("Invalid username: {}", e),
-        }),
+        Some(editor_handler.update_user_attributes(update_request))
+    };
+    if !password_changes.is_empty() {
+        let readable_handler = backend_handler
+            .get_readable_handler(credentials, &uid)
+            .ok_or_else(permission_error)?;
+        let user_is_admin = is_admin_user(readable_handler, &uid).await?;
+        for change in password_changes {
+            handle_modify_change(
+                opaque_handler,
+                ldap_info,
+                uid.clone(),
+                credentials,
+                user_is_admin,
+                change,
+            )
+            .await?
+        }
+    }
+    if let Some(update_user) = update_user {
+        update_user.await.map_err(|e| LdapError {
+            code: LdapResultCode::OperationsError,
+            message: format!("Could not update user: {:#?}", e),
+        })?;
+    }
+    Ok(vec![make_modify_response(
+        LdapResultCode::Success,
+        String::new(),
+    )])
+}
+
+fn get_member_id(ldap_info: &LdapInfo, value: &[u8]) -> LdapResult<UserId> {
+    let dn = std::str::from_utf8(value).map_err(|e| LdapError {
+        code: LdapResultCode::InvalidAttributeSyntax,
+        message: format!("Member DN is invalid UTF-8: {:#?} (value {:?})", e, value),
+    })?;
+    get_user_id_from_distinguished_name(
+        &dn.to_ascii_lowercase(),
+        &ldap_info.base_dn,
+        &ldap_info.base_dn_str,
+    )
+    .map_err(|e| LdapError {
+        code: LdapResultCode::InvalidAttributeSyntax,
+        message: format!("Invalid member: {}", e.message),
+    })
+}
+
+fn apply_member_change(
+    ldap_info: &LdapInfo,
+    group_name: &GroupName,
+    members: &mut Vec<UserId>,
+    change: &LdapModify,
+) -> LdapResult<()> {
+    let atype = &change.modification.atype;
+    if !atype.eq_ignore_ascii_case("member") && !atype.eq_ignore_ascii_case("uniquemember") {
+        return Err(LdapError {
+            code: LdapResultCode::UnwillingToPerform,
+            message: format!(
+                r#"Unsupported operation: `{:?}` for `{}` on a group"#,
+                change.operation, atype
+            ),
+        });
+    }
+    let values = change
+        .modification
+        .vals
+        .iter()
+        .map(|value| get_member_id(ldap_info, value))
+        .collect::<LdapResult<Vec<_>>>()?;
+    match change.operation {
+        LdapModifyType::Add => {
+            for user_id in values {
+                if members.contains(&user_id) {
+                    return Err(LdapError {
+                        code: LdapResultCode::AttributeOrValueExists,
+                        message: format!(
+                            "User `{}` is already a member of group `{}`",
+                            user_id, group_name
+                        ),
+                    });
+                }
+                members.push(user_id);
+            }
+        }
+        LdapModifyType::Delete if values.is_empty() => members.clear(),
+        LdapModifyType::Delete => {
+            for user_id in values {
+                let position =
+                    members
+                        .iter()
+                        .position(|m| m == &user_id)
+                        .ok_or_else(|| LdapError {
+                            code: LdapResultCode::NoSuchAttribute,
+                            message: format!(
+                                "User `{}` is not a member of group `{}`",
+                                user_id, group_name
+                            ),
+                        })?;
+                members.remove(position);
+            }
+        }
+        LdapModifyType::Replace => {
+            members.clear();
+            for user_id in values {
+                if !members.contains(&user_id) {
+                    members.push(user_id);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn handle_group_modify_request<Handler: BackendHandler>(
+    backend_handler: &AccessControlledBackendHandler<Handler>,
+    ldap_info: &LdapInfo,
+    credentials: &ValidationResults,
+    group_name: GroupName,
+    request: &LdapModifyRequest,
+) -> LdapResult<Vec<LdapOp>> {
+    let permission_error = || LdapError {
+        code: LdapResultCode::InsufficentAccessRights,
+        message: format!(
+            "User `{}` cannot modify group `{}`",
+            credentials.user.as_str(),
+            group_name
+        ),
+    };
+    // The complete member list is needed to apply the changes.
+    let can_read_members = |group_id: GroupId| {
+        credentials.can_read_all() || credentials.readable_group_ids().contains(&group_id.0)
+    };
+    if !credentials.can_read_all() && credentials.readable_group_ids().is_empty() {
+        return Err(permission_error());
+    }
+    let group = backend_handler
+        .get_user_restricted_lister_handler(credentials)
+        .list_groups(Some(GroupRequestFilter::DisplayName(group_name.clone())))
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::OperationsError,
+            message: format!("Error while finding group: {:?}", e),
+        })?
+        .into_iter()
+        .find(|g| g.display_name == group_name)
+        .ok_or_else(|| LdapError {
+            code: LdapResultCode::NoSuchObject,
+            message: "Could not find group".to_string(),
+        })?;
+    if !can_read_members(group.id) {
+        return Err(permission_error());
+    }
+    let backend_handler = backend_handler
+        .get_group_member_editor_handler(credentials, group.id)
+        .ok_or_else(permission_error)?;
+    if !credentials.is_admin()
+        && backend_handler
+            .group_grants_permissions(group.id)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Error while finding group: {:?}", e),
+            })?
+    {
+        return Err(permission_error());
+    }
+    let mut members = group.users.clone();
+    for change in &request.changes {
+        apply_member_change(ldap_info, &group_name, &mut members, change)?;
+    }
+    let removed = group
+        .users
+        .iter()
+        .filter(|u| !members.contains(u))
+        .cloned()
+        .collect::<Vec<_>>();
+    let added = members
+        .iter()
+        .filter(|u| !group.users.contains(u))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !added.is_empty() || !removed.is_empty() {
+        backend_handler
+            .update_group_members(group.id, &added, &removed)
+            .await
+            .map_err(|e| LdapError {
+                code: match e {
+                    DomainError::ValidationError(_) => LdapResultCode::UnwillingToPerform,
+                    _ => LdapResultCode::OperationsError,
+                },
+                message: format!(
+                    "Could not update the members of group `{}`: {:#?}",
+                    group_name, e
+                ),
+            })?;
+    }
+    Ok(vec![make_modify_response(
+        LdapResultCode::Success,
+        String::new(),
+    )])
+}
+
+pub(crate) async fn handle_modify_request<Handler: BackendHandler>(
+    opaque_handler: &impl OpaqueHandler,
+    backend_handler: &AccessControlledBackendHandler<Handler>,
+    ldap_info: &LdapInfo,
+    credentials: &ValidationResults,
+    request: &LdapModifyRequest,
+) -> LdapResult<Vec<LdapOp>> {
+    match get_user_or_group_id_from_distinguished_name(&request.dn, &ldap_info.base_dn) {
+        UserOrGroupName::Group(group_name) => {
+            handle_group_modify_request(
+                backend_handler,
+                ldap_info,
+                credentials,
+                group_name,
+                request,
+            )
+            .await
+        }
+        _ => {
+            handle_user_modify_request(
+                opaque_handler,
+                backend_handler,
+                ldap_info,
+                credentials,
+                request,
+            )
+            .await
+        }
     }
 }
 
@@ -126,14 +695,17 @@ mod tests {
     use crate::{
         handler::tests::{
             setup_bound_admin_handler, setup_bound_handler_with_group,
+            setup_bound_handler_with_group_and_owned_groups, setup_bound_handler_with_role,
             setup_bound_password_manager_handler,
         },
         password::tests::expect_password_change,
     };
     use chrono::TimeZone;
     use ldap3_proto::proto::LdapResult as LdapResultOp;
+    use lldap_auth::access_control::RolePermission;
     use lldap_domain::{
-        types::{GroupDetails, GroupId, GroupName, UserId},
+        schema::{AttributeList, AttributeSchema, Schema},
+        types::{Group, GroupDetails, GroupId, GroupName, UserId},
         uuid,
     };
     use lldap_test_utils::MockTestBackendHandler;
@@ -156,6 +728,7 @@ mod tests {
                         group_id: GroupId(42),
                         display_name: GroupName::from(group),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                         attributes: Vec::new(),
                     });
@@ -302,4 +875,535 @@ mod tests {
             )
         );
     }
+
+    fn expect_get_user(mock: &mut MockTestBackendHandler, user_id: &str) {
+        let user_id = UserId::from(user_id);
+        mock.expect_get_user_details()
+            .with(eq(user_id.clone()))
+            .times(1)
+            .return_once(move |_| {
+                Ok(User {
+                    user_id,
+                    email: "bob@bobmail.bob".into(),
+                    display_name: Some("Bob".to_string()),
+                    attributes: vec![Attribute {
+                        name: "last_name".into(),
+                        value: "Bobberson".to_string().into(),
+                    }],
+                    ..Default::default()
+                })
+            });
+    }
+
+    fn make_user_modify_request(
+        target_user: &str,
+        changes: Vec<(LdapModifyType, &str, Vec<&str>)>,
+    ) -> LdapModifyRequest {
+        LdapModifyRequest {
+            dn: format!("uid={},ou=people,dc=example,dc=com", target_user),
+            changes: changes
+                .into_iter()
+                .map(|(operation, atype, vals)| LdapModify {
+                    operation,
+                    modification: ldap3_proto::LdapPartialAttribute {
+                        atype: atype.to_string(),
+                        vals: vals.into_iter().map(|v| v.as_bytes().to_vec()).collect(),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modify_attributes_as_admin() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_get_user(&mut mock, "bob");
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@example.com".into()),
+                display_name: Some("Bobby".to_string()),
+                delete_attributes: vec!["last_name".into()],
+                insert_attributes: vec![Attribute {
+                    name: "first_name".into(),
+                    value: "Robert".to_string().into(),
+                }],
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_modify_request(
+            "bob",
+            vec![
+                (LdapModifyType::Replace, "mail", vec!["bob@example.com"]),
+                (LdapModifyType::Replace, "cn", vec!["Bobby"]),
+                (LdapModifyType::Add, "givenName", vec!["Robert"]),
+                (LdapModifyType::Delete, "sn", vec![]),
+            ],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_success_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_own_attributes_as_regular() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_get_user(&mut mock, "test");
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("test"),
+                insert_attributes: vec![Attribute {
+                    name: "last_name".into(),
+                    value: "Smith".to_string().into(),
+                }],
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        let request = make_user_modify_request(
+            "test",
+            vec![
+                (LdapModifyType::Delete, "sn", vec!["Bobberson"]),
+                (LdapModifyType::Add, "sn", vec!["Smith"]),
+            ],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_success_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_attributes_of_other_user_as_password_manager() {
+        let ldap_handler =
+            setup_bound_password_manager_handler(MockTestBackendHandler::new()).await;
+        let request = make_user_modify_request(
+            "bob",
+            vec![(LdapModifyType::Replace, "mail", vec!["bob@example.com"])],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify user `bob`"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_non_editable_attribute_as_regular() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![AttributeSchema {
+                        name: "employee_id".into(),
+                        attribute_type: AttributeType::String,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: false,
+                        is_hardcoded: false,
+                        is_readonly: false,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+                extra_user_object_classes: Vec::new(),
+                extra_group_object_classes: Vec::new(),
+            })
+        });
+        let ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        let request = make_user_modify_request(
+            "test",
+            vec![(LdapModifyType::Replace, "employee_id", vec!["42"])],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Permission denied: Attribute `employee_id` is not editable by regular users"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_readonly_attribute() {
+        let ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_user_modify_request(
+            "bob",
+            vec![(LdapModifyType::Replace, "entryUUID", vec!["abc"])],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Permission denied: Attribute `entryUUID` is read-only"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_delete_missing_value() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_get_user(&mut mock, "bob");
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request =
+            make_user_modify_request("bob", vec![(LdapModifyType::Delete, "sn", vec!["Smith"])]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::NoSuchAttribute,
+                "Value not present for attribute sn"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_attributes_of_other_user_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_target_user_groups(&mut mock, "bob", Vec::new());
+        expect_get_user(&mut mock, "bob");
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@example.com".into()),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let ldap_handler = setup_bound_handler_with_role(
+            mock,
+            vec![RolePermission::EditUserAttribute("mail".to_owned())],
+        )
+        .await;
+        let request = make_user_modify_request(
+            "bob",
+            vec![(LdapModifyType::Replace, "mail", vec!["bob@example.com"])],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_success_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_attribute_not_granted_by_role() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_target_user_groups(&mut mock, "bob", Vec::new());
+        expect_get_user(&mut mock, "bob");
+        let ldap_handler = setup_bound_handler_with_role(
+            mock,
+            vec![RolePermission::EditUserAttribute("mail".to_owned())],
+        )
+        .await;
+        let request = make_user_modify_request(
+            "bob",
+            vec![
+                (LdapModifyType::Replace, "mail", vec!["bob@example.com"]),
+                (LdapModifyType::Replace, "cn", vec!["Bobby"]),
+            ],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Permission denied: Cannot edit the attribute `display_name`"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_attributes_of_admin_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_target_user_groups(&mut mock, "bob", vec!["lldap_admin"]);
+        let ldap_handler = setup_bound_handler_with_role(
+            mock,
+            vec![RolePermission::EditUserAttribute("mail".to_owned())],
+        )
+        .await;
+        let request = make_user_modify_request(
+            "bob",
+            vec![(LdapModifyType::Replace, "mail", vec!["bob@example.com"])],
+        );
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify user `bob`"
+            )
+        );
+    }
+
+    fn expect_list_group(mock: &mut MockTestBackendHandler, members: Vec<&'static str>) {
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName("group1".into()))))
+            .times(1)
+            .return_once(move |_| {
+                Ok(vec![Group {
+                    id: GroupId(42),
+                    display_name: "group1".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    users: members.into_iter().map(UserId::new).collect(),
+                    subgroups: vec![],
+                    attributes: Vec::new(),
+                }])
+            });
+    }
+
+    fn make_group_modify_request(
+        changes: Vec<(LdapModifyType, &str, Vec<&str>)>,
+    ) -> LdapModifyRequest {
+        LdapModifyRequest {
+            dn: "cn=group1,ou=groups,dc=example,dc=com".to_string(),
+            ..make_user_modify_request("", changes)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members_as_admin() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, vec!["bob"]);
+        mock.expect_update_group_members()
+            .withf(|group_id, added, removed| {
+                *group_id == GroupId(42)
+                    && added.to_vec() == vec![UserId::new("alice")]
+                    && removed.to_vec() == vec![UserId::new("bob")]
+            })
+            .times(1)
+            .return_once(|_, _, _| Ok(()));
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_modify_request(vec![
+            (
+                LdapModifyType::Add,
+                "member",
+                vec!["uid=alice,ou=people,dc=example,dc=com"],
+            ),
+            (
+                LdapModifyType::Delete,
+                "uniqueMember",
+                vec!["uid=bob,ou=people,dc=example,dc=com"],
+            ),
+        ]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_success_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replace_group_members() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, vec!["bob", "alice"]);
+        mock.expect_update_group_members()
+            .withf(|group_id, added, removed| {
+                *group_id == GroupId(42)
+                    && added.to_vec() == vec![UserId::new("tom")]
+                    && removed.to_vec() == vec![UserId::new("bob")]
+            })
+            .times(1)
+            .return_once(|_, _, _| Ok(()));
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Replace,
+            "member",
+            vec![
+                "uid=alice,ou=people,dc=example,dc=com",
+                "uid=tom,ou=people,dc=example,dc=com",
+            ],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_success_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_dynamic_group_members() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, vec!["bob"]);
+        mock.expect_update_group_members()
+            .times(1)
+            .return_once(|_, _, _| {
+                Err(DomainError::ValidationError(
+                    "The members of the dynamic group GroupId(42) are computed from its filter"
+                        .to_owned(),
+                ))
+            });
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Add,
+            "member",
+            vec!["uid=alice,ou=people,dc=example,dc=com"],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::UnwillingToPerform,
+                &format!(
+                    "Could not update the members of group `group1`: {:#?}",
+                    DomainError::ValidationError(
+                        "The members of the dynamic group GroupId(42) are computed from its filter"
+                            .to_owned()
+                    )
+                )
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_existing_group_member() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, vec!["bob"]);
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Add,
+            "member",
+            vec!["uid=bob,ou=people,dc=example,dc=com"],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::AttributeOrValueExists,
+                "User `bob` is already a member of group `group1`"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_unsupported_group_attribute() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, vec![]);
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Replace,
+            "description",
+            vec!["Group"],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::UnwillingToPerform,
+                "Unsupported operation: `Replace` for `description` on a group"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members_as_regular() {
+        let ldap_handler =
+            setup_bound_handler_with_group(MockTestBackendHandler::new(), "regular").await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Add,
+            "member",
+            vec!["uid=test,ou=people,dc=example,dc=com"],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify group `group1`"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members_as_owner() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::DisplayName("group1".into()),
+                GroupRequestFilter::Or(vec![
+                    GroupRequestFilter::Member(UserId::new("test")),
+                    GroupRequestFilter::GroupId(GroupId(42)),
+                ]),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(42),
+                    display_name: "group1".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    users: vec![UserId::new("bob")],
+                    subgroups: vec![],
+                    attributes: Vec::new(),
+                }])
+            });
+        mock.expect_get_group_details()
+            .with(eq(GroupId(42)))
+            .return_once(|_| {
+                Ok(GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "group1".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                })
+            });
+        mock.expect_get_ancestor_groups()
+            .with(eq(GroupId(42)))
+            .return_once(|_| Ok(Vec::new()));
+        mock.expect_list_roles().return_once(|| Ok(Vec::new()));
+        mock.expect_update_group_members()
+            .withf(|group_id, added, removed| {
+                *group_id == GroupId(42)
+                    && added.to_vec() == vec![UserId::new("alice")]
+                    && removed.to_vec() == Vec::<UserId>::new()
+            })
+            .times(1)
+            .return_once(|_, _, _| Ok(()));
+        let ldap_handler =
+            setup_bound_handler_with_group_and_owned_groups(mock, "regular", vec![GroupId(42)])
+                .await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Add,
+            "member",
+            vec!["uid=alice,ou=people,dc=example,dc=com"],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_success_response()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members_as_owner_of_another_group() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups().times(1).return_once(|_| {
+            Ok(vec![Group {
+                id: GroupId(42),
+                display_name: "group1".into(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                users: vec![UserId::new("test")],
+                subgroups: vec![],
+                attributes: Vec::new(),
+            }])
+        });
+        let ldap_handler =
+            setup_bound_handler_with_group_and_owned_groups(mock, "regular", vec![GroupId(7)])
+                .await;
+        let request = make_group_modify_request(vec![(
+            LdapModifyType::Add,
+            "member",
+            vec!["uid=alice,ou=people,dc=example,dc=com"],
+        )]);
+        assert_eq!(
+            ldap_handler.do_modify_request(&request).await,
+            make_modify_failure_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify group `group1`"
+            )
+        );
+    }
 }
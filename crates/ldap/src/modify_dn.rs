@@ -0,0 +1,506 @@
+use crate::core::{
+    error::{LdapError, LdapResult, unauthorized_write},
+    utils::{LdapInfo, UserOrGroupName, get_user_or_group_id_from_distinguished_name},
+};
+use ldap3_proto::proto::{LdapModifyDNRequest, LdapOp, LdapResult as LdapResultOp, LdapResultCode};
+use lldap_access_control::{
+    AccessControlledBackendHandler, AdminBackendHandler, UserRenameBackendHandler,
+};
+use lldap_auth::access_control::ValidationResults;
+use lldap_domain::{
+    requests::UpdateGroupRequest,
+    types::{GroupName, UserId},
+};
+use lldap_domain_handlers::handler::{BackendHandler, GroupRequestFilter};
+use lldap_domain_model::error::DomainError;
+use std::collections::HashSet;
+use tracing::instrument;
+
+pub(crate) fn make_modify_dn_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::ModifyDNResponse(LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message,
+        referral: vec![],
+    })
+}
+
+#[instrument(skip_all, level = "debug")]
+pub(crate) async fn rename_user_or_group<Handler: BackendHandler>(
+    backend_handler: &AccessControlledBackendHandler<Handler>,
+    ldap_info: &LdapInfo,
+    credentials: &ValidationResults,
+    request: LdapModifyDNRequest,
+    blacklisted_jwts: &mut HashSet<u64>,
+) -> LdapResult<Vec<LdapOp>> {
+    let base_dn_str = &ldap_info.base_dn_str;
+    // The RDN attributes are single-valued, so the old value is always removed, regardless of
+    // `deleteoldrdn`.
+    let get_new_dn = |parent: &str| match &request.new_superior {
+        Some(new_superior) => format!("{},{}", request.newrdn, new_superior),
+        None => format!("{},{},{}", request.newrdn, parent, base_dn_str),
+    };
+    match get_user_or_group_id_from_distinguished_name(&request.dn, &ldap_info.base_dn) {
+        UserOrGroupName::User(user_id) => {
+            let new_dn = get_new_dn("ou=people");
+            match get_user_or_group_id_from_distinguished_name(&new_dn, &ldap_info.base_dn) {
+                UserOrGroupName::User(new_user_id) => {
+                    let backend_handler = backend_handler
+                        .get_user_rename_handler(credentials)
+                        .ok_or_else(unauthorized_write)?;
+                    rename_user(
+                        backend_handler,
+                        credentials,
+                        user_id,
+                        new_user_id,
+                        blacklisted_jwts,
+                    )
+                    .await
+                }
+                err => Err(into_new_dn_error(
+                    err,
+                    &new_dn,
+                    format!(r#""uid=id,ou=people,{}""#, base_dn_str),
+                )),
+            }
+        }
+        UserOrGroupName::Group(group_name) => {
+            let new_dn = get_new_dn("ou=groups");
+            match get_user_or_group_id_from_distinguished_name(&new_dn, &ldap_info.base_dn) {
+                UserOrGroupName::Group(new_group_name) => {
+                    let backend_handler = backend_handler
+                        .get_admin_handler(credentials)
+                        .ok_or_else(unauthorized_write)?;
+                    rename_group(backend_handler, group_name, new_group_name).await
+                }
+                err => Err(into_new_dn_error(
+                    err,
+                    &new_dn,
+                    format!(r#""cn=id,ou=groups,{}""#, base_dn_str),
+                )),
+            }
+        }
+        err => Err(err.into_ldap_error(
+            &request.dn,
+            format!(
+                r#""uid=id,ou=people,{}" or "cn=id,ou=groups,{}""#,
+                base_dn_str, base_dn_str
+            ),
+        )),
+    }
+}
+
+fn into_new_dn_error(err: UserOrGroupName, new_dn: &str, expected_format: String) -> LdapError {
+    match err {
+        UserOrGroupName::User(_) | UserOrGroupName::Group(_) | UserOrGroupName::BadSubStree => {
+            LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: format!(
+                    "Cannot move an entry to another subtree: {}, expected {}",
+                    new_dn, expected_format
+                ),
+            }
+        }
+        err => err.into_ldap_error(new_dn, expected_format),
+    }
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn rename_user(
+    backend_handler: &impl UserRenameBackendHandler,
+    credentials: &ValidationResults,
+    user_id: UserId,
+    new_user_id: UserId,
+    blacklisted_jwts: &mut HashSet<u64>,
+) -> LdapResult<Vec<LdapOp>> {
+    if user_id != new_user_id {
+        // The roles can only rename the regular users.
+        if !credentials.is_admin() {
+            let user_is_admin = backend_handler
+                .get_user_groups(&user_id)
+                .await
+                .map_err(|err| match err {
+                    DomainError::EntityNotFound(_) => LdapError {
+                        code: LdapResultCode::NoSuchObject,
+                        message: "Could not find user".to_string(),
+                    },
+                    e => LdapError {
+                        code: LdapResultCode::OperationsError,
+                        message: format!("Internal error while requesting user's groups: {:#?}", e),
+                    },
+                })?
+                .iter()
+                .any(|g| g.display_name == "lldap_admin".into());
+            if user_is_admin {
+                return Err(LdapError {
+                    code: LdapResultCode::InsufficentAccessRights,
+                    message: format!(
+                        "User `{}` cannot rename the admin `{}`",
+                        credentials.user, user_id
+                    ),
+                });
+            }
+        }
+        // The backend logs the user out: their JWTs are blacklisted in the database, and returned
+        // so that the HTTP server refuses them right away.
+        let jwt_hashes = backend_handler
+            .rename_user(&user_id, &new_user_id)
+            .await
+            .map_err(|err| match err {
+                DomainError::EntityNotFound(_) => LdapError {
+                    code: LdapResultCode::NoSuchObject,
+                    message: "Could not find user".to_string(),
+                },
+                DomainError::EntityAlreadyExists(_) => LdapError {
+                    code: LdapResultCode::EntryAlreadyExists,
+                    message: format!("User `{}` already exists", new_user_id),
+                },
+                e => LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Error while renaming user: {:?}", e),
+                },
+            })?;
+        blacklisted_jwts.extend(jwt_hashes);
+    }
+    Ok(vec![make_modify_dn_response(
+        LdapResultCode::Success,
+        String::new(),
+    )])
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn rename_group(
+    backend_handler: &impl AdminBackendHandler,
+    group_name: GroupName,
+    new_group_name: GroupName,
+) -> LdapResult<Vec<LdapOp>> {
+    let find_group = |group_name: GroupName| async move {
+        backend_handler
+            .list_groups(Some(GroupRequestFilter::DisplayName(group_name.clone())))
+            .await
+            .map(|groups| groups.into_iter().find(|g| g.display_name == group_name))
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Error while finding group: {:?}", e),
+            })
+    };
+    let group = find_group(group_name).await?.ok_or_else(|| LdapError {
+        code: LdapResultCode::NoSuchObject,
+        message: "Could not find group".to_string(),
+    })?;
+    if group.display_name == new_group_name {
+        return Ok(vec![make_modify_dn_response(
+            LdapResultCode::Success,
+            String::new(),
+        )]);
+    }
+    if find_group(new_group_name.clone())
+        .await?
+        .is_some_and(|g| g.id != group.id)
+    {
+        return Err(LdapError {
+            code: LdapResultCode::EntryAlreadyExists,
+            message: format!("Group `{}` already exists", new_group_name),
+        });
+    }
+    backend_handler
+        .update_group(UpdateGroupRequest {
+            group_id: group.id,
+            display_name: Some(new_group_name),
+            delete_attributes: Vec::new(),
+            insert_attributes: Vec::new(),
+        })
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::OperationsError,
+            message: format!("Error while renaming group: {:?}", e),
+        })?;
+    Ok(vec![make_modify_dn_response(
+        LdapResultCode::Success,
+        String::new(),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::tests::{
+        setup_bound_admin_handler, setup_bound_handler_with_role, setup_bound_readonly_handler,
+    };
+    use chrono::TimeZone;
+    use lldap_auth::access_control::RolePermission;
+    use lldap_domain::{
+        types::{Group, GroupDetails, GroupId},
+        uuid,
+    };
+    use lldap_test_utils::MockTestBackendHandler;
+    use mockall::predicate::eq;
+    use pretty_assertions::assert_eq;
+
+    fn make_modify_dn_request(dn: &str, newrdn: &str, new_superior: Option<&str>) -> LdapOp {
+        LdapOp::ModifyDNRequest(LdapModifyDNRequest {
+            dn: dn.to_string(),
+            newrdn: newrdn.to_string(),
+            deleteoldrdn: true,
+            new_superior: new_superior.map(str::to_string),
+        })
+    }
+
+    fn expect_list_group(mock: &mut MockTestBackendHandler, name: &'static str, id: Option<i32>) {
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName(GroupName::from(
+                name,
+            )))))
+            .times(1)
+            .return_once(move |_| {
+                Ok(id
+                    .into_iter()
+                    .map(|id| Group {
+                        id: GroupId(id),
+                        display_name: GroupName::from(name),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        users: Vec::new(),
+                        subgroups: Vec::new(),
+                        attributes: Vec::new(),
+                    })
+                    .collect())
+            });
+    }
+
+    #[tokio::test]
+    async fn test_rename_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_rename_user()
+            .with(eq(UserId::new("bob")), eq(UserId::new("robert")))
+            .times(1)
+            .return_once(|_, _| Ok(HashSet::from([1, 2])));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request =
+            make_modify_dn_request("uid=bob,ou=people,dc=example,dc=com", "uid=robert", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+        // The user was logged out.
+        assert_eq!(ldap_handler.take_blacklisted_jwts(), HashSet::from([1, 2]));
+        assert!(ldap_handler.take_blacklisted_jwts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_conflict() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_rename_user()
+            .with(eq(UserId::new("bob")), eq(UserId::new("robert")))
+            .times(1)
+            .return_once(|_, _| {
+                Err(DomainError::EntityAlreadyExists(
+                    "User 'robert' already exists".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_modify_dn_request(
+            "uid=bob,ou=people,dc=example,dc=com",
+            "uid=robert",
+            Some("ou=people,dc=example,dc=com"),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::EntryAlreadyExists,
+                "User `robert` already exists".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_user_to_groups() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_modify_dn_request(
+            "uid=bob,ou=people,dc=example,dc=com",
+            "cn=bob",
+            Some("ou=groups,dc=example,dc=com"),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::UnwillingToPerform,
+                r#"Cannot move an entry to another subtree: cn=bob,ou=groups,dc=example,dc=com, expected "uid=id,ou=people,dc=example,dc=com""#.to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_group_to_people() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_modify_dn_request(
+            "cn=group1,ou=groups,dc=example,dc=com",
+            "uid=group1",
+            Some("ou=people,dc=example,dc=com"),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::UnwillingToPerform,
+                r#"Cannot move an entry to another subtree: uid=group1,ou=people,dc=example,dc=com, expected "cn=id,ou=groups,dc=example,dc=com""#.to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_group() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, "group1", Some(42));
+        expect_list_group(&mut mock, "group2", None);
+        mock.expect_update_group()
+            .with(eq(UpdateGroupRequest {
+                group_id: GroupId(42),
+                display_name: Some(GroupName::from("group2")),
+                delete_attributes: Vec::new(),
+                insert_attributes: Vec::new(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request =
+            make_modify_dn_request("cn=group1,ou=groups,dc=example,dc=com", "cn=group2", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_group_conflict() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, "group1", Some(42));
+        expect_list_group(&mut mock, "group2", Some(43));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request =
+            make_modify_dn_request("cn=group1,ou=groups,dc=example,dc=com", "cn=group2", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::EntryAlreadyExists,
+                "Group `group2` already exists".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_group_not_found() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_group(&mut mock, "group1", None);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request =
+            make_modify_dn_request("cn=group1,ou=groups,dc=example,dc=com", "cn=group2", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::NoSuchObject,
+                "Could not find group".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_as_readonly() {
+        let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
+        let request =
+            make_modify_dn_request("uid=bob,ou=people,dc=example,dc=com", "uid=robert", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Unauthorized write".to_string()
+            )])
+        );
+    }
+
+    fn expect_get_user_groups(mock: &mut MockTestBackendHandler, user_id: &str, group: &str) {
+        let group = GroupName::from(group);
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new(user_id)))
+            .times(1)
+            .return_once(move |_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: group,
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                }]))
+            });
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_get_user_groups(&mut mock, "bob", "employees");
+        mock.expect_rename_user()
+            .with(eq(UserId::new("bob")), eq(UserId::new("robert")))
+            .times(1)
+            .return_once(|_, _| Ok(HashSet::from([1])));
+        let mut ldap_handler = setup_bound_handler_with_role(
+            mock,
+            vec![RolePermission::CreateUsers, RolePermission::DeleteUsers],
+        )
+        .await;
+        let request =
+            make_modify_dn_request("uid=bob,ou=people,dc=example,dc=com", "uid=robert", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+        assert_eq!(ldap_handler.take_blacklisted_jwts(), HashSet::from([1]));
+    }
+
+    #[tokio::test]
+    async fn test_rename_admin_with_role() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_get_user_groups(&mut mock, "bob", "lldap_admin");
+        let mut ldap_handler = setup_bound_handler_with_role(
+            mock,
+            vec![RolePermission::CreateUsers, RolePermission::DeleteUsers],
+        )
+        .await;
+        let request =
+            make_modify_dn_request("uid=bob,ou=people,dc=example,dc=com", "uid=robert", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot rename the admin `bob`".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_with_creation_role_only() {
+        let mut ldap_handler = setup_bound_handler_with_role(
+            MockTestBackendHandler::new(),
+            vec![RolePermission::CreateUsers],
+        )
+        .await;
+        let request =
+            make_modify_dn_request("uid=bob,ou=people,dc=example,dc=com", "uid=robert", None);
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_dn_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Unauthorized write".to_string()
+            )])
+        );
+    }
+}
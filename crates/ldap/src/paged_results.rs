@@ -0,0 +1,378 @@
+use crate::{
+    core::error::{LdapError, LdapResult},
+    search::make_search_success,
+};
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{LdapOp, LdapResultCode, LdapSearchRequest},
+};
+use std::collections::{BTreeMap, VecDeque};
+
+// See RFC 2696.
+pub(crate) const OID_PAGED_RESULTS: &str = "1.2.840.113556.1.4.319";
+
+/// The maximum number of paged searches in progress on a connection. Starting another one drops
+/// the oldest.
+const MAX_PAGED_SEARCHES: usize = 10;
+/// The maximum number of entries kept for the paged searches of a connection, beyond which the
+/// oldest searches are dropped.
+const MAX_CACHED_ENTRIES: usize = 50_000;
+
+pub(crate) fn get_paged_results_control(controls: &[LdapControl]) -> Option<(i64, &[u8])> {
+    controls.iter().find_map(|control| match control {
+        LdapControl::SimplePagedResults { size, cookie } => Some((*size, cookie.as_slice())),
+        _ => None,
+    })
+}
+
+struct PagedSearch {
+    request: LdapSearchRequest,
+    total: usize,
+    entries: VecDeque<LdapOp>,
+    done: LdapOp,
+    // Other controls to attach to every SearchResultDone, e.g. the sort response.
+    done_controls: Vec<LdapControl>,
+}
+
+/// The paged searches that are in progress on a connection, indexed by cookie.
+///
+/// The results are computed in full for the first page, and kept in memory until the client has
+/// fetched all the pages, abandoned the search, or the connection is closed. The number of
+/// searches and of entries kept is capped, see `MAX_PAGED_SEARCHES` and `MAX_CACHED_ENTRIES`.
+#[derive(Default)]
+pub(crate) struct PagedSearches {
+    next_cookie: u64,
+    // The cookies are increasing, so the oldest searches come first.
+    searches: BTreeMap<u64, PagedSearch>,
+}
+
+fn parse_cookie(cookie: &[u8]) -> Option<u64> {
+    <[u8; 8]>::try_from(cookie).ok().map(u64::from_be_bytes)
+}
+
+impl PagedSearches {
+    pub fn clear(&mut self) {
+        self.searches.clear();
+    }
+
+    pub fn first_page(
+        &mut self,
+        request: &LdapSearchRequest,
+        size: i64,
+        mut results: Vec<LdapOp>,
+        done_controls: Vec<LdapControl>,
+    ) -> Vec<(LdapOp, Vec<LdapControl>)> {
+        let done = match results.last() {
+            Some(LdapOp::SearchResultDone(_)) => results.pop().unwrap(),
+            _ => make_search_success(),
+        };
+        self.take_page(
+            PagedSearch {
+                request: request.clone(),
+                total: results.len(),
+                entries: results.into(),
+                done,
+                done_controls,
+            },
+            size,
+        )
+    }
+
+    pub fn next_page(
+        &mut self,
+        request: &LdapSearchRequest,
+        size: i64,
+        cookie: &[u8],
+    ) -> LdapResult<Vec<(LdapOp, Vec<LdapControl>)>> {
+        // A cookie can only be used once: the search is dropped, even if the request is invalid.
+        let search = parse_cookie(cookie)
+            .and_then(|cookie| self.searches.remove(&cookie))
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Invalid or expired paged results cookie".to_string(),
+            })?;
+        if &search.request != request {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "The search request changed between two pages".to_string(),
+            });
+        }
+        if size <= 0 {
+            // The client abandoned the search.
+            let mut controls = vec![LdapControl::SimplePagedResults {
+                size: 0,
+                cookie: vec![],
+            }];
+            controls.extend(search.done_controls);
+            return Ok(vec![(make_search_success(), controls)]);
+        }
+        Ok(self.take_page(search, size))
+    }
+
+    fn take_page(&mut self, mut search: PagedSearch, size: i64) -> Vec<(LdapOp, Vec<LdapControl>)> {
+        let page_size = usize::try_from(size)
+            .unwrap_or_default()
+            .min(search.entries.len());
+        let mut page: Vec<_> = search
+            .entries
+            .drain(..page_size)
+            .map(|entry| (entry, Vec::new()))
+            .collect();
+        let done = search.done.clone();
+        let done_controls = search.done_controls.clone();
+        let total = search.total.try_into().unwrap_or(i64::MAX);
+        let cookie = if search.entries.is_empty() {
+            Vec::new()
+        } else {
+            let cookie = self.next_cookie;
+            self.next_cookie += 1;
+            self.searches.insert(cookie, search);
+            self.evict_oldest_searches();
+            cookie.to_be_bytes().to_vec()
+        };
+        let mut controls = vec![LdapControl::SimplePagedResults {
+            size: total,
+            cookie,
+        }];
+        controls.extend(done_controls);
+        page.push((done, controls));
+        page
+    }
+
+    /// Drops the oldest searches until the limits are respected. The newest search is always
+    /// kept: its results have already been computed.
+    fn evict_oldest_searches(&mut self) {
+        let mut cached_entries: usize = self.searches.values().map(|s| s.entries.len()).sum();
+        while self.searches.len() > 1
+            && (self.searches.len() > MAX_PAGED_SEARCHES || cached_entries > MAX_CACHED_ENTRIES)
+        {
+            if let Some((_, search)) = self.searches.pop_first() {
+                cached_entries -= search.entries.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handler::tests::{make_user_search_request, setup_bound_admin_handler},
+        search::make_search_error,
+    };
+    use ldap3_proto::proto::{LdapFilter, LdapSearchResultEntry};
+    use lldap_domain::types::{User, UserAndGroups, UserId};
+    use lldap_test_utils::MockTestBackendHandler;
+    use pretty_assertions::assert_eq;
+
+    fn expect_list_users(mock: &mut MockTestBackendHandler, users: Vec<&'static str>) {
+        mock.expect_list_users().times(1).return_once(move |_, _| {
+            Ok(users
+                .into_iter()
+                .map(|user_id| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(user_id),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+    }
+
+    fn make_paged_control(size: i64, cookie: Vec<u8>) -> Vec<LdapControl> {
+        vec![LdapControl::SimplePagedResults { size, cookie }]
+    }
+
+    fn get_dns(response: &[(LdapOp, Vec<LdapControl>)]) -> Vec<&str> {
+        response
+            .iter()
+            .filter_map(|(op, _)| match op {
+                LdapOp::SearchResultEntry(entry) => Some(entry.dn.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn get_done_control(response: &[(LdapOp, Vec<LdapControl>)]) -> (i64, Vec<u8>) {
+        match response.last() {
+            Some((LdapOp::SearchResultDone(_), controls)) => get_paged_results_control(controls)
+                .map(|(size, cookie)| (size, cookie.to_vec()))
+                .unwrap(),
+            other => panic!("Expected SearchResultDone, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paged_search() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec!["bob", "jim", "tom"]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request.clone()),
+                &make_paged_control(2, vec![]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&response),
+            vec![
+                "uid=bob,ou=people,dc=example,dc=com",
+                "uid=jim,ou=people,dc=example,dc=com"
+            ]
+        );
+        let (size, cookie) = get_done_control(&response);
+        assert_eq!(size, 3);
+        assert!(!cookie.is_empty());
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &make_paged_control(2, cookie),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&response),
+            vec!["uid=tom,ou=people,dc=example,dc=com"]
+        );
+        assert_eq!(get_done_control(&response), (3, vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_paged_search_single_page() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec!["bob"]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &make_paged_control(10, vec![]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&response),
+            vec!["uid=bob,ou=people,dc=example,dc=com"]
+        );
+        assert_eq!(get_done_control(&response), (1, vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_paged_search_abandon() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec!["bob", "jim"]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request.clone()),
+                &make_paged_control(1, vec![]),
+            )
+            .await
+            .unwrap();
+        let (_, cookie) = get_done_control(&response);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request.clone()),
+                &make_paged_control(0, cookie.clone()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(get_dns(&response), Vec::<&str>::new());
+        assert_eq!(get_done_control(&response), (0, vec![]));
+        // The cookie is no longer valid.
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message_with_controls(
+                    LdapOp::SearchRequest(request),
+                    &make_paged_control(1, cookie),
+                )
+                .await
+                .unwrap(),
+            vec![(
+                make_search_error(
+                    LdapResultCode::UnwillingToPerform,
+                    "Invalid or expired paged results cookie".to_string()
+                ),
+                Vec::new()
+            )]
+        );
+    }
+
+    fn make_entries(count: usize) -> Vec<LdapOp> {
+        (0..count)
+            .map(|i| {
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: format!("uid={},ou=people,dc=example,dc=com", i),
+                    attributes: vec![],
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_paged_searches_are_capped() {
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let mut searches = PagedSearches::default();
+        let cookies: Vec<_> = (0..=MAX_PAGED_SEARCHES)
+            .map(|_| get_done_control(&searches.first_page(&request, 1, make_entries(2), vec![])).1)
+            .collect();
+        // The oldest search was dropped.
+        assert!(searches.next_page(&request, 1, &cookies[0]).is_err());
+        assert_eq!(
+            get_dns(&searches.next_page(&request, 1, &cookies[1]).unwrap()),
+            vec!["uid=1,ou=people,dc=example,dc=com"]
+        );
+        // Same when too many entries are kept.
+        let (_, big_cookie) = get_done_control(&searches.first_page(
+            &request,
+            1,
+            make_entries(MAX_CACHED_ENTRIES),
+            vec![],
+        ));
+        assert!(searches.next_page(&request, 1, &cookies[2]).is_err());
+        assert!(searches.next_page(&request, 1, &big_cookie).is_ok());
+        // A cookie cannot be reused.
+        assert!(searches.next_page(&request, 1, &big_cookie).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paged_search_changed_request() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec!["bob", "jim"]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(make_user_search_request::<String>(
+                    LdapFilter::And(vec![]),
+                    vec![],
+                )),
+                &make_paged_control(1, vec![]),
+            )
+            .await
+            .unwrap();
+        let (_, cookie) = get_done_control(&response);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message_with_controls(
+                    LdapOp::SearchRequest(make_user_search_request(
+                        LdapFilter::And(vec![]),
+                        vec!["uid"],
+                    )),
+                    &make_paged_control(1, cookie),
+                )
+                .await
+                .unwrap(),
+            vec![(
+                make_search_error(
+                    LdapResultCode::UnwillingToPerform,
+                    "The search request changed between two pages".to_string()
+                ),
+                Vec::new()
+            )]
+        );
+    }
+}
@@ -14,6 +14,7 @@ use lldap_auth::access_control::ValidationResults;
 use lldap_domain::types::UserId;
 use lldap_domain_handlers::handler::{BackendHandler, BindRequest, LoginHandler};
 use lldap_opaque_handler::OpaqueHandler;
+use lldap_validation::password::validate_password;
 
 pub(crate) async fn do_bind(
     ldap_info: &LdapInfo,
@@ -62,6 +63,24 @@ pub(crate) async fn do_bind(
     }
 }
 
+/// Checks the new password against the configured password policy.
+pub(crate) fn check_password_policy(
+    ldap_info: &LdapInfo,
+    user: &UserId,
+    password: &[u8],
+) -> LdapResult<()> {
+    let password = std::str::from_utf8(password).map_err(|_| LdapError {
+        code: LdapResultCode::InvalidAttributeSyntax,
+        message: "The password is not valid UTF-8".to_string(),
+    })?;
+    validate_password(&ldap_info.password_policy, user.as_str(), password).map_err(|errors| {
+        LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: format!("Password rejected by the policy: {}", errors.join("; ")),
+        }
+    })
+}
+
 pub(crate) async fn change_password<B: OpaqueHandler>(
     backend_handler: &B,
     user: UserId,
@@ -126,6 +145,10 @@ pub(crate) async fn do_password_modification<Handler: BackendHandler>(
                                 &credentials.user, &uid
                             ),
                         })
+                    } else if let Err(e) =
+                        check_password_policy(ldap_info, &uid, password.as_bytes())
+                    {
+                        Err(e)
                     } else if let Err(e) =
                         change_password(opaque_handler, uid, password.as_bytes()).await
                     {
@@ -232,6 +255,8 @@ pub mod tests {
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
             .return_once(|_| Ok(HashSet::new()));
+        mock.expect_get_owned_groups()
+            .return_once(|_| Ok(Vec::new()));
         let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=eXample,dc=com");
 
         let request = LdapOp::BindRequest(LdapBindRequest {
@@ -262,11 +287,16 @@ pub mod tests {
                     group_id: GroupId(42),
                     display_name: "lldap_admin".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     attributes: Vec::new(),
                 });
                 Ok(set)
             });
+        mock.expect_get_role_permissions()
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_get_owned_groups()
+            .return_once(|_| Ok(Vec::new()));
         let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
 
         let request = LdapBindRequest {
@@ -363,6 +393,31 @@ pub mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_password_change_rejected_by_policy() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("pass".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::ConstraintViolation,
+                "Password rejected by the policy: The password must be at least 8 characters long"
+                    .to_string(),
+            )])
+        );
+    }
+
     #[tokio::test]
     async fn test_password_change_modify_request() {
         let mut mock = MockTestBackendHandler::new();
@@ -519,6 +574,7 @@ pub mod tests {
             group_id: GroupId(0),
             display_name: "lldap_admin".into(),
             creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
             uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
             attributes: Vec::new(),
         });
@@ -1,12 +1,19 @@
-use crate::core::{
-    error::{LdapError, LdapResult},
-    group::{convert_groups_to_ldap_op, get_groups_list},
-    user::{convert_users_to_ldap_op, get_user_list},
-    utils::{LdapInfo, LdapSchemaDescription, is_subtree, parse_distinguished_name},
+use crate::{
+    core::{
+        error::{LdapError, LdapResult},
+        group::{convert_groups_to_ldap_op, get_groups_list},
+        user::{convert_users_to_ldap_op, get_user_list},
+        utils::{LdapInfo, LdapSchemaDescription, is_subtree, parse_distinguished_name},
+    },
+    handler::OID_START_TLS,
+    paged_results::OID_PAGED_RESULTS,
+    sort::{OID_SERVER_SIDE_SORT_REQUEST, SortRequest, SortResult, make_sort_response_control},
+    sync::OID_SYNC_REQUEST,
 };
 use chrono::Utc;
 use ldap3_proto::{
     LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry, LdapSearchScope,
+    control::LdapControl,
     proto::{
         LdapDerefAliases, LdapOp, LdapResult as LdapResultOp, LdapSearchRequest,
         OID_PASSWORD_MODIFY, OID_WHOAMI,
@@ -142,11 +149,16 @@ pub(crate) fn root_dse_response(base_dn: &str) -> LdapOp {
                 vals: vec![
                     OID_PASSWORD_MODIFY.as_bytes().to_vec(),
                     OID_WHOAMI.as_bytes().to_vec(),
+                    OID_START_TLS.as_bytes().to_vec(),
                 ],
             },
             LdapPartialAttribute {
                 atype: "supportedControl".to_string(),
-                vals: vec![],
+                vals: vec![
+                    OID_PAGED_RESULTS.as_bytes().to_vec(),
+                    OID_SERVER_SIDE_SORT_REQUEST.as_bytes().to_vec(),
+                    OID_SYNC_REQUEST.as_bytes().to_vec(),
+                ],
             },
             LdapPartialAttribute {
                 atype: "supportedFeatures".to_string(),
@@ -366,15 +378,32 @@ pub async fn do_search(
     backend_handler: &impl UserAndGroupListerBackendHandler,
     ldap_info: &LdapInfo,
     request: &LdapSearchRequest,
-) -> LdapResult<Vec<LdapOp>> {
+    sort_request: Option<&SortRequest>,
+) -> LdapResult<(Vec<LdapOp>, Option<LdapControl>)> {
     let schema = PublicSchema::from(backend_handler.get_schema().await.map_err(|e| LdapError {
         code: LdapResultCode::OperationsError,
         message: format!("Unable to get schema: {:#}", e),
     })?);
+    let unknown_sort_key = sort_request.and_then(|s| s.find_unknown_key(&schema));
+    let sort_control = sort_request.map(|_| match unknown_sort_key {
+        Some(key) => make_sort_response_control(SortResult::NoSuchAttribute, Some(&key.attribute)),
+        None => make_sort_response_control(SortResult::Success, None),
+    });
+    if let Some(key) = unknown_sort_key.filter(|_| sort_request.is_some_and(|s| s.criticality)) {
+        return Ok((
+            vec![make_search_error(
+                LdapResultCode::UnavailableCriticalExtension,
+                format!("Unknown sort key: {}", key.attribute),
+            )],
+            sort_control,
+        ));
+    }
+    // Unknown non-critical sort keys mean the results are returned unsorted.
+    let sort_request = sort_request.filter(|_| unknown_sort_key.is_none());
     let search_results = do_search_internal(ldap_info, backend_handler, request, &schema).await?;
     let mut results = match search_results {
-        InternalSearchResults::UsersAndGroups(users, groups) => {
-            convert_users_to_ldap_op(users, &request.attrs, ldap_info, &schema)
+        InternalSearchResults::UsersAndGroups(users, groups) => match sort_request {
+            None => convert_users_to_ldap_op(users, &request.attrs, ldap_info, &schema)
                 .chain(convert_groups_to_ldap_op(
                     groups,
                     &request.attrs,
@@ -382,15 +411,56 @@ pub async fn do_search(
                     backend_handler.user_filter(),
                     &schema,
                 ))
-                .collect()
-        }
+                .collect(),
+            Some(sort_request) => {
+                let user_sort_values: Vec<_> = users
+                    .iter()
+                    .map(|u| {
+                        sort_request.get_user_sort_values(
+                            &u.user,
+                            u.groups.as_deref(),
+                            ldap_info,
+                            &schema,
+                        )
+                    })
+                    .collect();
+                let group_sort_values: Vec<_> = groups
+                    .iter()
+                    .map(|g| {
+                        sort_request.get_group_sort_values(
+                            g,
+                            ldap_info,
+                            backend_handler.user_filter(),
+                            &schema,
+                        )
+                    })
+                    .collect();
+                sort_request.sort_entries(
+                    user_sort_values
+                        .into_iter()
+                        .zip(convert_users_to_ldap_op(
+                            users,
+                            &request.attrs,
+                            ldap_info,
+                            &schema,
+                        ))
+                        .chain(group_sort_values.into_iter().zip(convert_groups_to_ldap_op(
+                            groups,
+                            &request.attrs,
+                            ldap_info,
+                            backend_handler.user_filter(),
+                            &schema,
+                        ))),
+                )
+            }
+        },
         InternalSearchResults::Raw(raw_results) => raw_results,
         InternalSearchResults::Empty => Vec::new(),
     };
     if !matches!(results.last(), Some(LdapOp::SearchResultDone(_))) {
         results.push(make_search_success());
     }
-    Ok(results)
+    Ok((results, sort_control))
 }
 
 #[cfg(test)]
@@ -404,7 +474,9 @@ mod tests {
         },
     };
     use chrono::{DateTime, Duration, NaiveDateTime, TimeZone};
-    use ldap3_proto::proto::{LdapDerefAliases, LdapSearchScope, LdapSubstringFilter};
+    use ldap3_proto::proto::{
+        LdapDerefAliases, LdapMatchingRuleAssertion, LdapSearchScope, LdapSubstringFilter,
+    };
     use lldap_domain::{
         schema::{AttributeList, AttributeSchema, Schema},
         types::{
@@ -549,23 +621,19 @@ mod tests {
                     b"( 2.2 NAME 'JpegPhoto' SYNTAX 1.3.6.1.4.1.1466.115.121.1.28 )".to_vec(),
                     b"( 2.3 NAME 'DateTime' SYNTAX 1.3.6.1.4.1.1466.115.121.1.24 )".to_vec(),
                     b"( 2.4 NAME 'avatar' DESC 'LLDAP: builtin attribute' SUP JpegPhoto )".to_vec(),
-                    b"( 2.5 NAME 'creation_date' DESC 'LLDAP: builtin attribute' SUP DateTime )"
-                        .to_vec(),
-                    b"( 2.6 NAME 'display_name' DESC 'LLDAP: builtin attribute' SUP String )"
-                        .to_vec(),
-                    b"( 2.7 NAME 'first_name' DESC 'LLDAP: builtin attribute' SUP String )"
-                        .to_vec(),
-                    b"( 2.8 NAME 'last_name' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
-                    b"( 2.9 NAME 'mail' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
-                    b"( 2.10 NAME 'user_id' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
-                    b"( 2.11 NAME 'uuid' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
-                    b"( 2.12 NAME 'creation_date' DESC 'LLDAP: builtin attribute' SUP DateTime )"
-                        .to_vec(),
-                    b"( 2.13 NAME 'display_name' DESC 'LLDAP: builtin attribute' SUP String )"
-                        .to_vec(),
-                    b"( 2.14 NAME 'group_id' DESC 'LLDAP: builtin attribute' SUP Integer )"
-                        .to_vec(),
-                    b"( 2.15 NAME 'uuid' DESC 'LLDAP: builtin attribute' SUP String )".to_vec()
+                    b"( 2.5 NAME 'creation_date' DESC 'LLDAP: builtin attribute' SUP DateTime )".to_vec(),
+                    b"( 2.6 NAME 'display_name' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.7 NAME 'first_name' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.8 NAME 'last_login' DESC 'LLDAP: builtin attribute' SUP DateTime )".to_vec(),
+                    b"( 2.9 NAME 'last_name' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.10 NAME 'mail' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.11 NAME 'password_changed' DESC 'LLDAP: builtin attribute' SUP DateTime )".to_vec(),
+                    b"( 2.12 NAME 'user_id' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.13 NAME 'uuid' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.14 NAME 'creation_date' DESC 'LLDAP: builtin attribute' SUP DateTime )".to_vec(),
+                    b"( 2.15 NAME 'display_name' DESC 'LLDAP: builtin attribute' SUP String )".to_vec(),
+                    b"( 2.16 NAME 'group_id' DESC 'LLDAP: builtin attribute' SUP Integer )".to_vec(),
+                    b"( 2.17 NAME 'uuid' DESC 'LLDAP: builtin attribute' SUP String )".to_vec()
                 ]
             }
         );
@@ -574,7 +642,7 @@ mod tests {
             LdapPartialAttribute {
                 atype: "objectClasses".to_owned(),
                 vals: vec![
-                    b"( 3.0 NAME ( 'inetOrgPerson' 'posixAccount' 'mailAccount' 'person' 'customUserClass' ) DESC 'LLDAP builtin: a person' STRUCTURAL MUST ( mail $ user_id ) MAY ( avatar $ creation_date $ display_name $ first_name $ last_name $ uuid ) )".to_vec(),
+                    b"( 3.0 NAME ( 'inetOrgPerson' 'posixAccount' 'mailAccount' 'person' 'customUserClass' ) DESC 'LLDAP builtin: a person' STRUCTURAL MUST ( mail $ user_id ) MAY ( avatar $ creation_date $ display_name $ first_name $ last_login $ last_name $ password_changed $ uuid ) )".to_vec(),
                     b"( 3.1 NAME ( 'groupOfUniqueNames' 'groupOfNames' ) DESC 'LLDAP builtin: a group' STRUCTURAL MUST ( display_name ) MAY ( creation_date $ group_id $ uuid ) )".to_vec(),
                 ]
             }
@@ -661,6 +729,7 @@ mod tests {
                         group_id: GroupId(42),
                         display_name: "rockstars".into(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                         attributes: Vec::new(),
                     }]),
@@ -768,6 +837,7 @@ mod tests {
                             .with_ymd_and_hms(2014, 7, 8, 9, 10, 11)
                             .unwrap()
                             .naive_utc(),
+                        ..Default::default()
                     },
                     groups: None,
                 },
@@ -899,16 +969,20 @@ mod tests {
                         id: GroupId(1),
                         display_name: "group_1".into(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         users: vec![UserId::new("bob"), UserId::new("john")],
                         uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        subgroups: vec![],
                         attributes: Vec::new(),
                     },
                     Group {
                         id: GroupId(3),
                         display_name: "BestGroup".into(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         users: vec![UserId::new("john")],
                         uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        subgroups: vec![],
                         attributes: Vec::new(),
                     },
                 ])
@@ -997,8 +1071,10 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
                     attributes: Vec::new(),
                 }])
             });
@@ -1019,6 +1095,50 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_groups_with_subgroups() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName(
+                "engineering".into(),
+            ))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "engineering".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![UserId::new("bob")],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec!["backend".into(), "frontend".into()],
+                    attributes: Vec::new(),
+                }])
+            });
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::Equality("cn".to_string(), "engineering".to_string()),
+            vec!["member"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=engineering,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "member".to_string(),
+                        vals: vec![
+                            b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                            b"cn=backend,ou=groups,dc=example,dc=com".to_vec(),
+                            b"cn=frontend,ou=groups,dc=example,dc=com".to_vec(),
+                        ],
+                    }],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
     #[tokio::test]
     async fn test_search_groups_filter() {
         let mut mock = MockTestBackendHandler::new();
@@ -1047,8 +1167,10 @@ mod tests {
                     display_name: "group_1".into(),
                     id: GroupId(1),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
                     attributes: Vec::new(),
                 }])
             });
@@ -1118,8 +1240,10 @@ mod tests {
                     display_name: "group_1".into(),
                     id: GroupId(1),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
                     attributes: Vec::new(),
                 }])
             });
@@ -1166,8 +1290,10 @@ mod tests {
                     display_name: "group_1".into(),
                     id: GroupId(1),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
                     attributes: vec![Attribute {
                         name: "Attr".into(),
                         value: "TEST".to_string().into(),
@@ -1455,10 +1581,12 @@ mod tests {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_list_users()
             .with(
-                eq(Some(UserRequestFilter::MemberOf("group_1".into()))),
+                eq(Some(UserRequestFilter::TransitiveMemberOf(
+                    "group_1".into(),
+                ))),
                 eq(false),
             )
-            .times(2)
+            .times(3)
             .returning(|_, _| Ok(vec![]));
         let ldap_handler = setup_bound_admin_handler(mock).await;
         let request = make_user_search_request(
@@ -1480,6 +1608,19 @@ mod tests {
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![make_search_success()])
         );
+        let request = make_user_search_request(
+            LdapFilter::Extensible(LdapMatchingRuleAssertion {
+                matching_rule: Some("1.2.840.113556.1.4.1941".to_string()),
+                type_: Some("memberOf".to_string()),
+                match_value: "cn=Group_1,ou=groups,dc=example,dc=com".to_string(),
+                dn_attributes: false,
+            }),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
     }
     #[tokio::test]
     async fn test_search_member_of_filter_error() {
@@ -1594,6 +1735,176 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_account_status() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::Disabled,
+                    UserRequestFilter::HasExpiryDate,
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        disabled: true,
+                        expiry_date: Some(
+                            Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
+                                .unwrap()
+                                .naive_utc(),
+                        ),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Present("pwdAccountLockedTime".to_owned()),
+                LdapFilter::Present("shadowExpire".to_owned()),
+            ]),
+            vec!["pwdAccountLockedTime", "shadowExpire"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "pwdAccountLockedTime".to_string(),
+                            vals: vec![b"000001010000Z".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "shadowExpire".to_string(),
+                            vals: vec![b"19724".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_login_dates() {
+        let mut mock = MockTestBackendHandler::new();
+        let date = |day| {
+            Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0)
+                .unwrap()
+                .naive_utc()
+        };
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::DateLessOrEqual(UserColumn::LastLogin, date(2)),
+                    UserRequestFilter::DateGreaterOrEqual(UserColumn::PasswordChanged, date(1)),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(move |_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        last_login: Some(date(2)),
+                        password_changed: Some(date(1)),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::LessOrEqual("authTimestamp".to_owned(), "20240102000000Z".to_owned()),
+                LdapFilter::GreaterOrEqual(
+                    "pwdChangedTime".to_owned(),
+                    "2024-01-01T00:00:00+00:00".to_owned(),
+                ),
+            ]),
+            vec!["authTimestamp", "pwdChangedTime"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "authTimestamp".to_string(),
+                            vals: vec![b"2024-01-02T00:00:00+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "pwdChangedTime".to_string(),
+                            vals: vec![b"2024-01-01T00:00:00+00:00".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_modified_since() {
+        let mut mock = MockTestBackendHandler::new();
+        let date = |day| {
+            Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0)
+                .unwrap()
+                .naive_utc()
+        };
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::DateGreaterOrEqual(
+                    UserColumn::ModifiedDate,
+                    date(2),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(move |_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        creation_date: date(1),
+                        modified_date: date(3),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::GreaterOrEqual("modifyTimestamp".to_owned(), "20240102000000Z".to_owned()),
+            vec!["createTimestamp", "modifyTimestamp"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"2024-01-01T00:00:00+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "modifyTimestamp".to_string(),
+                            vals: vec![b"2024-01-03T00:00:00+00:00".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
     #[tokio::test]
     async fn test_search_both() {
         let mut mock = MockTestBackendHandler::new();
@@ -1626,8 +1937,10 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
                     attributes: Vec::new(),
                 }])
             });
@@ -1710,8 +2023,10 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
                     attributes: Vec::new(),
                 }])
             });
@@ -1967,8 +2282,10 @@ mod tests {
                 id: GroupId(1),
                 display_name: "group".into(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                subgroups: vec![],
                 attributes: vec![Attribute {
                     name: "club_name".into(),
                     value: "Breakfast Club".to_string().into(),
@@ -0,0 +1,576 @@
+use crate::core::{
+    error::{LdapError, LdapResult},
+    group::get_group_attribute,
+    user::get_user_attribute,
+    utils::{GroupFieldType, LdapInfo, UserFieldType, map_group_field, map_user_field},
+};
+use bytes::BytesMut;
+use lber::{
+    common::TagClass,
+    parse::parse_tag,
+    structure::StructureTag,
+    structures::{ASNTag, Enumerated, OctetString, Sequence, Tag},
+    universal::Types,
+    write::encode_into,
+};
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{LdapOp, LdapResultCode},
+};
+use lldap_domain::{
+    public_schema::PublicSchema,
+    types::{AttributeName, AttributeType, Group, GroupDetails, User, UserId},
+};
+use std::cmp::Ordering;
+
+// See RFC 2891.
+pub(crate) const OID_SERVER_SIDE_SORT_REQUEST: &str = "1.2.840.113556.1.4.473";
+pub(crate) const OID_SERVER_SIDE_SORT_RESPONSE: &str = "1.2.840.113556.1.4.474";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SortKey {
+    pub attribute: AttributeName,
+    pub reverse: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SortRequest {
+    pub keys: Vec<SortKey>,
+    pub criticality: bool,
+}
+
+// Subset of the sortResult enumeration from the RFC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SortResult {
+    Success = 0,
+    NoSuchAttribute = 16,
+}
+
+fn expect_sequence(tag: StructureTag) -> Option<Vec<StructureTag>> {
+    tag.match_class(TagClass::Universal)?
+        .match_id(Types::Sequence as u64)?
+        .expect_constructed()
+}
+
+fn parse_sort_key(key: StructureTag) -> Option<SortKey> {
+    let mut fields = expect_sequence(key)?.into_iter();
+    let attribute = fields
+        .next()?
+        .match_class(TagClass::Universal)?
+        .match_id(Types::OctetString as u64)?
+        .expect_primitive()?;
+    let mut reverse = false;
+    for field in fields {
+        let field = field.match_class(TagClass::Context)?;
+        match field.id {
+            // The ordering rule is ignored, we always use the natural ordering of the attribute.
+            0 => {}
+            1 => {
+                reverse = match field.expect_primitive()?.as_slice() {
+                    [b] => *b != 0,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(SortKey {
+        attribute: AttributeName::from(std::str::from_utf8(&attribute).ok()?),
+        reverse,
+    })
+}
+
+fn parse_sort_request(criticality: bool, value: &[u8]) -> Option<SortRequest> {
+    let (rest, list) = parse_tag(value).ok()?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let keys = expect_sequence(list)?
+        .into_iter()
+        .map(parse_sort_key)
+        .collect::<Option<Vec<_>>>()?;
+    if keys.is_empty() {
+        return None;
+    }
+    Some(SortRequest { keys, criticality })
+}
+
+pub(crate) fn get_sort_control(controls: &[LdapControl]) -> Option<LdapResult<SortRequest>> {
+    controls.iter().find_map(|control| match control {
+        LdapControl::Unknown {
+            oid,
+            criticality,
+            value,
+        } if oid == OID_SERVER_SIDE_SORT_REQUEST => Some(
+            value
+                .as_deref()
+                .and_then(|value| parse_sort_request(*criticality, value))
+                .ok_or_else(|| LdapError {
+                    code: LdapResultCode::ProtocolError,
+                    message: "Invalid server-side sort control".to_string(),
+                }),
+        ),
+        _ => None,
+    })
+}
+
+fn encode_tag(tag: Tag) -> Vec<u8> {
+    let mut buffer = BytesMut::new();
+    // Writing to memory cannot fail.
+    let _ = encode_into(&mut buffer, tag.into_structure());
+    buffer.to_vec()
+}
+
+pub(crate) fn make_sort_response_control(
+    result: SortResult,
+    attribute: Option<&AttributeName>,
+) -> LdapControl {
+    let mut fields = vec![Tag::Enumerated(Enumerated {
+        inner: result as i64,
+        ..Default::default()
+    })];
+    if let Some(attribute) = attribute {
+        fields.push(Tag::OctetString(OctetString {
+            class: TagClass::Context,
+            id: 0,
+            inner: attribute.as_str().as_bytes().to_vec(),
+        }));
+    }
+    LdapControl::Unknown {
+        oid: OID_SERVER_SIDE_SORT_RESPONSE.to_string(),
+        criticality: false,
+        value: Some(encode_tag(Tag::Sequence(Sequence {
+            inner: fields,
+            ..Default::default()
+        }))),
+    }
+}
+
+impl SortRequest {
+    /// Returns the first key that is neither a user nor a group attribute.
+    pub fn find_unknown_key(&self, schema: &PublicSchema) -> Option<&SortKey> {
+        self.keys.iter().find(|key| {
+            matches!(
+                map_user_field(&key.attribute, schema),
+                UserFieldType::NoMatch
+            ) && matches!(
+                map_group_field(&key.attribute, schema),
+                GroupFieldType::NoMatch
+            )
+        })
+    }
+
+    pub fn get_user_sort_values(
+        &self,
+        user: &User,
+        groups: Option<&[GroupDetails]>,
+        ldap_info: &LdapInfo,
+        schema: &PublicSchema,
+    ) -> Vec<Option<SortValue>> {
+        self.keys
+            .iter()
+            .map(|key| {
+                let is_integer = matches!(
+                    map_user_field(&key.attribute, schema),
+                    UserFieldType::Attribute(_, AttributeType::Integer, _)
+                );
+                SortValue::from_values(
+                    get_user_attribute(
+                        user,
+                        &key.attribute,
+                        &ldap_info.base_dn_str,
+                        groups,
+                        &ldap_info.ignored_user_attributes,
+                        schema,
+                    ),
+                    is_integer,
+                    key.reverse,
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_group_sort_values(
+        &self,
+        group: &Group,
+        ldap_info: &LdapInfo,
+        user_filter: &Option<UserId>,
+        schema: &PublicSchema,
+    ) -> Vec<Option<SortValue>> {
+        self.keys
+            .iter()
+            .map(|key| {
+                let is_integer = matches!(
+                    map_group_field(&key.attribute, schema),
+                    GroupFieldType::GroupId
+                        | GroupFieldType::Attribute(_, AttributeType::Integer, _)
+                );
+                SortValue::from_values(
+                    get_group_attribute(
+                        group,
+                        &ldap_info.base_dn_str,
+                        &key.attribute,
+                        user_filter,
+                        &ldap_info.ignored_group_attributes,
+                        schema,
+                    ),
+                    is_integer,
+                    key.reverse,
+                )
+            })
+            .collect()
+    }
+
+    /// Sorts the entries according to their sort values, as returned by `get_user_sort_values`
+    /// or `get_group_sort_values`. The sort is stable.
+    pub fn sort_entries(
+        &self,
+        entries: impl Iterator<Item = (Vec<Option<SortValue>>, LdapOp)>,
+    ) -> Vec<LdapOp> {
+        let mut entries: Vec<_> = entries.collect();
+        entries.sort_by(|(a, _), (b, _)| {
+            self.keys
+                .iter()
+                .zip(a.iter().zip(b.iter()))
+                .map(|(key, (a, b))| compare_sort_values(a, b, key.reverse))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        entries.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SortValue {
+    Integer(i64),
+    String(String),
+}
+
+impl SortValue {
+    /// For multi-valued attributes, the smallest value is used for ascending order, the largest
+    /// for descending order.
+    fn from_values(values: Option<Vec<Vec<u8>>>, is_integer: bool, reverse: bool) -> Option<Self> {
+        let values = values?.into_iter().map(|v| {
+            let s = String::from_utf8_lossy(&v).to_lowercase();
+            match s.parse::<i64>() {
+                Ok(i) if is_integer => SortValue::Integer(i),
+                _ => SortValue::String(s),
+            }
+        });
+        if reverse { values.max() } else { values.min() }
+    }
+}
+
+// Entries without a value for the key are considered larger than all the others.
+fn compare_sort_values(a: &Option<SortValue>, b: &Option<SortValue>, reverse: bool) -> Ordering {
+    let ordering = match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    if reverse {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handler::tests::{
+            make_group_search_request, make_user_search_request, setup_bound_admin_handler,
+        },
+        search::make_search_error,
+    };
+    use lber::structures::Boolean;
+    use ldap3_proto::proto::LdapFilter;
+    use lldap_domain::types::{Attribute, GroupId, UserAndGroups};
+    use lldap_test_utils::MockTestBackendHandler;
+    use pretty_assertions::assert_eq;
+
+    fn make_sort_control(keys: &[(&str, bool)], criticality: bool) -> LdapControl {
+        let keys: Vec<_> = keys
+            .iter()
+            .map(|(attribute, reverse)| {
+                let mut fields = vec![Tag::OctetString(OctetString {
+                    inner: attribute.as_bytes().to_vec(),
+                    ..Default::default()
+                })];
+                if *reverse {
+                    fields.push(Tag::Boolean(Boolean {
+                        class: TagClass::Context,
+                        id: 1,
+                        inner: true,
+                    }));
+                }
+                Tag::Sequence(Sequence {
+                    inner: fields,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        LdapControl::Unknown {
+            oid: OID_SERVER_SIDE_SORT_REQUEST.to_string(),
+            criticality,
+            value: Some(encode_tag(Tag::Sequence(Sequence {
+                inner: keys,
+                ..Default::default()
+            }))),
+        }
+    }
+
+    /// The paged results control that is attached to every search result.
+    fn make_paged_results_control(size: i64) -> LdapControl {
+        LdapControl::SimplePagedResults {
+            size,
+            cookie: vec![],
+        }
+    }
+
+    fn expect_list_users(mock: &mut MockTestBackendHandler, users: Vec<(&'static str, i64)>) {
+        mock.expect_list_users().times(1).return_once(move |_, _| {
+            Ok(users
+                .into_iter()
+                .map(|(user_id, age)| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(user_id),
+                        attributes: vec![Attribute {
+                            name: "first_name".into(),
+                            value: age.to_string().into(),
+                        }],
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+    }
+
+    fn get_dns(response: &[(LdapOp, Vec<LdapControl>)]) -> Vec<&str> {
+        response
+            .iter()
+            .filter_map(|(op, _)| match op {
+                LdapOp::SearchResultEntry(entry) => Some(entry.dn.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_sort_control() {
+        let control = make_sort_control(&[("cn", false), ("uid", true)], true);
+        assert_eq!(
+            get_sort_control(&[control]),
+            Some(Ok(SortRequest {
+                keys: vec![
+                    SortKey {
+                        attribute: "cn".into(),
+                        reverse: false
+                    },
+                    SortKey {
+                        attribute: "uid".into(),
+                        reverse: true
+                    },
+                ],
+                criticality: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_sort_control() {
+        let control = LdapControl::Unknown {
+            oid: OID_SERVER_SIDE_SORT_REQUEST.to_string(),
+            criticality: false,
+            value: Some(vec![0x30, 0x03, 0x04]),
+        };
+        assert_eq!(
+            get_sort_control(&[control]),
+            Some(Err(LdapError {
+                code: LdapResultCode::ProtocolError,
+                message: "Invalid server-side sort control".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_sort_response_control() {
+        let LdapControl::Unknown { value, .. } = make_sort_response_control(
+            SortResult::NoSuchAttribute,
+            Some(&AttributeName::from("foo")),
+        ) else {
+            panic!()
+        };
+        assert_eq!(
+            value,
+            Some(vec![
+                0x30, 0x08, 0x0a, 0x01, 0x10, 0x80, 0x03, b'f', b'o', b'o'
+            ])
+        );
+        let value = value.unwrap();
+        let (rest, tag) = parse_tag(&value).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(expect_sequence(tag).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sorted_search() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec![("bob", 30), ("alice", 4), ("jim", 100)]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &[make_sort_control(&[("uid", false)], true)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&response),
+            vec![
+                "uid=alice,ou=people,dc=example,dc=com",
+                "uid=bob,ou=people,dc=example,dc=com",
+                "uid=jim,ou=people,dc=example,dc=com",
+            ]
+        );
+        assert_eq!(
+            response.last().unwrap().1,
+            vec![
+                make_paged_results_control(3),
+                make_sort_response_control(SortResult::Success, None)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_search_reverse_by_missing_attribute() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec![("bob", 30), ("alice", 4)]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &[make_sort_control(
+                    &[("sn", true), ("givenName", true)],
+                    false,
+                )],
+            )
+            .await
+            .unwrap();
+        // Nobody has a last name, the first name (as a string) decides.
+        assert_eq!(
+            get_dns(&response),
+            vec![
+                "uid=alice,ou=people,dc=example,dc=com",
+                "uid=bob,ou=people,dc=example,dc=com",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_group_search_by_group_id() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups().times(1).return_once(|_| {
+            Ok(vec![
+                Group {
+                    id: GroupId(10),
+                    display_name: "group_10".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    users: vec![],
+                    uuid: lldap_domain::uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
+                    attributes: Vec::new(),
+                },
+                Group {
+                    id: GroupId(9),
+                    display_name: "group_9".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    users: vec![],
+                    uuid: lldap_domain::uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    subgroups: vec![],
+                    attributes: Vec::new(),
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &[make_sort_control(&[("groupId", false)], true)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&response),
+            vec![
+                "cn=group_9,ou=groups,dc=example,dc=com",
+                "cn=group_10,ou=groups,dc=example,dc=com",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_search_unknown_critical_key() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message_with_controls(
+                    LdapOp::SearchRequest(request),
+                    &[make_sort_control(&[("unknown", false)], true)],
+                )
+                .await
+                .unwrap(),
+            vec![(
+                make_search_error(
+                    LdapResultCode::UnavailableCriticalExtension,
+                    "Unknown sort key: unknown".to_string()
+                ),
+                vec![
+                    make_paged_results_control(0),
+                    make_sort_response_control(
+                        SortResult::NoSuchAttribute,
+                        Some(&"unknown".into())
+                    )
+                ]
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_search_unknown_non_critical_key() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec![("bob", 30), ("alice", 4)]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &[make_sort_control(&[("unknown", false)], false)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&response),
+            vec![
+                "uid=bob,ou=people,dc=example,dc=com",
+                "uid=alice,ou=people,dc=example,dc=com",
+            ]
+        );
+        assert_eq!(
+            response.last().unwrap().1,
+            vec![
+                make_paged_results_control(2),
+                make_sort_response_control(SortResult::NoSuchAttribute, Some(&"unknown".into()))
+            ]
+        );
+    }
+}
@@ -0,0 +1,641 @@
+use crate::core::{
+    error::{LdapError, LdapResult},
+    utils::{LdapInfo, is_subtree, parse_distinguished_name},
+};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeDelta};
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{
+        LdapIntermediateResponse, LdapOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry,
+        SyncRequestMode, SyncStateValue,
+    },
+};
+use lldap_domain::types::{GroupName, UserId};
+use lldap_domain_handlers::handler::{DeletedEntry, DeletedEntryKind};
+use std::collections::HashSet;
+
+// See RFC 4533.
+pub(crate) const OID_SYNC_REQUEST: &str = "1.3.6.1.4.1.4203.1.9.1.1";
+
+/// The changes made shortly before a synchronization are sent again in the next one: their
+/// transaction may have been committed after the search.
+const SYNC_MARGIN: TimeDelta = TimeDelta::seconds(10);
+
+/// Attributes added to the search to know the identity and the last change of each entry.
+const ENTRY_UUID: &str = "entryuuid";
+const MODIFY_TIMESTAMP: &str = "modifytimestamp";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SyncRequest {
+    /// refreshAndPersist: keep the search open and send the changes as they happen.
+    pub persist: bool,
+    /// The date of the last synchronization, from the cookie. None for a full refresh.
+    pub since: Option<NaiveDateTime>,
+}
+
+/// The refresh stage sends the content of the entries, the persist stage sends the changes as
+/// they happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyncStage {
+    Refresh,
+    Persist,
+}
+
+/// The refreshAndPersist search in progress on a connection.
+pub(crate) struct PersistentSync {
+    pub request: LdapSearchRequest,
+    pub last_sync: NaiveDateTime,
+}
+
+pub(crate) fn make_cookie(date: NaiveDateTime) -> Vec<u8> {
+    date.and_utc()
+        .to_rfc3339_opts(SecondsFormat::Micros, true)
+        .into_bytes()
+}
+
+/// An invalid cookie results in a full refresh, which the consumers handle like a new
+/// synchronization.
+fn parse_cookie(cookie: &[u8]) -> Option<NaiveDateTime> {
+    let cookie = std::str::from_utf8(cookie).ok()?;
+    Some(DateTime::parse_from_rfc3339(cookie).ok()?.naive_utc())
+}
+
+pub(crate) fn get_sync_request_control(controls: &[LdapControl]) -> Option<SyncRequest> {
+    controls.iter().find_map(|control| match control {
+        LdapControl::SyncRequest { mode, cookie, .. } => Some(SyncRequest {
+            persist: matches!(mode, SyncRequestMode::RefreshAndPersist),
+            since: cookie.as_deref().and_then(parse_cookie),
+        }),
+        _ => None,
+    })
+}
+
+/// Adds the attributes needed for the synchronization to the request. Returns the attributes
+/// that were added, to remove them from the results.
+pub(crate) fn make_sync_search_request(
+    request: &LdapSearchRequest,
+) -> (LdapSearchRequest, Vec<&'static str>) {
+    let mut request = request.clone();
+    let all_attributes = request.attrs.is_empty() || request.attrs.iter().any(|a| a == "*");
+    if request.attrs.is_empty() {
+        request.attrs.push("*".to_owned());
+    }
+    let mut added_attributes = Vec::new();
+    for attribute in [ENTRY_UUID, MODIFY_TIMESTAMP] {
+        // The entryUUID is part of the default user and group attributes.
+        let already_requested = (all_attributes && attribute == ENTRY_UUID)
+            || request
+                .attrs
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(attribute));
+        if !already_requested {
+            request.attrs.push(attribute.to_owned());
+            added_attributes.push(attribute);
+        }
+    }
+    (request, added_attributes)
+}
+
+fn get_attribute_value<'a>(entry: &'a LdapSearchResultEntry, attribute: &str) -> Option<&'a str> {
+    entry
+        .attributes
+        .iter()
+        .find(|a| a.atype.eq_ignore_ascii_case(attribute))
+        .and_then(|a| a.vals.first())
+        .and_then(|v| std::str::from_utf8(v).ok())
+}
+
+/// During the refresh stage, the cookie is only sent at the end.
+fn make_sync_state_control(
+    stage: SyncStage,
+    deleted: bool,
+    entry_uuid: uuid::Uuid,
+    cookie: &[u8],
+) -> LdapControl {
+    LdapControl::SyncState {
+        state: match (deleted, stage) {
+            (true, _) => SyncStateValue::Delete,
+            (false, SyncStage::Refresh) => SyncStateValue::Add,
+            (false, SyncStage::Persist) => SyncStateValue::Modify,
+        },
+        entry_uuid,
+        cookie: (stage == SyncStage::Persist).then(|| cookie.to_vec()),
+    }
+}
+
+pub(crate) fn make_sync_done_control(cookie: Vec<u8>, refresh_deletes: bool) -> LdapControl {
+    LdapControl::SyncDone {
+        cookie: Some(cookie),
+        refresh_deletes,
+    }
+}
+
+/// Marks the end of the refresh stage of a refreshAndPersist search.
+pub(crate) fn make_sync_info_refresh_done(cookie: Vec<u8>, refresh_deletes: bool) -> LdapOp {
+    LdapOp::IntermediateResponse(if refresh_deletes {
+        LdapIntermediateResponse::SyncInfoRefreshDelete {
+            cookie: Some(cookie),
+            done: true,
+        }
+    } else {
+        LdapIntermediateResponse::SyncInfoRefreshPresent {
+            cookie: Some(cookie),
+            done: true,
+        }
+    })
+}
+
+/// The (lowercase) DNs of the users and groups of the memberships that expired since the last
+/// synchronization: their modification date is only updated once the membership is purged.
+pub(crate) fn get_expired_membership_dns(
+    expired_memberships: Vec<(UserId, GroupName)>,
+    ldap_info: &LdapInfo,
+) -> HashSet<String> {
+    expired_memberships
+        .into_iter()
+        .flat_map(|(user_id, group_name)| {
+            [
+                format!("uid={},ou=people,{}", user_id, ldap_info.base_dn_str),
+                format!("cn={},ou=groups,{}", group_name, ldap_info.base_dn_str),
+            ]
+        })
+        .map(|dn| dn.to_ascii_lowercase())
+        .collect()
+}
+
+/// Attaches the sync state to the results of a search made with `make_sync_search_request`,
+/// keeping only the entries changed since the last synchronization, if any, or whose DN is in
+/// `changed_dns`.
+pub(crate) fn make_sync_entries(
+    results: Vec<LdapOp>,
+    added_attributes: &[&str],
+    since: Option<NaiveDateTime>,
+    changed_dns: &HashSet<String>,
+    stage: SyncStage,
+    cookie: &[u8],
+) -> LdapResult<Vec<(LdapOp, Vec<LdapControl>)>> {
+    let since = since.map(|s| s - SYNC_MARGIN);
+    let mut changes = Vec::new();
+    for op in results {
+        let mut entry = match op {
+            LdapOp::SearchResultEntry(entry) => entry,
+            LdapOp::SearchResultDone(result) if result.code == LdapResultCode::Success => continue,
+            LdapOp::SearchResultDone(result) => {
+                return Err(LdapError {
+                    code: result.code,
+                    message: result.message,
+                });
+            }
+            op => {
+                changes.push((op, Vec::new()));
+                continue;
+            }
+        };
+        // Entries without a change date, like the organizational units, never change.
+        let modified = get_attribute_value(&entry, MODIFY_TIMESTAMP)
+            .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+            .map(|d| d.naive_utc());
+        if since.is_some_and(|since| !modified.is_some_and(|m| m >= since))
+            && !changed_dns.contains(&entry.dn.to_ascii_lowercase())
+        {
+            continue;
+        }
+        let entry_uuid = get_attribute_value(&entry, ENTRY_UUID)
+            .and_then(|u| uuid::Uuid::parse_str(u).ok())
+            .unwrap_or_else(|| {
+                uuid::Uuid::new_v3(
+                    &uuid::Uuid::NAMESPACE_X500,
+                    entry.dn.to_ascii_lowercase().as_bytes(),
+                )
+            });
+        entry.attributes.retain(|a| {
+            !added_attributes
+                .iter()
+                .any(|added| a.atype.eq_ignore_ascii_case(added))
+        });
+        changes.push((
+            LdapOp::SearchResultEntry(entry),
+            vec![make_sync_state_control(stage, false, entry_uuid, cookie)],
+        ));
+    }
+    Ok(changes)
+}
+
+/// The deleted entries under the search base, as entries with only a DN and a delete state.
+pub(crate) fn make_deleted_entries(
+    deleted_entries: Vec<DeletedEntry>,
+    ldap_info: &LdapInfo,
+    search_base: &str,
+    stage: SyncStage,
+    cookie: &[u8],
+) -> LdapResult<Vec<(LdapOp, Vec<LdapControl>)>> {
+    let search_base = parse_distinguished_name(&search_base.to_ascii_lowercase())?;
+    Ok(deleted_entries
+        .into_iter()
+        .filter_map(|deleted| {
+            let dn = match deleted.kind {
+                DeletedEntryKind::User => {
+                    format!("uid={},ou=people,{}", deleted.name, ldap_info.base_dn_str)
+                }
+                DeletedEntryKind::Group => {
+                    format!("cn={},ou=groups,{}", deleted.name, ldap_info.base_dn_str)
+                }
+            };
+            if !is_subtree(
+                &parse_distinguished_name(&dn.to_ascii_lowercase()).ok()?,
+                &search_base,
+            ) {
+                return None;
+            }
+            let entry_uuid = uuid::Uuid::parse_str(deleted.uuid.as_str()).ok()?;
+            Some((
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn,
+                    attributes: Vec::new(),
+                }),
+                vec![make_sync_state_control(stage, true, entry_uuid, cookie)],
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        handler::tests::{make_user_search_request, setup_bound_admin_handler},
+        search::{make_search_request, make_search_success},
+    };
+    use chrono::TimeZone;
+    use ldap3_proto::proto::{LdapFilter, LdapPartialAttribute};
+    use lldap_domain::{
+        types::{User, UserAndGroups},
+        uuid,
+    };
+    use lldap_test_utils::MockTestBackendHandler;
+    use mockall::predicate::eq;
+    use pretty_assertions::assert_eq;
+
+    fn make_ldap_info() -> LdapInfo {
+        LdapInfo {
+            base_dn: parse_distinguished_name("dc=example,dc=com").unwrap(),
+            base_dn_str: "dc=example,dc=com".to_owned(),
+            ignored_user_attributes: vec![],
+            ignored_group_attributes: vec![],
+            password_policy: Default::default(),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDateTime {
+        chrono::Utc
+            .with_ymd_and_hms(2024, 1, day, 0, 0, 0)
+            .unwrap()
+            .naive_utc()
+    }
+
+    fn make_entry(uid: &str, uuid: &str, modified: NaiveDateTime) -> LdapOp {
+        LdapOp::SearchResultEntry(LdapSearchResultEntry {
+            dn: format!("uid={},ou=people,dc=example,dc=com", uid),
+            attributes: vec![
+                LdapPartialAttribute {
+                    atype: "uid".to_owned(),
+                    vals: vec![uid.as_bytes().to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: ENTRY_UUID.to_owned(),
+                    vals: vec![uuid.as_bytes().to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: MODIFY_TIMESTAMP.to_owned(),
+                    vals: vec![modified.and_utc().to_rfc3339().into_bytes()],
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn test_cookie_round_trip() {
+        let date = chrono::Utc
+            .with_ymd_and_hms(2024, 1, 2, 3, 4, 5)
+            .unwrap()
+            .naive_utc();
+        assert_eq!(parse_cookie(&make_cookie(date)), Some(date));
+        assert_eq!(parse_cookie(b"not a date"), None);
+    }
+
+    #[test]
+    fn test_sync_search_request_attributes() {
+        let request = make_search_request::<String>(
+            "dc=example,dc=com",
+            LdapFilter::Present("objectClass".to_owned()),
+            vec![],
+        );
+        let (sync_request, added) = make_sync_search_request(&request);
+        assert_eq!(sync_request.attrs, vec!["*", "modifytimestamp"]);
+        assert_eq!(added, vec![MODIFY_TIMESTAMP]);
+        let request = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::Present("objectClass".to_owned()),
+            vec!["uid", "modifyTimestamp"],
+        );
+        let (sync_request, added) = make_sync_search_request(&request);
+        assert_eq!(
+            sync_request.attrs,
+            vec!["uid", "modifyTimestamp", "entryuuid"]
+        );
+        assert_eq!(added, vec![ENTRY_UUID]);
+    }
+
+    #[test]
+    fn test_sync_entries_since_last_sync() {
+        let results = vec![
+            make_entry("bob", "698e1d5f-7a40-3151-8745-b9b8a37839da", date(1)),
+            make_entry("john", "04ac75e0-2900-3e21-926c-2f732c26b3fc", date(3)),
+            make_search_success(),
+        ];
+        let changes = make_sync_entries(
+            results,
+            &[ENTRY_UUID, MODIFY_TIMESTAMP],
+            Some(date(2)),
+            &HashSet::new(),
+            SyncStage::Persist,
+            b"cookie",
+        )
+        .unwrap();
+        assert_eq!(
+            changes,
+            vec![(
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_owned(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_owned(),
+                        vals: vec![b"john".to_vec()],
+                    }],
+                }),
+                vec![LdapControl::SyncState {
+                    state: SyncStateValue::Modify,
+                    entry_uuid: uuid::Uuid::parse_str("04ac75e0-2900-3e21-926c-2f732c26b3fc")
+                        .unwrap(),
+                    cookie: Some(b"cookie".to_vec()),
+                }],
+            )]
+        );
+    }
+
+    #[test]
+    fn test_sync_entries_expired_membership() {
+        let results = vec![
+            make_entry("bob", "698e1d5f-7a40-3151-8745-b9b8a37839da", date(1)),
+            make_entry("john", "04ac75e0-2900-3e21-926c-2f732c26b3fc", date(1)),
+        ];
+        let changed_dns = get_expired_membership_dns(
+            vec![(UserId::new("bob"), GroupName::from("Best Group"))],
+            &make_ldap_info(),
+        );
+        assert_eq!(
+            changed_dns,
+            HashSet::from([
+                "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                "cn=best group,ou=groups,dc=example,dc=com".to_owned(),
+            ])
+        );
+        let changes = make_sync_entries(
+            results,
+            &[],
+            Some(date(2)),
+            &changed_dns,
+            SyncStage::Persist,
+            b"cookie",
+        )
+        .unwrap();
+        assert_eq!(
+            get_states(&changes),
+            vec![(
+                "uid=bob,ou=people,dc=example,dc=com",
+                &SyncStateValue::Modify
+            )]
+        );
+    }
+
+    #[test]
+    fn test_sync_entries_search_error() {
+        let results = vec![crate::search::make_search_error(
+            LdapResultCode::InsufficentAccessRights,
+            "No".to_owned(),
+        )];
+        assert_eq!(
+            make_sync_entries(results, &[], None, &HashSet::new(), SyncStage::Refresh, b""),
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "No".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_deleted_entries_under_search_base() {
+        let deleted = vec![
+            DeletedEntry {
+                uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                kind: DeletedEntryKind::User,
+                name: "bob".to_owned(),
+                deletion_date: date(2),
+            },
+            DeletedEntry {
+                uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                kind: DeletedEntryKind::Group,
+                name: "Best Group".to_owned(),
+                deletion_date: date(3),
+            },
+        ];
+        let entries = make_deleted_entries(
+            deleted.clone(),
+            &make_ldap_info(),
+            "ou=groups,dc=example,dc=com",
+            SyncStage::Refresh,
+            b"cookie",
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![(
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=Best Group,ou=groups,dc=example,dc=com".to_owned(),
+                    attributes: vec![],
+                }),
+                vec![LdapControl::SyncState {
+                    state: SyncStateValue::Delete,
+                    entry_uuid: uuid::Uuid::parse_str("04ac75e0-2900-3e21-926c-2f732c26b3fc")
+                        .unwrap(),
+                    cookie: None,
+                }],
+            )]
+        );
+        assert_eq!(
+            make_deleted_entries(
+                deleted,
+                &make_ldap_info(),
+                "dc=example,dc=com",
+                SyncStage::Refresh,
+                b"cookie",
+            )
+            .unwrap()
+            .len(),
+            2
+        );
+    }
+
+    fn expect_list_users(mock: &mut MockTestBackendHandler, users: Vec<(&'static str, u32)>) {
+        mock.expect_list_users().times(1).return_once(move |_, _| {
+            Ok(users
+                .into_iter()
+                .map(|(user_id, modified_day)| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(user_id),
+                        creation_date: date(1),
+                        modified_date: date(modified_day),
+                        uuid: lldap_domain::types::Uuid::from_name_and_date(user_id, &date(1)),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+    }
+
+    fn make_sync_control(persist: bool, cookie: Option<Vec<u8>>) -> Vec<LdapControl> {
+        vec![LdapControl::SyncRequest {
+            criticality: true,
+            mode: if persist {
+                SyncRequestMode::RefreshAndPersist
+            } else {
+                SyncRequestMode::RefreshOnly
+            },
+            cookie,
+            reload_hint: false,
+        }]
+    }
+
+    fn get_states(response: &[(LdapOp, Vec<LdapControl>)]) -> Vec<(&str, &SyncStateValue)> {
+        response
+            .iter()
+            .filter_map(|(op, controls)| match (op, controls.as_slice()) {
+                (LdapOp::SearchResultEntry(entry), [LdapControl::SyncState { state, .. }]) => {
+                    Some((entry.dn.as_str(), state))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sync_refresh_only_with_cookie() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec![("bob", 1), ("john", 3)]);
+        mock.expect_list_deleted_entries()
+            .with(eq(date(2)))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![DeletedEntry {
+                    uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                    kind: DeletedEntryKind::User,
+                    name: "patrick".to_owned(),
+                    deletion_date: date(3),
+                }])
+            });
+        // Bob's membership expired since the last synchronization.
+        mock.expect_list_expired_memberships()
+            .with(eq(date(2)))
+            .times(1)
+            .return_once(|_| Ok(vec![(UserId::new("bob"), GroupName::from("Best Group"))]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["uid"]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &make_sync_control(false, Some(make_cookie(date(2)))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_states(&response),
+            vec![
+                ("uid=bob,ou=people,dc=example,dc=com", &SyncStateValue::Add),
+                ("uid=john,ou=people,dc=example,dc=com", &SyncStateValue::Add),
+                (
+                    "uid=patrick,ou=people,dc=example,dc=com",
+                    &SyncStateValue::Delete
+                ),
+            ]
+        );
+        // Only the requested attributes are returned.
+        match &response[1].0 {
+            LdapOp::SearchResultEntry(entry) => assert_eq!(
+                entry.attributes,
+                vec![LdapPartialAttribute {
+                    atype: "uid".to_owned(),
+                    vals: vec![b"john".to_vec()],
+                }]
+            ),
+            other => panic!("Expected SearchResultEntry, got {:?}", other),
+        }
+        match response.last() {
+            Some((
+                op,
+                [
+                    LdapControl::SyncDone {
+                        cookie: Some(cookie),
+                        refresh_deletes: true,
+                    },
+                ],
+            )) => {
+                assert_eq!(op, &make_search_success());
+                assert!(parse_cookie(cookie).unwrap() > date(3));
+            }
+            other => panic!("Expected SearchResultDone, got {:?}", other),
+        }
+        assert!(!ldap_handler.has_persistent_search());
+    }
+
+    #[tokio::test]
+    async fn test_sync_refresh_and_persist() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_list_users(&mut mock, vec![("bob", 1)]);
+        // Nothing changed since the refresh.
+        expect_list_users(&mut mock, vec![("bob", 1)]);
+        mock.expect_list_deleted_entries()
+            .times(1)
+            .return_once(|_| Ok(Vec::new()));
+        mock.expect_list_expired_memberships()
+            .times(1)
+            .return_once(|_| Ok(Vec::new()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request::<String>(LdapFilter::And(vec![]), vec![]);
+        let response = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(request),
+                &make_sync_control(true, None),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_states(&response),
+            vec![("uid=bob,ou=people,dc=example,dc=com", &SyncStateValue::Add)]
+        );
+        assert!(matches!(
+            response.last(),
+            Some((
+                LdapOp::IntermediateResponse(LdapIntermediateResponse::SyncInfoRefreshPresent {
+                    done: true,
+                    ..
+                }),
+                _
+            ))
+        ));
+        assert!(ldap_handler.has_persistent_search());
+        assert_eq!(ldap_handler.poll_persistent_search().await, Vec::new());
+        ldap_handler.cancel_persistent_search();
+        assert!(!ldap_handler.has_persistent_search());
+        assert_eq!(ldap_handler.poll_persistent_search().await, Vec::new());
+    }
+}
@@ -1,11 +1,19 @@
 pub(crate) mod logging;
+pub(crate) mod sql_api_token_backend_handler;
+pub(crate) mod sql_audit_log_backend_handler;
 pub(crate) mod sql_backend_handler;
+pub(crate) mod sql_deleted_entry_backend_handler;
 pub(crate) mod sql_group_backend_handler;
+pub(crate) mod sql_group_owner_backend_handler;
+pub(crate) mod sql_login_throttle_backend_handler;
 pub(crate) mod sql_opaque_handler;
+pub(crate) mod sql_role_backend_handler;
 pub(crate) mod sql_schema_backend_handler;
+pub(crate) mod sql_session_backend_handler;
 pub(crate) mod sql_user_backend_handler;
 
 pub use sql_backend_handler::SqlBackendHandler;
+pub use sql_login_throttle_backend_handler::LoginThrottleOptions;
 pub use sql_opaque_handler::register_password;
 pub mod sql_migrations;
 pub mod sql_tables;
@@ -0,0 +1,216 @@
+use crate::sql_backend_handler::SqlBackendHandler;
+use async_trait::async_trait;
+use lldap_domain::types::UserId;
+use lldap_domain_handlers::handler::{
+    API_TOKEN_PREFIX, ApiToken, ApiTokenBackendHandler, CreateApiTokenRequest,
+};
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, ApiTokensColumn},
+};
+use rand::{Rng, distributions::Alphanumeric, rngs::OsRng};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use tracing::instrument;
+
+const SECRET_LENGTH: usize = 40;
+
+fn generate_secret() -> String {
+    let random: String = OsRng
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(SECRET_LENGTH)
+        .collect();
+    format!("{API_TOKEN_PREFIX}{random}")
+}
+
+/// The secrets are random, so a fast hash is enough to protect them.
+fn hash_secret(secret: &str) -> Result<String> {
+    Ok(orion::hash::digest(secret.as_bytes())?
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+impl TryFrom<model::api_tokens::Model> for ApiToken {
+    type Error = DomainError;
+
+    fn try_from(token: model::api_tokens::Model) -> Result<Self> {
+        Ok(Self {
+            token_id: token.token_id,
+            user_id: token.user_id,
+            name: token.name,
+            scope: token.scope.parse().map_err(DomainError::InternalError)?,
+            creation_date: token.creation_date,
+            expiry_date: token.expiry_date,
+        })
+    }
+}
+
+#[async_trait]
+impl ApiTokenBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_api_tokens(&self, user_id: &UserId) -> Result<Vec<ApiToken>> {
+        model::ApiTokens::find()
+            .filter(ApiTokensColumn::UserId.eq(user_id))
+            .order_by_asc(ApiTokensColumn::TokenId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(ApiToken::try_from)
+            .collect()
+    }
+
+    #[instrument(skip(self), level = "debug", err, fields(user_id = ?request.user_id))]
+    async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)> {
+        let secret = generate_secret();
+        let new_token = model::api_tokens::ActiveModel {
+            token_hash: Set(hash_secret(&secret)?),
+            user_id: Set(request.user_id),
+            name: Set(request.name),
+            scope: Set(request.scope.as_str().to_owned()),
+            creation_date: Set(chrono::Utc::now().naive_utc()),
+            expiry_date: Set(request.expiry_date),
+            ..Default::default()
+        };
+        let token = new_token.insert(&self.sql_pool).await?;
+        Ok((token.try_into()?, secret))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_api_token_from_secret(&self, secret: &str) -> Result<Option<ApiToken>> {
+        model::ApiTokens::find()
+            .filter(ApiTokensColumn::TokenHash.eq(hash_secret(secret)?))
+            .filter(
+                Condition::any()
+                    .add(ApiTokensColumn::ExpiryDate.is_null())
+                    .add(ApiTokensColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc())),
+            )
+            .one(&self.sql_pool)
+            .await?
+            .map(ApiToken::try_from)
+            .transpose()
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_api_token(&self, user_id: &UserId, token_id: i32) -> Result<()> {
+        let res = model::ApiTokens::delete_many()
+            .filter(ApiTokensColumn::TokenId.eq(token_id))
+            .filter(ApiTokensColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such API token: '{}'",
+                token_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::*;
+    use lldap_auth::access_control::ApiTokenScope;
+    use pretty_assertions::assert_eq;
+
+    fn make_request(
+        user: &str,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> CreateApiTokenRequest {
+        CreateApiTokenRequest {
+            user_id: UserId::new(user),
+            name: "ci".to_owned(),
+            scope: ApiTokenScope::Readonly,
+            expiry_date,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_get_api_token() {
+        let fixture = TestFixture::new().await;
+        let (token, secret) = fixture
+            .handler
+            .create_api_token(make_request("bob", None))
+            .await
+            .unwrap();
+        assert!(secret.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(token.scope, ApiTokenScope::Readonly);
+        assert_eq!(
+            fixture
+                .handler
+                .get_api_token_from_secret(&secret)
+                .await
+                .unwrap(),
+            Some(token.clone())
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .list_api_tokens(&UserId::new("bob"))
+                .await
+                .unwrap(),
+            vec![token]
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .get_api_token_from_secret("lldap_wrong")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_api_token() {
+        let fixture = TestFixture::new().await;
+        let yesterday = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+        let (_, secret) = fixture
+            .handler
+            .create_api_token(make_request("bob", Some(yesterday)))
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_api_token_from_secret(&secret)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_api_token() {
+        let fixture = TestFixture::new().await;
+        let (token, secret) = fixture
+            .handler
+            .create_api_token(make_request("bob", None))
+            .await
+            .unwrap();
+        // Only the owner's tokens can be deleted.
+        fixture
+            .handler
+            .delete_api_token(&UserId::new("patrick"), token.token_id)
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .delete_api_token(&UserId::new("bob"), token.token_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_api_token_from_secret(&secret)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use crate::sql_backend_handler::SqlBackendHandler;
+use async_trait::async_trait;
+use lldap_domain_handlers::handler::{
+    AuditEvent, AuditEventFilter, AuditLogBackendHandler, CreateAuditEventRequest,
+};
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, AuditLogColumn},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use tracing::instrument;
+
+impl TryFrom<model::audit_log::Model> for AuditEvent {
+    type Error = DomainError;
+
+    fn try_from(event: model::audit_log::Model) -> Result<Self> {
+        Ok(Self {
+            event_id: event.event_id,
+            date: event.date,
+            actor: event.actor,
+            source: event.source.parse().map_err(DomainError::InternalError)?,
+            ip_address: event.ip_address,
+            target: event.target,
+            operation: event.operation,
+            success: event.success,
+            details: event.details,
+        })
+    }
+}
+
+#[async_trait]
+impl AuditLogBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn record_audit_event(&self, request: CreateAuditEventRequest) -> Result<()> {
+        model::audit_log::ActiveModel {
+            date: Set(chrono::Utc::now().naive_utc()),
+            // Like the user IDs, the actors are case-insensitive.
+            actor: Set(request.actor.map(|a| a.to_lowercase())),
+            source: Set(request.source.as_str().to_owned()),
+            ip_address: Set(request.ip_address),
+            target: Set(request.target),
+            operation: Set(request.operation),
+            success: Set(request.success),
+            details: Set(request.details),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_audit_events(
+        &self,
+        filter: AuditEventFilter,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<AuditEvent>> {
+        let AuditEventFilter {
+            actor,
+            target,
+            operation,
+            source,
+            success,
+            since,
+            until,
+        } = filter;
+        let mut query = model::AuditLog::find();
+        if let Some(actor) = actor {
+            query = query.filter(AuditLogColumn::Actor.eq(actor.to_lowercase()));
+        }
+        if let Some(target) = target {
+            query = query.filter(AuditLogColumn::Target.eq(target));
+        }
+        if let Some(operation) = operation {
+            query = query.filter(AuditLogColumn::Operation.eq(operation));
+        }
+        if let Some(source) = source {
+            query = query.filter(AuditLogColumn::Source.eq(source.as_str()));
+        }
+        if let Some(success) = success {
+            query = query.filter(AuditLogColumn::Success.eq(success));
+        }
+        if let Some(since) = since {
+            query = query.filter(AuditLogColumn::Date.gte(since));
+        }
+        if let Some(until) = until {
+            query = query.filter(AuditLogColumn::Date.lt(until));
+        }
+        query
+            .order_by_desc(AuditLogColumn::EventId)
+            .offset(offset)
+            .limit(limit)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(AuditEvent::try_from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::*;
+    use lldap_domain_handlers::handler::AuditSource;
+    use pretty_assertions::assert_eq;
+
+    fn make_request(actor: &str, operation: &str, success: bool) -> CreateAuditEventRequest {
+        CreateAuditEventRequest {
+            actor: Some(actor.to_owned()),
+            source: AuditSource::GraphQl,
+            ip_address: Some("127.0.0.1".to_owned()),
+            target: Some("user:bob".to_owned()),
+            operation: operation.to_owned(),
+            success,
+            details: None,
+        }
+    }
+
+    fn get_operations(events: Vec<AuditEvent>) -> Vec<String> {
+        events.into_iter().map(|e| e.operation).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_audit_events() {
+        let fixture = TestFixture::new().await;
+        for (actor, operation, success) in [
+            ("admin", "create_user", true),
+            ("Bob", "login", false),
+            ("bob", "login", true),
+            ("admin", "delete_user", true),
+        ] {
+            fixture
+                .handler
+                .record_audit_event(make_request(actor, operation, success))
+                .await
+                .unwrap();
+        }
+        let list = |filter: AuditEventFilter, offset, limit| {
+            let handler = fixture.handler.clone();
+            async move {
+                get_operations(
+                    handler
+                        .list_audit_events(filter, offset, limit)
+                        .await
+                        .unwrap(),
+                )
+            }
+        };
+        assert_eq!(
+            list(AuditEventFilter::default(), 0, 10).await,
+            vec!["delete_user", "login", "login", "create_user"]
+        );
+        assert_eq!(
+            list(AuditEventFilter::default(), 1, 2).await,
+            vec!["login", "login"]
+        );
+        assert_eq!(
+            list(
+                AuditEventFilter {
+                    actor: Some("Bob".to_owned()),
+                    success: Some(false),
+                    ..Default::default()
+                },
+                0,
+                10
+            )
+            .await,
+            vec!["login"]
+        );
+        assert_eq!(
+            list(
+                AuditEventFilter {
+                    source: Some(AuditSource::Ldap),
+                    ..Default::default()
+                },
+                0,
+                10
+            )
+            .await,
+            Vec::<String>::new()
+        );
+        let event = fixture
+            .handler
+            .list_audit_events(AuditEventFilter::default(), 0, 1)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(event.actor.as_deref(), Some("admin"));
+        assert_eq!(event.source, AuditSource::GraphQl);
+        assert_eq!(event.target.as_deref(), Some("user:bob"));
+    }
+}
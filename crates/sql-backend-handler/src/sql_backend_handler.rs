@@ -1,4 +1,4 @@
-use crate::sql_tables::DbConnection;
+use crate::{sql_login_throttle_backend_handler::LoginThrottleOptions, sql_tables::DbConnection};
 use async_trait::async_trait;
 use lldap_auth::opaque::server::ServerSetup;
 use lldap_domain_handlers::handler::BackendHandler;
@@ -7,13 +7,19 @@ use lldap_domain_handlers::handler::BackendHandler;
 pub struct SqlBackendHandler {
     pub(crate) opaque_setup: ServerSetup,
     pub(crate) sql_pool: DbConnection,
+    pub(crate) login_throttle: LoginThrottleOptions,
 }
 
 impl SqlBackendHandler {
-    pub fn new(opaque_setup: ServerSetup, sql_pool: DbConnection) -> Self {
+    pub fn new(
+        opaque_setup: ServerSetup,
+        sql_pool: DbConnection,
+        login_throttle: LoginThrottleOptions,
+    ) -> Self {
         SqlBackendHandler {
             opaque_setup,
             sql_pool,
+            login_throttle,
         }
     }
 
@@ -146,7 +152,11 @@ pub mod tests {
     impl TestFixture {
         pub async fn new() -> Self {
             let sql_pool = get_initialized_db().await;
-            let handler = SqlBackendHandler::new(generate_random_private_key(), sql_pool);
+            let handler = SqlBackendHandler::new(
+                generate_random_private_key(),
+                sql_pool,
+                LoginThrottleOptions::default(),
+            );
             insert_user_no_password(&handler, "bob").await;
             insert_user_no_password(&handler, "patrick").await;
             insert_user_no_password(&handler, "John").await;
@@ -166,7 +176,11 @@ pub mod tests {
     #[tokio::test]
     async fn test_sql_injection() {
         let sql_pool = get_initialized_db().await;
-        let handler = SqlBackendHandler::new(generate_random_private_key(), sql_pool);
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            sql_pool,
+            LoginThrottleOptions::default(),
+        );
         let user_name = UserId::new(r#"bob"e"i'o;aü"#);
         insert_user_no_password(&handler, user_name.as_str()).await;
         {
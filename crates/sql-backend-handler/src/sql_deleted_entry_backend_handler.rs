@@ -0,0 +1,119 @@
+use crate::sql_backend_handler::SqlBackendHandler;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use lldap_domain::types::Uuid;
+use lldap_domain_handlers::handler::{DeletedEntry, DeletedEntryBackendHandler, DeletedEntryKind};
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, DeletedEntriesColumn},
+};
+use sea_orm::{
+    ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, Set,
+    sea_query::OnConflict,
+};
+use tracing::instrument;
+
+impl TryFrom<model::deleted_entries::Model> for DeletedEntry {
+    type Error = DomainError;
+
+    fn try_from(entry: model::deleted_entries::Model) -> Result<Self> {
+        Ok(Self {
+            uuid: entry.uuid,
+            kind: entry.kind.parse().map_err(DomainError::InternalError)?,
+            name: entry.name,
+            deletion_date: entry.deletion_date,
+        })
+    }
+}
+
+/// Keeps a tombstone of the deleted user or group, in the same transaction as the deletion.
+pub(crate) async fn record_deleted_entry(
+    transaction: &DatabaseTransaction,
+    uuid: Uuid,
+    kind: DeletedEntryKind,
+    name: String,
+) -> Result<()> {
+    model::DeletedEntries::insert(model::deleted_entries::ActiveModel {
+        uuid: Set(uuid),
+        kind: Set(kind.as_str().to_owned()),
+        name: Set(name),
+        deletion_date: Set(chrono::Utc::now().naive_utc()),
+    })
+    .on_conflict(
+        OnConflict::column(DeletedEntriesColumn::Uuid)
+            .update_columns([
+                DeletedEntriesColumn::Kind,
+                DeletedEntriesColumn::Name,
+                DeletedEntriesColumn::DeletionDate,
+            ])
+            .to_owned(),
+    )
+    .exec(transaction)
+    .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl DeletedEntryBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_deleted_entries(&self, since: NaiveDateTime) -> Result<Vec<DeletedEntry>> {
+        model::DeletedEntries::find()
+            .filter(DeletedEntriesColumn::DeletionDate.gte(since))
+            .order_by_asc(DeletedEntriesColumn::DeletionDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(DeletedEntry::try_from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::*;
+    use lldap_domain::types::UserId;
+    use lldap_domain_handlers::handler::{GroupBackendHandler, UserBackendHandler};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_deleted_entries_are_recorded() {
+        let fixture = TestFixture::new().await;
+        let before = chrono::Utc::now().naive_utc();
+        let bob = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        let group = fixture
+            .handler
+            .get_group_details(fixture.groups[0])
+            .await
+            .unwrap();
+        fixture.handler.delete_user(&bob.user_id).await.unwrap();
+        fixture
+            .handler
+            .delete_group(fixture.groups[0])
+            .await
+            .unwrap();
+        let entries = fixture.handler.list_deleted_entries(before).await.unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.uuid.clone(), e.kind, e.name.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (bob.uuid, DeletedEntryKind::User, "bob"),
+                (group.uuid, DeletedEntryKind::Group, "Best Group"),
+            ]
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .list_deleted_entries(chrono::Utc::now().naive_utc())
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+}
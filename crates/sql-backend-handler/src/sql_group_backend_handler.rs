@@ -1,23 +1,224 @@
-use crate::sql_backend_handler::SqlBackendHandler;
+use crate::{
+    sql_backend_handler::SqlBackendHandler,
+    sql_deleted_entry_backend_handler::record_deleted_entry,
+    sql_user_backend_handler::get_matching_user_ids,
+};
 use async_trait::async_trait;
 use lldap_access_control::UserReadableBackendHandler;
 use lldap_domain::{
     requests::{CreateGroupRequest, UpdateGroupRequest},
-    types::{AttributeName, Group, GroupDetails, GroupId, Serialized, Uuid},
+    schema::AttributeList,
+    types::{AttributeName, Group, GroupDetails, GroupId, GroupName, Serialized, UserId, Uuid},
 };
-use lldap_domain_handlers::handler::{
-    GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
+use lldap_domain_handlers::{
+    handler::{
+        DeletedEntryKind, GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
+        UserRequestFilter,
+    },
+    user_filter::{parse_user_filter, serialize_user_filter},
 };
 use lldap_domain_model::{
     error::{DomainError, Result},
-    model::{self, GroupColumn, MembershipColumn, deserialize},
+    model::{self, GroupColumn, MembershipColumn, RoleGroupsColumn, deserialize},
 };
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder,
-    QuerySelect, QueryTrait, Set, TransactionTrait,
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
     sea_query::{Alias, Cond, Expr, Func, IntoCondition, OnConflict, SimpleExpr},
 };
-use tracing::instrument;
+use std::collections::{BTreeSet, HashMap};
+use tracing::{instrument, warn};
+
+/// The nesting of the groups in one another.
+#[derive(Debug, Default)]
+pub(crate) struct GroupHierarchy {
+    // From a parent group to its direct subgroups.
+    children: HashMap<GroupId, Vec<GroupId>>,
+    // From a subgroup to the groups it's directly nested in.
+    parents: HashMap<GroupId, Vec<GroupId>>,
+}
+
+impl GroupHierarchy {
+    pub(crate) async fn load(connection: &impl ConnectionTrait) -> Result<Self> {
+        let mut hierarchy = Self::default();
+        for edge in model::GroupMemberships::find().all(connection).await? {
+            hierarchy
+                .children
+                .entry(edge.parent_group_id)
+                .or_default()
+                .push(edge.child_group_id);
+            hierarchy
+                .parents
+                .entry(edge.child_group_id)
+                .or_default()
+                .push(edge.parent_group_id);
+        }
+        Ok(hierarchy)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// The groups that have at least one subgroup.
+    pub(crate) fn parent_group_ids(&self) -> impl Iterator<Item = GroupId> + '_ {
+        self.children.keys().copied()
+    }
+
+    /// The groups nested in the given group, at any depth.
+    pub(crate) fn descendants(&self, group_id: GroupId) -> BTreeSet<GroupId> {
+        Self::reachable(&self.children, group_id)
+    }
+
+    /// The groups the given group is nested in, at any depth.
+    pub(crate) fn ancestors(&self, group_id: GroupId) -> BTreeSet<GroupId> {
+        Self::reachable(&self.parents, group_id)
+    }
+
+    fn reachable(edges: &HashMap<GroupId, Vec<GroupId>>, start: GroupId) -> BTreeSet<GroupId> {
+        let mut visited = BTreeSet::new();
+        let mut to_visit = vec![start];
+        while let Some(group_id) = to_visit.pop() {
+            for next in edges.get(&group_id).into_iter().flatten() {
+                if visited.insert(*next) {
+                    to_visit.push(*next);
+                }
+            }
+        }
+        visited
+    }
+}
+
+pub(crate) fn parse_group_filter(
+    group_id: GroupId,
+    filter: &str,
+    user_attributes: &AttributeList,
+) -> Result<UserRequestFilter> {
+    parse_user_filter(filter, user_attributes).map_err(|e| {
+        DomainError::InternalError(format!(
+            "Invalid filter for the dynamic group {:?}: {}",
+            group_id, e
+        ))
+    })
+}
+
+/// The first attribute of the filter that the users can edit themselves, if any: a dynamic group
+/// on it would let them pick their own groups.
+fn find_user_editable_attribute(
+    filter: &UserRequestFilter,
+    user_attributes: &AttributeList,
+) -> Option<String> {
+    use UserRequestFilter::*;
+    let rec = |f: &UserRequestFilter| find_user_editable_attribute(f, user_attributes);
+    match filter {
+        And(fs) | Or(fs) => fs.iter().find_map(rec),
+        Not(f) => rec(f.as_ref()),
+        Equality(column, _) | SubString(column, _) | HasValue(column) => match column {
+            model::UserColumn::Email | model::UserColumn::LowercaseEmail => Some("mail".to_owned()),
+            model::UserColumn::DisplayName => Some("display_name".to_owned()),
+            _ => None,
+        },
+        AttributeEquality(name, _) | CustomAttributePresent(name) => user_attributes
+            .get_attribute_schema(name)
+            .filter(|schema| schema.is_editable)
+            .map(|_| name.as_str().to_owned()),
+        _ => None,
+    }
+}
+
+/// The groups whose members are computed from a filter, rather than from the memberships.
+#[derive(Debug, Default)]
+pub(crate) struct DynamicGroups {
+    groups: Vec<(model::groups::Model, UserRequestFilter)>,
+}
+
+impl DynamicGroups {
+    /// Loads the dynamic groups, skipping the ones with an invalid filter (e.g. on a deleted
+    /// attribute): they have no members.
+    pub(crate) async fn load(connection: &impl ConnectionTrait) -> Result<Self> {
+        let user_attributes = AttributeList {
+            attributes: SqlBackendHandler::get_user_attributes(connection).await?,
+        };
+        let groups = model::Group::find()
+            .filter(GroupColumn::DynamicFilter.is_not_null())
+            .order_by_asc(GroupColumn::GroupId)
+            .all(connection)
+            .await?
+            .into_iter()
+            .filter_map(|group| {
+                let filter = parse_group_filter(
+                    group.group_id,
+                    group.dynamic_filter.as_deref().unwrap_or_default(),
+                    &user_attributes,
+                )
+                .inspect_err(|e| warn!("Ignoring the dynamic group: {}", e))
+                .ok()?;
+                Some((group, filter))
+            })
+            .collect();
+        Ok(Self { groups })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (GroupDetails, &UserRequestFilter)> + '_ {
+        self.groups
+            .iter()
+            .map(|(group, filter)| (GroupDetails::from(group.clone()), filter))
+    }
+
+    pub(crate) fn get(&self, group_id: GroupId) -> Option<&UserRequestFilter> {
+        self.groups
+            .iter()
+            .find(|(group, _)| group.group_id == group_id)
+            .map(|(_, filter)| filter)
+    }
+
+    pub(crate) fn get_by_name(&self, name: &GroupName) -> Option<&UserRequestFilter> {
+        let name = name.as_str().to_lowercase();
+        self.groups
+            .iter()
+            .find(|(group, _)| group.lowercase_display_name == name)
+            .map(|(_, filter)| filter)
+    }
+}
+
+/// Replaces the member filters with the membership of the group, or of any of the dynamic groups
+/// that the user belongs to.
+fn expand_dynamic_member_filter(
+    filter: GroupRequestFilter,
+    dynamic_memberships: &HashMap<UserId, Vec<GroupId>>,
+) -> GroupRequestFilter {
+    use GroupRequestFilter::*;
+    let rec = |f: GroupRequestFilter| expand_dynamic_member_filter(f, dynamic_memberships);
+    match filter {
+        And(fs) => And(fs.into_iter().map(rec).collect()),
+        Or(fs) => Or(fs.into_iter().map(rec).collect()),
+        Not(f) => Not(Box::new(rec(*f))),
+        Member(user) => match dynamic_memberships.get(&user) {
+            Some(group_ids) => Or(group_ids
+                .iter()
+                .copied()
+                .map(GroupRequestFilter::GroupId)
+                .chain(std::iter::once(Member(user)))
+                .collect()),
+            None => Member(user),
+        },
+        f => f,
+    }
+}
+
+fn collect_member_filters(filter: &GroupRequestFilter, users: &mut Vec<UserId>) {
+    use GroupRequestFilter::*;
+    match filter {
+        And(fs) | Or(fs) => fs.iter().for_each(|f| collect_member_filters(f, users)),
+        Not(f) => collect_member_filters(f, users),
+        Member(user) => users.push(user.clone()),
+        _ => {}
+    }
+}
 
 fn attribute_condition(name: AttributeName, value: Option<Serialized>) -> Cond {
     Expr::in_subquery(
@@ -73,6 +274,7 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
                     .select_only()
                     .column(MembershipColumn::GroupId)
                     .filter(MembershipColumn::UserId.eq(user))
+                    .filter(model::memberships::active_condition())
                     .into_query(),
             )
             .into_condition(),
@@ -91,6 +293,40 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
 impl GroupListerBackendHandler for SqlBackendHandler {
     #[instrument(skip(self), level = "debug", ret, err)]
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
+        let dynamic_groups = DynamicGroups::load(&self.sql_pool).await?;
+        let mut member_filters = Vec::new();
+        if let Some(filters) = &filters {
+            collect_member_filters(filters, &mut member_filters);
+        }
+        let filters = match filters {
+            Some(filters) if !member_filters.is_empty() && !dynamic_groups.is_empty() => {
+                let mut dynamic_memberships = HashMap::<UserId, Vec<GroupId>>::new();
+                for (group, filter) in dynamic_groups.iter() {
+                    let members = get_matching_user_ids(
+                        &self.sql_pool,
+                        UserRequestFilter::And(vec![
+                            UserRequestFilter::Or(
+                                member_filters
+                                    .iter()
+                                    .cloned()
+                                    .map(UserRequestFilter::UserId)
+                                    .collect(),
+                            ),
+                            filter.clone(),
+                        ]),
+                    )
+                    .await?;
+                    for user_id in members {
+                        dynamic_memberships
+                            .entry(user_id)
+                            .or_default()
+                            .push(group.group_id);
+                    }
+                }
+                Some(expand_dynamic_member_filter(filters, &dynamic_memberships))
+            }
+            filters => filters,
+        };
         let filters = filters
             .map(|f| {
                 GroupColumn::GroupId
@@ -111,10 +347,15 @@ impl GroupListerBackendHandler for SqlBackendHandler {
             .filter(filters.clone())
             .all(&self.sql_pool)
             .await?;
+        let now = chrono::Utc::now().naive_utc();
         let mut groups: Vec<_> = results
             .into_iter()
             .map(|(group, users)| {
-                let users: Vec<_> = users.into_iter().map(|u| u.user_id).collect();
+                let users: Vec<_> = users
+                    .into_iter()
+                    .filter(|m| m.is_active(now))
+                    .map(|m| m.user_id)
+                    .collect();
                 Group {
                     users,
                     ..group.into()
@@ -151,6 +392,35 @@ impl GroupListerBackendHandler for SqlBackendHandler {
                 })
                 .collect::<Result<Vec<_>>>()?;
         }
+        for (group, filter) in dynamic_groups.iter() {
+            if let Some(group) = groups.iter_mut().find(|g| g.id == group.group_id) {
+                group.users = get_matching_user_ids(&self.sql_pool, filter.clone())
+                    .await?
+                    .into_iter()
+                    .collect();
+                group.users.sort();
+            }
+        }
+        let hierarchy = GroupHierarchy::load(&self.sql_pool).await?;
+        if !hierarchy.is_empty() {
+            let subgroup_names = model::Group::find()
+                .filter(GroupColumn::GroupId.is_in(hierarchy.parents.keys().copied()))
+                .all(&self.sql_pool)
+                .await?
+                .into_iter()
+                .map(|g| (g.group_id, g.display_name))
+                .collect::<HashMap<_, _>>();
+            for group in groups.iter_mut() {
+                group.subgroups = hierarchy
+                    .children
+                    .get(&group.id)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|id| subgroup_names.get(id).cloned())
+                    .collect();
+                group.subgroups.sort();
+            }
+        }
         groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
         Ok(groups)
     }
@@ -196,6 +466,137 @@ impl GroupBackendHandler for SqlBackendHandler {
             .await?)
     }
 
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn get_group_filter(&self, group_id: GroupId) -> Result<Option<UserRequestFilter>> {
+        let Some(filter) = model::Group::find_by_id(group_id)
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("{:?}", group_id)))?
+            .dynamic_filter
+        else {
+            return Ok(None);
+        };
+        let user_attributes = AttributeList {
+            attributes: Self::get_user_attributes(&self.sql_pool).await?,
+        };
+        parse_group_filter(group_id, &filter, &user_attributes).map(Some)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_group_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()> {
+        let dynamic_filter = filter.as_ref().map(serialize_user_filter);
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    if let Some(dynamic_filter) = &dynamic_filter {
+                        let user_attributes = AttributeList {
+                            attributes: Self::get_user_attributes(transaction).await?,
+                        };
+                        // Only store the filters that can be read back.
+                        let filter = parse_user_filter(dynamic_filter, &user_attributes)
+                            .map_err(|e| {
+                                DomainError::ValidationError(format!(
+                                    "Invalid group filter: {}",
+                                    e
+                                ))
+                            })?;
+                        if let Some(attribute) =
+                            find_user_editable_attribute(&filter, &user_attributes)
+                        {
+                            return Err(DomainError::ValidationError(format!(
+                                "The users can edit their own \"{}\", it can't be used in a group filter",
+                                attribute
+                            )));
+                        }
+                    }
+                    // The members of a dynamic group can change their own attributes: the group
+                    // must not grant any permission.
+                    if dynamic_filter.is_some() {
+                        let hierarchy = GroupHierarchy::load(transaction).await?;
+                        if Self::group_grants_permissions(transaction, &hierarchy, group_id).await?
+                        {
+                            return Err(DomainError::ValidationError(format!(
+                                "The group {:?} grants permissions, it can't be dynamic",
+                                group_id
+                            )));
+                        }
+                    }
+                    let now = chrono::Utc::now().naive_utc();
+                    let res = model::Group::update_many()
+                        .col_expr(GroupColumn::DynamicFilter, Expr::value(dynamic_filter))
+                        .col_expr(GroupColumn::ModifiedDate, Expr::value(now))
+                        .filter(GroupColumn::GroupId.eq(group_id))
+                        .exec(transaction)
+                        .await?;
+                    if res.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such group: '{:?}'",
+                            group_id
+                        )));
+                    }
+                    // The members are now computed from the filter: the static members lose the
+                    // group.
+                    model::User::update_many()
+                        .col_expr(model::UserColumn::ModifiedDate, Expr::value(now))
+                        .filter(
+                            model::UserColumn::UserId.in_subquery(
+                                model::Membership::find()
+                                    .select_only()
+                                    .column(MembershipColumn::UserId)
+                                    .filter(MembershipColumn::GroupId.eq(group_id))
+                                    .into_query(),
+                            ),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    model::Membership::delete_many()
+                        .filter(MembershipColumn::GroupId.eq(group_id))
+                        .exec(transaction)
+                        .await?;
+                    Ok(())
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn list_membership_expiry_dates(
+        &self,
+        group_id: GroupId,
+    ) -> Result<Vec<(UserId, chrono::NaiveDateTime)>> {
+        Ok(model::Membership::find()
+            .filter(MembershipColumn::GroupId.eq(group_id))
+            .filter(MembershipColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .order_by_asc(MembershipColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .filter_map(|m| Some((m.user_id, m.expiry_date?)))
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn list_expired_memberships(
+        &self,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<(UserId, GroupName)>> {
+        Ok(model::Membership::find()
+            .find_also_related(model::Group)
+            .filter(MembershipColumn::ExpiryDate.gte(since))
+            .filter(MembershipColumn::ExpiryDate.lte(chrono::Utc::now().naive_utc()))
+            .order_by_asc(MembershipColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .filter_map(|(m, g)| Some((m.user_id, g?.display_name)))
+            .collect())
+    }
+
     #[instrument(skip(self), level = "debug", ret, err)]
     async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId> {
         let now = chrono::Utc::now().naive_utc();
@@ -205,6 +606,7 @@ impl GroupBackendHandler for SqlBackendHandler {
             display_name: Set(request.display_name),
             lowercase_display_name: Set(lower_display_name),
             creation_date: Set(now),
+            modified_date: Set(now),
             uuid: Set(uuid),
             ..Default::default()
         };
@@ -247,20 +649,227 @@ impl GroupBackendHandler for SqlBackendHandler {
 
     #[instrument(skip(self), level = "debug", err)]
     async fn delete_group(&self, group_id: GroupId) -> Result<()> {
-        let res = model::Group::delete_by_id(group_id)
-            .exec(&self.sql_pool)
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let group = model::Group::find_by_id(group_id)
+                        .one(transaction)
+                        .await?
+                        .ok_or_else(|| {
+                            DomainError::EntityNotFound(format!("No such group: '{:?}'", group_id))
+                        })?;
+                    // The members lose a group.
+                    model::User::update_many()
+                        .col_expr(
+                            model::UserColumn::ModifiedDate,
+                            Expr::value(chrono::Utc::now().naive_utc()),
+                        )
+                        .filter(
+                            model::UserColumn::UserId.in_subquery(
+                                model::Membership::find()
+                                    .select_only()
+                                    .column(MembershipColumn::UserId)
+                                    .filter(MembershipColumn::GroupId.eq(group_id))
+                                    .into_query(),
+                            ),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    model::Group::delete_by_id(group_id)
+                        .exec(transaction)
+                        .await?;
+                    record_deleted_entry(
+                        transaction,
+                        group.uuid,
+                        DeletedEntryKind::Group,
+                        group.display_name.into_string(),
+                    )
+                    .await
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn add_group_to_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()> {
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let hierarchy = GroupHierarchy::load(transaction).await?;
+                    if parent_group_id == child_group_id
+                        || hierarchy
+                            .descendants(child_group_id)
+                            .contains(&parent_group_id)
+                    {
+                        return Err(DomainError::InternalError(format!(
+                            "Adding the group {:?} to the group {:?} would create a cycle",
+                            child_group_id, parent_group_id
+                        )));
+                    }
+                    if Self::group_grants_permissions(transaction, &hierarchy, parent_group_id)
+                        .await?
+                        && Self::contains_dynamic_group(transaction, &hierarchy, child_group_id)
+                            .await?
+                    {
+                        return Err(DomainError::ValidationError(format!(
+                            "The group {:?} grants permissions, it can't contain the dynamic group {:?}",
+                            parent_group_id, child_group_id
+                        )));
+                    }
+                    model::group_memberships::ActiveModel {
+                        parent_group_id: Set(parent_group_id),
+                        child_group_id: Set(child_group_id),
+                    }
+                    .insert(transaction)
+                    .await?;
+                    Self::touch_group_nesting(
+                        transaction,
+                        &hierarchy,
+                        parent_group_id,
+                        child_group_id,
+                    )
+                    .await
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn remove_group_from_group(
+        &self,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()> {
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let res =
+                        model::GroupMemberships::delete_by_id((parent_group_id, child_group_id))
+                            .exec(transaction)
+                            .await?;
+                    if res.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such group membership: {:?} -> {:?}",
+                            child_group_id, parent_group_id
+                        )));
+                    }
+                    let hierarchy = GroupHierarchy::load(transaction).await?;
+                    Self::touch_group_nesting(
+                        transaction,
+                        &hierarchy,
+                        parent_group_id,
+                        child_group_id,
+                    )
+                    .await
+                })
+            })
             .await?;
-        if res.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such group: '{:?}'",
-                group_id
-            )));
-        }
         Ok(())
     }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn get_ancestor_groups(&self, group_id: GroupId) -> Result<Vec<GroupDetails>> {
+        let ancestors = GroupHierarchy::load(&self.sql_pool)
+            .await?
+            .ancestors(group_id);
+        if ancestors.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(ancestors))
+            .order_by_asc(GroupColumn::GroupId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
 }
 
 impl SqlBackendHandler {
+    /// Bumps the modification date of the parent group, and of the users whose inherited groups
+    /// changed, after a subgroup is added or removed.
+    async fn touch_group_nesting(
+        transaction: &DatabaseTransaction,
+        hierarchy: &GroupHierarchy,
+        parent_group_id: GroupId,
+        child_group_id: GroupId,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().naive_utc();
+        model::Group::update_many()
+            .col_expr(GroupColumn::ModifiedDate, Expr::value(now))
+            .filter(GroupColumn::GroupId.eq(parent_group_id))
+            .exec(transaction)
+            .await?;
+        let mut nested_groups = hierarchy.descendants(child_group_id);
+        nested_groups.insert(child_group_id);
+        model::User::update_many()
+            .col_expr(model::UserColumn::ModifiedDate, Expr::value(now))
+            .filter(
+                model::UserColumn::UserId.in_subquery(
+                    model::Membership::find()
+                        .select_only()
+                        .column(MembershipColumn::UserId)
+                        .filter(MembershipColumn::GroupId.is_in(nested_groups))
+                        .into_query(),
+                ),
+            )
+            .exec(transaction)
+            .await?;
+        Ok(())
+    }
+
+    /// Whether the members of the group get permissions, from a built-in group or from a role,
+    /// either directly or through the groups it's nested in.
+    async fn group_grants_permissions(
+        connection: &impl ConnectionTrait,
+        hierarchy: &GroupHierarchy,
+        group_id: GroupId,
+    ) -> Result<bool> {
+        let mut groups = hierarchy.ancestors(group_id);
+        groups.insert(group_id);
+        if model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(groups.iter().copied()))
+            .filter(GroupColumn::LowercaseDisplayName.is_in([
+                "lldap_admin",
+                "lldap_password_manager",
+                "lldap_strict_readonly",
+            ]))
+            .one(connection)
+            .await?
+            .is_some()
+        {
+            return Ok(true);
+        }
+        Ok(model::RoleGroups::find()
+            .filter(RoleGroupsColumn::GroupId.is_in(groups))
+            .one(connection)
+            .await?
+            .is_some())
+    }
+
+    /// Whether the group, or one of the groups nested in it, is dynamic.
+    pub(crate) async fn contains_dynamic_group(
+        connection: &impl ConnectionTrait,
+        hierarchy: &GroupHierarchy,
+        group_id: GroupId,
+    ) -> Result<bool> {
+        let mut groups = hierarchy.descendants(group_id);
+        groups.insert(group_id);
+        Ok(model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(groups))
+            .filter(GroupColumn::DynamicFilter.is_not_null())
+            .one(connection)
+            .await?
+            .is_some())
+    }
+
     async fn update_group_with_transaction(
         request: UpdateGroupRequest,
         transaction: &DatabaseTransaction,
@@ -273,6 +882,7 @@ impl SqlBackendHandler {
             group_id: Set(request.group_id),
             display_name: request.display_name.map(Set).unwrap_or_default(),
             lowercase_display_name: lower_display_name.map(Set).unwrap_or_default(),
+            modified_date: Set(chrono::Utc::now().naive_utc()),
             ..Default::default()
         };
         update_group.update(transaction).await?;
@@ -339,11 +949,15 @@ impl SqlBackendHandler {
 mod tests {
     use super::*;
     use crate::sql_backend_handler::tests::*;
+    use lldap_auth::access_control::RolePermission;
     use lldap_domain::{
         requests::CreateAttributeRequest,
         types::{Attribute, AttributeType, GroupName, UserId},
     };
-    use lldap_domain_handlers::handler::{SchemaBackendHandler, SubStringFilter};
+    use lldap_domain_handlers::handler::{
+        RoleBackendHandler, SchemaBackendHandler, SubStringFilter, UserBackendHandler,
+        UserListerBackendHandler,
+    };
     use pretty_assertions::assert_eq;
 
     async fn get_group_ids(
@@ -549,6 +1163,321 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_nested_groups() {
+        let fixture = TestFixture::new().await;
+        // Best Group > Worst Group > Empty Group.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[0], fixture.groups[1])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[1], fixture.groups[2])
+            .await
+            .unwrap();
+        let subgroups = fixture
+            .handler
+            .list_groups(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| (g.display_name, g.subgroups))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subgroups,
+            vec![
+                ("Best Group".into(), vec!["Worst Group".into()]),
+                ("Empty Group".into(), vec![]),
+                ("Worst Group".into(), vec!["Empty Group".into()]),
+            ]
+        );
+        let ancestors = fixture
+            .handler
+            .get_ancestor_groups(fixture.groups[2])
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.display_name)
+            .collect::<Vec<_>>();
+        assert_eq!(ancestors, vec!["Best Group".into(), "Worst Group".into()]);
+        assert!(
+            fixture
+                .handler
+                .get_ancestor_groups(fixture.groups[0])
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        // Cycles are refused.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[2], fixture.groups[0])
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[1], fixture.groups[1])
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .remove_group_from_group(fixture.groups[0], fixture.groups[1])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .remove_group_from_group(fixture.groups[0], fixture.groups[1])
+            .await
+            .unwrap_err();
+        // Without the link, it's not a cycle anymore.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[2], fixture.groups[0])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_group() {
+        let fixture = TestFixture::new().await;
+        let filter =
+            UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new("bob"))));
+        fixture
+            .handler
+            .set_group_filter(fixture.groups[0], Some(filter.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_group_filter(fixture.groups[0])
+                .await
+                .unwrap(),
+            Some(filter)
+        );
+        assert_eq!(
+            model::Group::find_by_id(fixture.groups[0])
+                .one(&fixture.handler.sql_pool)
+                .await
+                .unwrap()
+                .unwrap()
+                .dynamic_filter
+                .as_deref(),
+            Some("(!(user_id=bob))")
+        );
+        let get_members = async || {
+            fixture
+                .handler
+                .list_groups(Some(GroupRequestFilter::GroupId(fixture.groups[0])))
+                .await
+                .unwrap()
+                .remove(0)
+                .users
+        };
+        assert_eq!(
+            get_members().await,
+            vec![
+                UserId::new("john"),
+                UserId::new("nogroup"),
+                UserId::new("patrick")
+            ]
+        );
+        assert_eq!(
+            get_group_ids(
+                &fixture.handler,
+                Some(GroupRequestFilter::Member(UserId::new("John")))
+            )
+            .await,
+            vec![fixture.groups[0], fixture.groups[1]]
+        );
+        // The members can't be edited manually.
+        assert!(matches!(
+            fixture
+                .handler
+                .add_user_to_group(&UserId::new("bob"), fixture.groups[0])
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+        // Back to a static group, without members.
+        fixture
+            .handler
+            .set_group_filter(fixture.groups[0], None)
+            .await
+            .unwrap();
+        assert_eq!(get_members().await, Vec::<UserId>::new());
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_group_invalid_filter() {
+        let fixture = TestFixture::new().await;
+        model::Group::update_many()
+            .col_expr(
+                GroupColumn::DynamicFilter,
+                Expr::value("(unknown_attribute=value)"),
+            )
+            .filter(GroupColumn::GroupId.eq(fixture.groups[2]))
+            .exec(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        // The group is ignored, rather than breaking the listings.
+        let group = fixture
+            .handler
+            .list_groups(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|g| g.id == fixture.groups[2])
+            .unwrap();
+        assert_eq!(group.users, Vec::<UserId>::new());
+        assert!(
+            fixture
+                .handler
+                .list_users(Some(UserRequestFilter::MemberOfId(fixture.groups[2])), true)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        fixture
+            .handler
+            .get_group_filter(fixture.groups[2])
+            .await
+            .unwrap_err();
+        // Filters on unknown attributes are refused.
+        assert!(matches!(
+            fixture
+                .handler
+                .set_group_filter(
+                    fixture.groups[1],
+                    Some(UserRequestFilter::CustomAttributePresent(
+                        "unknown_attribute".into()
+                    ))
+                )
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_group_user_editable_attribute() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .add_user_attribute(CreateAttributeRequest {
+                name: "department".into(),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_visible: true,
+                is_editable: false,
+            })
+            .await
+            .unwrap();
+        // The users could join these groups by editing their own attributes.
+        for filter in [
+            UserRequestFilter::Equality(model::UserColumn::Email, "bob@bob.bob".to_owned()),
+            UserRequestFilter::Or(vec![
+                UserRequestFilter::UserId(UserId::new("bob")),
+                UserRequestFilter::Not(Box::new(UserRequestFilter::HasValue(
+                    model::UserColumn::DisplayName,
+                ))),
+            ]),
+            UserRequestFilter::AttributeEquality(
+                "first_name".into(),
+                "first bob".to_string().into(),
+            ),
+        ] {
+            assert!(matches!(
+                fixture
+                    .handler
+                    .set_group_filter(fixture.groups[2], Some(filter))
+                    .await,
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+        fixture
+            .handler
+            .set_group_filter(
+                fixture.groups[2],
+                Some(UserRequestFilter::AttributeEquality(
+                    "department".into(),
+                    "Sales".to_string().into(),
+                )),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_group_cannot_grant_permissions() {
+        let fixture = TestFixture::new().await;
+        let filter = UserRequestFilter::UserId(UserId::new("bob"));
+        let admin_group = insert_group(&fixture.handler, "lldap_admin").await;
+        assert!(matches!(
+            fixture
+                .handler
+                .set_group_filter(admin_group, Some(filter.clone()))
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+        fixture
+            .handler
+            .set_role("helpdesk", vec![RolePermission::ResetPasswords])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_role_to_group("helpdesk", fixture.groups[1])
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .set_group_filter(fixture.groups[1], Some(filter.clone()))
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+        // Nor can the groups nested in them.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[1], fixture.groups[2])
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .set_group_filter(fixture.groups[2], Some(filter.clone()))
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+        // A dynamic group can't be nested in them, or get a role.
+        fixture
+            .handler
+            .set_group_filter(fixture.groups[0], Some(filter))
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .add_group_to_group(admin_group, fixture.groups[0])
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+        assert!(matches!(
+            fixture
+                .handler
+                .add_role_to_group("helpdesk", fixture.groups[0])
+                .await,
+            Err(DomainError::ValidationError(_))
+        ));
+        // Removing the filter is always allowed.
+        fixture
+            .handler
+            .set_group_filter(fixture.groups[2], None)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_create_group() {
         let fixture = TestFixture::new().await;
@@ -0,0 +1,178 @@
+use crate::sql_backend_handler::SqlBackendHandler;
+use async_trait::async_trait;
+use lldap_domain::types::{GroupId, UserId};
+use lldap_domain_handlers::handler::GroupOwnerBackendHandler;
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, GroupOwnersColumn},
+};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use tracing::instrument;
+
+#[async_trait]
+impl GroupOwnerBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_group_owners(&self, group_id: GroupId) -> Result<Vec<UserId>> {
+        Ok(model::GroupOwners::find()
+            .filter(GroupOwnersColumn::GroupId.eq(group_id))
+            .order_by_asc(GroupOwnersColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|owner| owner.user_id)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn get_owned_groups(&self, user_id: &UserId) -> Result<Vec<GroupId>> {
+        Ok(model::GroupOwners::find()
+            .filter(GroupOwnersColumn::UserId.eq(user_id))
+            .order_by_asc(GroupOwnersColumn::GroupId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|owner| owner.group_id)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn add_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        if model::GroupOwners::find_by_id((group_id, user_id.clone()))
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+        model::group_owners::ActiveModel {
+            group_id: Set(group_id),
+            user_id: Set(user_id.clone()),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn remove_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        let res = model::GroupOwners::delete_by_id((group_id, user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "'{}' is not an owner of the group '{}'",
+                user_id, group_id.0
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::*;
+    use lldap_domain_handlers::handler::{GroupBackendHandler, UserBackendHandler};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_add_and_list_group_owners() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[0], &bob)
+            .await
+            .unwrap();
+        // Adding it twice is a no-op.
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[0], &bob)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[2], &bob)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[0], &UserId::new("John"))
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_group_owners(fixture.groups[0])
+                .await
+                .unwrap(),
+            vec![bob.clone(), UserId::new("john")]
+        );
+        assert_eq!(
+            fixture.handler.get_owned_groups(&bob).await.unwrap(),
+            vec![fixture.groups[0], fixture.groups[2]]
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .get_owned_groups(&UserId::new("patrick"))
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_group_owner() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[0], &bob)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .remove_group_owner(fixture.groups[0], &bob)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .remove_group_owner(fixture.groups[0], &bob)
+            .await
+            .unwrap_err();
+        assert_eq!(fixture.handler.get_owned_groups(&bob).await.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_group_owners_deleted_with_group_and_user() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        let patrick = UserId::new("patrick");
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[0], &bob)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_group_owner(fixture.groups[1], &patrick)
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .delete_group(fixture.groups[0])
+            .await
+            .unwrap();
+        fixture.handler.delete_user(&patrick).await.unwrap();
+        assert_eq!(fixture.handler.get_owned_groups(&bob).await.unwrap(), vec![]);
+        assert_eq!(
+            fixture
+                .handler
+                .list_group_owners(fixture.groups[1])
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::sql_backend_handler::SqlBackendHandler;
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime};
+use lldap_domain_handlers::handler::{LoginThrottleBackendHandler, LoginThrottleSubject};
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, LoginFailuresColumn},
+};
+use sea_orm::{
+    ColumnTrait, EntityTrait, QueryFilter, Set, TransactionTrait,
+    sea_query::{Expr, OnConflict},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+/// How failed login attempts are throttled, per user and per source IP address.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LoginThrottleOptions {
+    pub enabled: bool,
+    /// Number of failed attempts allowed before the next ones are delayed.
+    pub free_attempts: u32,
+    /// Delay after the first attempt past the free ones. It doubles with each failed attempt.
+    pub initial_delay_seconds: u32,
+    /// Number of failed attempts after which the logins are refused for `lockout_seconds`.
+    pub lockout_threshold: u32,
+    /// Duration of the lockout, which is also the maximum delay. The failed attempts are
+    /// forgotten after that long without a new one.
+    pub lockout_seconds: u32,
+}
+
+impl Default for LoginThrottleOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            free_attempts: 5,
+            initial_delay_seconds: 1,
+            lockout_threshold: 20,
+            lockout_seconds: 900,
+        }
+    }
+}
+
+impl LoginThrottleOptions {
+    /// How long the logins are refused after the given number of consecutive failures.
+    fn get_delay(&self, failure_count: u32) -> Option<Duration> {
+        let max_delay = Duration::seconds(self.lockout_seconds.into());
+        if failure_count >= self.lockout_threshold {
+            Some(max_delay)
+        } else if failure_count > self.free_attempts {
+            let doublings = (failure_count - self.free_attempts - 1).min(31);
+            let delay = i64::from(self.initial_delay_seconds).saturating_mul(1i64 << doublings);
+            Some(Duration::seconds(delay).min(max_delay))
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl LoginThrottleBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err, fields(subject = %subject))]
+    async fn get_login_lockout(
+        &self,
+        subject: &LoginThrottleSubject,
+    ) -> Result<Option<NaiveDateTime>> {
+        if !self.login_throttle.enabled {
+            return Ok(None);
+        }
+        let now = chrono::Utc::now().naive_utc();
+        Ok(model::LoginFailures::find_by_id(subject.to_string())
+            .one(&self.sql_pool)
+            .await?
+            .and_then(|failures| failures.locked_until)
+            .filter(|locked_until| *locked_until > now))
+    }
+
+    #[instrument(skip_all, level = "debug", err, fields(subject = %subject))]
+    async fn register_login_failure(&self, subject: &LoginThrottleSubject) -> Result<()> {
+        if !self.login_throttle.enabled {
+            return Ok(());
+        }
+        let subject = subject.to_string();
+        let options = self.login_throttle.clone();
+        Ok(self
+            .sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let now = chrono::Utc::now().naive_utc();
+                    // The failures older than the lockout are forgotten: start counting again.
+                    model::LoginFailures::delete_many()
+                        .filter(LoginFailuresColumn::Subject.eq(subject.as_str()))
+                        .filter(
+                            LoginFailuresColumn::LastFailureDate
+                                .lte(now - Duration::seconds(options.lockout_seconds.into())),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    // Incremented in the database, so that concurrent failures are all counted.
+                    model::LoginFailures::insert(model::login_failures::ActiveModel {
+                        subject: Set(subject.clone()),
+                        failure_count: Set(1),
+                        last_failure_date: Set(now),
+                        locked_until: Set(None),
+                    })
+                    .on_conflict(
+                        OnConflict::column(LoginFailuresColumn::Subject)
+                            .value(
+                                LoginFailuresColumn::FailureCount,
+                                Expr::col((
+                                    model::LoginFailures,
+                                    LoginFailuresColumn::FailureCount,
+                                ))
+                                .add(1),
+                            )
+                            .update_column(LoginFailuresColumn::LastFailureDate)
+                            .to_owned(),
+                    )
+                    .exec(transaction)
+                    .await?;
+                    let failure_count = model::LoginFailures::find_by_id(subject.clone())
+                        .one(transaction)
+                        .await?
+                        .map(|failures| failures.failure_count)
+                        .unwrap_or(1);
+                    let locked_until = options
+                        .get_delay(failure_count.try_into().unwrap_or_default())
+                        .map(|delay| now + delay);
+                    if locked_until.is_some() {
+                        warn!(
+                            r#"Too many failed login attempts for "{}", locked out until {:?}"#,
+                            subject, locked_until
+                        );
+                    }
+                    model::LoginFailures::update_many()
+                        .col_expr(LoginFailuresColumn::LockedUntil, Expr::value(locked_until))
+                        .filter(LoginFailuresColumn::Subject.eq(subject.as_str()))
+                        .exec(transaction)
+                        .await?;
+                    Ok(())
+                })
+            })
+            .await?)
+    }
+
+    #[instrument(skip_all, level = "debug", err, fields(subject = %subject))]
+    async fn clear_login_failures(&self, subject: &LoginThrottleSubject) -> Result<()> {
+        model::LoginFailures::delete_by_id(subject.to_string())
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::get_initialized_db;
+    use lldap_auth::opaque::server::generate_random_private_key;
+    use lldap_domain::types::UserId;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_get_delay() {
+        let options = LoginThrottleOptions {
+            free_attempts: 2,
+            initial_delay_seconds: 10,
+            lockout_threshold: 6,
+            lockout_seconds: 60,
+            ..Default::default()
+        };
+        assert_eq!(options.get_delay(2), None);
+        assert_eq!(options.get_delay(3), Some(Duration::seconds(10)));
+        assert_eq!(options.get_delay(4), Some(Duration::seconds(20)));
+        assert_eq!(options.get_delay(5), Some(Duration::seconds(40)));
+        assert_eq!(options.get_delay(6), Some(Duration::seconds(60)));
+        assert_eq!(options.get_delay(1000), Some(Duration::seconds(60)));
+    }
+
+    #[tokio::test]
+    async fn test_lockout() {
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            get_initialized_db().await,
+            LoginThrottleOptions {
+                free_attempts: 1,
+                ..Default::default()
+            },
+        );
+        let bob = LoginThrottleSubject::User(UserId::new("bob"));
+        let ip = LoginThrottleSubject::Ip("127.0.0.1".parse().unwrap());
+        handler.register_login_failure(&bob).await.unwrap();
+        assert_eq!(handler.get_login_lockout(&bob).await.unwrap(), None);
+        handler.register_login_failure(&bob).await.unwrap();
+        assert!(handler.get_login_lockout(&bob).await.unwrap().is_some());
+        assert_eq!(handler.get_login_lockout(&ip).await.unwrap(), None);
+        handler.clear_login_failures(&bob).await.unwrap();
+        assert_eq!(handler.get_login_lockout(&bob).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_failure_count() {
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            get_initialized_db().await,
+            LoginThrottleOptions {
+                free_attempts: 3,
+                ..Default::default()
+            },
+        );
+        let bob = LoginThrottleSubject::User(UserId::new("bob"));
+        let get_failure_count = async || {
+            model::LoginFailures::find_by_id(bob.to_string())
+                .one(&handler.sql_pool)
+                .await
+                .unwrap()
+                .unwrap()
+                .failure_count
+        };
+        // Concurrent failures are all counted.
+        let results = tokio::join!(
+            handler.register_login_failure(&bob),
+            handler.register_login_failure(&bob),
+            handler.register_login_failure(&bob),
+            handler.register_login_failure(&bob),
+        );
+        results.0.unwrap();
+        results.1.unwrap();
+        results.2.unwrap();
+        results.3.unwrap();
+        assert_eq!(get_failure_count().await, 4);
+        assert!(handler.get_login_lockout(&bob).await.unwrap().is_some());
+        // The failures older than the lockout are forgotten.
+        model::LoginFailures::update_many()
+            .col_expr(
+                LoginFailuresColumn::LastFailureDate,
+                Expr::value(chrono::Utc::now().naive_utc() - Duration::days(1)),
+            )
+            .exec(&handler.sql_pool)
+            .await
+            .unwrap();
+        handler.register_login_failure(&bob).await.unwrap();
+        assert_eq!(get_failure_count().await, 1);
+        assert_eq!(handler.get_login_lockout(&bob).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            get_initialized_db().await,
+            LoginThrottleOptions {
+                enabled: false,
+                free_attempts: 0,
+                ..Default::default()
+            },
+        );
+        let bob = LoginThrottleSubject::User(UserId::new("bob"));
+        handler.register_login_failure(&bob).await.unwrap();
+        assert_eq!(handler.get_login_lockout(&bob).await.unwrap(), None);
+    }
+}
@@ -26,17 +26,25 @@ pub enum Users {
     PasswordHash,
     TotpSecret,
     MfaType,
+    TotpLastCounter,
     Uuid,
+    Disabled,
+    ExpiryDate,
+    LastLogin,
+    PasswordChanged,
+    ModifiedDate,
 }
 
 #[derive(DeriveIden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
-pub(crate) enum Groups {
+pub enum Groups {
     Table,
     GroupId,
     DisplayName,
     LowercaseDisplayName,
     CreationDate,
     Uuid,
+    ModifiedDate,
+    DynamicFilter,
 }
 
 #[derive(DeriveIden, Clone, Copy)]
@@ -44,6 +52,7 @@ pub(crate) enum Memberships {
     Table,
     UserId,
     GroupId,
+    ExpiryDate,
 }
 
 #[allow(clippy::enum_variant_names)] // The table names are generated from the enum.
@@ -100,6 +109,78 @@ pub(crate) enum GroupObjectClasses {
     ObjectClass,
 }
 
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum ApiTokens {
+    Table,
+    TokenId,
+    TokenHash,
+    UserId,
+    Name,
+    Scope,
+    CreationDate,
+    ExpiryDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum LoginFailures {
+    Table,
+    Subject,
+    FailureCount,
+    LastFailureDate,
+    LockedUntil,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum Roles {
+    Table,
+    RoleName,
+    Permissions,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum RoleGroups {
+    Table,
+    RoleName,
+    GroupId,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum GroupOwners {
+    Table,
+    GroupId,
+    UserId,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum AuditLog {
+    Table,
+    EventId,
+    Date,
+    Actor,
+    Source,
+    IpAddress,
+    Target,
+    Operation,
+    Success,
+    Details,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum DeletedEntries {
+    Table,
+    Uuid,
+    Kind,
+    Name,
+    DeletionDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub(crate) enum GroupMemberships {
+    Table,
+    ParentGroupId,
+    ChildGroupId,
+}
+
 // Metadata about the SQL DB.
 #[derive(DeriveIden)]
 pub(crate) enum Metadata {
@@ -1112,6 +1193,459 @@ async fn migrate_to_v10(transaction: DatabaseTransaction) -> Result<DatabaseTran
     Ok(transaction)
 }
 
+async fn migrate_to_v11(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(ApiTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiTokens::TokenId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ApiTokens::TokenHash)
+                            .string_len(255)
+                            .unique_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiTokens::UserId).string_len(255).not_null())
+                    .col(ColumnDef::new(ApiTokens::Name).string_len(255).not_null())
+                    .col(ColumnDef::new(ApiTokens::Scope).string_len(64).not_null())
+                    .col(
+                        ColumnDef::new(ApiTokens::CreationDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiTokens::ExpiryDate).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("ApiTokensUserForeignKey")
+                            .from(ApiTokens::Table, ApiTokens::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v12(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(LoginFailures::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LoginFailures::Subject)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(LoginFailures::FailureCount)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LoginFailures::LastFailureDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LoginFailures::LockedUntil).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v13(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::Disabled)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::ExpiryDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v14(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(Roles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Roles::RoleName)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Roles::Permissions).text().not_null()),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(RoleGroups::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RoleGroups::RoleName)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RoleGroups::GroupId).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(RoleGroups::RoleName)
+                            .col(RoleGroups::GroupId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("RoleGroupsRoleForeignKey")
+                            .from(RoleGroups::Table, RoleGroups::RoleName)
+                            .to(Roles::Table, Roles::RoleName)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("RoleGroupsGroupForeignKey")
+                            .from(RoleGroups::Table, RoleGroups::GroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v15(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(GroupOwners::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(GroupOwners::GroupId).integer().not_null())
+                    .col(
+                        ColumnDef::new(GroupOwners::UserId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(GroupOwners::GroupId)
+                            .col(GroupOwners::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupOwnersGroupForeignKey")
+                            .from(GroupOwners::Table, GroupOwners::GroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupOwnersUserForeignKey")
+                            .from(GroupOwners::Table, GroupOwners::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v16(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::EventId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Date).date_time().not_null())
+                    .col(ColumnDef::new(AuditLog::Actor).string_len(255))
+                    .col(ColumnDef::new(AuditLog::Source).string_len(64).not_null())
+                    .col(ColumnDef::new(AuditLog::IpAddress).string_len(64))
+                    .col(ColumnDef::new(AuditLog::Target).string_len(255))
+                    .col(
+                        ColumnDef::new(AuditLog::Operation)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Success).boolean().not_null())
+                    .col(ColumnDef::new(AuditLog::Details).text()),
+            ),
+        )
+        .await?;
+    // For the retention and the listing, which go by date.
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("audit-log-date")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::Date),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v17(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::LastLogin).date_time()),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::PasswordChanged).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v18(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    let now = chrono::Utc::now().naive_utc();
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::ModifiedDate)
+                        .date_time()
+                        .not_null()
+                        .default(now),
+                ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Groups::Table).add_column(
+                    ColumnDef::new(Groups::ModifiedDate)
+                        .date_time()
+                        .not_null()
+                        .default(now),
+                ),
+            ),
+        )
+        .await?;
+    // Until now, the entries were never modified.
+    transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(Users::Table)
+                    .value(Users::ModifiedDate, Expr::col(Users::CreationDate)),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(Groups::Table)
+                    .value(Groups::ModifiedDate, Expr::col(Groups::CreationDate)),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v19(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(DeletedEntries::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DeletedEntries::Uuid)
+                            .string_len(36)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DeletedEntries::Kind)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DeletedEntries::Name)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DeletedEntries::DeletionDate)
+                            .date_time()
+                            .not_null(),
+                    ),
+            ),
+        )
+        .await?;
+    // The replicas ask for the deletions since their last synchronization.
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("deleted-entries-deletion-date")
+                    .table(DeletedEntries::Table)
+                    .col(DeletedEntries::DeletionDate),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v20(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(GroupMemberships::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupMemberships::ParentGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupMemberships::ChildGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(GroupMemberships::ParentGroupId)
+                            .col(GroupMemberships::ChildGroupId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupMembershipsParentGroupForeignKey")
+                            .from(GroupMemberships::Table, GroupMemberships::ParentGroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupMembershipsChildGroupForeignKey")
+                            .from(GroupMemberships::Table, GroupMemberships::ChildGroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v21(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The serialized filter of the dynamic groups, NULL for the static ones.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Groups::Table)
+                    .add_column(ColumnDef::new(Groups::DynamicFilter).text()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v22(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The end of a temporary membership, NULL for the permanent ones.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Memberships::Table)
+                    .add_column(ColumnDef::new(Memberships::ExpiryDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v23(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The time step of the last accepted TOTP code, to reject replays.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::TotpLastCounter).big_integer()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
 // This is needed to make an array of async functions.
 macro_rules! to_sync {
     ($l:ident) => {
@@ -1142,6 +1676,19 @@ pub(crate) async fn migrate_from_version(
         to_sync!(migrate_to_v8),
         to_sync!(migrate_to_v9),
         to_sync!(migrate_to_v10),
+        to_sync!(migrate_to_v11),
+        to_sync!(migrate_to_v12),
+        to_sync!(migrate_to_v13),
+        to_sync!(migrate_to_v14),
+        to_sync!(migrate_to_v15),
+        to_sync!(migrate_to_v16),
+        to_sync!(migrate_to_v17),
+        to_sync!(migrate_to_v18),
+        to_sync!(migrate_to_v19),
+        to_sync!(migrate_to_v20),
+        to_sync!(migrate_to_v21),
+        to_sync!(migrate_to_v22),
+        to_sync!(migrate_to_v23),
     ];
     assert_eq!(migrations.len(), (LAST_SCHEMA_VERSION.0 - 1) as usize);
     for migration in 2..=last_version.0 {
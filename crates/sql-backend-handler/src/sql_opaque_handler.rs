@@ -1,9 +1,12 @@
 use crate::SqlBackendHandler;
 use async_trait::async_trait;
 use base64::Engine;
+use chrono::NaiveDateTime;
 use lldap_auth::opaque;
 use lldap_domain::types::UserId;
-use lldap_domain_handlers::handler::{BindRequest, LoginHandler};
+use lldap_domain_handlers::handler::{
+    BindRequest, LoginHandler, LoginThrottleBackendHandler, LoginThrottleSubject,
+};
 use lldap_domain_model::{
     error::{DomainError, Result},
     model::{self, UserColumn},
@@ -60,12 +63,77 @@ impl SqlBackendHandler {
             .await?
             .and_then(|u| u.0))
     }
+
+    /// Refuses the login if the account is disabled or expired. Only called once the password
+    /// was verified, so that it doesn't reveal the state of the account.
+    async fn check_user_active(&self, user_id: &UserId) -> Result<()> {
+        let (disabled, expiry_date) = model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::Disabled)
+            .column(UserColumn::ExpiryDate)
+            .into_tuple::<(bool, Option<NaiveDateTime>)>()
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
+        if disabled {
+            return Err(DomainError::AuthenticationError(format!(
+                r#"for user "{}": the account is disabled"#,
+                user_id
+            )));
+        }
+        if expiry_date.is_some_and(|expiry_date| expiry_date <= chrono::Utc::now().naive_utc()) {
+            return Err(DomainError::AuthenticationError(format!(
+                r#"for user "{}": the account is expired"#,
+                user_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Refuses the login if the user is locked out after too many failed attempts. Unknown users
+    /// are locked out the same way, so that it doesn't reveal whether the user exists.
+    async fn check_login_lockout(&self, user_id: &UserId) -> Result<()> {
+        match self
+            .get_login_lockout(&LoginThrottleSubject::User(user_id.clone()))
+            .await?
+        {
+            Some(locked_until) => Err(DomainError::AuthenticationError(format!(
+                r#"for user "{}": too many failed attempts, locked out until {}"#,
+                user_id, locked_until
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    async fn record_login_result(&self, user_id: &UserId, success: bool) -> Result<()> {
+        let subject = LoginThrottleSubject::User(user_id.clone());
+        if success {
+            self.clear_login_failures(&subject).await
+        } else {
+            self.register_login_failure(&subject).await
+        }
+    }
+
+    /// Lets the user in if they are active, and records the date of the login.
+    async fn finish_successful_login(&self, user_id: &UserId) -> Result<()> {
+        self.record_login_result(user_id, true).await?;
+        self.check_user_active(user_id).await?;
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            last_login: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl LoginHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn bind(&self, request: BindRequest) -> Result<()> {
+        self.check_login_lockout(&request.name).await?;
         if let Some(password_hash) = self
             .get_password_file_for_user(request.name.clone())
             .await?
@@ -79,7 +147,7 @@ impl LoginHandler for SqlBackendHandler {
             )
             .is_ok()
             {
-                return Ok(());
+                return self.finish_successful_login(&request.name).await;
             }
         } else {
             debug!(
@@ -87,6 +155,7 @@ impl LoginHandler for SqlBackendHandler {
                 &request.name
             );
         }
+        self.record_login_result(&request.name, false).await?;
         Err(DomainError::AuthenticationError(format!(
             r#"for user "{}""#,
             request.name
@@ -103,6 +172,7 @@ impl OpaqueHandler for SqlOpaqueHandler {
     ) -> Result<login::ServerLoginStartResponse> {
         let user_id = request.username;
         info!(r#"OPAQUE login attempt for "{}""#, &user_id);
+        self.check_login_lockout(&user_id).await?;
         let maybe_password_file = self
             .get_password_file_for_user(user_id.clone())
             .await?
@@ -145,15 +215,18 @@ impl OpaqueHandler for SqlOpaqueHandler {
             &secret_key,
             &base64::engine::general_purpose::STANDARD.decode(&request.server_data)?,
         )?)?;
+        self.check_login_lockout(&username).await?;
         // Finish the login: this makes sure the client data is correct, and gives a session key we
         // don't need.
         match opaque::server::login::finish_login(server_login, request.credential_finalization) {
             Ok(session) => {
                 info!(r#"OPAQUE login successful for "{}""#, &username);
                 let _ = session.session_key;
+                self.finish_successful_login(&username).await?;
             }
             Err(e) => {
                 warn!(r#"OPAQUE login attempt failed for "{}""#, &username);
+                self.record_login_result(&username, false).await?;
                 return Err(e.into());
             }
         };
@@ -200,6 +273,7 @@ impl OpaqueHandler for SqlOpaqueHandler {
         let user_update = model::users::ActiveModel {
             user_id: ActiveValue::Set(username.clone()),
             password_hash: ActiveValue::Set(Some(password_file.serialize())),
+            password_changed: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
             ..Default::default()
         };
         user_update.update(&self.sql_pool).await?;
@@ -243,8 +317,9 @@ mod tests {
     use self::opaque::server::generate_random_private_key;
 
     use super::*;
-    use crate::sql_backend_handler::tests::{
-        get_initialized_db, insert_user, insert_user_no_password,
+    use crate::{
+        LoginThrottleOptions,
+        sql_backend_handler::tests::{get_initialized_db, insert_user, insert_user_no_password},
     };
 
     async fn attempt_login(
@@ -278,7 +353,11 @@ mod tests {
     async fn test_opaque_flow() -> Result<()> {
         let sql_pool = get_initialized_db().await;
         crate::logging::init_for_tests();
-        let backend_handler = SqlBackendHandler::new(generate_random_private_key(), sql_pool);
+        let backend_handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            sql_pool,
+            LoginThrottleOptions::default(),
+        );
         insert_user_no_password(&backend_handler, "bob").await;
         insert_user_no_password(&backend_handler, "john").await;
         attempt_login(&backend_handler, "bob", "bob00")
@@ -300,7 +379,11 @@ mod tests {
     #[tokio::test]
     async fn test_bind_user() {
         let sql_pool = get_initialized_db().await;
-        let handler = SqlOpaqueHandler::new(generate_random_private_key(), sql_pool.clone());
+        let handler = SqlOpaqueHandler::new(
+            generate_random_private_key(),
+            sql_pool.clone(),
+            LoginThrottleOptions::default(),
+        );
         insert_user(&handler, "bob", "bob00").await;
 
         handler
@@ -326,10 +409,126 @@ mod tests {
             .unwrap_err();
     }
 
+    #[tokio::test]
+    async fn test_bind_lockout() {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlOpaqueHandler::new(
+            generate_random_private_key(),
+            sql_pool.clone(),
+            LoginThrottleOptions {
+                free_attempts: 1,
+                ..Default::default()
+            },
+        );
+        insert_user(&handler, "bob", "bob00").await;
+        let bind = |password: &str| {
+            handler.bind(BindRequest {
+                name: UserId::new("bob"),
+                password: password.to_string(),
+            })
+        };
+        bind("wrong_password").await.unwrap_err();
+        // A successful login resets the counter.
+        bind("bob00").await.unwrap();
+        bind("wrong_password").await.unwrap_err();
+        bind("wrong_password").await.unwrap_err();
+        // Even the right password is refused during the lockout.
+        bind("bob00").await.unwrap_err();
+        handler
+            .clear_login_failures(&LoginThrottleSubject::User(UserId::new("bob")))
+            .await
+            .unwrap();
+        bind("bob00").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_inactive_user() {
+        use lldap_domain::requests::UpdateUserRequest;
+        use lldap_domain_handlers::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlOpaqueHandler::new(
+            generate_random_private_key(),
+            sql_pool.clone(),
+            LoginThrottleOptions::default(),
+        );
+        insert_user(&handler, "bob", "bob00").await;
+        let bind = || {
+            handler.bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "bob00".to_string(),
+            })
+        };
+        let update = |disabled, expiry_date| {
+            handler.update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                disabled: Some(disabled),
+                expiry_date: Some(expiry_date),
+                ..Default::default()
+            })
+        };
+        update(true, None).await.unwrap();
+        bind().await.unwrap_err();
+        let yesterday = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+        update(false, Some(yesterday)).await.unwrap();
+        bind().await.unwrap_err();
+        let tomorrow = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+        update(false, Some(tomorrow)).await.unwrap();
+        bind().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_login_and_password_change_dates() {
+        use lldap_domain_handlers::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlOpaqueHandler::new(
+            generate_random_private_key(),
+            sql_pool.clone(),
+            LoginThrottleOptions::default(),
+        );
+        let before = chrono::Utc::now().naive_utc();
+        insert_user(&handler, "bob", "bob00").await;
+        let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert!(user.password_changed.is_some_and(|d| d >= before));
+        assert_eq!(user.last_login, None);
+
+        handler
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "wrong_password".to_string(),
+            })
+            .await
+            .unwrap_err();
+        let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert_eq!(user.last_login, None);
+
+        handler
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "bob00".to_string(),
+            })
+            .await
+            .unwrap();
+        let bind_date = handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap()
+            .last_login
+            .unwrap();
+        assert!(bind_date >= before);
+
+        attempt_login(&handler, "bob", "bob00").await.unwrap();
+        let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert!(user.last_login.unwrap() >= bind_date);
+    }
+
     #[tokio::test]
     async fn test_user_no_password() {
         let sql_pool = get_initialized_db().await;
-        let handler = SqlBackendHandler::new(generate_random_private_key(), sql_pool.clone());
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            sql_pool.clone(),
+            LoginThrottleOptions::default(),
+        );
         insert_user_no_password(&handler, "bob").await;
 
         handler
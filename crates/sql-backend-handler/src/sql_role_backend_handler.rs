@@ -0,0 +1,299 @@
+use crate::{sql_backend_handler::SqlBackendHandler, sql_group_backend_handler::GroupHierarchy};
+use async_trait::async_trait;
+use lldap_auth::access_control::RolePermission;
+use lldap_domain::types::{GroupId, GroupName};
+use lldap_domain_handlers::handler::{Role, RoleBackendHandler};
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, GroupColumn, RolesColumn},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    RelationTrait, Set, sea_query::OnConflict,
+};
+use std::collections::HashSet;
+use tracing::{instrument, warn};
+
+/// Parses the stored permissions, skipping the ones this version doesn't know about.
+fn parse_permissions(role_name: &str, permissions: &str) -> Vec<RolePermission> {
+    permissions
+        .lines()
+        .filter(|p| !p.is_empty())
+        .filter_map(|p| {
+            p.parse()
+                .inspect_err(|e| warn!(r#"Ignoring permission of role "{}": {}"#, role_name, e))
+                .ok()
+        })
+        .collect()
+}
+
+#[async_trait]
+impl RoleBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_roles(&self) -> Result<Vec<Role>> {
+        Ok(model::Roles::find()
+            .find_with_related(model::RoleGroups)
+            .order_by_asc(RolesColumn::RoleName)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|(role, groups)| Role {
+                permissions: parse_permissions(&role.role_name, &role.permissions),
+                name: role.role_name,
+                group_ids: groups.into_iter().map(|g| g.group_id).collect(),
+            })
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_role(&self, name: &str, permissions: Vec<RolePermission>) -> Result<()> {
+        if name.is_empty() {
+            return Err(DomainError::InternalError(
+                "The role name cannot be empty".to_owned(),
+            ));
+        }
+        model::Roles::insert(model::roles::ActiveModel {
+            role_name: Set(name.to_owned()),
+            permissions: Set(permissions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")),
+        })
+        .on_conflict(
+            OnConflict::column(RolesColumn::RoleName)
+                .update_column(RolesColumn::Permissions)
+                .to_owned(),
+        )
+        .exec(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_role(&self, name: &str) -> Result<()> {
+        let res = model::Roles::delete_by_id(name.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such role: '{}'",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn add_role_to_group(&self, name: &str, group_id: GroupId) -> Result<()> {
+        if model::Roles::find_by_id(name.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such role: '{}'",
+                name
+            )));
+        }
+        if model::RoleGroups::find_by_id((name.to_owned(), group_id))
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+        let hierarchy = GroupHierarchy::load(&self.sql_pool).await?;
+        if Self::contains_dynamic_group(&self.sql_pool, &hierarchy, group_id).await? {
+            return Err(DomainError::ValidationError(format!(
+                "The group {:?} is or contains a dynamic group, it can't have a role",
+                group_id
+            )));
+        }
+        model::role_groups::ActiveModel {
+            role_name: Set(name.to_owned()),
+            group_id: Set(group_id),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn remove_role_from_group(&self, name: &str, group_id: GroupId) -> Result<()> {
+        let res = model::RoleGroups::delete_by_id((name.to_owned(), group_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "The role '{}' isn't attached to the group '{}'",
+                name, group_id.0
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn get_role_permissions(
+        &self,
+        group_names: &[GroupName],
+    ) -> Result<HashSet<RolePermission>> {
+        if group_names.is_empty() {
+            return Ok(HashSet::new());
+        }
+        Ok(model::Roles::find()
+            .inner_join(model::RoleGroups)
+            .join(
+                JoinType::InnerJoin,
+                model::role_groups::Relation::Groups.def(),
+            )
+            .filter(
+                GroupColumn::LowercaseDisplayName
+                    .is_in(group_names.iter().map(|g| g.as_str().to_lowercase())),
+            )
+            .distinct()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .flat_map(|role| parse_permissions(&role.role_name, &role.permissions))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_set_and_list_roles() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .set_role("helpdesk", vec![RolePermission::ResetPasswords])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_role_to_group("helpdesk", fixture.groups[0])
+            .await
+            .unwrap();
+        // Adding it twice is a no-op.
+        fixture
+            .handler
+            .add_role_to_group("helpdesk", fixture.groups[0])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_role(
+                "helpdesk",
+                vec![
+                    RolePermission::ResetPasswords,
+                    RolePermission::EditUserAttribute("phone".to_owned()),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture.handler.list_roles().await.unwrap(),
+            vec![Role {
+                name: "helpdesk".to_owned(),
+                permissions: vec![
+                    RolePermission::ResetPasswords,
+                    RolePermission::EditUserAttribute("phone".to_owned()),
+                ],
+                group_ids: vec![fixture.groups[0]],
+            }]
+        );
+        fixture
+            .handler
+            .add_role_to_group("unknown", fixture.groups[0])
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_get_role_permissions() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .set_role("helpdesk", vec![RolePermission::ResetPasswords])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_role(
+                "hr",
+                vec![RolePermission::CreateUsers, RolePermission::ResetPasswords],
+            )
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_role_to_group("helpdesk", fixture.groups[0])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_role_to_group("hr", fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_role_permissions(&["Best Group".into(), "worst group".into()])
+                .await
+                .unwrap(),
+            HashSet::from([RolePermission::ResetPasswords, RolePermission::CreateUsers])
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .get_role_permissions(&["Empty Group".into()])
+                .await
+                .unwrap(),
+            HashSet::new()
+        );
+        fixture
+            .handler
+            .remove_role_from_group("hr", fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .get_role_permissions(&["Worst Group".into()])
+                .await
+                .unwrap(),
+            HashSet::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_role() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .set_role("helpdesk", vec![RolePermission::ResetPasswords])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .add_role_to_group("helpdesk", fixture.groups[0])
+            .await
+            .unwrap();
+        fixture.handler.delete_role("helpdesk").await.unwrap();
+        fixture.handler.delete_role("helpdesk").await.unwrap_err();
+        assert_eq!(fixture.handler.list_roles().await.unwrap(), vec![]);
+        assert_eq!(
+            fixture
+                .handler
+                .get_role_permissions(&["Best Group".into()])
+                .await
+                .unwrap(),
+            HashSet::new()
+        );
+    }
+}
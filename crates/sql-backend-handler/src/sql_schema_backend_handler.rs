@@ -11,7 +11,8 @@ use lldap_domain_model::{
     model,
 };
 use sea_orm::{
-    ActiveModelTrait, DatabaseTransaction, EntityTrait, QueryOrder, Set, TransactionTrait,
+    ActiveModelTrait, ConnectionTrait, DatabaseTransaction, EntityTrait, QueryOrder, Set,
+    TransactionTrait,
 };
 
 #[async_trait]
@@ -123,12 +124,12 @@ impl SqlBackendHandler {
         })
     }
 
-    async fn get_user_attributes(
-        transaction: &DatabaseTransaction,
+    pub(crate) async fn get_user_attributes(
+        connection: &impl ConnectionTrait,
     ) -> Result<Vec<AttributeSchema>> {
         Ok(model::UserAttributeSchema::find()
             .order_by_asc(model::UserAttributeSchemaColumn::AttributeName)
-            .all(transaction)
+            .all(connection)
             .await?
             .into_iter()
             .map(|m| m.into())
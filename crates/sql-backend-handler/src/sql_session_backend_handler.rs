@@ -0,0 +1,264 @@
+use crate::sql_backend_handler::SqlBackendHandler;
+use async_trait::async_trait;
+use lldap_domain::types::UserId;
+use lldap_domain_handlers::handler::{Session, SessionBackendHandler};
+use lldap_domain_model::{
+    error::{DomainError, Result},
+    model::{self, JwtRefreshStorageColumn, JwtStorageColumn},
+};
+use sea_orm::{
+    ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    TransactionTrait,
+    sea_query::{Cond, Expr},
+};
+use std::collections::HashSet;
+use tracing::instrument;
+
+impl From<model::jwt_refresh_storage::Model> for Session {
+    fn from(token: model::jwt_refresh_storage::Model) -> Self {
+        Self {
+            session_id: token.refresh_token_hash,
+            user_id: token.user_id,
+            creation_date: token.creation_date,
+            expiry_date: token.expiry_date,
+            ip_address: token.ip_address,
+            user_agent: token.user_agent,
+        }
+    }
+}
+
+impl SqlBackendHandler {
+    /// Blacklists the valid JWTs of the user matching the condition, and returns their hashes.
+    pub(crate) async fn blacklist_session_jwts(
+        transaction: &DatabaseTransaction,
+        user_id: &UserId,
+        condition: Cond,
+    ) -> Result<HashSet<u64>> {
+        let condition = Cond::all()
+            .add(JwtStorageColumn::UserId.eq(user_id))
+            .add(JwtStorageColumn::Blacklisted.eq(false))
+            .add(condition);
+        let jwt_hashes = model::JwtStorage::find()
+            .select_only()
+            .column(JwtStorageColumn::JwtHash)
+            .filter(condition.clone())
+            .into_tuple::<(i64,)>()
+            .all(transaction)
+            .await?
+            .into_iter()
+            .map(|t| t.0 as u64)
+            .collect::<HashSet<u64>>();
+        model::JwtStorage::update_many()
+            .col_expr(JwtStorageColumn::Blacklisted, Expr::value(true))
+            .filter(condition)
+            .exec(transaction)
+            .await?;
+        Ok(jwt_hashes)
+    }
+}
+
+#[async_trait]
+impl SessionBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>> {
+        Ok(model::JwtRefreshStorage::find()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .filter(JwtRefreshStorageColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .order_by_desc(JwtRefreshStorageColumn::CreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Session::from)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn revoke_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>> {
+        let user_id = user_id.clone();
+        Ok(self
+            .sql_pool
+            .transaction::<_, HashSet<u64>, DomainError>(|transaction| {
+                Box::pin(async move {
+                    let res = model::JwtRefreshStorage::delete_many()
+                        .filter(JwtRefreshStorageColumn::RefreshTokenHash.eq(session_id))
+                        .filter(JwtRefreshStorageColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    if res.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such session: '{}'",
+                            session_id
+                        )));
+                    }
+                    Self::blacklist_session_jwts(
+                        transaction,
+                        &user_id,
+                        Cond::all().add(JwtStorageColumn::RefreshTokenHash.eq(session_id)),
+                    )
+                    .await
+                })
+            })
+            .await?)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>> {
+        let user_id = user_id.clone();
+        Ok(self
+            .sql_pool
+            .transaction::<_, HashSet<u64>, DomainError>(|transaction| {
+                Box::pin(async move {
+                    model::JwtRefreshStorage::delete_many()
+                        .filter(JwtRefreshStorageColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    Self::blacklist_session_jwts(transaction, &user_id, Cond::all()).await
+                })
+            })
+            .await?)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::sql_backend_handler::tests::*;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{ActiveModelTrait, ConnectionTrait, IntoActiveModel, Schema};
+
+    /// The JWT tables are created by the server, create them from the entities for the tests.
+    pub async fn create_jwt_tables(handler: &SqlBackendHandler) {
+        let builder = handler.sql_pool.get_database_backend();
+        let schema = Schema::new(builder);
+        handler
+            .sql_pool
+            .execute(builder.build(&schema.create_table_from_entity(model::JwtRefreshStorage)))
+            .await
+            .unwrap();
+        handler
+            .sql_pool
+            .execute(builder.build(&schema.create_table_from_entity(model::JwtStorage)))
+            .await
+            .unwrap();
+    }
+
+    pub async fn insert_session(
+        handler: &SqlBackendHandler,
+        user: &str,
+        session_id: i64,
+        age: i64,
+    ) {
+        let now = chrono::Utc::now().naive_utc();
+        model::jwt_refresh_storage::Model {
+            refresh_token_hash: session_id,
+            user_id: UserId::new(user),
+            creation_date: now - chrono::Duration::days(age),
+            expiry_date: now + chrono::Duration::days(30 - age),
+            ip_address: Some("127.0.0.1".to_owned()),
+            user_agent: None,
+        }
+        .into_active_model()
+        .insert(&handler.sql_pool)
+        .await
+        .unwrap();
+        model::jwt_storage::Model {
+            jwt_hash: session_id * 10,
+            user_id: UserId::new(user),
+            expiry_date: now + chrono::Duration::days(1),
+            blacklisted: false,
+            refresh_token_hash: Some(session_id),
+        }
+        .into_active_model()
+        .insert(&handler.sql_pool)
+        .await
+        .unwrap();
+    }
+
+    fn get_session_ids(sessions: Vec<Session>) -> Vec<i64> {
+        sessions.into_iter().map(|s| s.session_id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions() {
+        let fixture = TestFixture::new().await;
+        create_jwt_tables(&fixture.handler).await;
+        insert_session(&fixture.handler, "bob", 1, 2).await;
+        insert_session(&fixture.handler, "bob", 2, 1).await;
+        // Expired.
+        insert_session(&fixture.handler, "bob", 3, 31).await;
+        insert_session(&fixture.handler, "patrick", 4, 0).await;
+        let sessions = fixture
+            .handler
+            .list_sessions(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(get_session_ids(sessions), vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_session() {
+        let fixture = TestFixture::new().await;
+        create_jwt_tables(&fixture.handler).await;
+        insert_session(&fixture.handler, "bob", 1, 0).await;
+        insert_session(&fixture.handler, "bob", 2, 0).await;
+        // Only the owner's sessions can be revoked.
+        fixture
+            .handler
+            .revoke_session(&UserId::new("patrick"), 1)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            fixture
+                .handler
+                .revoke_session(&UserId::new("bob"), 1)
+                .await
+                .unwrap(),
+            HashSet::from([10])
+        );
+        assert_eq!(
+            get_session_ids(
+                fixture
+                    .handler
+                    .list_sessions(&UserId::new("bob"))
+                    .await
+                    .unwrap()
+            ),
+            vec![2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_sessions() {
+        let fixture = TestFixture::new().await;
+        create_jwt_tables(&fixture.handler).await;
+        insert_session(&fixture.handler, "bob", 1, 0).await;
+        insert_session(&fixture.handler, "bob", 2, 0).await;
+        insert_session(&fixture.handler, "patrick", 3, 0).await;
+        assert_eq!(
+            fixture
+                .handler
+                .revoke_all_sessions(&UserId::new("bob"))
+                .await
+                .unwrap(),
+            HashSet::from([10, 20])
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .list_sessions(&UserId::new("bob"))
+                .await
+                .unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            get_session_ids(
+                fixture
+                    .handler
+                    .list_sessions(&UserId::new("patrick"))
+                    .await
+                    .unwrap()
+            ),
+            vec![3]
+        );
+    }
+}
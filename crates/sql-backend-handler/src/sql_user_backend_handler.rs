@@ -1,24 +1,29 @@
-use crate::sql_backend_handler::SqlBackendHandler;
+use crate::{
+    sql_backend_handler::SqlBackendHandler,
+    sql_deleted_entry_backend_handler::record_deleted_entry,
+    sql_group_backend_handler::{DynamicGroups, GroupHierarchy},
+};
 use async_trait::async_trait;
 use lldap_domain::{
     requests::{CreateUserRequest, UpdateUserRequest},
     types::{AttributeName, GroupDetails, GroupId, Serialized, User, UserAndGroups, UserId, Uuid},
 };
 use lldap_domain_handlers::handler::{
-    ReadSchemaBackendHandler, UserBackendHandler, UserListerBackendHandler, UserRequestFilter,
+    DeletedEntryKind, ReadSchemaBackendHandler, UserBackendHandler, UserListerBackendHandler,
+    UserRequestFilter,
 };
 use lldap_domain_model::{
     error::{DomainError, Result},
     model::{self, GroupColumn, UserColumn, deserialize},
 };
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait,
-    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait,
+    ModelTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
     sea_query::{
         Alias, Cond, Expr, Func, IntoColumnRef, IntoCondition, SimpleExpr, query::OnConflict,
     },
 };
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use tracing::instrument;
 
 fn attribute_condition(name: AttributeName, value: Option<Serialized>) -> Cond {
@@ -88,7 +93,8 @@ fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
             }
         }
         AttributeEquality(column, value) => attribute_condition(column, Some(value.into())),
-        MemberOf(group) => user_id_subcondition(
+        // The nested groups are resolved beforehand, see `expand_transitive_filter`.
+        MemberOf(group) | TransitiveMemberOf(group) => user_id_subcondition(
             Expr::col((group_table, GroupColumn::LowercaseDisplayName))
                 .eq(group.as_str().to_lowercase())
                 .into_condition(),
@@ -107,9 +113,112 @@ fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
                 .into_condition()
         }
         CustomAttributePresent(name) => attribute_condition(name, None),
+        Disabled => UserColumn::Disabled.eq(true).into_condition(),
+        HasExpiryDate => UserColumn::ExpiryDate.is_not_null().into_condition(),
+        // A membership that expired since the date changed the user, even if it's not purged
+        // yet: the incremental synchronizations (e.g. Keycloak's) must see it.
+        DateGreaterOrEqual(UserColumn::ModifiedDate, date) => Cond::any()
+            .add(UserColumn::ModifiedDate.gte(date))
+            .add(Expr::in_subquery(
+                Expr::col(UserColumn::UserId.as_column_ref()),
+                model::Membership::find()
+                    .select_only()
+                    .column(model::MembershipColumn::UserId)
+                    .filter(model::MembershipColumn::ExpiryDate.gte(date))
+                    .filter(model::MembershipColumn::ExpiryDate.lte(chrono::Utc::now().naive_utc()))
+                    .into_query(),
+            )),
+        DateGreaterOrEqual(column, date) => column.gte(date).into_condition(),
+        DateLessOrEqual(column, date) => column.lte(date).into_condition(),
+        HasValue(column) => column.is_not_null().into_condition(),
+    }
+}
+
+/// Lists the (lowercase) names of the groups in transitive membership filters.
+fn collect_transitive_groups(filter: &UserRequestFilter, groups: &mut Vec<String>) {
+    use UserRequestFilter::*;
+    match filter {
+        And(fs) | Or(fs) => fs.iter().for_each(|f| collect_transitive_groups(f, groups)),
+        Not(f) => collect_transitive_groups(f, groups),
+        TransitiveMemberOf(group) => groups.push(group.as_str().to_lowercase()),
+        _ => {}
+    }
+}
+
+/// Replaces the transitive membership filters with a membership of the group or of any of its
+/// nested groups, indexed by the lowercase name of the group.
+fn expand_transitive_filter(
+    filter: UserRequestFilter,
+    nested_groups: &HashMap<String, BTreeSet<GroupId>>,
+) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let rec = |f: UserRequestFilter| expand_transitive_filter(f, nested_groups);
+    match filter {
+        And(fs) => And(fs.into_iter().map(rec).collect()),
+        Or(fs) => Or(fs.into_iter().map(rec).collect()),
+        Not(f) => Not(Box::new(rec(*f))),
+        TransitiveMemberOf(group) => match nested_groups.get(&group.as_str().to_lowercase()) {
+            Some(ids) if !ids.is_empty() => Or(std::iter::once(MemberOf(group))
+                .chain(ids.iter().copied().map(MemberOfId))
+                .collect()),
+            _ => MemberOf(group),
+        },
+        f => f,
+    }
+}
+
+fn has_membership_filter(filter: &UserRequestFilter) -> bool {
+    use UserRequestFilter::*;
+    match filter {
+        And(fs) | Or(fs) => fs.iter().any(has_membership_filter),
+        Not(f) => has_membership_filter(f),
+        MemberOf(_) | MemberOfId(_) | TransitiveMemberOf(_) => true,
+        _ => false,
+    }
+}
+
+/// Replaces the membership filters on dynamic groups with the filters of the groups. The filters
+/// of the dynamic groups are not expanded themselves: they can only refer to static groups.
+fn expand_dynamic_filter(
+    filter: UserRequestFilter,
+    dynamic_groups: &DynamicGroups,
+) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let rec = |f: UserRequestFilter| expand_dynamic_filter(f, dynamic_groups);
+    match filter {
+        And(fs) => And(fs.into_iter().map(rec).collect()),
+        Or(fs) => Or(fs.into_iter().map(rec).collect()),
+        Not(f) => Not(Box::new(rec(*f))),
+        MemberOf(group) => dynamic_groups
+            .get_by_name(&group)
+            .cloned()
+            .unwrap_or_else(|| MemberOf(group)),
+        TransitiveMemberOf(group) => dynamic_groups
+            .get_by_name(&group)
+            .cloned()
+            .unwrap_or_else(|| TransitiveMemberOf(group)),
+        MemberOfId(group_id) => dynamic_groups
+            .get(group_id)
+            .cloned()
+            .unwrap_or_else(|| MemberOfId(group_id)),
+        f => f,
     }
 }
 
+/// The users matching the filter, e.g. the members of a dynamic group.
+pub(crate) async fn get_matching_user_ids(
+    connection: &impl ConnectionTrait,
+    filter: UserRequestFilter,
+) -> Result<HashSet<UserId>> {
+    Ok(model::User::find()
+        .filter(get_user_filter_expr(filter))
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|user| user.user_id)
+        .collect())
+}
+
 fn to_value(opt_name: &Option<String>) -> ActiveValue<Option<String>> {
     match opt_name {
         None => ActiveValue::NotSet,
@@ -127,10 +236,39 @@ impl UserListerBackendHandler for SqlBackendHandler {
     async fn list_users(
         &self,
         filters: Option<UserRequestFilter>,
-        // To simplify the query, we always fetch groups. TODO: cleanup.
-        _get_groups: bool,
+        // To simplify the query, we always fetch the direct groups. The inherited ones are only
+        // added on demand.
+        get_groups: bool,
     ) -> Result<Vec<UserAndGroups>> {
+        let mut transitive_groups = Vec::new();
+        if let Some(filters) = &filters {
+            collect_transitive_groups(filters, &mut transitive_groups);
+        }
+        let hierarchy = if get_groups || !transitive_groups.is_empty() {
+            GroupHierarchy::load(&self.sql_pool).await?
+        } else {
+            GroupHierarchy::default()
+        };
+        let dynamic_groups = if get_groups || filters.as_ref().is_some_and(has_membership_filter) {
+            DynamicGroups::load(&self.sql_pool).await?
+        } else {
+            DynamicGroups::default()
+        };
+        let filters = match filters {
+            Some(filters) if !transitive_groups.is_empty() && !hierarchy.is_empty() => {
+                let nested_groups = model::Group::find()
+                    .filter(GroupColumn::LowercaseDisplayName.is_in(transitive_groups))
+                    .all(&self.sql_pool)
+                    .await?
+                    .into_iter()
+                    .map(|g| (g.lowercase_display_name, hierarchy.descendants(g.group_id)))
+                    .collect();
+                Some(expand_transitive_filter(filters, &nested_groups))
+            }
+            filters => filters,
+        };
         let filters = filters
+            .map(|f| expand_dynamic_filter(f, &dynamic_groups))
             .map(get_user_filter_expr)
             .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition());
         let mut users: Vec<_> = model::User::find()
@@ -148,6 +286,12 @@ impl UserListerBackendHandler for SqlBackendHandler {
                 groups: Some(groups.into_iter().map(Into::<GroupDetails>::into).collect()),
             })
             .collect();
+        if get_groups && !dynamic_groups.is_empty() {
+            Self::add_dynamic_groups(&self.sql_pool, &dynamic_groups, &filters, &mut users).await?;
+        }
+        if get_groups && !hierarchy.is_empty() {
+            Self::add_inherited_groups(&self.sql_pool, &hierarchy, &mut users).await?;
+        }
 
         // At this point, the users don't have attributes, we need to populate it with another query.
         let attributes = model::UserAttributes::find()
@@ -185,18 +329,194 @@ impl UserListerBackendHandler for SqlBackendHandler {
 }
 
 impl SqlBackendHandler {
+    /// Adds the dynamic groups whose filter matches the users, which are selected by
+    /// `users_filter`.
+    async fn add_dynamic_groups(
+        connection: &impl ConnectionTrait,
+        dynamic_groups: &DynamicGroups,
+        users_filter: &Cond,
+        users: &mut [UserAndGroups],
+    ) -> Result<()> {
+        for (group, filter) in dynamic_groups.iter() {
+            // Only look for the listed users among the members.
+            let members = model::User::find()
+                .filter(users_filter.clone())
+                .filter(get_user_filter_expr(filter.clone()))
+                .all(connection)
+                .await?
+                .into_iter()
+                .map(|user| user.user_id)
+                .collect::<HashSet<_>>();
+            for user in users
+                .iter_mut()
+                .filter(|u| members.contains(&u.user.user_id))
+            {
+                user.groups.get_or_insert_with(Vec::new).push(group.clone());
+            }
+        }
+        for user in users.iter_mut() {
+            if let Some(groups) = &mut user.groups {
+                groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
+            }
+        }
+        Ok(())
+    }
+
+    /// The dynamic groups whose filter matches the user.
+    async fn get_dynamic_group_ids(
+        connection: &impl ConnectionTrait,
+        dynamic_groups: &DynamicGroups,
+        user_id: &UserId,
+    ) -> Result<Vec<GroupId>> {
+        let mut group_ids = Vec::new();
+        for (group, filter) in dynamic_groups.iter() {
+            if model::User::find()
+                .filter(UserColumn::UserId.eq(user_id))
+                .filter(get_user_filter_expr(filter.clone()))
+                .one(connection)
+                .await?
+                .is_some()
+            {
+                group_ids.push(group.group_id);
+            }
+        }
+        Ok(group_ids)
+    }
+
+    /// Refuses the manual changes to the members of a dynamic group.
+    async fn check_static_group(
+        transaction: &DatabaseTransaction,
+        group_id: GroupId,
+    ) -> Result<()> {
+        if model::Group::find_by_id(group_id)
+            .one(transaction)
+            .await?
+            .is_some_and(|g| g.dynamic_filter.is_some())
+        {
+            return Err(DomainError::ValidationError(format!(
+                "The members of the dynamic group {:?} are computed from its filter",
+                group_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Adds the groups that the users belong to through nested groups.
+    async fn add_inherited_groups(
+        connection: &impl ConnectionTrait,
+        hierarchy: &GroupHierarchy,
+        users: &mut [UserAndGroups],
+    ) -> Result<()> {
+        let parent_groups = model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(hierarchy.parent_group_ids()))
+            .all(connection)
+            .await?
+            .into_iter()
+            .map(|g| (g.group_id, GroupDetails::from(g)))
+            .collect::<HashMap<_, _>>();
+        for user in users.iter_mut() {
+            let groups = user.groups.get_or_insert_with(Vec::new);
+            let direct_groups = groups.iter().map(|g| g.group_id).collect::<HashSet<_>>();
+            let inherited_groups = direct_groups
+                .iter()
+                .flat_map(|group_id| hierarchy.ancestors(*group_id))
+                .filter(|group_id| !direct_groups.contains(group_id))
+                .collect::<BTreeSet<_>>();
+            if inherited_groups.is_empty() {
+                continue;
+            }
+            groups.extend(
+                inherited_groups
+                    .iter()
+                    .filter_map(|group_id| parent_groups.get(group_id).cloned()),
+            );
+            groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
+        }
+        Ok(())
+    }
+
+    /// Bumps the modification date of both the user and the group, after a membership change.
+    pub(crate) async fn touch_membership(
+        transaction: &DatabaseTransaction,
+        user_id: &UserId,
+        group_id: GroupId,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().naive_utc();
+        model::User::update_many()
+            .col_expr(UserColumn::ModifiedDate, Expr::value(now))
+            .filter(UserColumn::UserId.eq(user_id))
+            .exec(transaction)
+            .await?;
+        model::Group::update_many()
+            .col_expr(GroupColumn::ModifiedDate, Expr::value(now))
+            .filter(GroupColumn::GroupId.eq(group_id))
+            .exec(transaction)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_user_to_group_with_transaction(
+        transaction: &DatabaseTransaction,
+        user_id: &UserId,
+        group_id: GroupId,
+    ) -> Result<()> {
+        // An expired membership that hasn't been purged yet is replaced.
+        model::Membership::delete_many()
+            .filter(model::MembershipColumn::UserId.eq(user_id))
+            .filter(model::MembershipColumn::GroupId.eq(group_id))
+            .filter(model::MembershipColumn::ExpiryDate.lte(chrono::Utc::now().naive_utc()))
+            .exec(transaction)
+            .await?;
+        model::memberships::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            group_id: ActiveValue::Set(group_id),
+            expiry_date: ActiveValue::NotSet,
+        }
+        .insert(transaction)
+        .await?;
+        Self::touch_membership(transaction, user_id, group_id).await
+    }
+
+    async fn remove_user_from_group_with_transaction(
+        transaction: &DatabaseTransaction,
+        user_id: &UserId,
+        group_id: GroupId,
+    ) -> Result<()> {
+        let res = model::Membership::delete_by_id((user_id.clone(), group_id))
+            .exec(transaction)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such membership: '{}' -> {:?}",
+                user_id, group_id
+            )));
+        }
+        Self::touch_membership(transaction, user_id, group_id).await
+    }
+
     async fn update_user_with_transaction(
         transaction: &DatabaseTransaction,
         request: UpdateUserRequest,
     ) -> Result<()> {
+        let now = chrono::Utc::now().naive_utc();
         let lower_email = request.email.as_ref().map(|s| s.as_str().to_lowercase());
         let update_user = model::users::ActiveModel {
             user_id: ActiveValue::Set(request.user_id.clone()),
             email: request.email.map(ActiveValue::Set).unwrap_or_default(),
             lowercase_email: lower_email.map(ActiveValue::Set).unwrap_or_default(),
             display_name: to_value(&request.display_name),
+            disabled: request.disabled.map(ActiveValue::Set).unwrap_or_default(),
+            expiry_date: request
+                .expiry_date
+                .map(ActiveValue::Set)
+                .unwrap_or_default(),
+            modified_date: ActiveValue::Set(now),
             ..Default::default()
         };
+        // The user can join or leave dynamic groups with the update.
+        let dynamic_groups = DynamicGroups::load(transaction).await?;
+        let mut dynamic_group_ids =
+            Self::get_dynamic_group_ids(transaction, &dynamic_groups, &request.user_id).await?;
         let mut update_user_attributes = Vec::new();
         let mut remove_user_attributes = Vec::new();
         let mut process_serialized =
@@ -266,6 +586,16 @@ impl SqlBackendHandler {
                 .exec(transaction)
                 .await?;
         }
+        if !dynamic_groups.is_empty() {
+            dynamic_group_ids.extend(
+                Self::get_dynamic_group_ids(transaction, &dynamic_groups, &request.user_id).await?,
+            );
+            model::Group::update_many()
+                .col_expr(GroupColumn::ModifiedDate, Expr::value(now))
+                .filter(GroupColumn::GroupId.is_in(dynamic_group_ids))
+                .exec(transaction)
+                .await?;
+        }
         Ok(())
     }
 }
@@ -305,13 +635,33 @@ impl UserBackendHandler for SqlBackendHandler {
             .one(&self.sql_pool)
             .await?
             .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
-        Ok(HashSet::from_iter(
-            user.find_linked(model::memberships::UserToGroup)
-                .all(&self.sql_pool)
-                .await?
-                .into_iter()
-                .map(Into::<GroupDetails>::into),
-        ))
+        let groups = user
+            .find_linked(model::memberships::UserToGroup)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::<GroupDetails>::into)
+            .collect();
+        let mut users = [UserAndGroups {
+            user: user.into(),
+            groups: Some(groups),
+        }];
+        let dynamic_groups = DynamicGroups::load(&self.sql_pool).await?;
+        if !dynamic_groups.is_empty() {
+            Self::add_dynamic_groups(
+                &self.sql_pool,
+                &dynamic_groups,
+                &UserColumn::UserId.eq(user_id).into_condition(),
+                &mut users,
+            )
+            .await?;
+        }
+        let hierarchy = GroupHierarchy::load(&self.sql_pool).await?;
+        if !hierarchy.is_empty() {
+            Self::add_inherited_groups(&self.sql_pool, &hierarchy, &mut users).await?;
+        }
+        let [UserAndGroups { groups, .. }] = users;
+        Ok(HashSet::from_iter(groups.unwrap_or_default()))
     }
 
     #[instrument(skip(self), level = "debug", err, fields(user_id = ?request.user_id.as_str()))]
@@ -325,6 +675,7 @@ impl UserBackendHandler for SqlBackendHandler {
             lowercase_email: Set(lower_email),
             display_name: to_value(&request.display_name),
             creation_date: ActiveValue::Set(now),
+            modified_date: ActiveValue::Set(now),
             uuid: ActiveValue::Set(uuid),
             ..Default::default()
         };
@@ -379,39 +730,204 @@ impl UserBackendHandler for SqlBackendHandler {
 
     #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str()))]
     async fn delete_user(&self, user_id: &UserId) -> Result<()> {
-        let res = model::User::delete_by_id(user_id.clone())
-            .exec(&self.sql_pool)
+        let user_id = user_id.clone();
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let user = model::User::find_by_id(user_id.clone())
+                        .one(transaction)
+                        .await?
+                        .ok_or_else(|| {
+                            DomainError::EntityNotFound(format!("No such user: '{}'", user_id))
+                        })?;
+                    // The groups lose a member.
+                    model::Group::update_many()
+                        .col_expr(
+                            GroupColumn::ModifiedDate,
+                            Expr::value(chrono::Utc::now().naive_utc()),
+                        )
+                        .filter(
+                            GroupColumn::GroupId.in_subquery(
+                                model::Membership::find()
+                                    .select_only()
+                                    .column(model::MembershipColumn::GroupId)
+                                    .filter(model::MembershipColumn::UserId.eq(&user_id))
+                                    .into_query(),
+                            ),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    model::User::delete_by_id(user_id.clone())
+                        .exec(transaction)
+                        .await?;
+                    record_deleted_entry(
+                        transaction,
+                        user.uuid,
+                        DeletedEntryKind::User,
+                        user.user_id.into_string(),
+                    )
+                    .await
+                })
+            })
             .await?;
-        if res.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such user: '{}'",
-                user_id
-            )));
-        }
         Ok(())
     }
 
+    #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str(), new_user_id = ?new_user_id.as_str()))]
+    async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<HashSet<u64>> {
+        let user_id = user_id.clone();
+        let new_user_id = new_user_id.clone();
+        Ok(self
+            .sql_pool
+            .transaction::<_, HashSet<u64>, DomainError>(|transaction| {
+                Box::pin(async move {
+                    if model::User::find_by_id(new_user_id.clone())
+                        .one(transaction)
+                        .await?
+                        .is_some()
+                    {
+                        return Err(DomainError::EntityAlreadyExists(format!(
+                            "User '{}' already exists",
+                            new_user_id
+                        )));
+                    }
+                    // The sessions and the JWTs were issued for the old ID: log the user out.
+                    model::JwtRefreshStorage::delete_many()
+                        .filter(model::JwtRefreshStorageColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    let jwt_hashes =
+                        Self::blacklist_session_jwts(transaction, &user_id, Cond::all()).await?;
+                    let res = model::User::update_many()
+                        .col_expr(UserColumn::UserId, Expr::value(new_user_id.clone()))
+                        .col_expr(
+                            UserColumn::ModifiedDate,
+                            Expr::value(chrono::Utc::now().naive_utc()),
+                        )
+                        .filter(UserColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    if res.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such user: '{}'",
+                            user_id
+                        )));
+                    }
+                    // The foreign keys cascade the update, this only covers databases where they
+                    // were not enforced.
+                    model::Membership::update_many()
+                        .col_expr(
+                            model::MembershipColumn::UserId,
+                            Expr::value(new_user_id.clone()),
+                        )
+                        .filter(model::MembershipColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    model::UserAttributes::update_many()
+                        .col_expr(
+                            model::UserAttributesColumn::UserId,
+                            Expr::value(new_user_id.clone()),
+                        )
+                        .filter(model::UserAttributesColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    Ok(jwt_hashes)
+                })
+            })
+            .await?)
+    }
+
     #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str(), group_id))]
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
-        let new_membership = model::memberships::ActiveModel {
-            user_id: ActiveValue::Set(user_id.clone()),
-            group_id: ActiveValue::Set(group_id),
-        };
-        new_membership.insert(&self.sql_pool).await?;
+        let user_id = user_id.clone();
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    Self::check_static_group(transaction, group_id).await?;
+                    Self::add_user_to_group_with_transaction(transaction, &user_id, group_id).await
+                })
+            })
+            .await?;
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str(), group_id))]
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
-        let res = model::Membership::delete_by_id((user_id.clone(), group_id))
-            .exec(&self.sql_pool)
+        let user_id = user_id.clone();
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    Self::check_static_group(transaction, group_id).await?;
+                    Self::remove_user_from_group_with_transaction(transaction, &user_id, group_id)
+                        .await
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err, fields(group_id, added = ?added, removed = ?removed))]
+    async fn update_group_members(
+        &self,
+        group_id: GroupId,
+        added: &[UserId],
+        removed: &[UserId],
+    ) -> Result<()> {
+        let added = added.to_vec();
+        let removed = removed.to_vec();
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    Self::check_static_group(transaction, group_id).await?;
+                    for user_id in &removed {
+                        Self::remove_user_from_group_with_transaction(
+                            transaction,
+                            user_id,
+                            group_id,
+                        )
+                        .await?;
+                    }
+                    for user_id in &added {
+                        Self::add_user_to_group_with_transaction(transaction, user_id, group_id)
+                            .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str(), group_id, expiry_date = ?expiry_date))]
+    async fn set_membership_expiry_date(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        let user_id = user_id.clone();
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let res = model::Membership::update_many()
+                        .col_expr(
+                            model::MembershipColumn::ExpiryDate,
+                            Expr::value(expiry_date),
+                        )
+                        .filter(model::MembershipColumn::UserId.eq(&user_id))
+                        .filter(model::MembershipColumn::GroupId.eq(group_id))
+                        .exec(transaction)
+                        .await?;
+                    if res.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such membership: '{}' -> {:?}",
+                            user_id, group_id
+                        )));
+                    }
+                    Self::touch_membership(transaction, &user_id, group_id).await
+                })
+            })
             .await?;
-        if res.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such membership: '{}' -> {:?}",
-                user_id, group_id
-            )));
-        }
         Ok(())
     }
 }
@@ -419,10 +935,17 @@ impl UserBackendHandler for SqlBackendHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sql_backend_handler::tests::*;
+    use crate::{
+        LoginThrottleOptions,
+        sql_backend_handler::tests::*,
+        sql_session_backend_handler::tests::{create_jwt_tables, insert_session},
+    };
+    use chrono::TimeZone;
     use lldap_auth::opaque::server::generate_random_private_key;
-    use lldap_domain::types::{Attribute, JpegPhoto};
-    use lldap_domain_handlers::handler::SubStringFilter;
+    use lldap_domain::types::{Attribute, GroupName, JpegPhoto};
+    use lldap_domain_handlers::handler::{
+        GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter, SubStringFilter,
+    };
     use lldap_domain_model::model::UserColumn;
     use pretty_assertions::{assert_eq, assert_ne};
 
@@ -545,6 +1068,99 @@ mod tests {
         assert_eq!(users, vec!["bob", "patrick"]);
     }
 
+    #[tokio::test]
+    async fn test_list_users_transitive_member_of() {
+        let fixture = TestFixture::new().await;
+        // Worst Group is nested in Best Group.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[0], fixture.groups[1])
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::TransitiveMemberOf("best grOUp".into())),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "john", "patrick"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Not(Box::new(
+                UserRequestFilter::TransitiveMemberOf("Best Group".into()),
+            ))),
+        )
+        .await;
+        assert_eq!(users, vec!["nogroup"]);
+        // The direct membership is unchanged.
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOf("Best Group".into())),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "patrick"]);
+        // The inherited groups are listed along with the direct ones.
+        let users = fixture
+            .handler
+            .list_users(Some(UserRequestFilter::UserId(UserId::new("john"))), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            users[0]
+                .groups
+                .iter()
+                .flatten()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![fixture.groups[0], fixture.groups[1]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_member_of_dynamic_group() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .set_group_filter(
+                fixture.groups[2],
+                Some(UserRequestFilter::UserIdSubString(SubStringFilter {
+                    initial: Some("n".to_owned()),
+                    any: vec![],
+                    final_: None,
+                })),
+            )
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOf("Empty Group".into())),
+        )
+        .await;
+        assert_eq!(users, vec!["nogroup"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::MemberOfId(fixture.groups[2])),
+        )
+        .await;
+        assert_eq!(users, vec!["nogroup"]);
+        let users = fixture
+            .handler
+            .list_users(
+                Some(UserRequestFilter::UserId(UserId::new("nogroup"))),
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            users[0]
+                .groups
+                .iter()
+                .flatten()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![fixture.groups[2]]
+        );
+    }
+
     #[tokio::test]
     async fn test_list_users_member_of_and_uuid() {
         let fixture = TestFixture::new().await;
@@ -658,30 +1274,71 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_list_users_with_groups() {
+    async fn test_list_users_date_filters() {
         let fixture = TestFixture::new().await;
-        let users = fixture
-            .handler
-            .list_users(None, true)
-            .await
-            .unwrap()
-            .into_iter()
-            .map(|u| {
-                (
-                    u.user.user_id.to_string(),
-                    u.user
-                        .display_name
-                        .as_deref()
-                        .unwrap_or("<unknown>")
-                        .to_owned(),
-                    u.groups
-                        .unwrap_or_default()
-                        .into_iter()
-                        .map(|g| g.group_id)
-                        .collect::<Vec<_>>(),
-                )
-            })
-            .collect::<Vec<_>>();
+        let date = |secs| chrono::Utc.timestamp_opt(secs, 0).unwrap().naive_utc();
+        for (user, secs) in [("bob", 1_000_000), ("john", 2_000_000)] {
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new(user),
+                    expiry_date: Some(Some(date(secs))),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::HasValue(UserColumn::ExpiryDate)),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "john"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::DateGreaterOrEqual(
+                UserColumn::ExpiryDate,
+                date(2_000_000),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["john"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::DateLessOrEqual(
+                UserColumn::ExpiryDate,
+                date(1_500_000),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_with_groups() {
+        let fixture = TestFixture::new().await;
+        let users = fixture
+            .handler
+            .list_users(None, true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| {
+                (
+                    u.user.user_id.to_string(),
+                    u.user
+                        .display_name
+                        .as_deref()
+                        .unwrap_or("<unknown>")
+                        .to_owned(),
+                    u.groups
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|g| g.group_id)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
         assert_eq!(
             users,
             vec![
@@ -734,8 +1391,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_user_details() {
-        let handler =
-            SqlBackendHandler::new(generate_random_private_key(), get_initialized_db().await);
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            get_initialized_db().await,
+            LoginThrottleOptions::default(),
+        );
         insert_user_no_password(&handler, "bob").await;
         {
             let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
@@ -751,8 +1411,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_user_lowercase() {
-        let handler =
-            SqlBackendHandler::new(generate_random_private_key(), get_initialized_db().await);
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            get_initialized_db().await,
+            LoginThrottleOptions::default(),
+        );
         insert_user_no_password(&handler, "Bob").await;
         {
             let user = handler.get_user_details(&UserId::new("bOb")).await.unwrap();
@@ -822,6 +1485,99 @@ mod tests {
         assert_eq!(get_group_ids("nogroup").await, vec![]);
     }
 
+    #[tokio::test]
+    async fn test_get_user_groups_nested() {
+        let fixture = TestFixture::new().await;
+        // Empty Group > Best Group: bob belongs to both.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[2], fixture.groups[0])
+            .await
+            .unwrap();
+        let mut groups = fixture
+            .handler
+            .get_user_groups(&UserId::new("bob"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.display_name)
+            .collect::<Vec<_>>();
+        groups.sort();
+        assert_eq!(groups, vec!["Best Group".into(), "Empty Group".into()]);
+        // The inherited groups are the same as the ones listed with the users.
+        let listed_groups = fixture
+            .handler
+            .list_users(Some(UserRequestFilter::UserId(UserId::new("bob"))), true)
+            .await
+            .unwrap()
+            .remove(0)
+            .groups
+            .unwrap()
+            .into_iter()
+            .map(|g| g.display_name)
+            .collect::<Vec<_>>();
+        assert_eq!(listed_groups, groups);
+    }
+
+    #[tokio::test]
+    async fn test_get_user_groups_dynamic() {
+        let fixture = TestFixture::new().await;
+        // Empty Group is the members of Worst Group, and is nested in Everyone.
+        fixture
+            .handler
+            .set_group_filter(
+                fixture.groups[2],
+                Some(UserRequestFilter::MemberOf("Worst Group".into())),
+            )
+            .await
+            .unwrap();
+        let everyone = insert_group(&fixture.handler, "Everyone").await;
+        fixture
+            .handler
+            .add_group_to_group(everyone, fixture.groups[2])
+            .await
+            .unwrap();
+        let get_group_names = async |user: &'static str| {
+            let mut groups = fixture
+                .handler
+                .get_user_groups(&UserId::new(user))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.display_name)
+                .collect::<Vec<_>>();
+            groups.sort();
+            groups
+        };
+        assert_eq!(get_group_names("bob").await, vec!["Best Group".into()]);
+        let groups = get_group_names("patrick").await;
+        assert_eq!(
+            groups,
+            vec![
+                "Best Group".into(),
+                "Empty Group".into(),
+                "Everyone".into(),
+                "Worst Group".into()
+            ]
+        );
+        // The same groups as the ones listed with the users.
+        let listed_groups = fixture
+            .handler
+            .list_users(
+                Some(UserRequestFilter::UserId(UserId::new("patrick"))),
+                true,
+            )
+            .await
+            .unwrap()
+            .remove(0)
+            .groups
+            .unwrap()
+            .into_iter()
+            .map(|g| g.display_name)
+            .collect::<Vec<_>>();
+        assert_eq!(listed_groups, groups);
+    }
+
     #[tokio::test]
     async fn test_update_user_all_values() {
         let fixture = TestFixture::new().await;
@@ -847,6 +1603,10 @@ mod tests {
                         value: JpegPhoto::for_tests().into(),
                     },
                 ],
+                disabled: Some(true),
+                expiry_date: Some(Some(
+                    chrono::Utc.timestamp_opt(1_000_000, 0).unwrap().naive_utc(),
+                )),
             })
             .await
             .unwrap();
@@ -858,6 +1618,11 @@ mod tests {
             .unwrap();
         assert_eq!(user.email, "email".into());
         assert_eq!(user.display_name.unwrap(), "display_name");
+        assert!(user.disabled);
+        assert_eq!(
+            user.expiry_date,
+            Some(chrono::Utc.timestamp_opt(1_000_000, 0).unwrap().naive_utc())
+        );
         assert_eq!(
             user.attributes,
             vec![
@@ -916,6 +1681,56 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_update_user_dynamic_group_modified_date() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .set_group_filter(fixture.groups[2], Some(UserRequestFilter::Disabled))
+            .await
+            .unwrap();
+        let old_date = chrono::Utc.timestamp_opt(1_000_000, 0).unwrap().naive_utc();
+        let get_modified_dates = async || {
+            let dates = model::Group::find()
+                .all(&fixture.handler.sql_pool)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| (g.group_id, g.modified_date))
+                .collect::<HashMap<_, _>>();
+            // Reset the dates for the next update.
+            model::Group::update_many()
+                .col_expr(GroupColumn::ModifiedDate, Expr::value(old_date))
+                .exec(&fixture.handler.sql_pool)
+                .await
+                .unwrap();
+            (dates[&fixture.groups[1]], dates[&fixture.groups[2]])
+        };
+        get_modified_dates().await;
+        let update_bob = async |disabled: bool| {
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new("bob"),
+                    disabled: Some(disabled),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        };
+        // Bob joins the group.
+        update_bob(true).await;
+        let (static_date, dynamic_date) = get_modified_dates().await;
+        assert_eq!(static_date, old_date);
+        assert_ne!(dynamic_date, old_date);
+        // Bob leaves the group.
+        update_bob(false).await;
+        assert_ne!(get_modified_dates().await.1, old_date);
+        // Bob was not in the group, and still isn't.
+        update_bob(false).await;
+        assert_eq!(get_modified_dates().await.1, old_date);
+    }
+
     #[tokio::test]
     async fn test_update_user_insert_attribute() {
         let fixture = TestFixture::new().await;
@@ -1141,6 +1956,228 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_update_group_members() {
+        let fixture = TestFixture::new().await;
+
+        fixture
+            .handler
+            .update_group_members(
+                fixture.groups[0],
+                &[UserId::new("john"), UserId::new("nogroup")],
+                &[UserId::new("bob")],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+            .await,
+            vec!["john", "nogroup", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_group_members_is_atomic() {
+        let fixture = TestFixture::new().await;
+
+        fixture
+            .handler
+            .update_group_members(
+                fixture.groups[0],
+                &[UserId::new("john")],
+                &[UserId::new("bob"), UserId::new("nogroup")],
+            )
+            .await
+            .expect_err("nogroup is not a member");
+
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+            .await,
+            vec!["bob", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modified_date_bumped_on_changes() {
+        use lldap_domain_handlers::handler::GroupBackendHandler;
+        let fixture = TestFixture::new().await;
+        let epoch = chrono::Utc.timestamp_opt(0, 0).unwrap().naive_utc();
+        let handler = &fixture.handler;
+        let reset_modified_dates = move || async move {
+            model::User::update_many()
+                .col_expr(UserColumn::ModifiedDate, Expr::value(epoch))
+                .exec(&handler.sql_pool)
+                .await
+                .unwrap();
+            model::Group::update_many()
+                .col_expr(GroupColumn::ModifiedDate, Expr::value(epoch))
+                .exec(&handler.sql_pool)
+                .await
+                .unwrap();
+        };
+        let user_modified_date = move |user_id: &'static str| async move {
+            handler
+                .get_user_details(&UserId::new(user_id))
+                .await
+                .unwrap()
+                .modified_date
+        };
+        let group_modified_date = move |group_id| async move {
+            handler
+                .get_group_details(group_id)
+                .await
+                .unwrap()
+                .modified_date
+        };
+
+        reset_modified_dates().await;
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                display_name: Some("Bobby".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(user_modified_date("bob").await > epoch);
+        assert_eq!(user_modified_date("patrick").await, epoch);
+
+        reset_modified_dates().await;
+        fixture
+            .handler
+            .add_user_to_group(&UserId::new("nogroup"), fixture.groups[2])
+            .await
+            .unwrap();
+        assert!(user_modified_date("nogroup").await > epoch);
+        assert!(group_modified_date(fixture.groups[2]).await > epoch);
+        assert_eq!(group_modified_date(fixture.groups[0]).await, epoch);
+
+        reset_modified_dates().await;
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
+            .await
+            .unwrap();
+        assert!(user_modified_date("bob").await > epoch);
+        assert!(group_modified_date(fixture.groups[0]).await > epoch);
+
+        reset_modified_dates().await;
+        fixture
+            .handler
+            .delete_user(&UserId::new("patrick"))
+            .await
+            .unwrap();
+        assert!(group_modified_date(fixture.groups[0]).await > epoch);
+        assert!(group_modified_date(fixture.groups[1]).await > epoch);
+        assert_eq!(group_modified_date(fixture.groups[2]).await, epoch);
+
+        reset_modified_dates().await;
+        fixture
+            .handler
+            .delete_group(fixture.groups[1])
+            .await
+            .unwrap();
+        assert!(user_modified_date("john").await > epoch);
+        assert_eq!(user_modified_date("bob").await, epoch);
+    }
+
+    #[tokio::test]
+    async fn test_rename_user() {
+        let fixture = TestFixture::new().await;
+        create_jwt_tables(&fixture.handler).await;
+        let uuid = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap()
+            .uuid;
+
+        fixture
+            .handler
+            .rename_user(&UserId::new("bob"), &UserId::new("robert"))
+            .await
+            .unwrap();
+
+        fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap_err();
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("robert"))
+            .await
+            .unwrap();
+        assert_eq!(user.uuid, uuid);
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+            .await,
+            vec!["patrick", "robert"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_logs_out() {
+        use lldap_domain_handlers::handler::SessionBackendHandler;
+        let fixture = TestFixture::new().await;
+        create_jwt_tables(&fixture.handler).await;
+        insert_session(&fixture.handler, "bob", 1, 0).await;
+        insert_session(&fixture.handler, "patrick", 2, 0).await;
+
+        let jwt_hashes = fixture
+            .handler
+            .rename_user(&UserId::new("bob"), &UserId::new("robert"))
+            .await
+            .unwrap();
+
+        assert_eq!(jwt_hashes, HashSet::from([10]));
+        assert!(
+            fixture
+                .handler
+                .list_sessions(&UserId::new("robert"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .list_sessions(&UserId::new("patrick"))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_user_conflict() {
+        let fixture = TestFixture::new().await;
+        create_jwt_tables(&fixture.handler).await;
+
+        fixture
+            .handler
+            .rename_user(&UserId::new("bob"), &UserId::new("patrick"))
+            .await
+            .expect_err("Should have failed");
+        fixture
+            .handler
+            .rename_user(&UserId::new("not found"), &UserId::new("robert"))
+            .await
+            .expect_err("Should have failed");
+    }
+
     #[tokio::test]
     async fn test_delete_user_not_found() {
         let fixture = TestFixture::new().await;
@@ -1169,6 +2206,217 @@ mod tests {
             .expect_err("Should have failed");
     }
 
+    #[tokio::test]
+    async fn test_membership_expiry() {
+        let fixture = TestFixture::new().await;
+        let now = chrono::Utc::now().naive_utc();
+        let past = now - chrono::Duration::days(1);
+        let future = now + chrono::Duration::days(1);
+        fixture
+            .handler
+            .set_membership_expiry_date(&UserId::new("bob"), fixture.groups[0], Some(past))
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_membership_expiry_date(&UserId::new("patrick"), fixture.groups[0], Some(future))
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_membership_expiry_date(&UserId::new("nogroup"), fixture.groups[0], None)
+            .await
+            .expect_err("Should have failed");
+
+        // The expired membership is ignored, even before it is purged.
+        assert!(
+            fixture
+                .handler
+                .get_user_groups(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0]))
+            )
+            .await,
+            vec!["patrick"]
+        );
+        let users = fixture
+            .handler
+            .list_users(Some(UserRequestFilter::UserId(UserId::new("bob"))), true)
+            .await
+            .unwrap();
+        assert_eq!(users[0].groups, Some(vec![]));
+        let groups = fixture
+            .handler
+            .list_groups(Some(GroupRequestFilter::GroupId(fixture.groups[0])))
+            .await
+            .unwrap();
+        assert_eq!(groups[0].users, vec![UserId::new("patrick")]);
+        assert_eq!(
+            fixture
+                .handler
+                .list_membership_expiry_dates(fixture.groups[0])
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(user_id, _)| user_id)
+                .collect::<Vec<_>>(),
+            vec![UserId::new("patrick")]
+        );
+
+        // Adding the user again replaces the expired membership.
+        fixture
+            .handler
+            .add_user_to_group(&UserId::new("bob"), fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0]))
+            )
+            .await,
+            vec!["bob", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_membership_expiry_nested_and_dynamic() {
+        let fixture = TestFixture::new().await;
+        let past = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+        // Worst Group > Best Group, and Empty Group is the members of Best Group.
+        fixture
+            .handler
+            .add_group_to_group(fixture.groups[1], fixture.groups[0])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_group_filter(
+                fixture.groups[2],
+                Some(UserRequestFilter::MemberOf("Best Group".into())),
+            )
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_membership_expiry_date(&UserId::new("bob"), fixture.groups[0], Some(past))
+            .await
+            .unwrap();
+        // Bob loses Best Group, and with it the groups it gave him.
+        assert!(
+            fixture
+                .handler
+                .get_user_groups(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let users = fixture
+            .handler
+            .list_users(Some(UserRequestFilter::UserId(UserId::new("bob"))), true)
+            .await
+            .unwrap();
+        assert_eq!(users[0].groups, Some(vec![]));
+        // The LDAP memberOf filters, direct or transitive, ignore the expired membership.
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOf("Best Group".into()))
+            )
+            .await,
+            vec!["patrick"]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::TransitiveMemberOf("Worst Group".into()))
+            )
+            .await,
+            vec!["john", "patrick"]
+        );
+        // So does the filter of the dynamic group.
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[2]))
+            )
+            .await,
+            vec!["patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modified_date_filter_with_expired_membership() {
+        let fixture = TestFixture::new().await;
+        let now = chrono::Utc::now().naive_utc();
+        let since = now - chrono::Duration::hours(1);
+        fixture
+            .handler
+            .set_membership_expiry_date(
+                &UserId::new("bob"),
+                fixture.groups[0],
+                Some(now - chrono::Duration::minutes(1)),
+            )
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .set_membership_expiry_date(
+                &UserId::new("patrick"),
+                fixture.groups[0],
+                Some(now + chrono::Duration::days(1)),
+            )
+            .await
+            .unwrap();
+        // The memberships were set before the last synchronization.
+        model::User::update_many()
+            .col_expr(
+                UserColumn::ModifiedDate,
+                Expr::value(now - chrono::Duration::days(1)),
+            )
+            .exec(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        // Bob's membership expired since then, but it's not purged yet.
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::DateGreaterOrEqual(
+                    UserColumn::ModifiedDate,
+                    since
+                ))
+            )
+            .await,
+            vec!["bob"]
+        );
+        assert_eq!(
+            fixture
+                .handler
+                .list_expired_memberships(since)
+                .await
+                .unwrap(),
+            vec![(UserId::new("bob"), GroupName::from("Best Group"))]
+        );
+        // The next synchronization already saw it.
+        assert!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::DateGreaterOrEqual(
+                    UserColumn::ModifiedDate,
+                    now
+                ))
+            )
+            .await
+            .is_empty()
+        );
+    }
+
     #[tokio::test]
     async fn test_create_user_duplicate_email() {
         let fixture = TestFixture::new().await;
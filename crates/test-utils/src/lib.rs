@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use lldap_auth::access_control::RolePermission;
 use lldap_domain::{
     requests::{
         CreateAttributeRequest, CreateGroupRequest, CreateUserRequest, UpdateGroupRequest,
@@ -6,14 +7,18 @@ use lldap_domain::{
     },
     schema::{AttributeList, AttributeSchema, Schema},
     types::{
-        AttributeName, AttributeType, Group, GroupDetails, GroupId, LdapObjectClass, User,
-        UserAndGroups, UserId,
+        AttributeName, AttributeType, Group, GroupDetails, GroupId, GroupName, LdapObjectClass,
+        User, UserAndGroups, UserId,
     },
 };
 use lldap_domain_handlers::handler::{
-    BackendHandler, BindRequest, GroupBackendHandler, GroupListerBackendHandler,
-    GroupRequestFilter, LoginHandler, ReadSchemaBackendHandler, SchemaBackendHandler,
-    UserBackendHandler, UserListerBackendHandler, UserRequestFilter,
+    ApiToken, ApiTokenBackendHandler, AuditEvent, AuditEventFilter, AuditLogBackendHandler,
+    BackendHandler, BindRequest, CreateApiTokenRequest, CreateAuditEventRequest, DeletedEntry,
+    DeletedEntryBackendHandler, GroupBackendHandler, GroupListerBackendHandler,
+    GroupOwnerBackendHandler, GroupRequestFilter, LoginHandler, LoginThrottleBackendHandler,
+    LoginThrottleSubject, ReadSchemaBackendHandler, Role, RoleBackendHandler, SchemaBackendHandler,
+    Session, SessionBackendHandler, UserBackendHandler, UserListerBackendHandler,
+    UserRequestFilter,
 };
 use lldap_domain_model::error::Result;
 use lldap_opaque_handler::{OpaqueHandler, login, registration};
@@ -38,6 +43,13 @@ mockall::mock! {
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        async fn add_group_to_group(&self, parent_group_id: GroupId, child_group_id: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, parent_group_id: GroupId, child_group_id: GroupId) -> Result<()>;
+        async fn get_ancestor_groups(&self, group_id: GroupId) -> Result<Vec<GroupDetails>>;
+        async fn get_group_filter(&self, group_id: GroupId) -> Result<Option<UserRequestFilter>>;
+        async fn set_group_filter(&self, group_id: GroupId, filter: Option<UserRequestFilter>) -> Result<()>;
+        async fn list_membership_expiry_dates(&self, group_id: GroupId) -> Result<Vec<(UserId, chrono::NaiveDateTime)>>;
+        async fn list_expired_memberships(&self, since: chrono::NaiveDateTime) -> Result<Vec<(UserId, GroupName)>>;
     }
     #[async_trait]
     impl UserListerBackendHandler for TestBackendHandler {
@@ -49,9 +61,12 @@ mockall::mock! {
         async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
         async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
         async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+        async fn rename_user(&self, user_id: &UserId, new_user_id: &UserId) -> Result<HashSet<u64>>;
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn update_group_members(&self, group_id: GroupId, added: &[UserId], removed: &[UserId]) -> Result<()>;
+        async fn set_membership_expiry_date(&self, user_id: &UserId, group_id: GroupId, expiry_date: Option<chrono::NaiveDateTime>) -> Result<()>;
     }
     #[async_trait]
     impl ReadSchemaBackendHandler for TestBackendHandler {
@@ -69,6 +84,53 @@ mockall::mock! {
         async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
     }
     #[async_trait]
+    impl ApiTokenBackendHandler for TestBackendHandler {
+        async fn list_api_tokens(&self, user_id: &UserId) -> Result<Vec<ApiToken>>;
+        async fn create_api_token(&self, request: CreateApiTokenRequest) -> Result<(ApiToken, String)>;
+        async fn get_api_token_from_secret(&self, secret: &str) -> Result<Option<ApiToken>>;
+        async fn delete_api_token(&self, user_id: &UserId, token_id: i32) -> Result<()>;
+    }
+    #[async_trait]
+    impl LoginThrottleBackendHandler for TestBackendHandler {
+        async fn get_login_lockout(
+            &self,
+            subject: &LoginThrottleSubject
+        ) -> Result<Option<chrono::NaiveDateTime>>;
+        async fn register_login_failure(&self, subject: &LoginThrottleSubject) -> Result<()>;
+        async fn clear_login_failures(&self, subject: &LoginThrottleSubject) -> Result<()>;
+    }
+    #[async_trait]
+    impl SessionBackendHandler for TestBackendHandler {
+        async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+        async fn revoke_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+        async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+    }
+    #[async_trait]
+    impl RoleBackendHandler for TestBackendHandler {
+        async fn list_roles(&self) -> Result<Vec<Role>>;
+        async fn set_role(&self, name: &str, permissions: Vec<RolePermission>) -> Result<()>;
+        async fn delete_role(&self, name: &str) -> Result<()>;
+        async fn add_role_to_group(&self, name: &str, group_id: GroupId) -> Result<()>;
+        async fn remove_role_from_group(&self, name: &str, group_id: GroupId) -> Result<()>;
+        async fn get_role_permissions(&self, group_names: &[GroupName]) -> Result<HashSet<RolePermission>>;
+    }
+    #[async_trait]
+    impl GroupOwnerBackendHandler for TestBackendHandler {
+        async fn list_group_owners(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+        async fn get_owned_groups(&self, user_id: &UserId) -> Result<Vec<GroupId>>;
+        async fn add_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn remove_group_owner(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl AuditLogBackendHandler for TestBackendHandler {
+        async fn record_audit_event(&self, request: CreateAuditEventRequest) -> Result<()>;
+        async fn list_audit_events(&self, filter: AuditEventFilter, offset: u64, limit: u64) -> Result<Vec<AuditEvent>>;
+    }
+    #[async_trait]
+    impl DeletedEntryBackendHandler for TestBackendHandler {
+        async fn list_deleted_entries(&self, since: chrono::NaiveDateTime) -> Result<Vec<DeletedEntry>>;
+    }
+    #[async_trait]
     impl BackendHandler for TestBackendHandler {}
     #[async_trait]
     impl OpaqueHandler for TestBackendHandler {
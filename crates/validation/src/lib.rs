@@ -1,3 +1,5 @@
 #![forbid(non_ascii_idents)]
 
 pub mod attributes;
+pub mod password;
+pub mod user_id;
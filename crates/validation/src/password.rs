@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+/// Constraints on the passwords chosen by the users.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Minimum number of characters.
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    /// Require a character that is neither a letter nor a digit.
+    pub require_special: bool,
+    /// Words that cannot appear in the password, case-insensitive.
+    pub disallowed_words: Vec<String>,
+    /// Reject passwords that contain the user name, case-insensitive.
+    pub disallow_username: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_special: false,
+            disallowed_words: Vec::new(),
+            disallow_username: false,
+        }
+    }
+}
+
+/// Checks the password against the policy, returning the list of the rules it breaks.
+pub fn validate_password(
+    policy: &PasswordPolicy,
+    username: &str,
+    password: &str,
+) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    if password.chars().count() < policy.min_length {
+        errors.push(format!(
+            "The password must be at least {} characters long",
+            policy.min_length
+        ));
+    }
+    if policy.require_uppercase && !password.chars().any(char::is_uppercase) {
+        errors.push("The password must contain an uppercase letter".to_owned());
+    }
+    if policy.require_lowercase && !password.chars().any(char::is_lowercase) {
+        errors.push("The password must contain a lowercase letter".to_owned());
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        errors.push("The password must contain a digit".to_owned());
+    }
+    if policy.require_special && password.chars().all(char::is_alphanumeric) {
+        errors.push("The password must contain a special character".to_owned());
+    }
+    let lowercase_password = password.to_lowercase();
+    if policy.disallow_username
+        && !username.is_empty()
+        && lowercase_password.contains(&username.to_lowercase())
+    {
+        errors.push("The password cannot contain the user name".to_owned());
+    }
+    if let Some(word) = policy
+        .disallowed_words
+        .iter()
+        .filter(|w| !w.is_empty())
+        .find(|w| lowercase_password.contains(&w.to_lowercase()))
+    {
+        errors.push(format!("The password cannot contain \"{}\"", word));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(validate_password(&policy, "bob", "password"), Ok(()));
+        assert_eq!(
+            validate_password(&policy, "bob", "pass"),
+            Err(vec![
+                "The password must be at least 8 characters long".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let policy = PasswordPolicy {
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_special: true,
+            ..Default::default()
+        };
+        assert_eq!(validate_password(&policy, "bob", "Passw0rd!"), Ok(()));
+        assert_eq!(
+            validate_password(&policy, "bob", "password")
+                .unwrap_err()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_disallowed_words_and_username() {
+        let policy = PasswordPolicy {
+            disallowed_words: vec!["lldap".to_owned(), "".to_owned()],
+            disallow_username: true,
+            ..Default::default()
+        };
+        assert_eq!(validate_password(&policy, "bob", "correct horse"), Ok(()));
+        assert_eq!(
+            validate_password(&policy, "bob", "my-LLDAP-password"),
+            Err(vec!["The password cannot contain \"lldap\"".to_owned()])
+        );
+        assert_eq!(
+            validate_password(&policy, "Bob", "bob-password"),
+            Err(vec!["The password cannot contain the user name".to_owned()])
+        );
+    }
+}
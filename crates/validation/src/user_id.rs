@@ -0,0 +1,31 @@
+// Description of allowed characters. Intended for error messages.
+pub const ALLOWED_CHARACTERS_DESCRIPTION: &str =
+    "a-z, A-Z, 0-9, dash (-), underscore (_), dot (.) and at sign (@)";
+
+pub fn validate_user_id(user_id: &str) -> Result<(), Vec<char>> {
+    let invalid_chars: Vec<char> = user_id
+        .chars()
+        .filter(|c| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '@')))
+        .collect();
+    if invalid_chars.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid_chars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_valid_user_id() {
+        assert_eq!(super::validate_user_id("john.doe-01"), Ok(()));
+        assert_eq!(super::validate_user_id("john_doe@example.com"), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_user_id_chars() {
+        assert_eq!(super::validate_user_id("john doe"), Err(vec![' ']));
+        assert_eq!(super::validate_user_id("john,ou=doe"), Err(vec![',', '=']));
+    }
+}
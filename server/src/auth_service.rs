@@ -1,12 +1,13 @@
 use crate::{
     tcp_backend_handler::*,
     tcp_server::{AppState, TcpError, TcpResult, error_to_http_response},
+    totp,
 };
 use actix_web::{
     HttpRequest, HttpResponse,
     cookie::{Cookie, SameSite},
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    error::{ErrorBadRequest, ErrorUnauthorized},
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorUnauthorized},
     web,
 };
 use actix_web_httpauth::extractors::bearer::BearerAuth;
@@ -14,32 +15,33 @@ use anyhow::Result;
 use chrono::prelude::*;
 use futures::future::{Ready, ok};
 use futures_util::FutureExt;
-use hmac::Hmac;
 use jwt::{SignWithKey, VerifyWithKey};
 use lldap_access_control::{ReadonlyBackendHandler, UserReadableBackendHandler};
 use lldap_auth::{
-    JWTClaims, access_control::ValidationResults, login, password_reset, registration,
+    JWTClaims, access_control::ValidationResults, login, mfa, password_reset, registration,
 };
 use lldap_domain::types::{GroupDetails, GroupName, UserId};
 use lldap_domain_handlers::handler::{
-    BackendHandler, BindRequest, LoginHandler, UserRequestFilter,
+    API_TOKEN_PREFIX, ApiTokenBackendHandler, AuditLogBackendHandler, AuditSource, BackendHandler,
+    BindRequest, CreateAuditEventRequest, LoginHandler, LoginThrottleBackendHandler,
+    LoginThrottleSubject, UserRequestFilter,
 };
 use lldap_domain_model::{error::DomainError, model::UserColumn};
 use lldap_opaque_handler::OpaqueHandler;
-use sha2::Sha512;
+use serde::Deserialize;
 use std::{
     collections::HashSet,
     hash::Hash,
+    net::{IpAddr, SocketAddr},
     pin::Pin,
     task::{Context, Poll},
 };
 use time::ext::NumericalDuration;
-use tracing::{debug, info, instrument, warn};
+use tracing::{debug, error, info, instrument, warn};
 
 type Token<S> = jwt::Token<jwt::Header, JWTClaims, S>;
-type SignedToken = Token<jwt::token::Signed>;
 
-fn default_hash<T: Hash + ?Sized>(token: &T) -> u64 {
+pub(crate) fn default_hash<T: Hash + ?Sized>(token: &T) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::Hasher;
     let mut s = DefaultHasher::new();
@@ -47,12 +49,12 @@ fn default_hash<T: Hash + ?Sized>(token: &T) -> u64 {
     s.finish()
 }
 
-async fn create_jwt<Handler: TcpBackendHandler>(
-    handler: &Handler,
-    key: &Hmac<Sha512>,
+async fn create_jwt<Backend: TcpBackendHandler>(
+    data: &web::Data<AppState<Backend>>,
     user: &UserId,
     groups: HashSet<GroupDetails>,
-) -> SignedToken {
+    refresh_token_hash: Option<u64>,
+) -> String {
     let claims = JWTClaims {
         exp: Utc::now() + chrono::Duration::days(1),
         iat: Utc::now(),
@@ -63,18 +65,37 @@ async fn create_jwt<Handler: TcpBackendHandler>(
             .collect(),
     };
     let expiry = claims.exp.naive_utc();
-    let header = jwt::Header {
-        algorithm: jwt::AlgorithmType::Hs512,
-        ..Default::default()
-    };
-    let token = jwt::Token::new(header, claims).sign_with_key(key).unwrap();
-    handler
-        .register_jwt(user, default_hash(token.as_str()), expiry)
+    let token = sign_jwt(data, claims);
+    data.get_tcp_handler()
+        .register_jwt(
+            user,
+            default_hash(token.as_str()),
+            expiry,
+            refresh_token_hash,
+        )
         .await
         .unwrap();
     token
 }
 
+/// Signs the claims with the RSA key if it is configured, or with the `jwt_secret`.
+fn sign_jwt<Backend>(state: &AppState<Backend>, claims: JWTClaims) -> String {
+    match &state.jwt_rsa_key {
+        Some(key) => key.sign(&claims),
+        None => {
+            let header = jwt::Header {
+                algorithm: jwt::AlgorithmType::Hs512,
+                ..Default::default()
+            };
+            jwt::Token::new(header, claims)
+                .sign_with_key(&state.jwt_key)
+                .unwrap()
+                .as_str()
+                .to_owned()
+        }
+    }
+}
+
 fn parse_refresh_token(token: &str) -> TcpResult<(u64, UserId)> {
     match token.split_once('+') {
         None => Err(DomainError::AuthenticationError("Invalid refresh token".to_string()).into()),
@@ -103,7 +124,6 @@ async fn get_refresh<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
-    let jwt_key = &data.jwt_key;
     let (refresh_token_hash, user) = get_refresh_token(request)?;
     let found = data
         .get_tcp_handler()
@@ -114,12 +134,22 @@ where
             "Invalid refresh token".to_string(),
         )));
     }
+    if !data
+        .get_readonly_handler()
+        .get_user_details(&user)
+        .await?
+        .is_active_at(Utc::now().naive_utc())
+    {
+        return Err(TcpError::DomainError(DomainError::AuthenticationError(
+            "The account is disabled or expired".to_string(),
+        )));
+    }
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
         path.push('/');
     };
     let groups = data.get_readonly_handler().get_user_groups(&user).await?;
-    let token = create_jwt(data.get_tcp_handler(), jwt_key, &user, groups).await;
+    let token = create_jwt(&data, &user, groups, Some(refresh_token_hash)).await;
     Ok(HttpResponse::Ok()
         .cookie(
             Cookie::build("token", token.as_str())
@@ -244,7 +274,7 @@ where
         .delete_password_reset_token(token)
         .await;
     let groups = HashSet::new();
-    let token = create_jwt(data.get_tcp_handler(), &data.jwt_key, &user_id, groups).await;
+    let token = create_jwt(&data, &user_id, groups, None).await;
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
         path.push('/');
@@ -277,6 +307,22 @@ where
         .unwrap_or_else(error_to_http_response)
 }
 
+/// Blacklists all the outstanding JWTs of the user, in the DB and in memory.
+pub(crate) async fn blacklist_user_jwts<Backend>(
+    data: &AppState<Backend>,
+    user: &UserId,
+) -> TcpResult<()>
+where
+    Backend: TcpBackendHandler + BackendHandler,
+{
+    let new_blacklisted_jwt_hashes = data.get_tcp_handler().blacklist_jwts(user).await?;
+    let mut jwt_blacklist = data.jwt_blacklist.write().unwrap();
+    for jwt_hash in new_blacklisted_jwt_hashes {
+        jwt_blacklist.insert(jwt_hash);
+    }
+    Ok(())
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn get_logout<Backend>(
     data: web::Data<AppState<Backend>>,
@@ -289,11 +335,7 @@ where
     data.get_tcp_handler()
         .delete_refresh_token(refresh_token_hash)
         .await?;
-    let new_blacklisted_jwt_hashes = data.get_tcp_handler().blacklist_jwts(&user).await?;
-    let mut jwt_blacklist = data.jwt_blacklist.write().unwrap();
-    for jwt_hash in new_blacklisted_jwt_hashes {
-        jwt_blacklist.insert(jwt_hash);
-    }
+    blacklist_user_jwts(&data, &user).await?;
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
         path.push('/');
@@ -336,34 +378,207 @@ pub(crate) fn error_to_api_response<T, E: Into<TcpError>>(error: E) -> ApiResult
 
 pub type ApiResult<M> = actix_web::Either<web::Json<M>, HttpResponse>;
 
+/// The address of the client. It's the address of the connection, unless the connection comes
+/// from one of the trusted reverse proxies: the forwarded addresses are then read from the right,
+/// until one of them is not a trusted proxy.
+pub(crate) fn get_source_ip(request: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut source_ip = request.peer_addr()?.ip();
+    if !trusted_proxies.contains(&source_ip) {
+        return Some(source_ip);
+    }
+    let headers = request.headers();
+    let forwarded_addresses: Vec<&str> = if headers.contains_key(actix_http::header::FORWARDED) {
+        headers
+            .get_all(actix_http::header::FORWARDED)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (name, value) = pair.split_once('=')?;
+                    name.trim()
+                        .eq_ignore_ascii_case("for")
+                        .then(|| value.trim())
+                })
+            })
+            .collect()
+    } else {
+        headers
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect()
+    };
+    for address in forwarded_addresses.into_iter().rev() {
+        if !trusted_proxies.contains(&source_ip) {
+            break;
+        }
+        match parse_forwarded_address(address) {
+            Some(address) => source_ip = address,
+            // Obfuscated or unknown address: the last proxy is the best we have.
+            None => break,
+        }
+    }
+    Some(source_ip)
+}
+
+fn parse_forwarded_address(address: &str) -> Option<IpAddr> {
+    let address = address.trim_matches('"');
+    address
+        .parse::<SocketAddr>()
+        .map(|address| address.ip())
+        .or_else(|_| {
+            address
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+        })
+        .ok()
+}
+
+fn get_session_info(request: &HttpRequest, trusted_proxies: &[IpAddr]) -> SessionInfo {
+    SessionInfo {
+        ip_address: get_source_ip(request, trusted_proxies),
+        user_agent: request
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .map(str::to_owned),
+    }
+}
+
+/// Refuses the login if there were too many failed attempts from the client's address.
+async fn check_source_ip_lockout<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    source_ip: Option<IpAddr>,
+) -> TcpResult<()>
+where
+    Backend: LoginThrottleBackendHandler,
+{
+    let Some(ip) = source_ip else {
+        return Ok(());
+    };
+    match data
+        .get_login_throttle_handler()
+        .get_login_lockout(&LoginThrottleSubject::Ip(ip))
+        .await?
+    {
+        Some(locked_until) => Err(TcpError::TooManyRequests(format!(
+            "Too many failed login attempts, try again after {}",
+            locked_until
+        ))),
+        None => Ok(()),
+    }
+}
+
+async fn register_source_ip_failure<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    source_ip: Option<IpAddr>,
+) -> TcpResult<()>
+where
+    Backend: LoginThrottleBackendHandler,
+{
+    if let Some(ip) = source_ip {
+        data.get_login_throttle_handler()
+            .register_login_failure(&LoginThrottleSubject::Ip(ip))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Records an event in the audit log. Failing to record it doesn't fail the request.
+async fn record_audit_event<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    source_ip: Option<IpAddr>,
+    operation: &str,
+    actor: Option<&str>,
+    target: Option<String>,
+    success: bool,
+) where
+    Backend: AuditLogBackendHandler,
+{
+    if let Err(e) = data
+        .get_audit_log_handler()
+        .record_audit_event(CreateAuditEventRequest {
+            actor: actor.map(str::to_owned),
+            source: AuditSource::Http,
+            ip_address: source_ip.map(|ip| ip.to_string()),
+            target,
+            operation: operation.to_owned(),
+            success,
+            details: None,
+        })
+        .await
+    {
+        error!(
+            "Could not record the {} in the audit log: {:#}",
+            operation, e
+        );
+    }
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn opaque_login_start<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientLoginStartRequest>,
-) -> ApiResult<login::ServerLoginStartResponse>
+) -> TcpResult<login::ServerLoginStartResponse>
 where
-    Backend: OpaqueHandler + 'static,
+    Backend: OpaqueHandler + LoginThrottleBackendHandler + 'static,
 {
-    data.get_opaque_handler()
+    check_source_ip_lockout(&data, get_source_ip(&http_request, &data.trusted_proxies)).await?;
+    Ok(data
+        .get_opaque_handler()
         .login_start(request.into_inner())
+        .await?)
+}
+
+async fn opaque_login_start_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
+    request: web::Json<login::ClientLoginStartRequest>,
+) -> ApiResult<login::ServerLoginStartResponse>
+where
+    Backend: OpaqueHandler + LoginThrottleBackendHandler + 'static,
+{
+    opaque_login_start(data, http_request, request)
         .await
         .map(|res| ApiResult::Left(web::Json(res)))
-        .unwrap_or_else(error_to_api_response)
+        .unwrap_or_else(|e| ApiResult::Right(error_to_http_response(e)))
 }
 
 #[instrument(skip_all, level = "debug")]
 async fn get_login_successful_response<Backend>(
     data: &web::Data<AppState<Backend>>,
     name: &UserId,
+    session: &SessionInfo,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler,
 {
+    record_audit_event(
+        data,
+        session.ip_address,
+        "login",
+        Some(name.as_str()),
+        None,
+        true,
+    )
+    .await;
     // The authentication was successful, we need to fetch the groups to create the JWT
     // token.
     let groups = data.get_readonly_handler().get_user_groups(name).await?;
-    let (refresh_token, max_age) = data.get_tcp_handler().create_refresh_token(name).await?;
-    let token = create_jwt(data.get_tcp_handler(), &data.jwt_key, name, groups).await;
+    let (refresh_token, max_age) = data
+        .get_tcp_handler()
+        .create_refresh_token(name, session)
+        .await?;
+    let token = create_jwt(
+        data,
+        name,
+        groups,
+        Some(default_hash(refresh_token.as_str())),
+    )
+    .await;
     let refresh_token_plus_name = refresh_token + "+" + name.as_str();
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
@@ -392,32 +607,94 @@ where
         }))
 }
 
+/// Logs the user in, unless they enrolled a second factor: in that case, returns a token to
+/// finish the login with a TOTP code.
+#[instrument(skip_all, level = "debug")]
+async fn get_first_factor_successful_response<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    name: &UserId,
+    session: &SessionInfo,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler,
+{
+    if data
+        .get_tcp_handler()
+        .get_totp_secret(name)
+        .await?
+        .is_none()
+    {
+        return get_login_successful_response(data, name, session).await;
+    }
+    let mfa_token = data.get_tcp_handler().create_mfa_login_token(name).await?;
+    Ok(HttpResponse::Ok().json(&login::ServerMfaRequiredResponse { mfa_token }))
+}
+
+/// Checks a TOTP code, or failing that a recovery code (which is then consumed).
+async fn check_second_factor<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    user: &UserId,
+    secret: &str,
+    code: &str,
+) -> TcpResult<bool>
+where
+    Backend: TcpBackendHandler + BackendHandler,
+{
+    if let Some(counter) = totp::verify_code(secret, code, Utc::now().timestamp()) {
+        // A code that was already used is rejected.
+        return Ok(data
+            .get_tcp_handler()
+            .use_totp_counter(user, counter)
+            .await?);
+    }
+    Ok(data
+        .get_tcp_handler()
+        .use_recovery_code(user, default_hash(code.trim()))
+        .await?)
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn opaque_login_finish<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientLoginFinishRequest>,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
+    let source_ip = get_source_ip(&http_request, &data.trusted_proxies);
+    check_source_ip_lockout(&data, source_ip).await?;
     match data
         .get_opaque_handler()
         .login_finish(request.into_inner())
         .await
     {
-        Ok(name) => get_login_successful_response(&data, &name).await,
-        Err(e) => Err(e.into()),
+        Ok(name) => {
+            get_first_factor_successful_response(
+                &data,
+                &name,
+                &get_session_info(&http_request, &data.trusted_proxies),
+            )
+            .await
+        }
+        Err(e) => {
+            // The user name is only part of the first step, it isn't known here.
+            record_audit_event(&data, source_ip, "login", None, None, false).await;
+            register_source_ip_failure(&data, source_ip).await?;
+            Err(e.into())
+        }
     }
 }
 
 async fn opaque_login_finish_handler<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientLoginFinishRequest>,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    opaque_login_finish(data, request)
+    opaque_login_finish(data, http_request, request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -425,28 +702,420 @@ where
 #[instrument(skip_all, level = "debug")]
 async fn simple_login<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientSimpleLoginRequest>,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
-    let login::ClientSimpleLoginRequest { username, password } = request.into_inner();
+    let login::ClientSimpleLoginRequest {
+        username,
+        password,
+        totp_code,
+    } = request.into_inner();
+    let source_ip = get_source_ip(&http_request, &data.trusted_proxies);
+    check_source_ip_lockout(&data, source_ip).await?;
     let bind_request = BindRequest {
         name: username.clone(),
         password,
     };
-    data.get_login_handler().bind(bind_request).await?;
-    get_login_successful_response(&data, &username).await
+    if let Err(e) = data.get_login_handler().bind(bind_request).await {
+        record_audit_event(
+            &data,
+            source_ip,
+            "login",
+            Some(username.as_str()),
+            None,
+            false,
+        )
+        .await;
+        register_source_ip_failure(&data, source_ip).await?;
+        return Err(e.into());
+    }
+    if let Some(secret) = data.get_tcp_handler().get_totp_secret(&username).await? {
+        let code = totp_code
+            .ok_or_else(|| DomainError::AuthenticationError("Missing TOTP code".to_string()))?;
+        if !check_second_factor(&data, &username, &secret, &code).await? {
+            record_audit_event(
+                &data,
+                source_ip,
+                "login",
+                Some(username.as_str()),
+                None,
+                false,
+            )
+            .await;
+            return Err(DomainError::AuthenticationError("Invalid TOTP code".to_string()).into());
+        }
+    }
+    get_login_successful_response(
+        &data,
+        &username,
+        &get_session_info(&http_request, &data.trusted_proxies),
+    )
+    .await
 }
 
 async fn simple_login_handler<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientSimpleLoginRequest>,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
-    simple_login(data, request)
+    simple_login(data, http_request, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn totp_login<Backend>(
+    data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
+    request: web::Json<login::ClientTotpLoginRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let login::ClientTotpLoginRequest { mfa_token, code } = request.into_inner();
+    let token_hash = default_hash(mfa_token.as_str());
+    let user = data
+        .get_tcp_handler()
+        .get_user_id_for_mfa_login_token(token_hash)
+        .await
+        .map_err(|e| match e {
+            DomainError::EntityNotFound(_) => {
+                DomainError::AuthenticationError("Invalid or expired MFA token".to_string())
+            }
+            e => e,
+        })?;
+    let valid = match data.get_tcp_handler().get_totp_secret(&user).await? {
+        Some(secret) => check_second_factor(&data, &user, &secret, &code).await?,
+        // The second factor was reset since the password was checked.
+        None => true,
+    };
+    if !valid {
+        record_audit_event(
+            &data,
+            get_source_ip(&http_request, &data.trusted_proxies),
+            "login",
+            Some(user.as_str()),
+            None,
+            false,
+        )
+        .await;
+        data.get_tcp_handler()
+            .register_failed_mfa_attempt(token_hash)
+            .await?;
+        return Err(DomainError::AuthenticationError("Invalid TOTP code".to_string()).into());
+    }
+    data.get_tcp_handler()
+        .delete_mfa_login_token(token_hash)
+        .await?;
+    get_login_successful_response(
+        &data,
+        &user,
+        &get_session_info(&http_request, &data.trusted_proxies),
+    )
+    .await
+}
+
+async fn totp_login_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
+    request: web::Json<login::ClientTotpLoginRequest>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    totp_login(data, http_request, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+const TOTP_ISSUER: &str = "LLDAP";
+
+#[instrument(skip_all, level = "debug")]
+async fn totp_enroll_start<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> TcpResult<mfa::ServerTotpEnrollStartResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .await
+        .map_err(|_| {
+            TcpError::UnauthorizedError("Not authorized to enroll a second factor".to_string())
+        })?;
+    let secret = totp::generate_secret();
+    let uri = totp::get_otpauth_uri(TOTP_ISSUER, &validation_result.user, &secret);
+    Ok(mfa::ServerTotpEnrollStartResponse { secret, uri })
+}
+
+async fn totp_enroll_start_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> ApiResult<mfa::ServerTotpEnrollStartResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    totp_enroll_start(data, bearer)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn totp_enroll_finish<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: web::Json<mfa::ClientTotpEnrollFinishRequest>,
+) -> TcpResult<mfa::ServerTotpEnrollFinishResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .await
+        .map_err(|_| {
+            TcpError::UnauthorizedError("Not authorized to enroll a second factor".to_string())
+        })?;
+    if !validation_result.can_write(&validation_result.user) {
+        return Err(TcpError::UnauthorizedError(
+            "Not authorized to enroll a second factor".to_string(),
+        ));
+    }
+    // Replacing an enrolled second factor goes through the reset, which asks for the password or
+    // a current code.
+    if data
+        .get_tcp_handler()
+        .get_totp_secret(&validation_result.user)
+        .await?
+        .is_some()
+    {
+        return Err(TcpError::BadRequest(
+            "A second factor is already enrolled, reset it first".to_string(),
+        ));
+    }
+    let mfa::ClientTotpEnrollFinishRequest { secret, code } = request.into_inner();
+    // The secret is stored in a 64-character column.
+    if secret.len() > 64 {
+        return Err(TcpError::BadRequest("Invalid TOTP secret".to_string()));
+    }
+    let counter = totp::verify_code(&secret, &code, Utc::now().timestamp())
+        .ok_or_else(|| TcpError::BadRequest("Invalid TOTP code".to_string()))?;
+    let recovery_codes = totp::generate_recovery_codes();
+    let recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| default_hash(code.as_str()))
+        .collect::<Vec<_>>();
+    data.get_tcp_handler()
+        .set_totp_secret(&validation_result.user, &secret, &recovery_code_hashes)
+        .await?;
+    // The enrollment code cannot be used again to log in.
+    data.get_tcp_handler()
+        .use_totp_counter(&validation_result.user, counter)
+        .await?;
+    Ok(mfa::ServerTotpEnrollFinishResponse { recovery_codes })
+}
+
+async fn totp_enroll_finish_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: web::Json<mfa::ClientTotpEnrollFinishRequest>,
+) -> ApiResult<mfa::ServerTotpEnrollFinishResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    totp_enroll_finish(data, bearer, request)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+/// Checks that the user resetting their own second factor still knows their password, or has a
+/// current code: a stolen session is not enough.
+async fn check_totp_reset_confirmation<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    user_id: &UserId,
+    confirmation: mfa::ClientTotpResetRequest,
+) -> TcpResult<()>
+where
+    Backend: TcpBackendHandler + BackendHandler + LoginHandler,
+{
+    let confirmed = if let Some(password) = confirmation.password {
+        data.get_login_handler()
+            .bind(BindRequest {
+                name: user_id.clone(),
+                password,
+            })
+            .await
+            .is_ok()
+    } else if let Some(code) = confirmation.code {
+        match data.get_tcp_handler().get_totp_secret(user_id).await? {
+            Some(secret) => check_second_factor(data, user_id, &secret, &code).await?,
+            // Nothing to reset.
+            None => true,
+        }
+    } else {
+        false
+    };
+    if !confirmed {
+        return Err(TcpError::UnauthorizedError(
+            "The password or a current code is required to reset the second factor".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn totp_reset<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+    confirmation: web::Json<mfa::ClientTotpResetRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .await
+        .map_err(|_| {
+            TcpError::UnauthorizedError("Not authorized to reset the second factor".to_string())
+        })?;
+    let user_id = UserId::new(
+        request
+            .match_info()
+            .get("user_id")
+            .ok_or_else(|| TcpError::BadRequest("Missing user ID".to_string()))?,
+    );
+    if !validation_result.can_write(&user_id) {
+        return Err(TcpError::UnauthorizedError(
+            "Not authorized to reset the user's second factor".to_string(),
+        ));
+    }
+    if validation_result.user == user_id {
+        check_totp_reset_confirmation(&data, &user_id, confirmation.into_inner()).await?;
+    }
+    data.get_tcp_handler().delete_totp_secret(&user_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn totp_reset_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+    confirmation: web::Json<mfa::ClientTotpResetRequest>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
+{
+    totp_reset(data, bearer, request, confirmation)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[derive(Deserialize)]
+struct VerifyQuery {
+    /// If set, the user must be a member of this group.
+    group: Option<String>,
+    /// Whether to redirect unauthenticated users to the login page, or just answer 401 (as
+    /// expected by nginx's `auth_request`).
+    #[serde(default = "default_verify_redirect")]
+    redirect: bool,
+    /// URL to go back to after logging in. Defaults to the one given by the proxy in the
+    /// `X-Forwarded-*` headers.
+    rd: Option<String>,
+}
+
+fn default_verify_redirect() -> bool {
+    true
+}
+
+/// Gets the token from the `Authorization` header or, failing that, from the cookie.
+fn get_token_from_request(request: &HttpRequest) -> Option<String> {
+    request
+        .headers()
+        .get(actix_http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::to_owned)
+        .or_else(|| request.cookie("token").map(|c| c.value().to_owned()))
+}
+
+/// Gets the URL the user was trying to reach, to send them back there after they log in.
+fn get_original_url(request: &HttpRequest, query: &VerifyQuery) -> Option<String> {
+    if let Some(rd) = &query.rd {
+        return Some(rd.clone());
+    }
+    let get_header = |name: &str| request.headers().get(name).and_then(|h| h.to_str().ok());
+    let host = get_header("X-Forwarded-Host")?;
+    let proto = get_header("X-Forwarded-Proto").unwrap_or("https");
+    let uri = get_header("X-Forwarded-Uri").unwrap_or("/");
+    Some(format!("{}://{}{}", proto, host, uri))
+}
+
+/// Forward-auth endpoint for reverse proxies: answers whether the request is authenticated, and
+/// passes the user information in `Remote-*` headers.
+#[instrument(skip_all, level = "debug")]
+async fn verify<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    query: web::Query<VerifyQuery>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let validation_result = match get_token_from_request(&request) {
+        Some(token) => check_if_token_is_valid(&data, &token).await.ok(),
+        None => None,
+    };
+    let validation_result = match validation_result {
+        Some(validation_result) => validation_result,
+        None if query.redirect => {
+            let mut login_url = format!("{}/login", data.server_url.as_str().trim_end_matches('/'));
+            if let Some(original_url) = get_original_url(&request, &query) {
+                login_url.push_str("?rd=");
+                login_url.push_str(&urlencoding::encode(&original_url));
+            }
+            return Ok(HttpResponse::Found()
+                .insert_header((actix_http::header::LOCATION, login_url))
+                .finish());
+        }
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let user = &validation_result.user;
+    let groups = data.get_readonly_handler().get_user_groups(user).await?;
+    if let Some(group) = &query.group {
+        let group = GroupName::from(group.as_str());
+        if !groups.iter().any(|g| g.display_name == group) {
+            debug!(?user, ?group, "User is not a member of the required group");
+            return Ok(HttpResponse::Forbidden().finish());
+        }
+    }
+    let user_details = data.get_readonly_handler().get_user_details(user).await?;
+    let mut group_names = groups
+        .iter()
+        .map(|g| g.display_name.as_str())
+        .collect::<Vec<_>>();
+    group_names.sort_unstable();
+    Ok(HttpResponse::Ok()
+        .insert_header(("Remote-User", user.as_str()))
+        .insert_header(("Remote-Groups", group_names.join(",")))
+        .insert_header(("Remote-Email", user_details.email.as_str()))
+        .finish())
+}
+
+async fn verify_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    query: web::Query<VerifyQuery>,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    verify(data, request, query)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -462,13 +1131,13 @@ where
 {
     use actix_web::FromRequest;
     let inner_payload = &mut payload.into_inner();
-    let validation_result = BearerAuth::from_request(&request, inner_payload)
-        .await
-        .ok()
-        .and_then(|bearer| check_if_token_is_valid(&data, bearer.token()).ok())
-        .ok_or_else(|| {
-            TcpError::UnauthorizedError("Not authorized to change the user's password".to_string())
-        })?;
+    let validation_result = match BearerAuth::from_request(&request, inner_payload).await {
+        Ok(bearer) => check_if_token_is_valid(&data, bearer.token()).await.ok(),
+        Err(_) => None,
+    }
+    .ok_or_else(|| {
+        TcpError::UnauthorizedError("Not authorized to change the user's password".to_string())
+    })?;
     let registration_start_request =
         web::Json::<registration::ClientRegistrationStartRequest>::from_request(
             &request,
@@ -484,7 +1153,19 @@ where
         .await?
         .iter()
         .any(|g| g.display_name == "lldap_admin".into());
-    if !validation_result.can_change_password(user_id, user_is_admin) {
+    // The new password is only uploaded in the second step, which doesn't know the user: the
+    // change is recorded when it's authorized.
+    let authorized = validation_result.can_change_password(user_id, user_is_admin);
+    record_audit_event(
+        &data,
+        get_source_ip(&request, &data.trusted_proxies),
+        "change_password",
+        Some(validation_result.user.as_str()),
+        Some(format!("user:{}", user_id)),
+        authorized,
+    )
+    .await;
+    if !authorized {
         return Err(TcpError::UnauthorizedError(
             "Not authorized to change the user's password".to_string(),
         ));
@@ -572,6 +1253,13 @@ where
     }
 
     fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        // An explicit header, e.g. with an API token, takes precedence over the session cookie.
+        if req
+            .headers()
+            .contains_key(actix_http::header::AUTHORIZATION)
+        {
+            return Box::pin(self.service.call(req));
+        }
         if let Some(token_cookie) = req.cookie("token") {
             if let Ok(header_value) = actix_http::header::HeaderValue::from_str(&format!(
                 "Bearer {}",
@@ -592,33 +1280,84 @@ where
 }
 
 #[instrument(skip_all, level = "debug", err, ret)]
-pub(crate) fn check_if_token_is_valid<Backend: BackendHandler>(
+async fn check_if_api_token_is_valid<Backend: BackendHandler>(
     state: &AppState<Backend>,
     token_str: &str,
 ) -> Result<ValidationResults, actix_web::Error> {
-    let token: Token<_> = VerifyWithKey::verify_with_key(token_str, &state.jwt_key)
-        .map_err(|_| ErrorUnauthorized("Invalid JWT"))?;
-    if token.claims().exp.lt(&Utc::now()) {
+    let api_token = state
+        .backend_handler
+        .unsafe_get_handler()
+        .get_api_token_from_secret(token_str)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .ok_or_else(|| ErrorUnauthorized("Invalid API token"))?;
+    if !state
+        .get_readonly_handler()
+        .get_user_details(&api_token.user_id)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .is_active_at(Utc::now().naive_utc())
+    {
+        return Err(ErrorUnauthorized("The account is disabled or expired"));
+    }
+    let mut validation_result = state
+        .backend_handler
+        .get_permissions_for_user(api_token.user_id)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    validation_result.api_token_scope = Some(api_token.scope);
+    Ok(validation_result)
+}
+
+/// Checks either a JWT or, if it has the right prefix, an API token.
+#[instrument(skip_all, level = "debug", err, ret)]
+pub(crate) async fn check_if_token_is_valid<Backend: BackendHandler>(
+    state: &AppState<Backend>,
+    token_str: &str,
+) -> Result<ValidationResults, actix_web::Error> {
+    if token_str.starts_with(API_TOKEN_PREFIX) {
+        return check_if_api_token_is_valid(state, token_str).await;
+    }
+    let claims = verify_jwt(state, token_str)?;
+    if claims.exp.lt(&Utc::now()) {
         return Err(ErrorUnauthorized("Expired JWT"));
     }
+    let jwt_hash = default_hash(token_str);
+    if state.jwt_blacklist.read().unwrap().contains(&jwt_hash) {
+        return Err(ErrorUnauthorized("JWT was logged out"));
+    }
+    state
+        .backend_handler
+        .get_permissions_from_groups(
+            UserId::new(&claims.user),
+            claims.groups.iter().map(|s| GroupName::from(s.as_str())),
+        )
+        .await
+        .map_err(ErrorInternalServerError)
+}
+
+/// Checks the signature of the JWT, with the RSA key if it is configured. Tokens signed with the
+/// `jwt_secret` are always accepted, so that switching to RS256 doesn't log everyone out.
+fn verify_jwt<Backend>(
+    state: &AppState<Backend>,
+    token_str: &str,
+) -> Result<JWTClaims, actix_web::Error> {
+    if let Some(claims) = state
+        .jwt_rsa_key
+        .as_ref()
+        .and_then(|key| key.verify::<JWTClaims>(token_str).ok())
+    {
+        return Ok(claims);
+    }
+    let token: Token<_> = VerifyWithKey::verify_with_key(token_str, &state.jwt_key)
+        .map_err(|_| ErrorUnauthorized("Invalid JWT"))?;
     if token.header().algorithm != jwt::AlgorithmType::Hs512 {
         return Err(ErrorUnauthorized(format!(
-            "Unsupported JWT algorithm: '{:?}'. Supported ones are: ['HS512']",
+            "Unsupported JWT algorithm: '{:?}'. Supported ones are: ['HS512', 'RS256']",
             token.header().algorithm
         )));
     }
-    let jwt_hash = default_hash(token_str);
-    if state.jwt_blacklist.read().unwrap().contains(&jwt_hash) {
-        return Err(ErrorUnauthorized("JWT was logged out"));
-    }
-    Ok(state.backend_handler.get_permissions_from_groups(
-        UserId::new(&token.claims().user),
-        token
-            .claims()
-            .groups
-            .iter()
-            .map(|s| GroupName::from(s.as_str())),
-    ))
+    Ok(token.claims().clone())
 }
 
 pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig, enable_password_reset: bool)
@@ -626,7 +1365,8 @@ where
     Backend: TcpBackendHandler + LoginHandler + OpaqueHandler + BackendHandler + 'static,
 {
     cfg.service(
-        web::resource("/opaque/login/start").route(web::post().to(opaque_login_start::<Backend>)),
+        web::resource("/opaque/login/start")
+            .route(web::post().to(opaque_login_start_handler::<Backend>)),
     )
     .service(
         web::resource("/opaque/login/finish")
@@ -635,6 +1375,25 @@ where
     .service(web::resource("/simple/login").route(web::post().to(simple_login_handler::<Backend>)))
     .service(web::resource("/refresh").route(web::get().to(get_refresh_handler::<Backend>)))
     .service(web::resource("/logout").route(web::get().to(get_logout_handler::<Backend>)))
+    // Reverse proxies forward the method of the original request.
+    .service(web::resource("/verify").route(web::route().to(verify_handler::<Backend>)))
+    .service(web::resource("/totp/login").route(web::post().to(totp_login_handler::<Backend>)))
+    .service(
+        web::scope("/totp")
+            .wrap(CookieToHeaderTranslatorFactory)
+            .service(
+                web::resource("/enroll/start")
+                    .route(web::post().to(totp_enroll_start_handler::<Backend>)),
+            )
+            .service(
+                web::resource("/enroll/finish")
+                    .route(web::post().to(totp_enroll_finish_handler::<Backend>)),
+            )
+            .service(
+                web::resource("/reset/{user_id}")
+                    .route(web::post().to(totp_reset_handler::<Backend>)),
+            ),
+    )
     .service(
         web::scope("/opaque/register")
             .wrap(CookieToHeaderTranslatorFactory)
@@ -662,3 +1421,419 @@ where
         );
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::oidc_key::OidcKey;
+    use actix_web::{FromRequest, test::TestRequest};
+    use lldap_access_control::AccessControlledBackendHandler;
+    use lldap_auth::opaque::server::generate_random_private_key;
+    use lldap_domain::requests::CreateUserRequest;
+    use lldap_domain::types::{GroupId, User};
+    use lldap_domain_handlers::handler::UserBackendHandler;
+    use lldap_sql_backend_handler::{LoginThrottleOptions, SqlBackendHandler};
+    use lldap_test_utils::MockTestBackendHandler;
+    use std::{path::PathBuf, sync::Arc};
+
+    pub(crate) fn make_app_state<Backend: BackendHandler>(
+        backend_handler: Backend,
+    ) -> AppState<Backend> {
+        AppState {
+            backend_handler: AccessControlledBackendHandler::new(backend_handler),
+            jwt_key: hmac::Mac::new_from_slice(b"secret").unwrap(),
+            jwt_rsa_key: None,
+            jwt_blacklist: Default::default(),
+            server_url: url::Url::parse("https://auth.example.com/").unwrap(),
+            assets_path: PathBuf::new(),
+            mail_options: Default::default(),
+            oidc_key: None,
+            oidc_options: Default::default(),
+            password_policy: Default::default(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+
+    fn make_state(mock: MockTestBackendHandler) -> web::Data<AppState<MockTestBackendHandler>> {
+        web::Data::new(make_app_state(mock))
+    }
+
+    fn make_group(id: i32, name: &str) -> GroupDetails {
+        GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.into(),
+            creation_date: chrono::NaiveDateTime::default(),
+            modified_date: chrono::NaiveDateTime::default(),
+            uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Expects a logged in "bob", member of "family" and "admins".
+    fn make_logged_in_mock() -> MockTestBackendHandler {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_role_permissions()
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_get_owned_groups().returning(|_| Ok(Vec::new()));
+        mock.expect_get_user_groups()
+            .withf(|user_id| user_id.as_str() == "bob")
+            .returning(|_| {
+                Ok(HashSet::from([
+                    make_group(1, "family"),
+                    make_group(2, "admins"),
+                ]))
+            });
+        mock
+    }
+
+    fn make_claims() -> JWTClaims {
+        JWTClaims {
+            exp: Utc::now() + chrono::Duration::days(1),
+            iat: Utc::now(),
+            user: "bob".to_owned(),
+            groups: HashSet::from(["family".to_owned(), "admins".to_owned()]),
+        }
+    }
+
+    fn make_jwt<Backend>(state: &AppState<Backend>) -> String {
+        sign_jwt(state, make_claims())
+    }
+
+    pub(crate) fn make_rsa_key() -> Arc<OidcKey> {
+        // A small key keeps the tests fast.
+        Arc::new(OidcKey::new(
+            rsa::RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap(),
+        ))
+    }
+
+    fn get_jwt_algorithm(token: &str) -> String {
+        let (header, _) = token.split_once('.').unwrap();
+        let header: serde_json::Value = serde_json::from_slice(
+            &data_encoding::BASE64URL_NOPAD
+                .decode(header.as_bytes())
+                .unwrap(),
+        )
+        .unwrap();
+        header["alg"].as_str().unwrap().to_owned()
+    }
+
+    async fn call_verify(
+        state: web::Data<AppState<MockTestBackendHandler>>,
+        request: TestRequest,
+        query: &str,
+    ) -> HttpResponse {
+        verify_handler(
+            state,
+            request.to_http_request(),
+            web::Query::from_query(query).unwrap(),
+        )
+        .await
+    }
+
+    fn get_header<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response.headers().get(name).and_then(|h| h.to_str().ok())
+    }
+
+    #[tokio::test]
+    async fn test_verify_with_authorization_header() {
+        let mut mock = make_logged_in_mock();
+        mock.expect_get_user_details().returning(|_| {
+            Ok(User {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".into(),
+                ..Default::default()
+            })
+        });
+        let state = make_state(mock);
+        let request = TestRequest::default().insert_header((
+            actix_http::header::AUTHORIZATION,
+            format!("Bearer {}", make_jwt(&state)),
+        ));
+        let response = call_verify(state, request, "").await;
+        assert_eq!(response.status(), actix_http::StatusCode::OK);
+        assert_eq!(get_header(&response, "Remote-User"), Some("bob"));
+        assert_eq!(
+            get_header(&response, "Remote-Groups"),
+            Some("admins,family")
+        );
+        assert_eq!(
+            get_header(&response, "Remote-Email"),
+            Some("bob@example.com")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_with_cookie() {
+        let mut mock = make_logged_in_mock();
+        mock.expect_get_user_details().returning(|_| {
+            Ok(User {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".into(),
+                ..Default::default()
+            })
+        });
+        let state = make_state(mock);
+        let request = TestRequest::default().cookie(Cookie::new("token", make_jwt(&state)));
+        let response = call_verify(state, request, "group=family").await;
+        assert_eq!(response.status(), actix_http::StatusCode::OK);
+        assert_eq!(get_header(&response, "Remote-User"), Some("bob"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_not_in_group() {
+        let state = make_state(make_logged_in_mock());
+        let request = TestRequest::default().cookie(Cookie::new("token", make_jwt(&state)));
+        let response = call_verify(state, request, "group=dashboard_users").await;
+        assert_eq!(response.status(), actix_http::StatusCode::FORBIDDEN);
+        assert_eq!(get_header(&response, "Remote-User"), None);
+    }
+
+    #[tokio::test]
+    async fn test_verify_unauthenticated_redirects_to_login() {
+        let state = make_state(MockTestBackendHandler::new());
+        let request = TestRequest::default()
+            .insert_header(("X-Forwarded-Proto", "http"))
+            .insert_header(("X-Forwarded-Host", "auth.example.com"))
+            .insert_header(("X-Forwarded-Uri", "/dashboard/?page=1"));
+        let response = call_verify(state.clone(), request, "").await;
+        assert_eq!(response.status(), actix_http::StatusCode::FOUND);
+        assert_eq!(
+            get_header(&response, "Location"),
+            Some(
+                "https://auth.example.com/login?rd=http%3A%2F%2Fauth.example.com%2Fdashboard%2F%3Fpage%3D1"
+            )
+        );
+        // An explicit `rd` takes precedence over the headers.
+        let request = TestRequest::default()
+            .insert_header(("X-Forwarded-Host", "auth.example.com"))
+            .cookie(Cookie::new("token", "invalid"));
+        let response = call_verify(
+            state.clone(),
+            request,
+            "rd=https%3A%2F%2Fauth.example.com%2Fapp%2F",
+        )
+        .await;
+        assert_eq!(response.status(), actix_http::StatusCode::FOUND);
+        assert_eq!(
+            get_header(&response, "Location"),
+            Some("https://auth.example.com/login?rd=https%3A%2F%2Fauth.example.com%2Fapp%2F")
+        );
+        // Without the headers, there is nowhere to go back to.
+        let response = call_verify(state, TestRequest::default(), "").await;
+        assert_eq!(
+            get_header(&response, "Location"),
+            Some("https://auth.example.com/login")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_unauthenticated_without_redirect() {
+        let state = make_state(MockTestBackendHandler::new());
+        let response = call_verify(state.clone(), TestRequest::default(), "redirect=false").await;
+        assert_eq!(response.status(), actix_http::StatusCode::UNAUTHORIZED);
+        let request = TestRequest::default()
+            .insert_header((actix_http::header::AUTHORIZATION, "Bearer invalid"));
+        let response = call_verify(state, request, "redirect=false").await;
+        assert_eq!(response.status(), actix_http::StatusCode::UNAUTHORIZED);
+        assert_eq!(get_header(&response, "Location"), None);
+    }
+
+    #[test]
+    fn test_jwt_round_trip_hs512() {
+        let state = make_app_state(MockTestBackendHandler::new());
+        let token = make_jwt(&state);
+        assert_eq!(get_jwt_algorithm(&token), "HS512");
+        let claims = verify_jwt(&state, &token).unwrap();
+        assert_eq!(claims.user, "bob");
+        assert_eq!(claims.groups, make_claims().groups);
+        // The dates are serialized as numbers, as required by the standard.
+        let payload = token.split('.').nth(1).unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(
+            &data_encoding::BASE64URL_NOPAD
+                .decode(payload.as_bytes())
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(payload["exp"].is_i64());
+        assert!(payload["iat"].is_i64());
+    }
+
+    #[test]
+    fn test_jwt_round_trip_rs256() {
+        let hs512_token = make_jwt(&make_app_state(MockTestBackendHandler::new()));
+        let mut state = make_app_state(MockTestBackendHandler::new());
+        state.jwt_rsa_key = Some(make_rsa_key());
+        let token = make_jwt(&state);
+        assert_eq!(get_jwt_algorithm(&token), "RS256");
+        let claims = verify_jwt(&state, &token).unwrap();
+        assert_eq!(claims.user, "bob");
+        assert_eq!(claims.groups, make_claims().groups);
+        // The tokens issued before switching to RS256 are still valid.
+        assert_eq!(verify_jwt(&state, &hs512_token).unwrap().user, "bob");
+        // But not the ones signed with another key.
+        let mut other_state = make_app_state(MockTestBackendHandler::new());
+        other_state.jwt_rsa_key = Some(make_rsa_key());
+        assert!(verify_jwt(&state, &make_jwt(&other_state)).is_err());
+    }
+
+    #[test]
+    fn test_legacy_jwt_with_rfc3339_dates() {
+        let state = make_app_state(MockTestBackendHandler::new());
+        let exp = Utc::now() + chrono::Duration::days(1);
+        // Tokens issued by older versions serialized the dates as RFC 3339 strings.
+        let claims = serde_json::json!({
+            "exp": exp.to_rfc3339(),
+            "iat": Utc::now().to_rfc3339(),
+            "user": "bob",
+            "groups": ["family"],
+        });
+        let header = jwt::Header {
+            algorithm: jwt::AlgorithmType::Hs512,
+            ..Default::default()
+        };
+        let token = jwt::Token::new(header, claims)
+            .sign_with_key(&state.jwt_key)
+            .unwrap()
+            .as_str()
+            .to_owned();
+        let claims = verify_jwt(&state, &token).unwrap();
+        assert_eq!(claims.user, "bob");
+        assert_eq!(claims.exp.timestamp(), exp.timestamp());
+    }
+
+    #[test]
+    fn test_get_source_ip() {
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let source_ip = |peer: IpAddr, header: (&str, &str), trusted_proxies: &[IpAddr]| {
+            let request = TestRequest::default()
+                .peer_addr(SocketAddr::new(peer, 1234))
+                .insert_header(header)
+                .to_http_request();
+            get_source_ip(&request, trusted_proxies)
+        };
+        // Without trusted proxies, the headers are ignored.
+        assert_eq!(
+            source_ip(proxy, ("X-Forwarded-For", "192.0.2.1"), &[]),
+            Some(proxy)
+        );
+        assert_eq!(
+            source_ip(proxy, ("Forwarded", "for=192.0.2.1"), &[]),
+            Some(proxy)
+        );
+        // Behind a trusted proxy, the address added by the proxy is used.
+        assert_eq!(
+            source_ip(proxy, ("X-Forwarded-For", "192.0.2.1"), &[proxy]),
+            Some(client)
+        );
+        assert_eq!(
+            source_ip(
+                proxy,
+                ("Forwarded", r#"for="[2001:db8::1]:4711";proto=https"#),
+                &[proxy]
+            ),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        // The addresses sent by the client before the proxy are ignored.
+        assert_eq!(
+            source_ip(
+                proxy,
+                ("X-Forwarded-For", "203.0.113.7, 192.0.2.1"),
+                &[proxy]
+            ),
+            Some(client)
+        );
+        // An untrusted client cannot pretend to be a proxy.
+        assert_eq!(
+            source_ip(client, ("X-Forwarded-For", "203.0.113.7"), &[proxy]),
+            Some(client)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_forwarded_header_does_not_change_throttle_subject() {
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let subject = LoginThrottleSubject::Ip(client);
+        let mut mock = MockTestBackendHandler::new();
+        let expected_subject = subject.clone();
+        mock.expect_get_login_lockout()
+            .withf(move |s| *s == expected_subject)
+            .times(1)
+            .returning(|_| Ok(None));
+        mock.expect_bind().times(1).returning(|_| {
+            Err(DomainError::AuthenticationError(
+                "Invalid password".to_string(),
+            ))
+        });
+        mock.expect_record_audit_event().returning(|_| Ok(()));
+        mock.expect_register_login_failure()
+            .withf(move |s| *s == subject)
+            .times(1)
+            .returning(|_| Ok(()));
+        let mut state = make_app_state(mock);
+        state.trusted_proxies = vec!["10.0.0.1".parse().unwrap()];
+        let request = TestRequest::default()
+            .peer_addr(SocketAddr::new(client, 1234))
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        let response = simple_login(
+            web::Data::new(state),
+            request,
+            web::Json(login::ClientSimpleLoginRequest {
+                username: UserId::new("bob"),
+                password: "wrong".to_string(),
+                totp_code: None,
+            }),
+        )
+        .await;
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_totp_enroll_over_existing_secret() {
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            crate::db_cleaner::tests::get_initialized_db().await,
+            LoginThrottleOptions::default(),
+        );
+        let bob = UserId::new("bob");
+        handler
+            .create_user(CreateUserRequest {
+                user_id: bob.clone(),
+                email: "bob@example.com".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let state = web::Data::new(make_app_state(handler));
+        let enroll = async |secret: &str| {
+            let request = TestRequest::default()
+                .insert_header((
+                    actix_http::header::AUTHORIZATION,
+                    format!("Bearer {}", make_jwt(&state)),
+                ))
+                .to_http_request();
+            let bearer = BearerAuth::extract(&request).await.unwrap();
+            totp_enroll_finish(
+                state.clone(),
+                bearer,
+                web::Json(mfa::ClientTotpEnrollFinishRequest {
+                    secret: secret.to_owned(),
+                    code: totp::tests::get_code(secret, Utc::now().timestamp()),
+                }),
+            )
+            .await
+        };
+        let secret = totp::generate_secret();
+        enroll(&secret).await.unwrap();
+        // A stolen session cannot silently replace the second factor.
+        assert!(matches!(
+            enroll(&totp::generate_secret()).await,
+            Err(TcpError::BadRequest(_))
+        ));
+        assert_eq!(
+            state.get_tcp_handler().get_totp_secret(&bob).await.unwrap(),
+            Some(secret)
+        );
+    }
+}
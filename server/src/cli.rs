@@ -207,6 +207,14 @@ pub struct LdapsOpts {
     /// Ldaps certificate key file. Default: key.pem
     #[clap(long, env = "LLDAP_LDAPS_OPTIONS__KEY_FILE")]
     pub ldaps_key_file: Option<String>,
+
+    /// Allow StartTLS on the plain LDAP port, with the LDAPS certificate. Default: false
+    #[clap(long, env = "LLDAP_LDAPS_OPTIONS__START_TLS")]
+    pub ldaps_start_tls: Option<bool>,
+
+    /// Refuse binds on unencrypted connections (neither LDAPS nor StartTLS). Default: false
+    #[clap(long, env = "LLDAP_LDAPS_OPTIONS__REQUIRE_TLS_FOR_BIND")]
+    pub ldaps_require_tls_for_bind: Option<bool>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, clap::ValueEnum)]
@@ -16,12 +16,15 @@ use lldap_auth::opaque::{
     server::{ServerSetup, generate_random_private_key},
 };
 use lldap_domain::types::{AttributeName, UserId};
-use lldap_sql_backend_handler::sql_tables::{
-    ConfigLocation, PrivateKeyHash, PrivateKeyInfo, PrivateKeyLocation,
+use lldap_sql_backend_handler::{
+    LoginThrottleOptions,
+    sql_tables::{ConfigLocation, PrivateKeyHash, PrivateKeyInfo, PrivateKeyLocation},
 };
+use lldap_validation::password::PasswordPolicy;
 use secstr::SecUtf8;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::net::IpAddr;
 use std::path::PathBuf;
 use url::Url;
 
@@ -75,6 +78,10 @@ pub struct LdapsOptions {
     pub cert_file: String,
     #[builder(default = r#"String::from("key.pem")"#)]
     pub key_file: String,
+    #[builder(default = "false")]
+    pub start_tls: bool,
+    #[builder(default = "false")]
+    pub require_tls_for_bind: bool,
 }
 
 impl std::default::Default for LdapsOptions {
@@ -83,6 +90,54 @@ impl std::default::Default for LdapsOptions {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct OidcOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// RSA private key used to sign the tokens, generated on the first start if missing.
+    #[builder(default = r#"String::from("oidc_private_key.pem")"#)]
+    pub key_file: String,
+    /// Additional claims sent with the "profile" scope, mapped to the user attribute they are
+    /// read from.
+    #[builder(default)]
+    pub extra_claims: BTreeMap<String, AttributeName>,
+}
+
+impl std::default::Default for OidcOptions {
+    fn default() -> Self {
+        OidcOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Algorithm used to sign the JWTs issued on login.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum JwtAlgorithm {
+    /// HMAC with the `jwt_secret`: only LLDAP can verify the tokens.
+    #[default]
+    #[serde(rename = "HS512")]
+    Hs512,
+    /// RSA key, whose public part is published at `/.well-known/jwks.json`.
+    #[serde(rename = "RS256")]
+    Rs256,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct JwtOptions {
+    #[builder(default)]
+    pub algorithm: JwtAlgorithm,
+    /// RSA private key used with RS256, generated on the first start if missing.
+    #[builder(default = r#"String::from("jwt_private_key.pem")"#)]
+    pub key_file: String,
+}
+
+impl std::default::Default for JwtOptions {
+    fn default() -> Self {
+        JwtOptionsBuilder::default().build().unwrap()
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, derive_more::Debug)]
 #[debug(r#""{_0}""#)]
 pub struct HttpUrl(pub Url);
@@ -98,8 +153,14 @@ pub struct Configuration {
     pub http_host: String,
     #[builder(default = "17170")]
     pub http_port: u16,
+    /// The reverse proxies allowed to report the client address, with the `Forwarded` or
+    /// `X-Forwarded-For` headers.
+    #[builder(default)]
+    pub trusted_proxies: Vec<IpAddr>,
     #[builder(default)]
     pub jwt_secret: Option<SecUtf8>,
+    #[builder(default)]
+    pub jwt_options: JwtOptions,
     #[builder(default = r#"String::from("dc=example,dc=com")"#)]
     pub ldap_base_dn: String,
     #[builder(default = r#"UserId::new("admin")"#)]
@@ -132,6 +193,15 @@ pub struct Configuration {
     pub smtp_options: MailOptions,
     #[builder(default)]
     pub ldaps_options: LdapsOptions,
+    #[builder(default)]
+    pub oidc_options: OidcOptions,
+    #[builder(default)]
+    pub password_policy: PasswordPolicy,
+    #[builder(default)]
+    pub login_throttle: LoginThrottleOptions,
+    /// Number of days the audit events are kept. 0 keeps them forever.
+    #[builder(default = "365")]
+    pub audit_log_retention_days: u32,
     #[builder(default = r#"HttpUrl(Url::parse("http://localhost").unwrap())"#)]
     pub http_url: HttpUrl,
     #[debug(skip)]
@@ -475,6 +545,12 @@ impl ConfigOverrider for LdapsOpts {
         if let Some(path) = self.ldaps_key_file.as_ref() {
             config.ldaps_options.key_file.clone_from(path);
         }
+        if let Some(start_tls) = self.ldaps_start_tls {
+            config.ldaps_options.start_tls = start_tls;
+        }
+        if let Some(require_tls_for_bind) = self.ldaps_require_tls_for_bind {
+            config.ldaps_options.require_tls_for_bind = require_tls_for_bind;
+        }
     }
 }
 
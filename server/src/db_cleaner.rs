@@ -2,9 +2,14 @@ use crate::sql_tables::DbConnection;
 use actix::prelude::{Actor, AsyncContext, Context};
 use cron::Schedule;
 use lldap_domain_model::model::{
-    self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn,
+    self, AuditLogColumn, GroupColumn, JwtRefreshStorageColumn, JwtStorageColumn,
+    LoginFailuresColumn, MembershipColumn, MfaLoginTokensColumn, OidcAuthorizationsColumn,
+    OidcRefreshTokensColumn, PasswordResetTokensColumn, UserColumn,
+};
+use sea_orm::{
+    ColumnTrait, DbErr, EntityTrait, QueryFilter, TransactionError, TransactionTrait,
+    sea_query::Expr,
 };
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 use std::{str::FromStr, time::Duration};
 use tracing::{error, info, instrument};
 
@@ -12,6 +17,10 @@ use tracing::{error, info, instrument};
 pub struct Scheduler {
     schedule: Schedule,
     sql_pool: DbConnection,
+    /// How long the audit events are kept, forever if `None`.
+    audit_log_retention: Option<chrono::Duration>,
+    /// How long the failed login attempts are counted, i.e. the lockout duration.
+    login_failure_retention: chrono::Duration,
 }
 
 // Provide Actor implementation for our actor
@@ -32,13 +41,27 @@ impl Actor for Scheduler {
 }
 
 impl Scheduler {
-    pub fn new(cron_expression: &str, sql_pool: DbConnection) -> Self {
+    pub fn new(
+        cron_expression: &str,
+        sql_pool: DbConnection,
+        audit_log_retention: Option<chrono::Duration>,
+        login_failure_retention: chrono::Duration,
+    ) -> Self {
         let schedule = Schedule::from_str(cron_expression).unwrap();
-        Self { schedule, sql_pool }
+        Self {
+            schedule,
+            sql_pool,
+            audit_log_retention,
+            login_failure_retention,
+        }
     }
 
     fn schedule_task(&self, ctx: &mut Context<Self>) {
-        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(self.sql_pool.clone()));
+        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(
+            self.sql_pool.clone(),
+            self.audit_log_retention,
+            self.login_failure_retention,
+        ));
         ctx.spawn(future);
 
         ctx.run_later(self.duration_until_next(), move |this, ctx| {
@@ -47,7 +70,11 @@ impl Scheduler {
     }
 
     #[instrument(skip_all)]
-    async fn cleanup_db(sql_pool: DbConnection) {
+    async fn cleanup_db(
+        sql_pool: DbConnection,
+        audit_log_retention: Option<chrono::Duration>,
+        login_failure_retention: chrono::Duration,
+    ) {
         if let Err(e) = model::JwtRefreshStorage::delete_many()
             .filter(JwtRefreshStorageColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
             .exec(&sql_pool)
@@ -69,6 +96,94 @@ impl Scheduler {
         {
             error!("DB error while cleaning up password reset tokens: {}", e);
         };
+        if let Err(e) = model::MfaLoginTokens::delete_many()
+            .filter(MfaLoginTokensColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up MFA login tokens: {}", e);
+        };
+        if let Err(e) = model::OidcAuthorizations::delete_many()
+            .filter(OidcAuthorizationsColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up OIDC authorizations: {}", e);
+        };
+        if let Err(e) = model::OidcRefreshTokens::delete_many()
+            .filter(OidcRefreshTokensColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up OIDC refresh tokens: {}", e);
+        };
+        if let Err(e) = Self::purge_expired_memberships(&sql_pool).await {
+            error!(
+                "DB error while cleaning up expired group memberships: {}",
+                e
+            );
+        };
+        if let Err(e) = model::LoginFailures::delete_many()
+            .filter(
+                LoginFailuresColumn::LastFailureDate
+                    .lt(chrono::Utc::now().naive_utc() - login_failure_retention),
+            )
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up failed login attempts: {}", e);
+        };
+        if let Some(retention) = audit_log_retention {
+            if let Err(e) = model::AuditLog::delete_many()
+                .filter(AuditLogColumn::Date.lt(chrono::Utc::now().naive_utc() - retention))
+                .exec(&sql_pool)
+                .await
+            {
+                error!("DB error while cleaning up the audit log: {}", e);
+            };
+        }
+    }
+
+    /// The users and groups of the purged memberships changed: their modification date is bumped
+    /// for the incremental synchronizations.
+    async fn purge_expired_memberships(
+        sql_pool: &DbConnection,
+    ) -> Result<(), TransactionError<DbErr>> {
+        sql_pool
+            .transaction::<_, (), DbErr>(|transaction| {
+                Box::pin(async move {
+                    let now = chrono::Utc::now().naive_utc();
+                    let expired_memberships = model::Membership::find()
+                        .filter(MembershipColumn::ExpiryDate.lt(now))
+                        .all(transaction)
+                        .await?;
+                    if expired_memberships.is_empty() {
+                        return Ok(());
+                    }
+                    model::Membership::delete_many()
+                        .filter(MembershipColumn::ExpiryDate.lt(now))
+                        .exec(transaction)
+                        .await?;
+                    model::User::update_many()
+                        .col_expr(UserColumn::ModifiedDate, Expr::value(now))
+                        .filter(
+                            UserColumn::UserId
+                                .is_in(expired_memberships.iter().map(|m| &m.user_id)),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    model::Group::update_many()
+                        .col_expr(GroupColumn::ModifiedDate, Expr::value(now))
+                        .filter(
+                            GroupColumn::GroupId
+                                .is_in(expired_memberships.iter().map(|m| m.group_id)),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    Ok(())
+                })
+            })
+            .await
     }
 
     fn duration_until_next(&self) -> Duration {
@@ -78,3 +193,138 @@ impl Scheduler {
         duration_until.to_std().unwrap()
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use lldap_auth::opaque::server::generate_random_private_key;
+    use lldap_domain::{
+        requests::{CreateGroupRequest, CreateUserRequest},
+        types::UserId,
+    };
+    use lldap_domain_handlers::handler::{GroupBackendHandler, UserBackendHandler};
+    use lldap_sql_backend_handler::{LoginThrottleOptions, SqlBackendHandler};
+    use pretty_assertions::assert_eq;
+    use sea_orm::{ConnectOptions, Database, Set};
+
+    pub(crate) async fn get_initialized_db() -> DbConnection {
+        let mut sql_opt = ConnectOptions::new("sqlite::memory:".to_owned());
+        sql_opt.max_connections(1);
+        let sql_pool = Database::connect(sql_opt).await.unwrap();
+        crate::sql_tables::init_table(&sql_pool).await.unwrap();
+        crate::jwt_sql_tables::init_table(&sql_pool).await.unwrap();
+        crate::oidc_sql_tables::init_table(&sql_pool).await.unwrap();
+        sql_pool
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_expired_memberships() {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(
+            generate_random_private_key(),
+            sql_pool.clone(),
+            LoginThrottleOptions::default(),
+        );
+        let group_id = handler
+            .create_group(CreateGroupRequest {
+                display_name: "contractors".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let now = chrono::Utc::now().naive_utc();
+        for (user, expiry_date) in [
+            ("bob", Some(now - chrono::Duration::days(1))),
+            ("patrick", Some(now + chrono::Duration::days(1))),
+            ("john", None),
+        ] {
+            let user_id = UserId::new(user);
+            handler
+                .create_user(CreateUserRequest {
+                    user_id: user_id.clone(),
+                    email: format!("{}@example.com", user).into(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            handler.add_user_to_group(&user_id, group_id).await.unwrap();
+            handler
+                .set_membership_expiry_date(&user_id, group_id, expiry_date)
+                .await
+                .unwrap();
+        }
+        let old_date = now - chrono::Duration::days(7);
+        model::User::update_many()
+            .col_expr(UserColumn::ModifiedDate, Expr::value(old_date))
+            .exec(&sql_pool)
+            .await
+            .unwrap();
+        model::Group::update_many()
+            .col_expr(GroupColumn::ModifiedDate, Expr::value(old_date))
+            .exec(&sql_pool)
+            .await
+            .unwrap();
+        Scheduler::cleanup_db(sql_pool.clone(), None, chrono::Duration::minutes(15)).await;
+        // Bob and the group changed, for the incremental synchronizations.
+        let get_user_modified_date = |user| {
+            let sql_pool = sql_pool.clone();
+            async move {
+                model::User::find_by_id(UserId::new(user))
+                    .one(&sql_pool)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .modified_date
+            }
+        };
+        assert!(get_user_modified_date("bob").await >= now);
+        assert_eq!(get_user_modified_date("patrick").await, old_date);
+        assert!(
+            model::Group::find_by_id(group_id)
+                .one(&sql_pool)
+                .await
+                .unwrap()
+                .unwrap()
+                .modified_date
+                >= now
+        );
+        let mut members = model::Membership::find()
+            .all(&sql_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.user_id)
+            .collect::<Vec<_>>();
+        members.sort();
+        assert_eq!(members, vec![UserId::new("john"), UserId::new("patrick")]);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_login_failures() {
+        let sql_pool = get_initialized_db().await;
+        let now = chrono::Utc::now().naive_utc();
+        for (subject, last_failure_date) in [
+            ("user:bob", now - chrono::Duration::hours(1)),
+            ("user:patrick", now - chrono::Duration::minutes(1)),
+        ] {
+            model::LoginFailures::insert(model::login_failures::ActiveModel {
+                subject: Set(subject.to_owned()),
+                failure_count: Set(3),
+                last_failure_date: Set(last_failure_date),
+                locked_until: Set(None),
+            })
+            .exec(&sql_pool)
+            .await
+            .unwrap();
+        }
+        Scheduler::cleanup_db(sql_pool.clone(), None, chrono::Duration::minutes(15)).await;
+        let subjects = model::LoginFailures::find()
+            .all(&sql_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| f.subject)
+            .collect::<Vec<_>>();
+        assert_eq!(subjects, vec!["user:patrick".to_owned()]);
+    }
+}
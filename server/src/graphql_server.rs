@@ -1,4 +1,8 @@
-use crate::{auth_service::check_if_token_is_valid, tcp_server::AppState};
+use crate::{
+    auth_service::{check_if_token_is_valid, get_source_ip},
+    tcp_backend_handler::TcpBackendHandler,
+    tcp_server::AppState,
+};
 use actix_web::FromRequest;
 use actix_web::HttpMessage;
 use actix_web::{Error, HttpRequest, HttpResponse, error::JsonPayloadError, web};
@@ -10,7 +14,7 @@ use juniper::{
         playground::playground_source,
     },
 };
-use lldap_domain_handlers::handler::BackendHandler;
+use lldap_domain_handlers::handler::{AuditLogBackendHandler, BackendHandler};
 use lldap_graphql_server::api::Context;
 use lldap_graphql_server::api::schema;
 
@@ -119,30 +123,42 @@ where
     Ok(response.content_type("application/json").body(gql_response))
 }
 
-async fn graphql_route<Handler: BackendHandler + Clone>(
+async fn graphql_route<Handler: BackendHandler + TcpBackendHandler + Clone>(
     req: actix_web::HttpRequest,
     payload: actix_web::web::Payload,
     data: web::Data<AppState<Handler>>,
 ) -> Result<HttpResponse, Error> {
     let mut inner_payload = payload.into_inner();
     let bearer = BearerAuth::from_request(&req, &mut inner_payload).await?;
-    let validation_result = check_if_token_is_valid(&data, bearer.token())?;
+    let validation_result = check_if_token_is_valid(&data, bearer.token()).await?;
     let context = Context::<Handler> {
         handler: data.backend_handler.clone(),
         validation_result,
+        blacklisted_jwts: Default::default(),
+        source_ip: get_source_ip(&req, &data.trusted_proxies),
+        audit_events: Default::default(),
     };
     let schema = &schema();
     let context = &context;
-    match *req.method() {
+    let response = match *req.method() {
         actix_http::Method::POST => post_graphql_handler(schema, context, req, inner_payload).await,
         actix_http::Method::GET => get_graphql_handler(schema, context, req).await,
         _ => Err(actix_web::error::UrlGenerationError::ResourceNotFound.into()),
+    };
+    let blacklisted_jwts = std::mem::take(&mut *context.blacklisted_jwts.lock().unwrap());
+    data.jwt_blacklist.write().unwrap().extend(blacklisted_jwts);
+    let audit_events = std::mem::take(&mut *context.audit_events.lock().unwrap());
+    for event in audit_events {
+        if let Err(e) = data.get_audit_log_handler().record_audit_event(event).await {
+            log::error!("Could not record a change in the audit log: {:#}", e);
+        }
     }
+    response
 }
 
 pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
 where
-    Backend: BackendHandler + Clone + 'static,
+    Backend: BackendHandler + TcpBackendHandler + Clone + 'static,
 {
     let json_config = web::JsonConfig::default()
         .limit(4096)
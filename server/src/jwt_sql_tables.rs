@@ -2,6 +2,7 @@ use sea_orm::{
     ConnectionTrait, DeriveIden,
     sea_query::{ColumnDef, ForeignKey, ForeignKeyAction, Table},
 };
+use tracing::warn;
 
 pub use lldap_sql_backend_handler::{sql_migrations::Users, sql_tables::DbConnection};
 
@@ -12,6 +13,9 @@ pub enum JwtRefreshStorage {
     RefreshTokenHash,
     UserId,
     ExpiryDate,
+    CreationDate,
+    IpAddress,
+    UserAgent,
 }
 
 /// Contains the blacklisted JWT that haven't expired yet.
@@ -22,6 +26,7 @@ pub enum JwtStorage {
     UserId,
     ExpiryDate,
     Blacklisted,
+    RefreshTokenHash,
 }
 
 /// Contains the temporary tokens to reset the password, sent by email.
@@ -33,6 +38,24 @@ pub enum PasswordResetTokens {
     ExpiryDate,
 }
 
+/// Contains the temporary tokens of logins waiting for their second factor.
+#[derive(DeriveIden)]
+pub enum MfaLoginTokens {
+    Table,
+    TokenHash,
+    UserId,
+    ExpiryDate,
+    FailedAttempts,
+}
+
+/// Contains the single-use codes to log in without the TOTP second factor.
+#[derive(DeriveIden)]
+pub enum MfaRecoveryCodes {
+    Table,
+    CodeHash,
+    UserId,
+}
+
 /// This needs to be initialized after the domain tables are.
 pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
     let builder = pool.get_database_backend();
@@ -58,6 +81,13 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
                         .date_time()
                         .not_null(),
                 )
+                .col(
+                    ColumnDef::new(JwtRefreshStorage::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(JwtRefreshStorage::IpAddress).string_len(64))
+                .col(ColumnDef::new(JwtRefreshStorage::UserAgent).string_len(255))
                 .foreign_key(
                     ForeignKey::create()
                         .name("JwtRefreshStorageUserForeignKey")
@@ -97,6 +127,7 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
                         .default(false)
                         .not_null(),
                 )
+                .col(ColumnDef::new(JwtStorage::RefreshTokenHash).big_integer())
                 .foreign_key(
                     ForeignKey::create()
                         .name("JwtStorageUserForeignKey")
@@ -109,6 +140,63 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
     )
     .await?;
 
+    // The session columns were added later: create them if they don't exist.
+    if pool
+        .execute(
+            builder.build(
+                Table::alter().table(JwtRefreshStorage::Table).add_column(
+                    ColumnDef::new(JwtRefreshStorage::CreationDate)
+                        .date_time()
+                        .not_null()
+                        .default(chrono::Utc::now().naive_utc()),
+                ),
+            ),
+        )
+        .await
+        .is_ok()
+    {
+        warn!("`creation_date` column not found in `jwt_refresh_storage`, creating it");
+    }
+    if pool
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(JwtRefreshStorage::Table)
+                    .add_column(ColumnDef::new(JwtRefreshStorage::IpAddress).string_len(64)),
+            ),
+        )
+        .await
+        .is_ok()
+    {
+        warn!("`ip_address` column not found in `jwt_refresh_storage`, creating it");
+    }
+    if pool
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(JwtRefreshStorage::Table)
+                    .add_column(ColumnDef::new(JwtRefreshStorage::UserAgent).string_len(255)),
+            ),
+        )
+        .await
+        .is_ok()
+    {
+        warn!("`user_agent` column not found in `jwt_refresh_storage`, creating it");
+    }
+    if pool
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(JwtStorage::Table)
+                    .add_column(ColumnDef::new(JwtStorage::RefreshTokenHash).big_integer()),
+            ),
+        )
+        .await
+        .is_ok()
+    {
+        warn!("`refresh_token_hash` column not found in `jwt_storage`, creating it");
+    }
+
     pool.execute(
         builder.build(
             Table::create()
@@ -142,5 +230,72 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
     )
     .await?;
 
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(MfaLoginTokens::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(MfaLoginTokens::TokenHash)
+                        .big_integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(MfaLoginTokens::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MfaLoginTokens::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MfaLoginTokens::FailedAttempts)
+                        .integer()
+                        .default(0)
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("MfaLoginTokensUserForeignKey")
+                        .from(MfaLoginTokens::Table, MfaLoginTokens::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(MfaRecoveryCodes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(MfaRecoveryCodes::CodeHash)
+                        .big_integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(MfaRecoveryCodes::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("MfaRecoveryCodesUserForeignKey")
+                        .from(MfaRecoveryCodes::Table, MfaRecoveryCodes::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+
     Ok(())
 }
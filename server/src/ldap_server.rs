@@ -2,24 +2,41 @@ use crate::configuration::{Configuration, LdapsOptions};
 use actix_rt::net::TcpStream;
 use actix_server::ServerBuilder;
 use actix_service::{ServiceFactoryExt, fn_service};
-use anyhow::{Context, Result, anyhow};
-use ldap3_proto::{LdapCodec, control::LdapControl, proto::LdapMsg, proto::LdapOp};
+use anyhow::{Context, Result, anyhow, bail};
+use ldap3_proto::{
+    LdapCodec,
+    control::LdapControl,
+    proto::{LdapMsg, LdapOp},
+};
 use lldap_access_control::AccessControlledBackendHandler;
 use lldap_domain::types::AttributeName;
-use lldap_domain_handlers::handler::{BackendHandler, LoginHandler};
-use lldap_ldap::LdapHandler;
+use lldap_domain_handlers::handler::{AuditLogBackendHandler, BackendHandler, LoginHandler};
+use lldap_ldap::{LdapHandler, TlsStatus};
 use lldap_opaque_handler::OpaqueHandler;
+use lldap_validation::password::PasswordPolicy;
 use rustls::PrivateKey;
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::time::MissedTickBehavior;
 use tokio_rustls::TlsAcceptor as RustlsTlsAcceptor;
 use tokio_util::codec::{FramedRead, FramedWrite};
 use tracing::{debug, error, info, instrument};
 use uuid::Uuid;
 
+/// How often the changes are sent to the clients of a refreshAndPersist content synchronization.
+const PERSISTENT_SEARCH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 #[instrument(skip_all, level = "info", name = "LDAP request", fields(session_id = %session.session_uuid()))]
 async fn handle_ldap_message<Backend, Writer>(
     msg: Result<LdapMsg, std::io::Error>,
     resp: &mut Writer,
     session: &mut LdapHandler<Backend>,
+    persistent_search_msgid: &mut Option<i32>,
+    jwt_blacklist: &RwLock<HashSet<u64>>,
 ) -> Result<bool>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler,
@@ -34,23 +51,48 @@ where
         }
     }
     debug!(?msg);
-    match session.handle_ldap_message(msg.op).await {
+    if matches!(&msg.op, LdapOp::AbandonRequest(id) if *persistent_search_msgid == Some(*id)) {
+        debug!("Abandoning the persistent search");
+        session.cancel_persistent_search();
+        *persistent_search_msgid = None;
+        // No response to an abandon request (per rfc4511).
+        return Ok(true);
+    }
+    let starts_sync = matches!(msg.op, LdapOp::SearchRequest(_))
+        && msg
+            .ctrl
+            .iter()
+            .any(|c| matches!(c, LdapControl::SyncRequest { .. }));
+    let result = session
+        .handle_ldap_message_with_controls(msg.op, &msg.ctrl)
+        .await;
+    if starts_sync || !session.has_persistent_search() {
+        *persistent_search_msgid = session.has_persistent_search().then_some(msg.msgid);
+    }
+    for event in session.take_audit_events() {
+        if let Err(e) = session
+            .get_audit_log_handler()
+            .record_audit_event(event)
+            .await
+        {
+            error!(
+                "Could not record an LDAP operation in the audit log: {:#}",
+                e
+            );
+        }
+    }
+    let blacklisted_jwts = session.take_blacklisted_jwts();
+    if !blacklisted_jwts.is_empty() {
+        jwt_blacklist.write().unwrap().extend(blacklisted_jwts);
+    }
+    match result {
         None => return Ok(false),
         Some(result) => {
             if result.is_empty() {
                 debug!("No response");
             }
-            let results: i64 = result.len().try_into().unwrap();
-            for response in result.into_iter() {
+            for (response, controls) in result.into_iter() {
                 debug!(?response);
-                let controls = if matches!(response, LdapOp::SearchResultDone(_)) {
-                    vec![LdapControl::SimplePagedResults {
-                        size: results - 1, // Avoid counting SearchResultDone as a result
-                        cookie: vec![],
-                    }]
-                } else {
-                    vec![]
-                };
                 resp.send(LdapMsg {
                     msgid: msg.msgid,
                     op: response,
@@ -68,12 +110,41 @@ where
     Ok(true)
 }
 
-async fn handle_ldap_stream<Stream, Backend>(
+/// Sends the changes of the refreshAndPersist content synchronization, as responses to its
+/// search request.
+async fn send_persistent_search_changes<Backend, Writer>(
+    msgid: i32,
+    resp: &mut Writer,
+    session: &mut LdapHandler<Backend>,
+) -> Result<()>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler,
+    Writer: futures_util::Sink<LdapMsg> + Unpin,
+    <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
+{
+    use futures_util::SinkExt;
+    let changes = session.poll_persistent_search().await;
+    if changes.is_empty() {
+        return Ok(());
+    }
+    for (response, controls) in changes.into_iter() {
+        debug!(?response);
+        resp.send(LdapMsg {
+            msgid,
+            op: response,
+            ctrl: controls,
+        })
+        .await
+        .context("while sending a change")?
+    }
+    resp.flush().await.context("while flushing the changes")
+}
+
+/// Handles the messages of the session until the client disconnects, or StartTLS was accepted.
+async fn run_ldap_session<Stream, Backend>(
     stream: Stream,
-    backend_handler: Backend,
-    ldap_base_dn: String,
-    ignored_user_attributes: Vec<AttributeName>,
-    ignored_group_attributes: Vec<AttributeName>,
+    session: &mut LdapHandler<Backend>,
+    jwt_blacklist: &RwLock<HashSet<u64>>,
 ) -> Result<Stream>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
@@ -85,26 +156,101 @@ where
     let mut requests = FramedRead::new(r, LdapCodec::default());
     let mut resp = FramedWrite::new(w, LdapCodec::default());
 
+    // The message ID of the refreshAndPersist search in progress, if any.
+    let mut persistent_search_msgid = None;
+    let mut poll_interval = tokio::time::interval(PERSISTENT_SEARCH_POLL_INTERVAL);
+    poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let msg = tokio::select! {
+            msg = requests.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = poll_interval.tick(), if persistent_search_msgid.is_some() => {
+                if let Some(msgid) = persistent_search_msgid {
+                    send_persistent_search_changes(msgid, &mut resp, session)
+                        .await
+                        .context("while sending the changes of a persistent search")?;
+                }
+                if !session.has_persistent_search() {
+                    persistent_search_msgid = None;
+                }
+                continue;
+            }
+        };
+        if !handle_ldap_message(
+            msg,
+            &mut resp,
+            session,
+            &mut persistent_search_msgid,
+            jwt_blacklist,
+        )
+        .await
+        .context("while handling incoming messages")?
+        {
+            break;
+        }
+        if session.tls_status() == TlsStatus::StartTlsPending {
+            if !requests.read_buffer().is_empty() {
+                bail!("Received more data after a StartTLS request");
+            }
+            break;
+        }
+    }
+    Ok(requests.into_inner().unsplit(resp.into_inner()))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_ldap_stream<Stream, Backend>(
+    stream: Stream,
+    backend_handler: Backend,
+    ldap_base_dn: String,
+    ignored_user_attributes: Vec<AttributeName>,
+    ignored_group_attributes: Vec<AttributeName>,
+    password_policy: PasswordPolicy,
+    require_tls_for_bind: bool,
+    source_ip: Option<IpAddr>,
+    tls_status: TlsStatus,
+    start_tls_acceptor: Option<RustlsTlsAcceptor>,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
+) -> Result<Stream>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
+    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + std::marker::Unpin,
+{
     let session_uuid = Uuid::new_v4();
     let mut session = LdapHandler::new(
         AccessControlledBackendHandler::new(backend_handler),
         ldap_base_dn,
         ignored_user_attributes,
         ignored_group_attributes,
+        password_policy,
         session_uuid,
+        source_ip,
+        tls_status,
+        require_tls_for_bind,
     );
 
     info!("LDAP session start: {}", session_uuid);
-    while let Some(msg) = requests.next().await {
-        if !handle_ldap_message(msg, &mut resp, &mut session)
+    let stream = run_ldap_session(stream, &mut session, &jwt_blacklist).await?;
+    let stream = if session.tls_status() == TlsStatus::StartTlsPending {
+        let tls_acceptor =
+            start_tls_acceptor.context("StartTLS was accepted without a TLS configuration")?;
+        debug!("Upgrading the connection with StartTLS");
+        let tls_stream = tls_acceptor
+            .accept(stream)
             .await
-            .context("while handling incoming messages")?
-        {
-            break;
-        }
-    }
+            .context("while establishing the StartTLS connection")?;
+        session.set_tls_status(TlsStatus::Encrypted);
+        run_ldap_session(tls_stream, &mut session, &jwt_blacklist)
+            .await?
+            .into_inner()
+            .0
+    } else {
+        stream
+    };
     info!("LDAP session end: {}", session_uuid);
-    Ok(requests.into_inner().unsplit(resp.into_inner()))
+    Ok(stream)
 }
 
 fn read_private_key(key_file: &str) -> Result<PrivateKey> {
@@ -166,32 +312,74 @@ fn get_tls_acceptor(ldaps_options: &LdapsOptions) -> Result<RustlsTlsAcceptor> {
 pub fn build_ldap_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
 {
+    if config.ldaps_options.require_tls_for_bind
+        && !config.ldaps_options.enabled
+        && !config.ldaps_options.start_tls
+    {
+        bail!(
+            "ldaps_options.require_tls_for_bind is set, but neither LDAPS nor StartTLS are enabled: nobody would be able to bind"
+        );
+    }
     let context = (
         backend_handler,
         config.ldap_base_dn.clone(),
         config.ignored_user_attributes.clone(),
         config.ignored_group_attributes.clone(),
+        config.password_policy.clone(),
+        config.ldaps_options.require_tls_for_bind,
+        jwt_blacklist,
     );
 
     let context_for_tls = context.clone();
+    let start_tls_acceptor = if config.ldaps_options.start_tls {
+        Some(
+            get_tls_acceptor(&config.ldaps_options)
+                .context("while setting up the SSL certificate for StartTLS")?,
+        )
+    } else {
+        None
+    };
 
     let binder = move || {
         let context = context.clone();
+        let start_tls_acceptor = start_tls_acceptor.clone();
         fn_service(move |stream: TcpStream| {
             let context = context.clone();
+            let start_tls_acceptor = start_tls_acceptor.clone();
             async move {
-                let (handler, base_dn, ignored_user_attributes, ignored_group_attributes) = context;
+                let (
+                    handler,
+                    base_dn,
+                    ignored_user_attributes,
+                    ignored_group_attributes,
+                    password_policy,
+                    require_tls_for_bind,
+                    jwt_blacklist,
+                ) = context;
+                let source_ip = stream.peer_addr().ok().map(|addr| addr.ip());
+                let tls_status = if start_tls_acceptor.is_some() {
+                    TlsStatus::StartTlsAvailable
+                } else {
+                    TlsStatus::Unavailable
+                };
                 handle_ldap_stream(
                     stream,
                     handler,
                     base_dn,
                     ignored_user_attributes,
                     ignored_group_attributes,
+                    password_policy,
+                    require_tls_for_bind,
+                    source_ip,
+                    tls_status,
+                    start_tls_acceptor,
+                    jwt_blacklist,
                 )
                 .await
             }
@@ -215,9 +403,18 @@ where
                 let tls_context = tls_context.clone();
                 async move {
                     let (
-                        (handler, base_dn, ignored_user_attributes, ignored_group_attributes),
+                        (
+                            handler,
+                            base_dn,
+                            ignored_user_attributes,
+                            ignored_group_attributes,
+                            password_policy,
+                            require_tls_for_bind,
+                            jwt_blacklist,
+                        ),
                         tls_acceptor,
                     ) = tls_context;
+                    let source_ip = stream.peer_addr().ok().map(|addr| addr.ip());
                     let tls_stream = tls_acceptor.accept(stream).await?;
                     handle_ldap_stream(
                         tls_stream,
@@ -225,6 +422,12 @@ where
                         base_dn,
                         ignored_user_attributes,
                         ignored_group_attributes,
+                        password_policy,
+                        require_tls_for_bind,
+                        source_ip,
+                        TlsStatus::Encrypted,
+                        None,
+                        jwt_blacklist,
                     )
                     .await
                 }
@@ -14,15 +14,20 @@ mod jwt_sql_tables;
 mod ldap_server;
 mod logging;
 mod mail;
+mod oidc_key;
+mod oidc_service;
+mod oidc_sql_tables;
 mod sql_tcp_backend_handler;
 mod tcp_backend_handler;
 mod tcp_server;
+mod totp;
 
 use crate::{
     cli::{Command, RunOpts, TestEmailOpts},
     configuration::{Configuration, compare_private_key_hashes},
     database_string::DatabaseUrl,
     db_cleaner::Scheduler,
+    tcp_backend_handler::TcpBackendHandler,
 };
 use actix::Actor;
 use actix_server::ServerBuilder;
@@ -33,13 +38,20 @@ use lldap_sql_backend_handler::{
     sql_tables::{self, get_private_key_info, set_private_key_info},
 };
 use sea_orm::{Database, DatabaseConnection};
-use std::time::Duration;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 use tracing::{Instrument, Level, debug, error, info, instrument, span, warn};
 
-use lldap_domain::requests::{CreateGroupRequest, CreateUserRequest};
+use lldap_domain::{
+    requests::{CreateGroupRequest, CreateUserRequest},
+    types::UserId,
+};
 use lldap_domain_handlers::handler::{
-    GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter, UserBackendHandler,
-    UserListerBackendHandler, UserRequestFilter,
+    AuditLogBackendHandler, AuditSource, CreateAuditEventRequest, GroupBackendHandler,
+    GroupListerBackendHandler, GroupRequestFilter, UserBackendHandler, UserListerBackendHandler,
+    UserRequestFilter,
 };
 
 const ADMIN_PASSWORD_MISSING_ERROR: &str = "The LDAP admin password must be initialized. \
@@ -84,6 +96,27 @@ async fn create_admin_user(handler: &SqlBackendHandler, config: &Configuration)
         .context("Error adding admin user to group")
 }
 
+/// Records a change made by the server itself from its configuration.
+async fn record_startup_event(handler: &SqlBackendHandler, operation: &str, user_id: &UserId) {
+    if let Err(e) = handler
+        .record_audit_event(CreateAuditEventRequest {
+            actor: None,
+            source: AuditSource::Cli,
+            ip_address: None,
+            target: Some(format!("user:{}", user_id)),
+            operation: operation.to_owned(),
+            success: true,
+            details: None,
+        })
+        .await
+    {
+        error!(
+            "Could not record the {} in the audit log: {:#}",
+            operation, e
+        );
+    }
+}
+
 async fn ensure_group_exists(handler: &SqlBackendHandler, group_name: &str) -> Result<()> {
     if handler
         .list_groups(Some(GroupRequestFilter::DisplayName(group_name.into())))
@@ -122,6 +155,9 @@ async fn setup_sql_tables(database_url: &DatabaseUrl) -> Result<DatabaseConnecti
     jwt_sql_tables::init_table(&sql_pool)
         .await
         .context("while creating jwt tables")?;
+    oidc_sql_tables::init_table(&sql_pool)
+        .await
+        .context("while creating oidc tables")?;
     Ok(sql_pool)
 }
 
@@ -152,8 +188,11 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
             return Err(anyhow!("The private key encoding the passwords has changed since last successful startup. Changing the private key will invalidate all existing passwords. If you want to proceed, restart the server with the CLI arg --force-update-private-key=true or the env variable LLDAP_FORCE_UPDATE_PRIVATE_KEY=true. You probably also want --force-ldap-user-pass-reset / LLDAP_FORCE_LDAP_USER_PASS_RESET=true to reset the admin password to the value in the configuration.").context(e));
         }
     }
-    let backend_handler =
-        SqlBackendHandler::new(config.get_server_setup().clone(), sql_pool.clone());
+    let backend_handler = SqlBackendHandler::new(
+        config.get_server_setup().clone(),
+        sql_pool.clone(),
+        config.login_throttle.clone(),
+    );
     ensure_group_exists(&backend_handler, "lldap_admin").await?;
     ensure_group_exists(&backend_handler, "lldap_password_manager").await?;
     ensure_group_exists(&backend_handler, "lldap_strict_readonly").await?;
@@ -176,6 +215,7 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
             .await
             .map_err(|e| anyhow!("Error setting up admin login/account: {:#}", e))
             .context("while creating the admin user")?;
+        record_startup_event(&backend_handler, "create_user", &config.ldap_user_dn).await;
     } else if config.force_ldap_user_pass_reset.is_positive() {
         let span = if config.force_ldap_user_pass_reset.is_yes() {
             span!(
@@ -199,23 +239,41 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
             "while resetting admin password for {}",
             &config.ldap_user_dn
         ))?;
+        record_startup_event(&backend_handler, "change_password", &config.ldap_user_dn).await;
     }
     if config.force_update_private_key || config.force_ldap_user_pass_reset.is_yes() {
         bail!(
             "Restart the server without --force-update-private-key or --force-ldap-user-pass-reset to continue."
         );
     }
+    // Shared by the HTTP and LDAP servers, which can both log users out.
+    let jwt_blacklist = Arc::new(RwLock::new(
+        backend_handler
+            .get_jwt_blacklist()
+            .await
+            .context("while getting the jwt blacklist")?,
+    ));
     let server_builder = ldap_server::build_ldap_server(
         &config,
         backend_handler.clone(),
+        jwt_blacklist.clone(),
         actix_server::Server::build(),
     )
     .context("while binding the LDAP server")?;
-    let server_builder = tcp_server::build_tcp_server(&config, backend_handler, server_builder)
-        .await
-        .context("while binding the TCP server")?;
+    let server_builder =
+        tcp_server::build_tcp_server(&config, backend_handler, jwt_blacklist, server_builder)
+            .context("while binding the TCP server")?;
+    let audit_log_retention = (config.audit_log_retention_days > 0)
+        .then(|| chrono::Duration::days(config.audit_log_retention_days.into()));
     // Run every hour.
-    let scheduler = Scheduler::new("0 0 * * * * *", sql_pool);
+    let login_failure_retention =
+        chrono::Duration::seconds(config.login_throttle.lockout_seconds.into());
+    let scheduler = Scheduler::new(
+        "0 0 * * * * *",
+        sql_pool,
+        audit_log_retention,
+        login_failure_retention,
+    );
     scheduler.start();
     Ok(server_builder)
 }
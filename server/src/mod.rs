@@ -9,6 +9,10 @@ pub mod jwt_sql_tables;
 pub mod ldap_server;
 pub mod logging;
 pub mod mail;
+pub mod oidc_key;
+pub mod oidc_service;
+pub mod oidc_sql_tables;
 pub mod sql_tcp_backend_handler;
 pub mod tcp_backend_handler;
 pub mod tcp_server;
+pub mod totp;
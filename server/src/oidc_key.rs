@@ -0,0 +1,171 @@
+//! RSA key used to sign tokens (RS256), and published as a JWK so that clients can verify them.
+//! It is used by the OpenID Connect provider, and for the login JWTs when they are configured to
+//! use RS256.
+
+use anyhow::{Context, Result, bail};
+use data_encoding::BASE64URL_NOPAD;
+use rsa::{
+    RsaPrivateKey, RsaPublicKey,
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+    signature::{SignatureEncoding, Signer, Verifier},
+    traits::PublicKeyParts,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+const KEY_SIZE: usize = 2048;
+
+pub struct OidcKey {
+    signing_key: SigningKey<Sha256>,
+    verifying_key: VerifyingKey<Sha256>,
+    public_key: RsaPublicKey,
+    key_id: String,
+}
+
+#[derive(Serialize, serde::Deserialize)]
+struct JwsHeader {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+impl OidcKey {
+    pub fn new(private_key: RsaPrivateKey) -> Self {
+        let public_key = private_key.to_public_key();
+        let key_id = BASE64URL_NOPAD.encode(&Sha256::digest(public_key.n().to_bytes_be())[..16]);
+        Self {
+            signing_key: SigningKey::new(private_key),
+            verifying_key: VerifyingKey::new(public_key.clone()),
+            public_key,
+            key_id,
+        }
+    }
+
+    /// Reads the PKCS#8 PEM key from the file, or generates it if the file doesn't exist.
+    pub fn load_or_generate(key_file: &str) -> Result<Self> {
+        let path = std::path::Path::new(key_file);
+        let private_key = if path.exists() {
+            RsaPrivateKey::read_pkcs8_pem_file(path)
+                .with_context(|| format!("while reading the key file {key_file}"))?
+        } else {
+            info!("Generating a new signing key in {key_file}");
+            let private_key = RsaPrivateKey::new(&mut rand::rngs::OsRng, KEY_SIZE)
+                .context("while generating the signing key")?;
+            private_key
+                .write_pkcs8_pem_file(path, LineEnding::LF)
+                .with_context(|| format!("while writing the key file {key_file}"))?;
+            private_key
+        };
+        Ok(Self::new(private_key))
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Signs the claims as a compact JWS.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> String {
+        let header = JwsHeader {
+            alg: "RS256".to_owned(),
+            typ: "JWT".to_owned(),
+            kid: self.key_id.clone(),
+        };
+        let signing_input = format!(
+            "{}.{}",
+            BASE64URL_NOPAD.encode(&serde_json::to_vec(&header).unwrap()),
+            BASE64URL_NOPAD.encode(&serde_json::to_vec(claims).unwrap()),
+        );
+        let signature = self.signing_key.sign(signing_input.as_bytes());
+        format!(
+            "{}.{}",
+            signing_input,
+            BASE64URL_NOPAD.encode(&signature.to_bytes())
+        )
+    }
+
+    /// Checks the signature of a token created by `sign`, and returns its claims. The expiry
+    /// date, if any, is left to the caller.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T> {
+        let (signing_input, signature) = token.rsplit_once('.').context("Malformed token")?;
+        let (header, claims) = signing_input.split_once('.').context("Malformed token")?;
+        let header: JwsHeader =
+            serde_json::from_slice(&BASE64URL_NOPAD.decode(header.as_bytes())?)?;
+        if header.alg != "RS256" || header.kid != self.key_id {
+            bail!("Token not signed by this key");
+        }
+        let signature =
+            Signature::try_from(BASE64URL_NOPAD.decode(signature.as_bytes())?.as_slice())?;
+        self.verifying_key
+            .verify(signing_input.as_bytes(), &signature)
+            .context("Invalid token signature")?;
+        Ok(serde_json::from_slice(
+            &BASE64URL_NOPAD.decode(claims.as_bytes())?,
+        )?)
+    }
+
+    /// The public key, in the JSON Web Key format.
+    pub fn jwk(&self) -> serde_json::Value {
+        serde_json::json!({
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": self.key_id,
+            "n": BASE64URL_NOPAD.encode(&self.public_key.n().to_bytes_be()),
+            "e": BASE64URL_NOPAD.encode(&self.public_key.e().to_bytes_be()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct Claims {
+        sub: String,
+    }
+
+    fn make_key() -> OidcKey {
+        // A small key keeps the tests fast.
+        OidcKey::new(RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap())
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = make_key();
+        let claims = Claims {
+            sub: "bob".to_owned(),
+        };
+        let token = key.sign(&claims);
+        assert_eq!(key.verify::<Claims>(&token).unwrap(), claims);
+    }
+
+    #[test]
+    fn test_verify_tampered_token() {
+        let key = make_key();
+        let token = key.sign(&Claims {
+            sub: "bob".to_owned(),
+        });
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let (header, _) = token.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}.{}",
+            header,
+            BASE64URL_NOPAD.encode(br#"{"sub":"admin"}"#),
+            signature
+        );
+        assert!(key.verify::<Claims>(&forged).is_err());
+        assert!(make_key().verify::<Claims>(&token).is_err());
+    }
+
+    #[test]
+    fn test_jwk() {
+        let key = make_key();
+        let jwk = key.jwk();
+        assert_eq!(jwk["kid"], key.key_id());
+        assert_eq!(jwk["e"], "AQAB");
+    }
+}
@@ -0,0 +1,1115 @@
+//! Built-in OpenID Connect provider, with the authorization code flow and PKCE.
+//!
+//! The user approves the request in the web app, with their usual session. The tokens are signed
+//! with the [`OidcKey`], and the refresh tokens are opaque, bound to the client and the scopes.
+
+use crate::{
+    auth_service::{
+        ApiResult, CookieToHeaderTranslatorFactory, check_if_token_is_valid, default_hash,
+        error_to_api_response,
+    },
+    oidc_key::OidcKey,
+    tcp_backend_handler::*,
+    tcp_server::{AppState, TcpError, TcpResult, error_to_http_response},
+};
+use actix_web::{
+    HttpRequest, HttpResponse,
+    http::{StatusCode, header},
+    web,
+};
+use actix_web_httpauth::extractors::{basic::BasicAuth, bearer::BearerAuth};
+use chrono::Utc;
+use data_encoding::{BASE64URL_NOPAD, HEXLOWER};
+use lldap_access_control::UserReadableBackendHandler;
+use lldap_auth::oidc;
+use lldap_domain::types::{
+    AttributeName, AttributeValue, Cardinality, GroupDetails, GroupId, User, UserId,
+};
+use lldap_domain_handlers::handler::BackendHandler;
+use lldap_domain_model::error::DomainError;
+use rand::{Rng, distributions::Alphanumeric, rngs::OsRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashSet};
+use tracing::{debug, instrument};
+
+const ACCESS_TOKEN_LIFETIME_SECONDS: i64 = 3600;
+const SUPPORTED_SCOPES: &[&str] = &["openid", "profile", "email", "groups"];
+
+#[derive(Serialize, Deserialize)]
+struct AccessTokenClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    exp: i64,
+    iat: i64,
+    scope: String,
+    user: String,
+}
+
+#[derive(Serialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    exp: i64,
+    iat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(flatten)]
+    claims: Map<String, Value>,
+}
+
+/// An error of the token or userinfo endpoints, in the format of RFC 6749, section 5.2.
+#[derive(Debug)]
+struct OidcError {
+    status: StatusCode,
+    error: &'static str,
+    description: String,
+}
+
+impl OidcError {
+    fn new(status: StatusCode, error: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            status,
+            error,
+            description: description.into(),
+        }
+    }
+
+    fn invalid_request(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request", description)
+    }
+
+    fn invalid_client(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "invalid_client", description)
+    }
+
+    fn invalid_grant(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_grant", description)
+    }
+
+    fn invalid_token(description: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "invalid_token", description)
+    }
+
+    fn into_response(self) -> HttpResponse {
+        HttpResponse::build(self.status).json(json!({
+            "error": self.error,
+            "error_description": self.description,
+        }))
+    }
+}
+
+impl From<DomainError> for OidcError {
+    fn from(e: DomainError) -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "server_error",
+            e.to_string(),
+        )
+    }
+}
+
+fn gen_random_string(len: usize) -> String {
+    OsRng
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(len)
+        .collect()
+}
+
+pub(crate) fn hash_client_secret(secret: &str) -> String {
+    HEXLOWER.encode(&Sha512::digest(secret.as_bytes()))
+}
+
+/// Checks the PKCE code verifier against the S256 challenge (RFC 7636).
+fn check_code_verifier(verifier: &str, challenge: &str) -> bool {
+    BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes())) == challenge
+}
+
+fn get_issuer<Backend>(data: &AppState<Backend>) -> String {
+    data.server_url.as_str().trim_end_matches('/').to_owned()
+}
+
+fn get_oidc_key<Backend>(data: &AppState<Backend>) -> Result<&OidcKey, DomainError> {
+    data.oidc_key
+        .as_deref()
+        .ok_or_else(|| DomainError::InternalError("OIDC is not enabled".to_owned()))
+}
+
+/// Filters the requested scopes down to the ones the user may get: a scope restricted to some
+/// groups is only granted to their members. Returns an empty list if the user isn't allowed to
+/// log in to the client at all.
+fn get_allowed_scopes(
+    client: &OidcClient,
+    requested_scopes: &[String],
+    user_groups: &HashSet<GroupId>,
+) -> Vec<String> {
+    let allowed_scopes = requested_scopes
+        .iter()
+        .filter(|scope| {
+            let mut groups = client
+                .scope_groups
+                .iter()
+                .filter(|(s, _)| s == *scope)
+                .map(|(_, group_id)| group_id)
+                .peekable();
+            groups.peek().is_none() || groups.any(|group_id| user_groups.contains(group_id))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if allowed_scopes.iter().any(|scope| scope == "openid") {
+        allowed_scopes
+    } else {
+        Vec::new()
+    }
+}
+
+fn cardinality_to_json<T: Clone>(value: &Cardinality<T>, to_json: impl Fn(&T) -> Value) -> Value {
+    match value {
+        Cardinality::Singleton(v) => to_json(v),
+        Cardinality::Unbounded(l) => Value::Array(l.iter().map(to_json).collect()),
+    }
+}
+
+fn attribute_to_json(value: &AttributeValue) -> Option<Value> {
+    Some(match value {
+        AttributeValue::String(s) => cardinality_to_json(s, |s| Value::from(s.as_str())),
+        AttributeValue::Integer(i) => cardinality_to_json(i, |i| Value::from(*i)),
+        AttributeValue::DateTime(d) => {
+            cardinality_to_json(d, |d| Value::from(d.and_utc().to_rfc3339()))
+        }
+        AttributeValue::JpegPhoto(_) => return None,
+    })
+}
+
+/// The claims about the user that the scopes give access to.
+fn get_scope_claims(
+    user: &User,
+    groups: &HashSet<GroupDetails>,
+    scopes: &[String],
+    extra_claims: &BTreeMap<String, AttributeName>,
+) -> Map<String, Value> {
+    let has_scope = |scope: &str| scopes.iter().any(|s| s == scope);
+    let get_attribute = |name: &AttributeName| {
+        user.attributes
+            .iter()
+            .find(|a| &a.name == name)
+            .and_then(|a| attribute_to_json(&a.value))
+    };
+    let mut claims = Map::new();
+    if has_scope("profile") {
+        claims.insert(
+            "preferred_username".to_owned(),
+            user.user_id.as_str().into(),
+        );
+        if let Some(name) = user.display_name.as_deref().filter(|n| !n.is_empty()) {
+            claims.insert("name".to_owned(), name.into());
+        }
+        for (claim, attribute) in [("given_name", "first_name"), ("family_name", "last_name")] {
+            if let Some(value) = get_attribute(&attribute.into()) {
+                claims.insert(claim.to_owned(), value);
+            }
+        }
+        for (claim, attribute) in extra_claims {
+            if let Some(value) = get_attribute(attribute) {
+                claims.insert(claim.clone(), value);
+            }
+        }
+    }
+    if has_scope("email") {
+        claims.insert("email".to_owned(), user.email.as_str().into());
+    }
+    if has_scope("groups") {
+        let mut group_names = groups
+            .iter()
+            .map(|g| g.display_name.as_str())
+            .collect::<Vec<_>>();
+        group_names.sort_unstable();
+        claims.insert("groups".to_owned(), group_names.into());
+    }
+    claims
+}
+
+fn get_user_group_ids(groups: &HashSet<GroupDetails>) -> HashSet<GroupId> {
+    groups.iter().map(|g| g.group_id).collect()
+}
+
+/// Appends the parameters (and the state, if any) to the client's redirect URI.
+fn get_redirect_uri(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+    state: Option<&str>,
+) -> TcpResult<String> {
+    let mut url = url::Url::parse(redirect_uri)
+        .map_err(|e| TcpError::BadRequest(format!("Invalid redirect URI: {e}")))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    Ok(url.into())
+}
+
+fn redirect_to(location: &str) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+pub async fn discovery<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    let issuer = get_issuer(&data);
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/auth/oidc/authorize"),
+        "token_endpoint": format!("{issuer}/auth/oidc/token"),
+        "userinfo_endpoint": format!("{issuer}/auth/oidc/userinfo"),
+        "jwks_uri": format!("{issuer}/auth/oidc/jwks"),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "scopes_supported": SUPPORTED_SCOPES,
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": [
+            "sub", "iss", "aud", "exp", "iat", "nonce", "name", "preferred_username",
+            "given_name", "family_name", "email", "groups",
+        ],
+    }))
+}
+
+async fn jwks<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    match get_oidc_key(&data) {
+        Ok(key) => HttpResponse::Ok().json(json!({ "keys": [key.jwk()] })),
+        Err(e) => error_to_http_response(e.into()),
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthorizeQuery {
+    response_type: Option<String>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+/// Validates the authorization request, then sends the user to the consent page of the web app.
+#[instrument(skip_all, level = "debug")]
+async fn authorize<Backend>(
+    data: web::Data<AppState<Backend>>,
+    query: web::Query<AuthorizeQuery>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let query = query.into_inner();
+    let client = match &query.client_id {
+        Some(client_id) => data.get_tcp_handler().get_oidc_client(client_id).await?,
+        None => None,
+    }
+    .ok_or_else(|| TcpError::BadRequest("Unknown client".to_owned()))?;
+    // Until the redirect URI is validated, errors can't be sent back to the client.
+    let redirect_uri = query
+        .redirect_uri
+        .filter(|uri| client.redirect_uris.contains(uri))
+        .ok_or_else(|| TcpError::BadRequest("Invalid redirect URI".to_owned()))?;
+    let state = query.state;
+    let redirect_error = |error: &str| -> TcpResult<HttpResponse> {
+        Ok(redirect_to(&get_redirect_uri(
+            &redirect_uri,
+            &[("error", error)],
+            state.as_deref(),
+        )?))
+    };
+    if query.response_type.as_deref() != Some("code") {
+        return redirect_error("unsupported_response_type");
+    }
+    let mut scopes = Vec::<String>::new();
+    for scope in query
+        .scope
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        if SUPPORTED_SCOPES.contains(&scope) && !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_owned());
+        }
+    }
+    if !scopes.iter().any(|s| s == "openid") {
+        return redirect_error("invalid_scope");
+    }
+    let code_challenge = match (query.code_challenge, query.code_challenge_method.as_deref()) {
+        (Some(code_challenge), Some("S256")) => code_challenge,
+        _ => return redirect_error("invalid_request"),
+    };
+    let request_id = data
+        .get_tcp_handler()
+        .create_oidc_authorization(OidcAuthorizationRequest {
+            client_id: client.client_id,
+            redirect_uri: redirect_uri.clone(),
+            scopes,
+            state: state.clone(),
+            nonce: query.nonce,
+            code_challenge,
+        })
+        .await?;
+    let mut path = data.server_url.path().to_string();
+    if !path.ends_with('/') {
+        path.push('/');
+    };
+    Ok(redirect_to(&format!("{path}oidc/authorize/{request_id}")))
+}
+
+async fn authorize_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    query: web::Query<AuthorizeQuery>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    authorize(data, query)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Gets the pending request, with the client and the groups of the user.
+async fn get_consent_context<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    user: &UserId,
+    request: &HttpRequest,
+) -> TcpResult<(OidcAuthorization, OidcClient, HashSet<GroupId>)>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let request_id = request
+        .match_info()
+        .get("request_id")
+        .ok_or_else(|| TcpError::BadRequest("Missing request ID".to_owned()))?;
+    let authorization = data
+        .get_tcp_handler()
+        .get_oidc_authorization(request_id)
+        .await?
+        .ok_or_else(|| {
+            TcpError::NotFoundError("Unknown or expired authorization request".to_owned())
+        })?;
+    let client = data
+        .get_tcp_handler()
+        .get_oidc_client(&authorization.request.client_id)
+        .await?
+        .ok_or_else(|| TcpError::NotFoundError("Unknown client".to_owned()))?;
+    let groups = data.get_readonly_handler().get_user_groups(user).await?;
+    Ok((authorization, client, get_user_group_ids(&groups)))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_consent<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+) -> TcpResult<oidc::ServerAuthorizationRequestResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .await
+        .map_err(|_| TcpError::UnauthorizedError("Not logged in".to_string()))?;
+    let (authorization, client, groups) =
+        get_consent_context(&data, &validation_result.user, &request).await?;
+    Ok(oidc::ServerAuthorizationRequestResponse {
+        client_name: client.display_name.clone(),
+        scopes: get_allowed_scopes(&client, &authorization.request.scopes, &groups),
+    })
+}
+
+async fn get_consent_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+) -> ApiResult<oidc::ServerAuthorizationRequestResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_consent(data, bearer, request)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_consent<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+    decision: web::Json<oidc::ClientAuthorizationDecisionRequest>,
+) -> TcpResult<oidc::ServerAuthorizationDecisionResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .await
+        .map_err(|_| TcpError::UnauthorizedError("Not logged in".to_string()))?;
+    let user = &validation_result.user;
+    // Restricted API tokens can't grant access on behalf of the user.
+    if !validation_result.can_write(user) {
+        return Err(TcpError::UnauthorizedError(
+            "Not authorized to approve the request".to_string(),
+        ));
+    }
+    let (authorization, client, groups) = get_consent_context(&data, user, &request).await?;
+    let scopes = get_allowed_scopes(&client, &authorization.request.scopes, &groups);
+    let code = gen_random_string(32);
+    let params = if decision.approve && !scopes.is_empty() {
+        data.get_tcp_handler()
+            .approve_oidc_authorization(
+                &authorization.request_id,
+                user,
+                &scopes,
+                default_hash(code.as_str()),
+            )
+            .await?;
+        [("code", code.as_str())]
+    } else {
+        debug!(?user, approve = decision.approve, "Authorization denied");
+        data.get_tcp_handler()
+            .delete_oidc_authorization(&authorization.request_id)
+            .await?;
+        [("error", "access_denied")]
+    };
+    Ok(oidc::ServerAuthorizationDecisionResponse {
+        redirect_uri: get_redirect_uri(
+            &authorization.request.redirect_uri,
+            &params,
+            authorization.request.state.as_deref(),
+        )?,
+    })
+}
+
+async fn post_consent_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+    decision: web::Json<oidc::ClientAuthorizationDecisionRequest>,
+) -> ApiResult<oidc::ServerAuthorizationDecisionResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    post_consent(data, bearer, request, decision)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Authenticates the client with either HTTP Basic or the request body. Public clients only
+/// need their ID.
+async fn authenticate_client<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    basic: Option<BasicAuth>,
+    request: &TokenRequest,
+) -> Result<OidcClient, OidcError>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let decode = |s: &str| {
+        urlencoding::decode(s)
+            .map(|s| s.into_owned())
+            .map_err(|_| OidcError::invalid_client("Malformed client credentials"))
+    };
+    let (client_id, client_secret) = match &basic {
+        Some(basic) => (
+            decode(basic.user_id())?,
+            basic.password().map(decode).transpose()?,
+        ),
+        None => (
+            request
+                .client_id
+                .clone()
+                .ok_or_else(|| OidcError::invalid_client("Missing client ID"))?,
+            request.client_secret.clone(),
+        ),
+    };
+    let client = data
+        .get_tcp_handler()
+        .get_oidc_client(&client_id)
+        .await?
+        .ok_or_else(|| OidcError::invalid_client("Unknown client"))?;
+    match (&client.client_secret_hash, client_secret) {
+        (None, _) => Ok(client),
+        (Some(hash), Some(secret)) if *hash == hash_client_secret(&secret) => Ok(client),
+        _ => Err(OidcError::invalid_client("Invalid client credentials")),
+    }
+}
+
+async fn issue_tokens<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    client: &OidcClient,
+    user: &UserId,
+    scopes: &[String],
+    nonce: Option<String>,
+) -> Result<HttpResponse, OidcError>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let key = get_oidc_key(data)?;
+    let user_details = data.get_readonly_handler().get_user_details(user).await?;
+    let groups = data.get_readonly_handler().get_user_groups(user).await?;
+    let issuer = get_issuer(data);
+    let now = Utc::now();
+    let expiry = now + chrono::Duration::seconds(ACCESS_TOKEN_LIFETIME_SECONDS);
+    let scope = scopes.join(" ");
+    let refresh_token = data
+        .get_tcp_handler()
+        .create_oidc_refresh_token(&client.client_id, user, scopes)
+        .await?;
+    let access_token = key.sign(&AccessTokenClaims {
+        iss: issuer.clone(),
+        sub: user_details.uuid.to_string(),
+        aud: client.client_id.clone(),
+        exp: expiry.timestamp(),
+        iat: now.timestamp(),
+        scope: scope.clone(),
+        user: user.to_string(),
+    });
+    // Registered like the regular JWTs, so that logging out revokes it too.
+    data.get_tcp_handler()
+        .register_jwt(
+            user,
+            default_hash(access_token.as_str()),
+            expiry.naive_utc(),
+            None,
+        )
+        .await?;
+    let id_token = key.sign(&IdTokenClaims {
+        iss: issuer,
+        sub: user_details.uuid.to_string(),
+        aud: client.client_id.clone(),
+        exp: expiry.timestamp(),
+        iat: now.timestamp(),
+        nonce,
+        claims: get_scope_claims(
+            &user_details,
+            &groups,
+            scopes,
+            &data.oidc_options.extra_claims,
+        ),
+    });
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": ACCESS_TOKEN_LIFETIME_SECONDS,
+            "id_token": id_token,
+            "refresh_token": refresh_token,
+            "scope": scope,
+        })))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn token<Backend>(
+    data: web::Data<AppState<Backend>>,
+    basic: Option<BasicAuth>,
+    request: web::Form<TokenRequest>,
+) -> Result<HttpResponse, OidcError>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let request = request.into_inner();
+    let client = authenticate_client(&data, basic, &request).await?;
+    let (user, scopes, nonce) = match request.grant_type.as_str() {
+        "authorization_code" => {
+            let code = request
+                .code
+                .ok_or_else(|| OidcError::invalid_request("Missing code"))?;
+            let authorization = data
+                .get_tcp_handler()
+                .consume_oidc_authorization_code(default_hash(code.as_str()))
+                .await?
+                .ok_or_else(|| OidcError::invalid_grant("Invalid or expired code"))?;
+            let authorization_request = authorization.request;
+            if authorization_request.client_id != client.client_id
+                || request.redirect_uri.as_ref() != Some(&authorization_request.redirect_uri)
+            {
+                return Err(OidcError::invalid_grant(
+                    "The code was issued for another client or redirect URI",
+                ));
+            }
+            if !request.code_verifier.as_deref().is_some_and(|verifier| {
+                check_code_verifier(verifier, &authorization_request.code_challenge)
+            }) {
+                return Err(OidcError::invalid_grant("Invalid code verifier"));
+            }
+            let user = authorization
+                .user_id
+                .ok_or_else(|| OidcError::invalid_grant("Authorization not approved"))?;
+            (
+                user,
+                authorization_request.scopes,
+                authorization_request.nonce,
+            )
+        }
+        "refresh_token" => {
+            let refresh_token = request
+                .refresh_token
+                .ok_or_else(|| OidcError::invalid_request("Missing refresh token"))?;
+            // Refresh tokens are rotated: the token is consumed.
+            let (user, requested_scopes) = data
+                .get_tcp_handler()
+                .consume_oidc_refresh_token(&client.client_id, default_hash(refresh_token.as_str()))
+                .await?
+                .ok_or_else(|| OidcError::invalid_grant("Invalid refresh token"))?;
+            // Unlike the sessions, the refresh tokens of the clients are not revoked when the
+            // account is disabled.
+            if !data
+                .get_readonly_handler()
+                .get_user_details(&user)
+                .await?
+                .is_active_at(Utc::now().naive_utc())
+            {
+                return Err(OidcError::invalid_grant(
+                    "The account is disabled or expired",
+                ));
+            }
+            // The group memberships may have changed since the authorization.
+            let groups = data.get_readonly_handler().get_user_groups(&user).await?;
+            let scopes =
+                get_allowed_scopes(&client, &requested_scopes, &get_user_group_ids(&groups));
+            if scopes.is_empty() {
+                return Err(OidcError::invalid_grant(
+                    "The user is not allowed to use this client anymore",
+                ));
+            }
+            (user, scopes, None)
+        }
+        _ => {
+            return Err(OidcError::new(
+                StatusCode::BAD_REQUEST,
+                "unsupported_grant_type",
+                "Only authorization_code and refresh_token are supported",
+            ));
+        }
+    };
+    issue_tokens(&data, &client, &user, &scopes, nonce).await
+}
+
+async fn token_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    basic: Option<BasicAuth>,
+    request: web::Form<TokenRequest>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    token(data, basic, request)
+        .await
+        .unwrap_or_else(OidcError::into_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn userinfo<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> Result<HttpResponse, OidcError>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let claims = get_oidc_key(&data)?
+        .verify::<AccessTokenClaims>(bearer.token())
+        .map_err(|_| OidcError::invalid_token("Invalid access token"))?;
+    if claims.exp < Utc::now().timestamp() {
+        return Err(OidcError::invalid_token("Expired access token"));
+    }
+    if data
+        .jwt_blacklist
+        .read()
+        .unwrap()
+        .contains(&default_hash(bearer.token()))
+    {
+        return Err(OidcError::invalid_token("Access token was logged out"));
+    }
+    let user = UserId::new(&claims.user);
+    let user_details = data.get_readonly_handler().get_user_details(&user).await?;
+    let groups = data.get_readonly_handler().get_user_groups(&user).await?;
+    let scopes = claims
+        .scope
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let mut response = get_scope_claims(
+        &user_details,
+        &groups,
+        &scopes,
+        &data.oidc_options.extra_claims,
+    );
+    response.insert("sub".to_owned(), claims.sub.into());
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn userinfo_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    userinfo(data, bearer)
+        .await
+        .unwrap_or_else(OidcError::into_response)
+}
+
+#[derive(Serialize)]
+struct ClientResponse {
+    client_id: String,
+    display_name: String,
+    redirect_uris: Vec<String>,
+    confidential: bool,
+    scope_groups: BTreeMap<String, Vec<GroupId>>,
+}
+
+impl From<OidcClient> for ClientResponse {
+    fn from(client: OidcClient) -> Self {
+        let mut scope_groups = BTreeMap::<String, Vec<GroupId>>::new();
+        for (scope, group_id) in client.scope_groups {
+            scope_groups.entry(scope).or_default().push(group_id);
+        }
+        Self {
+            client_id: client.client_id,
+            display_name: client.display_name,
+            redirect_uris: client.redirect_uris,
+            confidential: client.client_secret_hash.is_some(),
+            scope_groups,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateClientRequest {
+    client_id: String,
+    display_name: String,
+    redirect_uris: Vec<String>,
+    /// Public clients (e.g. single-page apps) don't get a secret.
+    #[serde(default)]
+    public: bool,
+    #[serde(default)]
+    scope_groups: BTreeMap<String, Vec<GroupId>>,
+}
+
+#[derive(Serialize)]
+struct CreateClientResponse {
+    /// Only returned once, at creation.
+    client_secret: Option<String>,
+}
+
+async fn check_admin<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    bearer: &BearerAuth,
+) -> TcpResult<()> {
+    match check_if_token_is_valid(data, bearer.token()).await {
+        Ok(validation_result) if validation_result.is_admin() => Ok(()),
+        _ => Err(TcpError::UnauthorizedError(
+            "Only admins can manage OIDC clients".to_string(),
+        )),
+    }
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_clients<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> TcpResult<Vec<ClientResponse>>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_admin(&data, &bearer).await?;
+    Ok(data
+        .get_tcp_handler()
+        .list_oidc_clients()
+        .await?
+        .into_iter()
+        .map(ClientResponse::from)
+        .collect())
+}
+
+async fn list_clients_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> ApiResult<Vec<ClientResponse>>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    list_clients(data, bearer)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_client<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: web::Json<CreateClientRequest>,
+) -> TcpResult<CreateClientResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_admin(&data, &bearer).await?;
+    let request = request.into_inner();
+    if request.client_id.is_empty() {
+        return Err(TcpError::BadRequest("Client ID cannot be empty".to_owned()));
+    }
+    if request.redirect_uris.is_empty() {
+        return Err(TcpError::BadRequest(
+            "At least one redirect URI is required".to_owned(),
+        ));
+    }
+    if let Some(uri) = request
+        .redirect_uris
+        .iter()
+        .find(|uri| url::Url::parse(uri).is_err())
+    {
+        return Err(TcpError::BadRequest(format!("Invalid redirect URI: {uri}")));
+    }
+    if data
+        .get_tcp_handler()
+        .get_oidc_client(&request.client_id)
+        .await?
+        .is_some()
+    {
+        return Err(DomainError::EntityAlreadyExists(format!(
+            "OIDC client '{}' already exists",
+            request.client_id
+        ))
+        .into());
+    }
+    let client_secret = (!request.public).then(|| gen_random_string(48));
+    data.get_tcp_handler()
+        .create_oidc_client(OidcClient {
+            client_id: request.client_id,
+            display_name: request.display_name,
+            client_secret_hash: client_secret.as_deref().map(hash_client_secret),
+            redirect_uris: request.redirect_uris,
+            scope_groups: request
+                .scope_groups
+                .into_iter()
+                .flat_map(|(scope, groups)| {
+                    groups
+                        .into_iter()
+                        .map(move |group_id| (scope.clone(), group_id))
+                })
+                .collect(),
+        })
+        .await?;
+    Ok(CreateClientResponse { client_secret })
+}
+
+async fn create_client_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: web::Json<CreateClientRequest>,
+) -> ApiResult<CreateClientResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    create_client(data, bearer, request)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn delete_client<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_admin(&data, &bearer).await?;
+    let client_id = request
+        .match_info()
+        .get("client_id")
+        .ok_or_else(|| TcpError::BadRequest("Missing client ID".to_owned()))?;
+    data.get_tcp_handler().delete_oidc_client(client_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn delete_client_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    delete_client(data, bearer, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Configures the endpoints under `/auth/oidc`.
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    cfg.service(web::resource("/authorize").route(web::get().to(authorize_handler::<Backend>)))
+        .service(web::resource("/token").route(web::post().to(token_handler::<Backend>)))
+        .service(
+            web::resource("/userinfo")
+                .route(web::get().to(userinfo_handler::<Backend>))
+                .route(web::post().to(userinfo_handler::<Backend>)),
+        )
+        .service(web::resource("/jwks").route(web::get().to(jwks::<Backend>)))
+        // The consent page and the client management use the regular session.
+        .service(
+            web::scope("/consent")
+                .wrap(CookieToHeaderTranslatorFactory)
+                .service(
+                    web::resource("/{request_id}")
+                        .route(web::get().to(get_consent_handler::<Backend>))
+                        .route(web::post().to(post_consent_handler::<Backend>)),
+                ),
+        )
+        .service(
+            web::scope("/clients")
+                .wrap(CookieToHeaderTranslatorFactory)
+                .service(
+                    web::resource("")
+                        .route(web::get().to(list_clients_handler::<Backend>))
+                        .route(web::post().to(create_client_handler::<Backend>)),
+                )
+                .service(
+                    web::resource("/{client_id}")
+                        .route(web::delete().to(delete_client_handler::<Backend>)),
+                ),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lldap_domain::types::{Attribute, GroupName};
+
+    fn make_client(scope_groups: Vec<(&str, i32)>) -> OidcClient {
+        OidcClient {
+            client_id: "app".to_owned(),
+            display_name: "App".to_owned(),
+            client_secret_hash: None,
+            redirect_uris: vec!["https://app.example.com/callback".to_owned()],
+            scope_groups: scope_groups
+                .into_iter()
+                .map(|(scope, group_id)| (scope.to_owned(), GroupId(group_id)))
+                .collect(),
+        }
+    }
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_code_verifier() {
+        // Example from RFC 7636, appendix B.
+        assert!(check_code_verifier(
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        ));
+        assert!(!check_code_verifier(
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"
+        ));
+    }
+
+    #[test]
+    fn test_get_allowed_scopes() {
+        let requested = scopes(&["openid", "email", "groups"]);
+        let client = make_client(vec![("groups", 2), ("groups", 3)]);
+        assert_eq!(
+            get_allowed_scopes(&client, &requested, &HashSet::from([GroupId(3)])),
+            requested
+        );
+        assert_eq!(
+            get_allowed_scopes(&client, &requested, &HashSet::from([GroupId(1)])),
+            scopes(&["openid", "email"])
+        );
+        // Restricting "openid" restricts the whole client.
+        let client = make_client(vec![("openid", 2)]);
+        assert_eq!(
+            get_allowed_scopes(&client, &requested, &HashSet::from([GroupId(1)])),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_get_scope_claims() {
+        let user = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".into(),
+            display_name: Some("Bob Bobberson".to_owned()),
+            attributes: vec![
+                Attribute {
+                    name: "first_name".into(),
+                    value: "Bob".to_string().into(),
+                },
+                Attribute {
+                    name: "club".into(),
+                    value: vec!["chess".to_string(), "go".to_string()].into(),
+                },
+            ],
+            ..Default::default()
+        };
+        let groups = HashSet::from([GroupDetails {
+            group_id: GroupId(1),
+            display_name: GroupName::from("family"),
+            creation_date: chrono::NaiveDateTime::default(),
+            modified_date: chrono::NaiveDateTime::default(),
+            uuid: lldap_domain::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            attributes: Vec::new(),
+        }]);
+        let extra_claims = BTreeMap::from([("clubs".to_owned(), AttributeName::from("club"))]);
+        assert_eq!(
+            Value::Object(get_scope_claims(
+                &user,
+                &groups,
+                &scopes(&["openid", "profile", "email", "groups"]),
+                &extra_claims
+            )),
+            json!({
+                "preferred_username": "bob",
+                "name": "Bob Bobberson",
+                "given_name": "Bob",
+                "clubs": ["chess", "go"],
+                "email": "bob@example.com",
+                "groups": ["family"],
+            })
+        );
+        assert_eq!(
+            Value::Object(get_scope_claims(
+                &user,
+                &groups,
+                &scopes(&["openid", "email"]),
+                &extra_claims
+            )),
+            json!({ "email": "bob@example.com" })
+        );
+    }
+}
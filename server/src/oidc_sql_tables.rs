@@ -0,0 +1,241 @@
+use sea_orm::{
+    ConnectionTrait, DeriveIden,
+    sea_query::{ColumnDef, ForeignKey, ForeignKeyAction, Index, Table},
+};
+
+pub use lldap_sql_backend_handler::{
+    sql_migrations::{Groups, Users},
+    sql_tables::DbConnection,
+};
+
+/// Contains the clients registered with the OpenID Connect provider.
+#[derive(DeriveIden)]
+pub enum OidcClients {
+    Table,
+    ClientId,
+    DisplayName,
+    ClientSecretHash,
+    RedirectUris,
+}
+
+/// Restricts the scopes a client can get to the members of some groups.
+#[derive(DeriveIden)]
+pub enum OidcClientScopeGroups {
+    Table,
+    ClientId,
+    Scope,
+    GroupId,
+}
+
+/// Contains the pending authorization requests, and the authorization codes waiting to be
+/// exchanged for tokens.
+#[derive(DeriveIden)]
+pub enum OidcAuthorizations {
+    Table,
+    RequestId,
+    ClientId,
+    RedirectUri,
+    Scope,
+    State,
+    Nonce,
+    CodeChallenge,
+    UserId,
+    CodeHash,
+    ExpiryDate,
+}
+
+/// Contains the hashes of the refresh tokens given to the clients.
+#[derive(DeriveIden)]
+pub enum OidcRefreshTokens {
+    Table,
+    RefreshTokenHash,
+    ClientId,
+    UserId,
+    Scope,
+    ExpiryDate,
+}
+
+/// This needs to be initialized after the domain tables are.
+pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcClients::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcClients::ClientId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(OidcClients::DisplayName)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(OidcClients::ClientSecretHash).string_len(255))
+                .col(ColumnDef::new(OidcClients::RedirectUris).text().not_null()),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcClientScopeGroups::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcClientScopeGroups::ClientId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcClientScopeGroups::Scope)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcClientScopeGroups::GroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(OidcClientScopeGroups::ClientId)
+                        .col(OidcClientScopeGroups::Scope)
+                        .col(OidcClientScopeGroups::GroupId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcClientScopeGroupsClientForeignKey")
+                        .from(
+                            OidcClientScopeGroups::Table,
+                            OidcClientScopeGroups::ClientId,
+                        )
+                        .to(OidcClients::Table, OidcClients::ClientId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcClientScopeGroupsGroupForeignKey")
+                        .from(OidcClientScopeGroups::Table, OidcClientScopeGroups::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcAuthorizations::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcAuthorizations::RequestId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizations::ClientId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizations::RedirectUri)
+                        .text()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(OidcAuthorizations::Scope).text().not_null())
+                .col(ColumnDef::new(OidcAuthorizations::State).text())
+                .col(ColumnDef::new(OidcAuthorizations::Nonce).text())
+                .col(
+                    ColumnDef::new(OidcAuthorizations::CodeChallenge)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(OidcAuthorizations::UserId).string_len(255))
+                .col(
+                    ColumnDef::new(OidcAuthorizations::CodeHash)
+                        .big_integer()
+                        .unique_key(),
+                )
+                .col(
+                    ColumnDef::new(OidcAuthorizations::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcAuthorizationsClientForeignKey")
+                        .from(OidcAuthorizations::Table, OidcAuthorizations::ClientId)
+                        .to(OidcClients::Table, OidcClients::ClientId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcAuthorizationsUserForeignKey")
+                        .from(OidcAuthorizations::Table, OidcAuthorizations::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(OidcRefreshTokens::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(OidcRefreshTokens::RefreshTokenHash)
+                        .big_integer()
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(OidcRefreshTokens::ClientId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(OidcRefreshTokens::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(OidcRefreshTokens::Scope).text().not_null())
+                .col(
+                    ColumnDef::new(OidcRefreshTokens::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcRefreshTokensClientForeignKey")
+                        .from(OidcRefreshTokens::Table, OidcRefreshTokens::ClientId)
+                        .to(OidcClients::Table, OidcClients::ClientId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("OidcRefreshTokensUserForeignKey")
+                        .from(OidcRefreshTokens::Table, OidcRefreshTokens::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -1,19 +1,37 @@
-use crate::tcp_backend_handler::TcpBackendHandler;
+use crate::{
+    auth_service::default_hash,
+    tcp_backend_handler::{
+        OidcAuthorization, OidcAuthorizationRequest, OidcClient, SessionInfo, TcpBackendHandler,
+    },
+};
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use lldap_domain::types::UserId;
 use lldap_domain_model::{
     error::*,
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
+    model::{
+        self, JwtRefreshStorageColumn, JwtStorageColumn, MfaLoginTokensColumn,
+        MfaRecoveryCodesColumn, OidcAuthorizationsColumn, OidcClientsColumn,
+        OidcRefreshTokensColumn, PasswordResetTokensColumn, UserColumn,
+    },
 };
 use lldap_sql_backend_handler::SqlBackendHandler;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QuerySelect,
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveModel, ModelTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
     sea_query::{Cond, Expr},
 };
 use std::collections::HashSet;
 use tracing::{debug, instrument};
 
+const TOTP_MFA_TYPE: &str = "totp";
+const MAX_FAILED_MFA_ATTEMPTS: i32 = 5;
+/// How long the user has to approve an authorization request.
+const OIDC_AUTHORIZATION_LIFETIME_MINUTES: i64 = 10;
+/// How long the client has to exchange the authorization code.
+const OIDC_CODE_LIFETIME_MINUTES: i64 = 1;
+const OIDC_REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+
 fn gen_random_string(len: usize) -> String {
     use rand::{Rng, SeedableRng, distributions::Alphanumeric, rngs::SmallRng};
     let mut rng = SmallRng::from_entropy();
@@ -24,6 +42,43 @@ fn gen_random_string(len: usize) -> String {
         .collect()
 }
 
+fn to_oidc_client(
+    client: model::oidc_clients::Model,
+    scope_groups: Vec<model::oidc_client_scope_groups::Model>,
+) -> OidcClient {
+    OidcClient {
+        client_id: client.client_id,
+        display_name: client.display_name,
+        client_secret_hash: client.client_secret_hash,
+        redirect_uris: client.redirect_uris.lines().map(str::to_owned).collect(),
+        scope_groups: scope_groups
+            .into_iter()
+            .map(|s| (s.scope, s.group_id))
+            .collect(),
+    }
+}
+
+impl From<model::oidc_authorizations::Model> for OidcAuthorization {
+    fn from(authorization: model::oidc_authorizations::Model) -> Self {
+        OidcAuthorization {
+            request_id: authorization.request_id,
+            request: OidcAuthorizationRequest {
+                client_id: authorization.client_id,
+                redirect_uri: authorization.redirect_uri,
+                scopes: authorization
+                    .scope
+                    .split_whitespace()
+                    .map(str::to_owned)
+                    .collect(),
+                state: authorization.state,
+                nonce: authorization.nonce,
+                code_challenge: authorization.code_challenge,
+            },
+            user_id: authorization.user_id,
+        }
+    }
+}
+
 #[async_trait]
 impl TcpBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug")]
@@ -41,7 +96,11 @@ impl TcpBackendHandler for SqlBackendHandler {
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)> {
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        session: &SessionInfo,
+    ) -> Result<(String, chrono::Duration)> {
         debug!(?user);
         // TODO: Initialize the rng only once. Maybe Arc<Cell>?
         let refresh_token = gen_random_string(100);
@@ -53,10 +112,17 @@ impl TcpBackendHandler for SqlBackendHandler {
             s.finish()
         };
         let duration = chrono::Duration::days(30);
+        let now = chrono::Utc::now().naive_utc();
         let new_token = model::jwt_refresh_storage::Model {
             refresh_token_hash: refresh_token_hash as i64,
             user_id: user.clone(),
-            expiry_date: chrono::Utc::now().naive_utc() + duration,
+            expiry_date: now + duration,
+            creation_date: now,
+            ip_address: session.ip_address.map(|ip| ip.to_string()),
+            user_agent: session
+                .user_agent
+                .as_ref()
+                .map(|agent| agent.chars().take(255).collect()),
         }
         .into_active_model();
         new_token.insert(self.pool()).await?;
@@ -69,6 +135,7 @@ impl TcpBackendHandler for SqlBackendHandler {
         user: &UserId,
         jwt_hash: u64,
         expiry_date: NaiveDateTime,
+        refresh_token_hash: Option<u64>,
     ) -> Result<()> {
         debug!(?user, ?jwt_hash);
         let new_token = model::jwt_storage::Model {
@@ -76,6 +143,7 @@ impl TcpBackendHandler for SqlBackendHandler {
             user_id: user.clone(),
             blacklisted: false,
             expiry_date,
+            refresh_token_hash: refresh_token_hash.map(|h| h as i64),
         }
         .into_active_model();
         new_token.insert(self.pool()).await?;
@@ -175,4 +243,421 @@ impl TcpBackendHandler for SqlBackendHandler {
         }
         Ok(())
     }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn create_mfa_login_token(&self, user: &UserId) -> Result<String> {
+        debug!(?user);
+        let token = gen_random_string(100);
+        let token_hash = {
+            use std::collections::hash_map::DefaultHasher;
+            use std::hash::{Hash, Hasher};
+            let mut s = DefaultHasher::new();
+            token.hash(&mut s);
+            s.finish()
+        };
+        let duration = chrono::Duration::minutes(5);
+        let new_token = model::mfa_login_tokens::Model {
+            token_hash: token_hash as i64,
+            user_id: user.clone(),
+            expiry_date: chrono::Utc::now().naive_utc() + duration,
+            failed_attempts: 0,
+        }
+        .into_active_model();
+        new_token.insert(self.pool()).await?;
+        Ok(token)
+    }
+
+    #[instrument(skip_all, level = "debug", ret)]
+    async fn get_user_id_for_mfa_login_token(&self, token_hash: u64) -> Result<UserId> {
+        Ok(model::MfaLoginTokens::find_by_id(token_hash as i64)
+            .filter(MfaLoginTokensColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .filter(MfaLoginTokensColumn::FailedAttempts.lt(MAX_FAILED_MFA_ATTEMPTS))
+            .one(self.pool())
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound("Invalid MFA login token".to_owned()))?
+            .user_id)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn register_failed_mfa_attempt(&self, token_hash: u64) -> Result<()> {
+        model::MfaLoginTokens::update_many()
+            .col_expr(
+                MfaLoginTokensColumn::FailedAttempts,
+                Expr::col(MfaLoginTokensColumn::FailedAttempts).add(1),
+            )
+            .filter(MfaLoginTokensColumn::TokenHash.eq(token_hash as i64))
+            .exec(self.pool())
+            .await?;
+        model::MfaLoginTokens::delete_many()
+            .filter(MfaLoginTokensColumn::TokenHash.eq(token_hash as i64))
+            .filter(MfaLoginTokensColumn::FailedAttempts.gte(MAX_FAILED_MFA_ATTEMPTS))
+            .exec(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn delete_mfa_login_token(&self, token_hash: u64) -> Result<()> {
+        model::MfaLoginTokens::delete_by_id(token_hash as i64)
+            .exec(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn get_totp_secret(&self, user: &UserId) -> Result<Option<String>> {
+        debug!(?user);
+        let user = model::User::find_by_id(user.clone())
+            .one(self.pool())
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("No such user: '{}'", user)))?;
+        Ok(match user.mfa_type.as_deref() {
+            Some(TOTP_MFA_TYPE) => user.totp_secret,
+            _ => None,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn set_totp_secret(
+        &self,
+        user: &UserId,
+        secret: &str,
+        recovery_code_hashes: &[u64],
+    ) -> Result<()> {
+        debug!(?user);
+        let user = user.clone();
+        let secret = secret.to_owned();
+        let recovery_code_hashes = recovery_code_hashes.to_vec();
+        self.pool()
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let result = model::User::update_many()
+                        .set(model::users::ActiveModel {
+                            totp_secret: ActiveValue::Set(Some(secret)),
+                            mfa_type: ActiveValue::Set(Some(TOTP_MFA_TYPE.to_owned())),
+                            totp_last_counter: ActiveValue::Set(None),
+                            ..Default::default()
+                        })
+                        .filter(UserColumn::UserId.eq(&user))
+                        .exec(transaction)
+                        .await?;
+                    if result.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such user: '{}'",
+                            user
+                        )));
+                    }
+                    model::MfaRecoveryCodes::delete_many()
+                        .filter(MfaRecoveryCodesColumn::UserId.eq(&user))
+                        .exec(transaction)
+                        .await?;
+                    if !recovery_code_hashes.is_empty() {
+                        model::MfaRecoveryCodes::insert_many(recovery_code_hashes.into_iter().map(
+                            |code_hash| {
+                                model::mfa_recovery_codes::Model {
+                                    code_hash: code_hash as i64,
+                                    user_id: user.clone(),
+                                }
+                                .into_active_model()
+                            },
+                        ))
+                        .exec(transaction)
+                        .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn use_totp_counter(&self, user: &UserId, counter: i64) -> Result<bool> {
+        debug!(?user, counter);
+        let result = model::User::update_many()
+            .col_expr(UserColumn::TotpLastCounter, Expr::value(counter))
+            .filter(UserColumn::UserId.eq(user))
+            .filter(
+                Cond::any()
+                    .add(UserColumn::TotpLastCounter.is_null())
+                    .add(UserColumn::TotpLastCounter.lt(counter)),
+            )
+            .exec(self.pool())
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn delete_totp_secret(&self, user: &UserId) -> Result<()> {
+        debug!(?user);
+        let user = user.clone();
+        self.pool()
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    let result = model::User::update_many()
+                        .set(model::users::ActiveModel {
+                            totp_secret: ActiveValue::Set(None),
+                            mfa_type: ActiveValue::Set(None),
+                            totp_last_counter: ActiveValue::Set(None),
+                            ..Default::default()
+                        })
+                        .filter(UserColumn::UserId.eq(&user))
+                        .exec(transaction)
+                        .await?;
+                    if result.rows_affected == 0 {
+                        return Err(DomainError::EntityNotFound(format!(
+                            "No such user: '{}'",
+                            user
+                        )));
+                    }
+                    model::MfaRecoveryCodes::delete_many()
+                        .filter(MfaRecoveryCodesColumn::UserId.eq(&user))
+                        .exec(transaction)
+                        .await?;
+                    Ok(())
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn use_recovery_code(&self, user: &UserId, code_hash: u64) -> Result<bool> {
+        debug!(?user);
+        let result = model::MfaRecoveryCodes::delete_many()
+            .filter(MfaRecoveryCodesColumn::CodeHash.eq(code_hash as i64))
+            .filter(MfaRecoveryCodesColumn::UserId.eq(user))
+            .exec(self.pool())
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>> {
+        Ok(model::OidcClients::find()
+            .order_by_asc(OidcClientsColumn::ClientId)
+            .find_with_related(model::OidcClientScopeGroups)
+            .all(self.pool())
+            .await?
+            .into_iter()
+            .map(|(client, scope_groups)| to_oidc_client(client, scope_groups))
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn get_oidc_client(&self, client_id: &str) -> Result<Option<OidcClient>> {
+        debug!(?client_id);
+        let Some(client) = model::OidcClients::find_by_id(client_id.to_owned())
+            .one(self.pool())
+            .await?
+        else {
+            return Ok(None);
+        };
+        let scope_groups = client
+            .find_related(model::OidcClientScopeGroups)
+            .all(self.pool())
+            .await?;
+        Ok(Some(to_oidc_client(client, scope_groups)))
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn create_oidc_client(&self, client: OidcClient) -> Result<()> {
+        debug!(?client.client_id);
+        self.pool()
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move {
+                    model::oidc_clients::Model {
+                        client_id: client.client_id.clone(),
+                        display_name: client.display_name,
+                        client_secret_hash: client.client_secret_hash,
+                        redirect_uris: client.redirect_uris.join("\n"),
+                    }
+                    .into_active_model()
+                    .insert(transaction)
+                    .await?;
+                    if !client.scope_groups.is_empty() {
+                        model::OidcClientScopeGroups::insert_many(
+                            client.scope_groups.into_iter().map(|(scope, group_id)| {
+                                model::oidc_client_scope_groups::Model {
+                                    client_id: client.client_id.clone(),
+                                    scope,
+                                    group_id,
+                                }
+                                .into_active_model()
+                            }),
+                        )
+                        .exec(transaction)
+                        .await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn delete_oidc_client(&self, client_id: &str) -> Result<()> {
+        debug!(?client_id);
+        let result = model::OidcClients::delete_by_id(client_id.to_owned())
+            .exec(self.pool())
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such OIDC client: '{}'",
+                client_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn create_oidc_authorization(&self, request: OidcAuthorizationRequest) -> Result<String> {
+        debug!(?request.client_id);
+        let request_id = gen_random_string(32);
+        model::oidc_authorizations::Model {
+            request_id: request_id.clone(),
+            client_id: request.client_id,
+            redirect_uri: request.redirect_uri,
+            scope: request.scopes.join(" "),
+            state: request.state,
+            nonce: request.nonce,
+            code_challenge: request.code_challenge,
+            user_id: None,
+            code_hash: None,
+            expiry_date: chrono::Utc::now().naive_utc()
+                + chrono::Duration::minutes(OIDC_AUTHORIZATION_LIFETIME_MINUTES),
+        }
+        .into_active_model()
+        .insert(self.pool())
+        .await?;
+        Ok(request_id)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn get_oidc_authorization(&self, request_id: &str) -> Result<Option<OidcAuthorization>> {
+        Ok(model::OidcAuthorizations::find_by_id(request_id.to_owned())
+            .filter(OidcAuthorizationsColumn::UserId.is_null())
+            .filter(OidcAuthorizationsColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .one(self.pool())
+            .await?
+            .map(OidcAuthorization::from))
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn approve_oidc_authorization(
+        &self,
+        request_id: &str,
+        user: &UserId,
+        scopes: &[String],
+        code_hash: u64,
+    ) -> Result<()> {
+        debug!(?user);
+        let result = model::OidcAuthorizations::update_many()
+            .set(model::oidc_authorizations::ActiveModel {
+                user_id: ActiveValue::Set(Some(user.clone())),
+                scope: ActiveValue::Set(scopes.join(" ")),
+                code_hash: ActiveValue::Set(Some(code_hash as i64)),
+                expiry_date: ActiveValue::Set(
+                    chrono::Utc::now().naive_utc()
+                        + chrono::Duration::minutes(OIDC_CODE_LIFETIME_MINUTES),
+                ),
+                ..Default::default()
+            })
+            .filter(OidcAuthorizationsColumn::RequestId.eq(request_id))
+            .filter(OidcAuthorizationsColumn::UserId.is_null())
+            .filter(OidcAuthorizationsColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .exec(self.pool())
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such authorization request: '{}'",
+                request_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn delete_oidc_authorization(&self, request_id: &str) -> Result<()> {
+        model::OidcAuthorizations::delete_by_id(request_id.to_owned())
+            .exec(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn consume_oidc_authorization_code(
+        &self,
+        code_hash: u64,
+    ) -> Result<Option<OidcAuthorization>> {
+        let Some(authorization) = model::OidcAuthorizations::find()
+            .filter(OidcAuthorizationsColumn::CodeHash.eq(code_hash as i64))
+            .filter(OidcAuthorizationsColumn::UserId.is_not_null())
+            .filter(OidcAuthorizationsColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .one(self.pool())
+            .await?
+        else {
+            return Ok(None);
+        };
+        // Only one of concurrent requests with the same code manages to delete it.
+        let result = model::OidcAuthorizations::delete_by_id(authorization.request_id.clone())
+            .exec(self.pool())
+            .await?;
+        if result.rows_affected == 0 {
+            return Ok(None);
+        }
+        Ok(Some(authorization.into()))
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn create_oidc_refresh_token(
+        &self,
+        client_id: &str,
+        user: &UserId,
+        scopes: &[String],
+    ) -> Result<String> {
+        debug!(?client_id, ?user);
+        let refresh_token = gen_random_string(100);
+        model::oidc_refresh_tokens::Model {
+            refresh_token_hash: default_hash(refresh_token.as_str()) as i64,
+            client_id: client_id.to_owned(),
+            user_id: user.clone(),
+            scope: scopes.join(" "),
+            expiry_date: chrono::Utc::now().naive_utc()
+                + chrono::Duration::days(OIDC_REFRESH_TOKEN_LIFETIME_DAYS),
+        }
+        .into_active_model()
+        .insert(self.pool())
+        .await?;
+        Ok(refresh_token)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn consume_oidc_refresh_token(
+        &self,
+        client_id: &str,
+        token_hash: u64,
+    ) -> Result<Option<(UserId, Vec<String>)>> {
+        debug!(?client_id);
+        let Some(token) = model::OidcRefreshTokens::find_by_id(token_hash as i64)
+            .filter(OidcRefreshTokensColumn::ClientId.eq(client_id))
+            .filter(OidcRefreshTokensColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .one(self.pool())
+            .await?
+        else {
+            return Ok(None);
+        };
+        // Only one of concurrent requests with the same token manages to delete it.
+        let result = model::OidcRefreshTokens::delete_by_id(token_hash as i64)
+            .exec(self.pool())
+            .await?;
+        if result.rows_affected == 0 {
+            return Ok(None);
+        }
+        Ok(Some((
+            token.user_id,
+            token.scope.split_whitespace().map(str::to_owned).collect(),
+        )))
+    }
 }
@@ -1,18 +1,62 @@
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
-use lldap_domain::types::UserId;
+use lldap_domain::types::{GroupId, UserId};
 use lldap_domain_model::error::Result;
-use std::collections::HashSet;
+use std::{collections::HashSet, net::IpAddr};
+
+/// A client registered with the OpenID Connect provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcClient {
+    pub client_id: String,
+    pub display_name: String,
+    /// `None` for public clients, which authenticate with PKCE only.
+    pub client_secret_hash: Option<String>,
+    pub redirect_uris: Vec<String>,
+    /// Scopes restricted to the members of some groups. Other scopes are available to everyone.
+    pub scope_groups: Vec<(String, GroupId)>,
+}
+
+/// An authorization request, as received from the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcAuthorizationRequest {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcAuthorization {
+    pub request_id: String,
+    pub request: OidcAuthorizationRequest,
+    /// Set once the user approved the request.
+    pub user_id: Option<UserId>,
+}
+
+/// Where a login came from, recorded with the session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
 
 #[async_trait]
 pub trait TcpBackendHandler: Sync {
     async fn get_jwt_blacklist(&self) -> anyhow::Result<HashSet<u64>>;
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)>;
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        session: &SessionInfo,
+    ) -> Result<(String, chrono::Duration)>;
+    /// Record a JWT, along with the session (refresh token) it was issued for, if any.
     async fn register_jwt(
         &self,
         user: &UserId,
         jwt_hash: u64,
         expiry_date: NaiveDateTime,
+        refresh_token_hash: Option<u64>,
     ) -> Result<()>;
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool>;
     async fn blacklist_jwts(&self, user: &UserId) -> Result<HashSet<u64>>;
@@ -26,4 +70,81 @@ pub trait TcpBackendHandler: Sync {
     async fn get_user_id_for_password_reset_token(&self, token: &str) -> Result<UserId>;
 
     async fn delete_password_reset_token(&self, token: &str) -> Result<()>;
+
+    /// Create a token for a login that is waiting for the second factor.
+    async fn create_mfa_login_token(&self, user: &UserId) -> Result<String>;
+
+    /// Get the user ID associated with a (non-expired) MFA login token.
+    async fn get_user_id_for_mfa_login_token(&self, token_hash: u64) -> Result<UserId>;
+
+    /// Record a wrong code for the MFA login token. After too many, the token is deleted.
+    async fn register_failed_mfa_attempt(&self, token_hash: u64) -> Result<()>;
+
+    async fn delete_mfa_login_token(&self, token_hash: u64) -> Result<()>;
+
+    /// Get the TOTP secret of the user, if they enrolled a second factor.
+    async fn get_totp_secret(&self, user: &UserId) -> Result<Option<String>>;
+
+    /// Enable TOTP for the user, replacing any previous secret and recovery codes.
+    async fn set_totp_secret(
+        &self,
+        user: &UserId,
+        secret: &str,
+        recovery_code_hashes: &[u64],
+    ) -> Result<()>;
+
+    /// Record the time step of an accepted TOTP code. Returns false if a code of the same or a
+    /// later time step was already accepted: the code is a replay.
+    async fn use_totp_counter(&self, user: &UserId, counter: i64) -> Result<bool>;
+
+    /// Remove the second factor of the user, along with their recovery codes.
+    async fn delete_totp_secret(&self, user: &UserId) -> Result<()>;
+
+    /// Consume a recovery code. Returns whether the code was valid.
+    async fn use_recovery_code(&self, user: &UserId, code_hash: u64) -> Result<bool>;
+
+    async fn list_oidc_clients(&self) -> Result<Vec<OidcClient>>;
+    async fn get_oidc_client(&self, client_id: &str) -> Result<Option<OidcClient>>;
+    async fn create_oidc_client(&self, client: OidcClient) -> Result<()>;
+    async fn delete_oidc_client(&self, client_id: &str) -> Result<()>;
+
+    /// Store a pending authorization request, and return its ID.
+    async fn create_oidc_authorization(&self, request: OidcAuthorizationRequest) -> Result<String>;
+
+    /// Get a pending (non-expired, not yet approved) authorization request.
+    async fn get_oidc_authorization(&self, request_id: &str) -> Result<Option<OidcAuthorization>>;
+
+    /// Attach the user and the authorization code to the request, possibly narrowing the scopes.
+    async fn approve_oidc_authorization(
+        &self,
+        request_id: &str,
+        user: &UserId,
+        scopes: &[String],
+        code_hash: u64,
+    ) -> Result<()>;
+
+    async fn delete_oidc_authorization(&self, request_id: &str) -> Result<()>;
+
+    /// Exchange an authorization code: returns the approved authorization, which can't be used
+    /// again.
+    async fn consume_oidc_authorization_code(
+        &self,
+        code_hash: u64,
+    ) -> Result<Option<OidcAuthorization>>;
+
+    /// Store the hash of a new refresh token for the client, with the granted scopes.
+    async fn create_oidc_refresh_token(
+        &self,
+        client_id: &str,
+        user: &UserId,
+        scopes: &[String],
+    ) -> Result<String>;
+
+    /// Exchange a refresh token of the client: returns the user and the scopes it was issued
+    /// for. The token can't be used again.
+    async fn consume_oidc_refresh_token(
+        &self,
+        client_id: &str,
+        token_hash: u64,
+    ) -> Result<Option<(UserId, Vec<String>)>>;
 }
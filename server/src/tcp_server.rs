@@ -1,7 +1,9 @@
 use crate::{
     auth_service,
-    configuration::{Configuration, MailOptions},
+    configuration::{Configuration, JwtAlgorithm, MailOptions, OidcOptions},
     logging::CustomRootSpanBuilder,
+    oidc_key::OidcKey,
+    oidc_service,
     tcp_backend_handler::*,
 };
 use actix_files::Files;
@@ -12,13 +14,17 @@ use actix_web::{App, HttpResponse, Responder, dev::AppConfig, guard, web};
 use anyhow::{Context, Result};
 use hmac::Hmac;
 use lldap_access_control::{AccessControlledBackendHandler, ReadonlyBackendHandler};
-use lldap_domain_handlers::handler::{BackendHandler, LoginHandler};
+use lldap_domain_handlers::handler::{
+    AuditLogBackendHandler, BackendHandler, LoginHandler, LoginThrottleBackendHandler,
+};
 use lldap_domain_model::error::DomainError;
 use lldap_opaque_handler::OpaqueHandler;
+use lldap_validation::password::PasswordPolicy;
 use sha2::Sha512;
 use std::collections::HashSet;
+use std::net::IpAddr;
 use std::path::PathBuf;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 use tracing::{info, warn};
 
 async fn index<Backend>(data: web::Data<AppState<Backend>>) -> actix_web::Result<impl Responder> {
@@ -48,6 +54,8 @@ pub enum TcpError {
     NotFoundError(String),
     #[error("Unauthorized: `{0}`")]
     UnauthorizedError(String),
+    #[error("Too many requests: `{0}`")]
+    TooManyRequests(String),
 }
 
 pub type TcpResult<T> = std::result::Result<T, TcpError>;
@@ -64,12 +72,15 @@ pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
             | DomainError::UnknownCryptoError(_) => HttpResponse::InternalServerError(),
             DomainError::Base64DecodeError(_)
             | DomainError::BinarySerializationError(_)
-            | DomainError::EntityNotFound(_) => HttpResponse::BadRequest(),
+            | DomainError::EntityNotFound(_)
+            | DomainError::EntityAlreadyExists(_)
+            | DomainError::ValidationError(_) => HttpResponse::BadRequest(),
         },
         TcpError::BadRequest(_) => HttpResponse::BadRequest(),
         TcpError::NotFoundError(_) => HttpResponse::NotFound(),
         TcpError::InternalServerError(_) => HttpResponse::InternalServerError(),
         TcpError::UnauthorizedError(_) => HttpResponse::Unauthorized(),
+        TcpError::TooManyRequests(_) => HttpResponse::TooManyRequests(),
     }
     .body(error.to_string())
 }
@@ -109,38 +120,76 @@ async fn wasm_handler_compressed<Backend>(
 async fn get_settings<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
     HttpResponse::Ok().json(lldap_frontend_options::Options {
         password_reset_enabled: data.mail_options.enable_password_reset,
+        password_policy: data.password_policy.clone(),
     })
 }
 
+/// The public keys that can verify the tokens issued by LLDAP: the login JWTs when they are
+/// signed with RS256, and the OpenID Connect tokens.
+async fn get_jwks<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    let mut keys: Vec<&OidcKey> = Vec::new();
+    for key in [&data.jwt_rsa_key, &data.oidc_key].into_iter().flatten() {
+        if !keys.iter().any(|k| k.key_id() == key.key_id()) {
+            keys.push(key);
+        }
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "keys": keys.into_iter().map(OidcKey::jwk).collect::<Vec<_>>(),
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn http_config<Backend>(
     cfg: &mut web::ServiceConfig,
     backend_handler: Backend,
     jwt_secret: secstr::SecUtf8,
-    jwt_blacklist: HashSet<u64>,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
     server_url: url::Url,
     assets_path: PathBuf,
     mail_options: MailOptions,
+    jwt_rsa_key: Option<Arc<OidcKey>>,
+    oidc_key: Option<Arc<OidcKey>>,
+    oidc_options: OidcOptions,
+    password_policy: PasswordPolicy,
+    trusted_proxies: Vec<IpAddr>,
 ) where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
 {
     let enable_password_reset = mail_options.enable_password_reset;
+    let enable_oidc = oidc_key.is_some();
     cfg.app_data(web::Data::new(AppState::<Backend> {
         backend_handler: AccessControlledBackendHandler::new(backend_handler),
         jwt_key: hmac::Mac::new_from_slice(jwt_secret.unsecure().as_bytes()).unwrap(),
-        jwt_blacklist: RwLock::new(jwt_blacklist),
+        jwt_rsa_key,
+        jwt_blacklist,
         server_url,
         assets_path: assets_path.clone(),
         mail_options,
+        oidc_key,
+        oidc_options,
+        password_policy,
+        trusted_proxies,
     }))
     .route(
         "/health",
         web::get().to(async || HttpResponse::Ok().finish()),
     )
     .route("/settings", web::get().to(get_settings::<Backend>))
-    .service(
-        web::scope("/auth")
-            .configure(|cfg| auth_service::configure_server::<Backend>(cfg, enable_password_reset)),
-    )
+    .route("/.well-known/jwks.json", web::get().to(get_jwks::<Backend>))
+    .configure(|cfg| {
+        if enable_oidc {
+            cfg.route(
+                "/.well-known/openid-configuration",
+                web::get().to(oidc_service::discovery::<Backend>),
+            );
+        }
+    })
+    .service(web::scope("/auth").configure(|cfg| {
+        auth_service::configure_server::<Backend>(cfg, enable_password_reset);
+        if enable_oidc {
+            cfg.service(web::scope("/oidc").configure(oidc_service::configure_server::<Backend>));
+        }
+    }))
     // API endpoint.
     .service(
         web::scope("/api")
@@ -171,10 +220,19 @@ fn http_config<Backend>(
 pub(crate) struct AppState<Backend> {
     pub backend_handler: AccessControlledBackendHandler<Backend>,
     pub jwt_key: Hmac<Sha512>,
-    pub jwt_blacklist: RwLock<HashSet<u64>>,
+    /// Only set when the JWTs are signed with RS256, instead of HS512 with `jwt_key`.
+    pub jwt_rsa_key: Option<Arc<OidcKey>>,
+    /// Shared with the LDAP server, which can log users out too.
+    pub jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
     pub server_url: url::Url,
     pub assets_path: PathBuf,
     pub mail_options: MailOptions,
+    /// Only set when the OpenID Connect provider is enabled.
+    pub oidc_key: Option<Arc<OidcKey>>,
+    pub oidc_options: OidcOptions,
+    pub password_policy: PasswordPolicy,
+    /// The reverse proxies allowed to report the client address.
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 impl<Backend: BackendHandler> AppState<Backend> {
@@ -197,23 +255,44 @@ impl<Backend: LoginHandler> AppState<Backend> {
         self.backend_handler.unsafe_get_handler()
     }
 }
+impl<Backend: LoginThrottleBackendHandler> AppState<Backend> {
+    pub fn get_login_throttle_handler(&self) -> &(impl LoginThrottleBackendHandler + use<Backend>) {
+        self.backend_handler.unsafe_get_handler()
+    }
+}
+impl<Backend: AuditLogBackendHandler> AppState<Backend> {
+    pub fn get_audit_log_handler(&self) -> &(impl AuditLogBackendHandler + use<Backend>) {
+        self.backend_handler.unsafe_get_handler()
+    }
+}
 
-pub async fn build_tcp_server<Backend>(
+pub fn build_tcp_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
 {
     let jwt_secret = config.jwt_secret.clone().unwrap();
-    let jwt_blacklist = backend_handler
-        .get_jwt_blacklist()
-        .await
-        .context("while getting the jwt blacklist")?;
     let server_url = config.http_url.0.clone();
     let assets_path = config.assets_path.clone();
     let mail_options = config.smtp_options.clone();
+    let jwt_rsa_key = match config.jwt_options.algorithm {
+        JwtAlgorithm::Hs512 => None,
+        JwtAlgorithm::Rs256 => Some(Arc::new(OidcKey::load_or_generate(
+            &config.jwt_options.key_file,
+        )?)),
+    };
+    let oidc_options = config.oidc_options.clone();
+    let oidc_key = if oidc_options.enabled {
+        Some(Arc::new(OidcKey::load_or_generate(&oidc_options.key_file)?))
+    } else {
+        None
+    };
+    let password_policy = config.password_policy.clone();
+    let trusted_proxies = config.trusted_proxies.clone();
     let verbose = config.verbose;
     if !assets_path.join("index.html").exists() {
         warn!(
@@ -233,6 +312,11 @@ where
                 let server_url = server_url.clone();
                 let assets_path = assets_path.clone();
                 let mail_options = mail_options.clone();
+                let jwt_rsa_key = jwt_rsa_key.clone();
+                let oidc_key = oidc_key.clone();
+                let oidc_options = oidc_options.clone();
+                let password_policy = password_policy.clone();
+                let trusted_proxies = trusted_proxies.clone();
                 HttpServiceBuilder::default()
                     .finish(map_config(
                         App::new()
@@ -249,6 +333,11 @@ where
                                     server_url,
                                     assets_path,
                                     mail_options,
+                                    jwt_rsa_key,
+                                    oidc_key,
+                                    oidc_options,
+                                    password_policy,
+                                    trusted_proxies,
                                 )
                             }),
                         |_| AppConfig::default(),
@@ -263,3 +352,49 @@ where
             )
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth_service::tests::{make_app_state, make_rsa_key};
+    use lldap_test_utils::MockTestBackendHandler;
+
+    async fn call_get_jwks(state: AppState<MockTestBackendHandler>) -> serde_json::Value {
+        let response = get_jwks(web::Data::new(state)).await;
+        assert_eq!(response.status(), actix_http::StatusCode::OK);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_jwks_without_keys() {
+        let state = make_app_state(MockTestBackendHandler::new());
+        assert_eq!(
+            call_get_jwks(state).await,
+            serde_json::json!({ "keys": [] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jwks() {
+        let jwt_key = make_rsa_key();
+        let oidc_key = make_rsa_key();
+        let mut state = make_app_state(MockTestBackendHandler::new());
+        state.jwt_rsa_key = Some(jwt_key.clone());
+        state.oidc_key = Some(oidc_key.clone());
+        assert_eq!(
+            call_get_jwks(state).await,
+            serde_json::json!({ "keys": [jwt_key.jwk(), oidc_key.jwk()] })
+        );
+        // The same key is only published once.
+        let mut state = make_app_state(MockTestBackendHandler::new());
+        state.jwt_rsa_key = Some(jwt_key.clone());
+        state.oidc_key = Some(jwt_key.clone());
+        assert_eq!(
+            call_get_jwks(state).await,
+            serde_json::json!({ "keys": [jwt_key.jwk()] })
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Time-based one-time passwords (RFC 6238), as generated by authenticator apps.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use lldap_domain::types::UserId;
+use rand::{Rng, RngCore, distributions::Alphanumeric, rngs::OsRng};
+use sha1::Sha1;
+
+const SECRET_LENGTH: usize = 20;
+const DIGITS: u32 = 6;
+const PERIOD_SECONDS: i64 = 30;
+/// Number of periods before and after the current one for which a code is still accepted, to
+/// account for clock drift.
+const ALLOWED_SKEW: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 12;
+
+fn gen_random_string(len: usize) -> String {
+    OsRng
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(len)
+        .collect()
+}
+
+/// Generates a new base32-encoded secret.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    std::iter::repeat_with(|| gen_random_string(RECOVERY_CODE_LENGTH))
+        .take(RECOVERY_CODE_COUNT)
+        .collect()
+}
+
+/// The URI understood by authenticator apps, usually scanned from a QR code.
+pub fn get_otpauth_uri(issuer: &str, user: &UserId, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECONDS}",
+        issuer = urlencoding::encode(issuer),
+        user = urlencoding::encode(user.as_str()),
+    )
+}
+
+fn get_code_for_counter(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // Dynamic truncation, see RFC 4226 section 5.3.
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Checks the code against the base32-encoded secret, at the given unix timestamp.
+///
+/// Returns the time step that the code was generated for: it must be recorded, so that the same
+/// code is not accepted twice.
+pub fn verify_code(secret: &str, code: &str, timestamp: i64) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let current_counter = timestamp / PERIOD_SECONDS;
+    (current_counter - ALLOWED_SKEW..=current_counter + ALLOWED_SKEW)
+        .rev()
+        .filter(|&counter| counter >= 0)
+        .find(|&counter| get_code_for_counter(&secret, counter as u64) == code)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The code expected at the given unix timestamp.
+    pub(crate) fn get_code(secret: &str, timestamp: i64) -> String {
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        format!(
+            "{:0width$}",
+            get_code_for_counter(&secret, (timestamp / PERIOD_SECONDS) as u64),
+            width = DIGITS as usize
+        )
+    }
+
+    // Test vectors from RFC 6238, appendix B, truncated to 6 digits.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc_vectors() {
+        assert_eq!(get_code_for_counter(RFC_SECRET, 59 / 30), 287082);
+        assert_eq!(get_code_for_counter(RFC_SECRET, 1111111109 / 30), 81804);
+        assert_eq!(get_code_for_counter(RFC_SECRET, 1234567890 / 30), 5924);
+        assert_eq!(get_code_for_counter(RFC_SECRET, 2000000000 / 30), 279037);
+    }
+
+    #[test]
+    fn test_verify_code() {
+        let secret = BASE32_NOPAD.encode(RFC_SECRET);
+        let counter = 1111111109 / 30;
+        assert_eq!(verify_code(&secret, "081804", 1111111109), Some(counter));
+        // Previous and next periods are accepted.
+        assert_eq!(
+            verify_code(&secret, "081804", 1111111109 + 30),
+            Some(counter)
+        );
+        assert_eq!(
+            verify_code(&secret, "081804", 1111111109 - 30),
+            Some(counter)
+        );
+        assert_eq!(verify_code(&secret, "081804", 1111111109 + 90), None);
+        assert_eq!(verify_code(&secret, "81804", 1111111109), None);
+        assert_eq!(verify_code(&secret, "abcdef", 1111111109), None);
+        assert_eq!(verify_code("not base32!", "081804", 1111111109), None);
+    }
+
+    #[test]
+    fn test_generate_secret() {
+        let secret = generate_secret();
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), 20);
+    }
+}
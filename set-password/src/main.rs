@@ -1,6 +1,7 @@
 use anyhow::{Context, Result, bail, ensure};
 use clap::Parser;
 use lldap_auth::{opaque, registration};
+use lldap_validation::password::{PasswordPolicy, validate_password};
 use reqwest::Url;
 use serde::Serialize;
 
@@ -19,6 +20,10 @@ pub struct CliOpts {
     #[clap(long)]
     pub admin_password: Option<String>,
 
+    /// Admin TOTP or recovery code, if the admin enrolled a second factor.
+    #[clap(long)]
+    pub admin_totp_code: Option<String>,
+
     /// Connection token (JWT).
     #[clap(short, long)]
     pub token: Option<String>,
@@ -27,13 +32,9 @@ pub struct CliOpts {
     #[clap(short, long)]
     pub username: String,
 
-    /// New password for the user.
+    /// New password for the user. It must follow the password policy configured on the server.
     #[clap(short, long)]
     pub password: String,
-
-    /// Bypass password requirements such as minimum length. Unsafe.
-    #[clap(long)]
-    pub bypass_password_policy: bool,
 }
 
 fn append_to_url(base_url: &Url, path: &str) -> Url {
@@ -42,7 +43,12 @@ fn append_to_url(base_url: &Url, path: &str) -> Url {
     new_url
 }
 
-fn get_token(base_url: &Url, username: &str, password: &str) -> Result<String> {
+fn get_token(
+    base_url: &Url,
+    username: &str,
+    password: &str,
+    totp_code: Option<String>,
+) -> Result<String> {
     let client = reqwest::blocking::Client::new();
     let response = client
         .post(append_to_url(base_url, "auth/simple/login"))
@@ -51,6 +57,7 @@ fn get_token(base_url: &Url, username: &str, password: &str) -> Result<String> {
             serde_json::to_string(&lldap_auth::login::ClientSimpleLoginRequest {
                 username: username.into(),
                 password: password.to_string(),
+                totp_code,
             })
             .expect("Failed to encode the username/password as json to log in"),
         )
@@ -59,6 +66,12 @@ fn get_token(base_url: &Url, username: &str, password: &str) -> Result<String> {
     Ok(serde_json::from_str::<lldap_auth::login::ServerLoginResponse>(&response.text()?)?.token)
 }
 
+fn get_password_policy(base_url: &Url) -> Result<PasswordPolicy> {
+    let response =
+        reqwest::blocking::get(append_to_url(base_url, "settings"))?.error_for_status()?;
+    Ok(serde_json::from_str::<lldap_frontend_options::Options>(&response.text()?)?.password_policy)
+}
+
 fn call_server(url: Url, token: &str, body: impl Serialize) -> Result<String> {
     let client = reqwest::blocking::Client::new();
     let request = client
@@ -100,19 +113,24 @@ pub fn register_finish(
 
 fn main() -> Result<()> {
     let opts = CliOpts::parse();
-    ensure!(
-        opts.bypass_password_policy || opts.password.len() >= 8,
-        "New password is too short, expected at least 8 characters"
-    );
     ensure!(
         opts.base_url.scheme() == "http" || opts.base_url.scheme() == "https",
         "Base URL should start with `http://` or `https://`"
     );
+    let policy = get_password_policy(&opts.base_url)
+        .context("While fetching the password policy from the server")?;
+    if let Err(errors) = validate_password(&policy, &opts.username, &opts.password) {
+        bail!("The new password is rejected: {}", errors.join(". "));
+    }
     let token = match (opts.token.as_ref(), opts.admin_password.as_ref()) {
         (Some(token), _) => token.clone(),
-        (None, Some(password)) => {
-            get_token(&opts.base_url, &opts.admin_username, password).context("While logging in")?
-        }
+        (None, Some(password)) => get_token(
+            &opts.base_url,
+            &opts.admin_username,
+            password,
+            opts.admin_totp_code.clone(),
+        )
+        .context("While logging in")?,
         (None, None) => bail!("Either the token or the admin password is required"),
     };
 